@@ -0,0 +1,330 @@
+//! Command line of the assembler (the `compiler` binary and `vmcpu asm`)
+
+extern crate libcustomvmcpu;
+
+use std::str;
+use std::collections::HashMap;
+use std::{fs, process::exit};
+use std::io::{self, Read, Write};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap::error::ErrorKind;
+use num_traits::FromPrimitive;
+
+use libcustomvmcpu::{runtime, parser, compiler, common, format, diagnostic, lint, symbols, image, listing, debuginfo, hexfile, elf, disassembler};
+
+/// Assembler for the custom virtual CPU: compiles a program to a binary
+#[derive(Parser)]
+#[command(name = "compiler", version, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    args: AsmArgs,
+    /// Colors diagnostics (auto, always or never)
+    #[arg(long, value_name = "WHEN", default_value = "auto", global = true)]
+    color: diagnostic::ColorChoice,
+}
+
+/// Options for compiling a program (`compiler` and `vmcpu asm`)
+#[derive(Args)]
+pub struct AsmArgs {
+    /// Program to compile (`-` is the standard input)
+    #[arg(required = true)]
+    file: Option<String>,
+    /// Path of the compiled program (`-` is the standard output)
+    #[arg(short = 'o', long = "out", visible_alias = "output", value_name = "FILE", default_value = "out.bin")]
+    out: String,
+    /// Writes the addresses of all labels to FILE
+    #[arg(long, value_name = "FILE")]
+    map: Option<String>,
+    /// Writes a listing (addresses and bytes of every line) to FILE
+    #[arg(long, value_name = "FILE")]
+    listing: Option<String>,
+    /// Format of the compiled program
+    #[arg(long, value_enum, default_value = "bin")]
+    format: OutputFormat,
+    /// Embeds a line table into the binary (only with `--format bin`)
+    #[arg(long)]
+    debug_info: bool,
+    /// Fails, if there are warnings
+    #[arg(long)]
+    deny_warnings: bool,
+    /// Only checks the program and writes nothing, fails on any diagnostic
+    #[arg(long, conflicts_with_all = ["out", "map", "listing"])]
+    check: bool,
+    /// Checks the program for suspicious code
+    #[arg(long)]
+    lint: bool,
+    /// Removes data of labels, which are never referenced
+    #[arg(long)]
+    strip_unused: bool,
+    /// Prints the log of the compiler
+    #[arg(long)]
+    verbose: bool,
+    /// Address of the `.data` section (decimal or hexadecimal with 0x)
+    #[arg(long, value_name = "ADDRESS", value_parser = parse_number)]
+    data_address: Option<u32>,
+    /// Newest instruction set version the program may use
+    #[arg(long, value_name = "VERSION", value_parser = parse_isa_version)]
+    isa_version: Option<common::IsaVersion>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Prints the program in the canonical format
+    Fmt(FmtArgs),
+    /// Prints the addresses, words and instructions of a compiled program
+    Disasm(DisasmArgs),
+}
+
+#[derive(Args)]
+pub struct FmtArgs {
+    /// Overwrites FILE with the formatted program
+    #[arg(short = 'w', long)]
+    write: bool,
+    /// Program to format (`-` is the standard input)
+    file: String,
+}
+
+#[derive(Args)]
+pub struct DisasmArgs {
+    /// Prints assembler source, which compiles to the same program
+    #[arg(long, conflicts_with = "symbols")]
+    source: bool,
+    /// Labels from a map file (see `--map`)
+    #[arg(long, value_name = "FILE")]
+    symbols: Option<String>,
+    /// Compiled program
+    file: String,
+}
+
+/// Format of the written program (`--format`)
+#[derive(PartialEq, Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// Image (see `image::Image::to_bytes`)
+    #[value(name = "bin")]
+    Binary,
+    /// Intel HEX records
+    #[value(name = "ihex")]
+    IntelHex,
+    /// Hexadecimal bytes, 16 per line
+    Hex,
+    /// ELF executable with symbols
+    Elf,
+    /// JSON object with the entry point, the bytes and the symbols
+    Json,
+}
+
+/// Parses a decimal or hexadecimal (0x prefix) number
+fn parse_number(value: &str) -> Result<u32, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse::<u32>(),
+    }.map_err(|err| err.to_string())
+}
+
+fn parse_isa_version(value: &str) -> Result<common::IsaVersion, String> {
+    value.parse::<u8>().ok()
+        .and_then(common::IsaVersion::from_u8)
+        .ok_or_else(|| format!("expected instruction set version (1 to {})", common::LATEST_ISA_VERSION as u8))
+}
+
+/// Reads the program from `file` (`-` is the standard input)
+fn read_input(file: &str) -> String {
+    if file != "-" {
+        if let Ok(data) = fs::read(file) {
+            let result = str::from_utf8(&data[0..]);
+            if let Ok(result) = result {
+                result.to_string()
+            }
+            else {
+                eprintln!("Error: Could not read from standard input");
+                exit(1);
+            }
+        }
+        else {
+            eprintln!("Error: Could not read file \"{}\"", file);
+            exit(1);
+        }
+    } else {
+        let mut result: Vec<u8> = Vec::new();
+        if let Err(_) = io::stdin().lock().read_to_end(&mut result) {
+            eprintln!("Error: Could not read from standard input");
+            exit(1);
+        }
+        let result = str::from_utf8(&result[0..]);
+        if let Ok(result) = result {
+            result.to_string()
+        }
+        else {
+            eprintln!("Error: Could not read from standard input");
+            exit(1);
+        }
+    }
+}
+
+/// `fmt [-w] FILE`: prints the formatted program (or overwrites FILE with it)
+pub fn format_command(args: &FmtArgs, color: bool) {
+    let (write, file) = (args.write, args.file.as_str());
+    let input = read_input(file);
+    match format::format_source(&input) {
+        Ok(formatted) => {
+            if write && file != "-" {
+                if fs::write(file, formatted).is_err() {
+                    eprintln!("Could not write to {}", file);
+                    exit(1);
+                }
+            }
+            else {
+                print!("{}", formatted);
+            }
+        },
+        Err(errors) => {
+            for error in &errors {
+                eprintln!("{}", error.render_pretty(&input, color));
+            }
+            exit(1);
+        }
+    }
+}
+
+/// `disasm FILE`: prints assembler source for a compiled image, which
+/// compiles to the same image
+pub fn disassemble_command(args: &DisasmArgs) {
+    let (source, symbols_file, file) = (args.source, args.symbols.as_deref(), args.file.as_str());
+    let data = if let Ok(data) = fs::read(file) {
+        data
+    }
+    else {
+        eprintln!("Error: Could not read file \"{}\"", file);
+        exit(1);
+    };
+
+    let image = image::Image::from_bytes(&data);
+    if source {
+        print!("{}", disassembler::disassemble_program(&image.program, image.entry));
+        return;
+    }
+
+    let symbols = match symbols_file {
+        Some(symbols_file) => match fs::read_to_string(symbols_file).ok().and_then(|content| symbols::parse_map_file(&content)) {
+            Some(symbols) => symbols,
+            None => {
+                eprintln!("Error: Could not read the symbols from \"{}\"", symbols_file);
+                exit(1);
+            }
+        },
+        None => HashMap::new(),
+    };
+
+    if image.entry != 0 {
+        println!("Entry point: 0x{:08X}", image.entry);
+    }
+    print!("{}", disassembler::disassemble_listing(&image.program, &symbols));
+}
+
+/// Compiles the program (or runs the subcommand)
+pub fn run(cli: Cli) {
+    match &cli.command {
+        Some(Command::Fmt(args)) => format_command(args, cli.color.use_color()),
+        Some(Command::Disasm(args)) => disassemble_command(args),
+        None => assemble(cli.args, cli.color.use_color()),
+    }
+}
+
+/// Compiles the program and writes it (and the `--map` and `--listing`
+/// files)
+pub fn assemble(args: AsmArgs, color: bool) {
+    if args.verbose {
+        env_logger::Builder::new().filter_level(log::LevelFilter::Debug).parse_default_env().init();
+    }
+
+    let format = args.format;
+    if args.debug_info && format != OutputFormat::Binary {
+        Cli::command().error(ErrorKind::ArgumentConflict, "debug information can only be embedded into binaries (--format bin)").exit();
+    }
+
+    let options = compiler::CompileOptions {
+        isa_version: args.isa_version.unwrap_or(common::LATEST_ISA_VERSION),
+        data_address: args.data_address,
+        strip_unused: args.strip_unused,
+    };
+
+    let file = args.file.as_deref().expect("Made sure by clap");
+    let input = read_input(file);
+    let outfile = args.out;
+
+    let mut parser = parser::parse_string(&input);
+    let compile_result = compiler::compile_output(&mut parser, &options);
+    let diagnostics = parser.diagnostics();
+    for diagnostic in &diagnostics {
+        eprintln!("{}", diagnostic.render_pretty(&input, color));
+    }
+
+    let lints = if args.lint { lint::lint(&parser.program, &lint::LintOptions::default()) } else { Vec::new() };
+    for lint in &lints {
+        eprintln!("{}", diagnostic::Diagnostic::from(lint).render_pretty(&input, color));
+    }
+
+    if args.check {
+        if compile_result.is_none() || !diagnostics.is_empty() || !lints.is_empty() {
+            eprintln!("Error: Check failed");
+            exit(1);
+        }
+
+        return;
+    }
+
+    if args.deny_warnings && (!parser.warnings.is_empty() || !lints.is_empty()) {
+        eprintln!("Error: Warnings are denied");
+        exit(1);
+    }
+
+    if let Some(output) = compile_result {
+        if let Some(map_file) = args.map {
+            if fs::write(&map_file, symbols::format_map_file(&output.symbols)).is_err() {
+                eprintln!("Could not write the symbols to {}", map_file);
+                exit(1);
+            }
+        }
+
+        if let Some(listing_file) = args.listing {
+            if fs::write(&listing_file, listing::format_listing(&input, &output)).is_err() {
+                eprintln!("Could not write the listing to {}", listing_file);
+                exit(1);
+            }
+        }
+
+        let bytes = match format {
+            OutputFormat::Binary => {
+                let line_table = if args.debug_info { Some(debuginfo::LineTable::new(file, &input, &output)) } else { None };
+                let mut image = image::Image::new(output.binary, output.entry).with_imports(output.imports);
+                if let Some(line_table) = line_table {
+                    image = image.with_debug(line_table);
+                }
+
+                image.to_bytes()
+            },
+            OutputFormat::IntelHex => hexfile::to_intel_hex(&output.binary, output.entry).into_bytes(),
+            OutputFormat::Hex => hexfile::to_hex(&output.binary).into_bytes(),
+            OutputFormat::Elf => elf::to_elf(&output.binary, output.entry, &output.symbols),
+            OutputFormat::Json => hexfile::to_json(&output.binary, output.entry, &output.symbols).into_bytes(),
+        };
+        if outfile == "-" {
+            let mut stdout = io::stdout().lock();
+            if stdout.write_all(&bytes).and_then(|_| stdout.flush()).is_err() {
+                eprintln!("Could not write the result to the standard output");
+                exit(1);
+            }
+        }
+        else if let Result::Ok(_) = fs::write(outfile.clone(), bytes) {
+            println!("Compiled");
+        }
+        else {
+            eprintln!("Could not write to result to {}", outfile);
+        }
+    }
+    else {
+        eprintln!("Cannot compile program");
+    }
+}
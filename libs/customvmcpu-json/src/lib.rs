@@ -1,81 +1,553 @@
 extern crate libcustomvmcpu;
 
-#[macro_use]
-extern crate json;
-
-use libcustomvmcpu::{common, parser, compiler, runtime};
-
-pub fn interpreter_to_json_string(program: &String) -> String {
-    interpreter_to_json(program).dump()
-}
-
-pub fn interpreter_to_json(program: &String) -> json::JsonValue {
-    let mut parser = parser::parse_string(&program);
-    let errors_json = json::JsonValue::Array(
-        (&parser.errors).iter().map(|error| {
-            return object!{
-                "pos_start" => error.pos.start,
-                "pos_end" => error.pos.end,
-                "error_type" => error.err_type.to_string()
-            };
-    }).collect());
-
-    let program = compiler::compile(&mut parser);
-
-    if let Some(program) = program {
-        let interpreter = runtime::BinaryInterpreter::new_with_initial(&program);
-        if let Some(interpreter) = interpreter {
-            let mut stdout = Vec::new();
-            let mut vm = runtime::BinaryVirtualMachine::new(interpreter, &mut stdout);
-            let exit_code = vm.execute_first() as i32;
-
-            let registers = object!{
-                "R0" => vm.read_register_value(common::Register::R0),
-                "R1" => vm.read_register_value(common::Register::R1),
-                "R2" => vm.read_register_value(common::Register::R2),
-                "R3" => vm.read_register_value(common::Register::R3),
-                "R4" => vm.read_register_value(common::Register::R4),
-                "R5" => vm.read_register_value(common::Register::R5),
-                "R6" => vm.read_register_value(common::Register::R6),
-                "R7" => vm.read_register_value(common::Register::R7),
-                "IP" => vm.read_register_value(common::Register::IP),
-                "SP" => vm.read_register_value(common::Register::SP),
-                "RA" => vm.read_register_value(common::Register::RA),
-                "ERR" => vm.read_register_value(common::Register::ERR),
-            };
-
-            return object!{
-                "success" => true,
-                "errors" => errors_json,
-                "exit_code" => exit_code,
-                "stdout" => String::from_utf8(stdout).unwrap_or(String::new()),
-                "registers" => registers,
-            };
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Write};
+use serde::{Deserialize, Serialize};
+use libcustomvmcpu::runtime::Interpreter;
+use libcustomvmcpu::{common, parser, compiler, runtime, builder, image};
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "node")]
+pub mod node;
+
+/// Version of the JSON output (`RunResult::schema`), incremented on
+/// incompatible changes
+pub const SCHEMA_VERSION: u32 = 3;
+
+/// Result of compiling and running a program: the JSON output
+/// (`to_string`, `to_value`) is defined by these types
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RunResult {
+    /// `SCHEMA_VERSION`
+    pub schema: u32,
+    /// False, if the program couldn't be compiled or loaded
+    pub success: bool,
+    pub errors: Vec<ParserErrorJson>,
+    pub diagnostics: Vec<DiagnosticJson>,
+    /// Addresses of the labels, if the program was compiled (also if it
+    /// couldn't be run; empty for compiled programs, see `run_binary`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub symbols: Option<BTreeMap<String, u32>>,
+    /// None, if the program wasn't run
+    #[serde(flatten)]
+    pub execution: Option<Execution>,
+}
+
+/// Error of the parser or compiler. Positions are bytes, lines and columns
+/// (in characters) start with 1.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ParserErrorJson {
+    /// Stable code (like `E0016`)
+    pub code: String,
+    pub pos_start: usize,
+    pub pos_end: usize,
+    pub line: usize,
+    pub column: usize,
+    /// Source code of the error
+    pub excerpt: String,
+    /// Description of the error with its line and notes (like ``undefined
+    /// label `mian` referenced at line 2 (did you mean `main`?)``)
+    pub message: String,
+}
+
+/// `diagnostic::Diagnostic` with its position in the source
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct DiagnosticJson {
+    pub severity: String,
+    pub code: String,
+    pub pos_start: usize,
+    pub pos_end: usize,
+    pub line: usize,
+    pub column: usize,
+    pub excerpt: String,
+    pub message: String,
+    pub notes: Vec<String>,
+}
+
+/// The registers after the run
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub struct RegisterState {
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r4: u32,
+    pub r5: u32,
+    pub r6: u32,
+    pub r7: u32,
+    pub ip: u32,
+    pub sp: u32,
+    pub ra: u32,
+    pub err: u32,
+}
+
+/// Results of a program, which ran
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Execution {
+    /// None, if the program exceeded the step limit
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    /// The output was longer than `RunOptions::stdout_limit`
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub stdout_truncated: bool,
+    pub registers: RegisterState,
+    /// Every executed instruction (`RunOptions::trace`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace: Option<Vec<TraceStep>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fault: Option<FaultJson>,
+    /// The memory ranges of `RunOptions::memory`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<Vec<MemoryRange>>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub step_limit_exceeded: bool,
+}
+
+/// `runtime::TraceEntry` with its mnemonic
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TraceStep {
+    pub ip: u32,
+    pub instruction: u32,
+    /// None, if the instruction is invalid
+    pub mnemonic: Option<String>,
+    pub register_writes: BTreeMap<String, u32>,
+}
+
+/// `runtime::Fault`
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct FaultJson {
+    pub kind: String,
+    pub ip: u32,
+    pub address: Option<u32>,
+    pub instruction: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct MemoryRange {
+    pub start: u32,
+    pub length: u32,
+    /// Encoded bytes, None if the range is outside of the memory
+    pub data: Option<String>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+impl RunResult {
+    /// The result as JSON value
+    pub fn to_value(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("RunResult is always serializable")
+    }
+
+    fn to_json(&self) -> json::JsonValue {
+        json::parse(&self.to_string()).expect("serde_json writes valid JSON")
+    }
+}
+
+/// The result as JSON string
+impl fmt::Display for RunResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&serde_json::to_string(self).map_err(|_| fmt::Error)?)
+    }
+}
+
+/// How `run` executes the program and what it records besides the result.
+/// The defaults run the program without limits.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct RunOptions {
+    /// Records every executed instruction
+    pub trace: bool,
+    /// Stops the program after this many instructions
+    pub max_steps: Option<u64>,
+    /// Size of the memory (`runtime::BinaryInterpreter::with_memory_size`)
+    pub memory_size: Option<u32>,
+    /// Keeps at most this many bytes of the output
+    pub stdout_limit: Option<usize>,
+    /// Syscalls, which may be called (`VirtualMachine::set_allowed_syscalls`)
+    pub allowed_syscalls: Option<Vec<u32>>,
+    /// Memory ranges (start and length) read after the run
+    pub memory: Vec<(u32, u32)>,
+    pub encoding: MemoryEncoding,
+    /// Registers and memory set before the program runs (it isn't run, if a
+    /// memory patch is outside of the memory)
+    pub initial_state: Option<InitialState>,
+}
+
+/// Registers and memory patches applied before the program runs (after it was
+/// loaded), for example for table-driven tests. Read from a JSON document
+/// with `from_json`.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct InitialState {
+    pub registers: Vec<(common::Register, u32)>,
+    /// Bytes written at an address
+    pub memory: Vec<(u32, Vec<u8>)>,
+}
+
+/// JSON document of `InitialState`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InitialStateJson {
+    #[serde(default)]
+    registers: BTreeMap<String, u32>,
+    #[serde(default)]
+    memory: Vec<MemoryPatchJson>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MemoryPatchJson {
+    address: u32,
+    /// Two hex digits per byte
+    data: String,
+}
+
+impl InitialState {
+    /// Reads a document like
+    /// `{"registers": {"$r0": 5}, "memory": [{"address": 256, "data": "0A0B"}]}`.
+    /// Register names are accepted with or without `$` and in any case. `$ip`
+    /// and `$err` are set when the program starts and can't be given.
+    pub fn from_json(document: &str) -> Result<InitialState, String> {
+        let state: InitialStateJson = serde_json::from_str(document).map_err(|err| err.to_string())?;
+        let registers = state.registers.iter().map(|(name, value)| {
+            match parser::get_register_by_name(&name.trim_start_matches('$').to_lowercase()) {
+                Some(common::Register::IP) | Some(common::Register::ERR) => Err(format!("register `{}` can't be set", name)),
+                Some(register) => Ok((register, *value)),
+                None => Err(format!("unknown register `{}`", name)),
+            }
+        }).collect::<Result<Vec<_>, String>>()?;
+        let memory = state.memory.iter().map(|patch| {
+            decode_hex(&patch.data)
+                .map(|data| (patch.address, data))
+                .ok_or_else(|| format!("invalid hex data at address {}", patch.address))
+        }).collect::<Result<Vec<_>, String>>()?;
+
+        Ok(InitialState { registers, memory })
+    }
+}
+
+fn decode_hex(data: &str) -> Option<Vec<u8>> {
+    if !data.is_ascii() || !data.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..data.len()).step_by(2).map(|i| u8::from_str_radix(&data[i..i + 2], 16).ok()).collect()
+}
+
+/// Output of the program, which keeps at most `limit` bytes
+struct LimitedOutput {
+    data: Vec<u8>,
+    limit: Option<usize>,
+    truncated: bool,
+}
+
+impl Write for LimitedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let keep = match self.limit {
+            Some(limit) => buf.len().min(limit.saturating_sub(self.data.len())),
+            None => buf.len(),
+        };
+        self.truncated |= keep < buf.len();
+        self.data.extend_from_slice(&buf[..keep]);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Like `interpreter_to_json`, but with the limits and recordings of
+/// `options`
+pub fn run_with_options(program: &str, options: &RunOptions) -> json::JsonValue {
+    run(program, options).to_json()
+}
+
+pub fn interpreter_to_json_string(program: &str) -> String {
+    run(program, &RunOptions::default()).to_string()
+}
+
+pub fn interpreter_to_json(program: &str) -> json::JsonValue {
+    run(program, &RunOptions::default()).to_json()
+}
+
+/// Like `interpreter_to_json`, but the result contains every executed
+/// instruction in `trace`
+pub fn interpreter_to_json_traced(program: &str) -> json::JsonValue {
+    run(program, &RunOptions { trace: true, ..RunOptions::default() }).to_json()
+}
+
+/// Like `interpreter_to_json_traced`, but the program is stopped after
+/// `max_steps` instructions (for step-through visualizations of programs,
+/// which might not terminate). Then `exit_code` is null and
+/// `step_limit_exceeded` is true.
+pub fn interpreter_to_json_with_trace(program: &str, max_steps: u64) -> json::JsonValue {
+    run(program, &RunOptions { trace: true, max_steps: Some(max_steps), ..RunOptions::default() }).to_json()
+}
+
+/// Encoding of the memory in the JSON output
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum MemoryEncoding {
+    /// Two hex digits per byte
+    #[default]
+    Hex,
+    /// Base64 with padding
+    Base64,
+}
+
+/// Like `interpreter_to_json`, but the result contains the memory after the
+/// run at the `ranges` (start and length) in `memory`. The data of a range
+/// outside of the memory is null.
+pub fn interpreter_to_json_with_memory(program: &str, ranges: &[(u32, u32)], encoding: MemoryEncoding) -> json::JsonValue {
+    run(program, &RunOptions { memory: ranges.to_vec(), encoding, ..RunOptions::default() }).to_json()
+}
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_memory(data: &[u8], encoding: MemoryEncoding) -> String {
+    match encoding {
+        MemoryEncoding::Hex => data.iter().map(|byte| format!("{:02X}", byte)).collect(),
+        MemoryEncoding::Base64 => {
+            let mut result = String::new();
+            for chunk in data.chunks(3) {
+                let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| bits | (*byte as u32) << (16 - 8 * i));
+                for i in 0..4 {
+                    if i <= chunk.len() {
+                        result.push(BASE64_ALPHABET[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+                    }
+                    else {
+                        result.push('=');
+                    }
+                }
+            }
+
+            result
+        },
+    }
+}
+
+impl From<&runtime::TraceEntry> for TraceStep {
+    fn from(entry: &runtime::TraceEntry) -> TraceStep {
+        TraceStep {
+            ip: entry.ip,
+            instruction: entry.instruction,
+            mnemonic: entry.mnemonic(),
+            register_writes: entry.register_writes.iter().map(|(reg, value)| (format!("{:?}", reg), *value)).collect(),
+        }
+    }
+}
+
+/// Writes the executed instructions as NDJSON: one `TraceStep` object per
+/// line or, with `batch_size`, one array of up to that many steps per line.
+/// Buffered steps are written, when the sink is dropped.
+pub struct NdjsonTraceSink<W: Write> {
+    writer: W,
+    batch_size: usize,
+    batch: Vec<TraceStep>,
+}
+
+impl<W: Write> NdjsonTraceSink<W> {
+    pub fn new(writer: W) -> NdjsonTraceSink<W> {
+        NdjsonTraceSink { writer, batch_size: 1, batch: Vec::new() }
+    }
+
+    /// Writes `batch_size` steps per line (as array, if greater than 1)
+    pub fn batch_size(mut self, batch_size: usize) -> NdjsonTraceSink<W> {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    fn write_batch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+
+        let line = if self.batch_size == 1 {
+            serde_json::to_string(&self.batch[0])
         }
         else {
-            return object!{
-                "success" => false,
-                "errors" => errors_json
-            };
+            serde_json::to_string(&self.batch)
+        };
+        self.batch.clear();
+
+        // Tracing must not influence the execution, so errors are ignored
+        if let Ok(line) = line {
+            let _ = writeln!(self.writer, "{}", line);
         }
     }
-    else {
-        return object!{
-            "success" => false,
-            "errors" => errors_json
-        };
+}
+
+impl<W: Write> runtime::TraceSink for NdjsonTraceSink<W> {
+    fn trace(&mut self, entry: runtime::TraceEntry) {
+        self.batch.push(TraceStep::from(&entry));
+        if self.batch.len() >= self.batch_size {
+            self.write_batch();
+        }
+    }
+}
+
+impl<W: Write> Drop for NdjsonTraceSink<W> {
+    fn drop(&mut self) {
+        self.write_batch();
+        let _ = self.writer.flush();
+    }
+}
+
+fn register_state(vm: &runtime::BinaryVirtualMachine) -> RegisterState {
+    RegisterState {
+        r0: vm.read_register_value(common::Register::R0),
+        r1: vm.read_register_value(common::Register::R1),
+        r2: vm.read_register_value(common::Register::R2),
+        r3: vm.read_register_value(common::Register::R3),
+        r4: vm.read_register_value(common::Register::R4),
+        r5: vm.read_register_value(common::Register::R5),
+        r6: vm.read_register_value(common::Register::R6),
+        r7: vm.read_register_value(common::Register::R7),
+        ip: vm.read_register_value(common::Register::IP),
+        sp: vm.read_register_value(common::Register::SP),
+        ra: vm.read_register_value(common::Register::RA),
+        err: vm.read_register_value(common::Register::ERR),
     }
 }
 
+/// Runs the compiled program. The executed instructions are given to
+/// `tracer` instead of being recorded, if there's one.
+fn execute(program: &[u8], entry: u32, options: &RunOptions, tracer: Option<&mut dyn runtime::TraceSink>) -> Option<Execution> {
+    let mut interpreter = runtime::BinaryInterpreter::with_memory_size(options.memory_size.unwrap_or(runtime::BINARY_INTERPRETER_MEM_SIZE))?;
+    if !interpreter.load_program(program) {
+        return None;
+    }
+
+    let mut stdout = LimitedOutput { data: Vec::new(), limit: options.stdout_limit, truncated: false };
+    let mut trace_entries: Vec<runtime::TraceEntry> = Vec::new();
+    let streamed = tracer.is_some();
+    let mut builder = builder::VmBuilder::new(interpreter, &mut stdout).entry(entry);
+    if let Some(tracer) = tracer {
+        builder = builder.tracer(tracer);
+    }
+    else if options.trace {
+        builder = builder.tracer(&mut trace_entries);
+    }
+    if let Some(allowed_syscalls) = &options.allowed_syscalls {
+        builder = builder.allowed_syscalls(allowed_syscalls.clone());
+    }
+    let mut vm = builder.build();
+    if let Some(state) = &options.initial_state {
+        for (register, value) in &state.registers {
+            vm.write_register_value(*register, *value);
+        }
+        for (address, data) in &state.memory {
+            vm.write_bytes(*address, data).ok()?;
+        }
+    }
+
+    let exit_code = match options.max_steps {
+        Some(max_steps) => {
+            vm.start(entry);
+            vm.run_for(max_steps).map(|exit_code| exit_code as i32)
+        },
+        None => Some(vm.execute_first() as i32),
+    };
+
+    let registers = register_state(&vm);
+
+    let memory: Vec<MemoryRange> = options.memory.iter().map(|(start, length)| MemoryRange {
+        start: *start,
+        length: *length,
+        data: vm.read_memory(*start..start.saturating_add(*length)).map(|data| encode_memory(&data, options.encoding)),
+    }).collect();
+
+    let fault = vm.last_fault().map(|fault| FaultJson {
+        kind: format!("{:?}", fault.kind),
+        ip: fault.ip,
+        address: fault.address,
+        instruction: fault.instruction,
+    });
+    drop(vm);
+
+    Some(Execution {
+        exit_code,
+        stdout: String::from_utf8_lossy(&stdout.data).into_owned(),
+        stdout_truncated: stdout.truncated,
+        registers,
+        trace: if options.trace && !streamed { Some(trace_entries.iter().map(TraceStep::from).collect()) } else { None },
+        fault,
+        memory: if options.memory.is_empty() { None } else { Some(memory) },
+        step_limit_exceeded: exit_code.is_none(),
+    })
+}
+
+/// Compiles and runs the program
+pub fn run(program: &str, options: &RunOptions) -> RunResult {
+    compile_and_execute(program, options, None)
+}
+
+/// Like `run`, but gives the executed instructions to `tracer` (like a
+/// `NdjsonTraceSink`) while the program runs instead of recording them in
+/// the result
+pub fn run_streaming(program: &str, options: &RunOptions, tracer: &mut dyn runtime::TraceSink) -> RunResult {
+    compile_and_execute(program, options, Some(tracer))
+}
+
+fn compile_and_execute(program: &str, options: &RunOptions, tracer: Option<&mut dyn runtime::TraceSink>) -> RunResult {
+    let mut parser = parser::parse_string(&program.to_string());
+    let output = compiler::compile_output(&mut parser, &compiler::CompileOptions::default());
+    let errors = parser.errors.iter().map(|error| ParserErrorJson {
+        code: error.code.to_string(),
+        pos_start: error.span.start,
+        pos_end: error.span.end,
+        line: error.line(program),
+        column: error.column(program),
+        excerpt: error.excerpt(program).to_string(),
+        message: error.describe(program),
+    }).collect();
+    let diagnostics = parser.diagnostics().iter().map(|diagnostic| DiagnosticJson {
+        severity: diagnostic.severity.to_string(),
+        code: diagnostic.code.to_string(),
+        pos_start: diagnostic.span.start,
+        pos_end: diagnostic.span.end,
+        line: diagnostic.line(program),
+        column: diagnostic.column(program),
+        excerpt: diagnostic.excerpt(program).to_string(),
+        message: diagnostic.message.clone(),
+        notes: diagnostic.notes.clone(),
+    }).collect();
+
+    let symbols = output.as_ref().map(|output| output.symbols.iter().map(|(name, address)| (name.clone(), *address)).collect());
+    let execution = output.and_then(|output| execute(&output.binary, output.entry, options, tracer));
+
+    RunResult { schema: SCHEMA_VERSION, success: execution.is_some(), errors, diagnostics, symbols, execution }
+}
+
+/// Runs a compiled program (an image, see `image::Image::from_bytes`)
+/// without compiling it again. The result has no errors and diagnostics and
+/// empty symbols (the image has no labels).
+pub fn run_binary(bytes: &[u8], options: &RunOptions) -> RunResult {
+    let image = image::Image::from_bytes(bytes);
+    let execution = execute(&image.program, image.entry, options, None);
+    RunResult { schema: SCHEMA_VERSION, success: execution.is_some(), errors: Vec::new(), diagnostics: Vec::new(), symbols: Some(BTreeMap::new()), execution }
+}
+
+/// Like `interpreter_to_json`, but for a compiled program (see `run_binary`)
+pub fn binary_to_json(bytes: &[u8]) -> json::JsonValue {
+    run_binary(bytes, &RunOptions::default()).to_json()
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{runtime, interpreter_to_json};
+    use json::{array, object};
+    use super::{common, runtime, run, run_streaming, run_with_options, NdjsonTraceSink, TraceStep, InitialState, run_binary, binary_to_json, RunOptions, RunResult, RegisterState, interpreter_to_json, interpreter_to_json_traced, interpreter_to_json_with_trace, interpreter_to_json_with_memory, encode_memory, MemoryEncoding};
     #[test]
     fn basic() {
-        let result = interpreter_to_json(&"syscalli 0".into());
+        let result = interpreter_to_json("syscalli 0");
         let expect: json::JsonValue = object!{
+            "schema" => super::SCHEMA_VERSION,
             "success" => true,
             "errors" => array![],
+            "diagnostics" => array![],
             "exit_code" => 0,
             "stdout" => String::new(),
             "registers" => object!{
@@ -91,7 +563,8 @@ mod tests {
                 "SP" => runtime::BINARY_INTERPRETER_MEM_SIZE,
                 "RA" => 4,
                 "ERR" => 0,
-            }
+            },
+            "symbols" => object!{},
         };
 
         assert_eq!(
@@ -99,4 +572,219 @@ mod tests {
             result
         );
     }
+
+    #[test]
+    fn traced() {
+        let result = interpreter_to_json_traced("li $r1, 2\nsyscalli 0");
+        assert_eq!(2, result["trace"].len());
+        assert_eq!(object!{
+            "ip" => 0,
+            "instruction" => 0x07100002,
+            "mnemonic" => "li $r1, 2",
+            "register_writes" => object!{ "R1" => 2 },
+        }, result["trace"][0]);
+        assert_eq!(4, result["trace"][1]["ip"]);
+        assert!(interpreter_to_json("syscalli 0")["trace"].is_null());
+    }
+
+    #[test]
+    fn trace_limit() {
+        let program = "li $r1, 3\nloop:\nsubi $r1, 1\njnzi $r1, %loop\nsyscalli 0";
+        let result = interpreter_to_json_with_trace(program, 100);
+        assert_eq!(0, result["exit_code"]);
+        assert_eq!(8, result["trace"].len());
+        assert!(result["step_limit_exceeded"].is_null());
+
+        let result = interpreter_to_json_with_trace(program, 4);
+        assert!(result["exit_code"].is_null());
+        assert_eq!(true, result["step_limit_exceeded"]);
+        assert_eq!(4, result["trace"].len());
+        assert_eq!("subi $r1, 1", result["trace"][3]["mnemonic"]);
+        assert_eq!(object!{ "R1" => 1 }, result["trace"][3]["register_writes"]);
+    }
+
+    #[test]
+    fn streaming() {
+        let program = "li $r1, 3\nloop:\nsubi $r1, 1\njnzi $r1, %loop\nsyscalli 0";
+        let mut lines = Vec::new();
+        let result = run_streaming(program, &RunOptions { trace: true, ..RunOptions::default() }, &mut NdjsonTraceSink::new(&mut lines));
+        assert!(result.success);
+        assert_eq!(None, result.execution.and_then(|execution| execution.trace));
+
+        let lines = String::from_utf8(lines).expect("Expected UTF-8");
+        let steps: Vec<TraceStep> = lines.lines().map(|line| serde_json::from_str(line).expect("Expected a step per line")).collect();
+        assert_eq!(8, steps.len());
+        assert_eq!(Some("subi $r1, 1".to_string()), steps[1].mnemonic);
+        assert_eq!(Some(&2), steps[1].register_writes.get("R1"));
+
+        // The last batch is written, when the sink is dropped
+        let mut lines = Vec::new();
+        run_streaming(program, &RunOptions::default(), &mut NdjsonTraceSink::new(&mut lines).batch_size(3));
+        let batches: Vec<Vec<TraceStep>> = String::from_utf8(lines).expect("Expected UTF-8").lines()
+            .map(|line| serde_json::from_str(line).expect("Expected a batch per line"))
+            .collect();
+        assert_eq!(vec![3, 3, 2], batches.iter().map(|batch| batch.len()).collect::<Vec<usize>>());
+        assert_eq!(steps, batches.concat());
+    }
+
+    #[test]
+    fn initial_state() {
+        let state = InitialState::from_json(r#"{"registers": {"$r0": 5, "R2": 7}, "memory": [{"address": 256, "data": "0A0b0000"}]}"#)
+            .expect("Expected a valid state");
+        assert_eq!(vec![(common::Register::R0, 5), (common::Register::R2, 7)], state.registers);
+        assert_eq!(vec![(256, vec![0x0A, 0x0B, 0, 0])], state.memory);
+
+        let program = "lwi $r1, 256\nadd $r1, $r0\nadd $r1, $r2\nsyscalli 0";
+        let options = RunOptions { initial_state: Some(state), ..RunOptions::default() };
+        assert_eq!(Some(Some(0x0B0A + 12)), run(program, &options).execution.map(|execution| execution.exit_code));
+
+        // Patches outside of the memory
+        let state = InitialState::from_json(r#"{"memory": [{"address": 4294967295, "data": "0102"}]}"#).expect("Expected a valid state");
+        assert!(!run(program, &RunOptions { initial_state: Some(state), ..RunOptions::default() }).success);
+
+        assert_eq!(Ok(InitialState::default()), InitialState::from_json("{}"));
+        assert_eq!(Err("unknown register `$r9`".to_string()), InitialState::from_json(r#"{"registers": {"$r9": 1}}"#));
+        assert_eq!(Err("register `$ip` can't be set".to_string()), InitialState::from_json(r#"{"registers": {"$ip": 1}}"#));
+        assert_eq!(Err("invalid hex data at address 0".to_string()), InitialState::from_json(r#"{"memory": [{"address": 0, "data": "ABC"}]}"#));
+        assert!(InitialState::from_json(r#"{"regs": {}}"#).is_err());
+    }
+
+    #[test]
+    fn symbols() {
+        let result = interpreter_to_json("main:\nji %end\nend:\nsyscalli 0");
+        assert_eq!(object!{ "main" => 0, "end" => 4 }, result["symbols"]);
+
+        // Also, if the program can't be run
+        let result = run("main:\nji %end\nend:\nsyscalli 0", &RunOptions { memory_size: Some(4), ..RunOptions::default() });
+        assert!(!result.success);
+        assert_eq!(Some(vec![("end".to_string(), 4), ("main".to_string(), 0)]), result.symbols.map(|symbols| symbols.into_iter().collect()));
+        assert!(interpreter_to_json("ji %mian")["symbols"].is_null());
+    }
+
+    #[test]
+    fn undefined_label() {
+        let result = interpreter_to_json("main:\nji %mian");
+        assert_eq!(false, result["success"]);
+        assert_eq!(1, result["errors"].len());
+        assert_eq!(object!{
+            "code" => "E0016",
+            "pos_start" => 6,
+            "pos_end" => 14,
+            "line" => 2,
+            "column" => 1,
+            "excerpt" => "ji %mian",
+            "message" => "undefined label `mian` referenced at line 2 (did you mean `main`?)",
+        }, result["errors"][0]);
+        assert_eq!(object!{
+            "severity" => "error",
+            "code" => "E0016",
+            "pos_start" => 6,
+            "pos_end" => 14,
+            "line" => 2,
+            "column" => 1,
+            "excerpt" => "ji %mian",
+            "message" => "undefined label `mian`",
+            "notes" => array!["did you mean `main`?"],
+        }, result["diagnostics"][0]);
+    }
+
+    #[test]
+    fn fault() {
+        let result = interpreter_to_json("li $r0, -1\nsw $r0, $r0");
+        assert_eq!("Memory", result["fault"]["kind"]);
+        assert_eq!(4, result["fault"]["ip"]);
+        assert_eq!(0xFFFFFFFFu32, result["fault"]["address"]);
+        assert_eq!(0x02000000, result["fault"]["instruction"]);
+        assert!(interpreter_to_json("syscalli 0")["fault"].is_null());
+    }
+
+    #[test]
+    fn memory() {
+        let program = "syscalli 0\ndata:\n.str \"Hi!\"";
+        let result = interpreter_to_json_with_memory(program, &[(4, 3), (4, 4), (0xFFFFFFFF, 4)], MemoryEncoding::Hex);
+        assert_eq!(array![
+            object!{ "start" => 4, "length" => 3, "data" => "486921" },
+            object!{ "start" => 4, "length" => 4, "data" => "48692100" },
+            object!{ "start" => 0xFFFFFFFFu32, "length" => 4, "data" => json::JsonValue::Null },
+        ], result["memory"]);
+
+        let result = interpreter_to_json_with_memory(program, &[(4, 3)], MemoryEncoding::Base64);
+        assert_eq!("SGkh", result["memory"][0]["data"]);
+        assert!(interpreter_to_json(program)["memory"].is_null());
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!("", encode_memory(b"", MemoryEncoding::Base64));
+        assert_eq!("Zg==", encode_memory(b"f", MemoryEncoding::Base64));
+        assert_eq!("Zm8=", encode_memory(b"fo", MemoryEncoding::Base64));
+        assert_eq!("Zm9vYmFy", encode_memory(b"foobar", MemoryEncoding::Base64));
+        assert_eq!("/+8=", encode_memory(&[0xFF, 0xEF], MemoryEncoding::Base64));
+    }
+
+    #[test]
+    fn typed() {
+        let result = run("li $r1, 7\nsyscalli 0", &RunOptions::default());
+        assert!(result.success);
+        let execution = result.execution.as_ref().unwrap();
+        assert_eq!(Some(7), execution.exit_code);
+        assert_eq!(RegisterState {
+            r0: 0, r1: 7, r2: 0, r3: 0, r4: 0, r5: 0, r6: 0, r7: 0,
+            ip: 4, sp: runtime::BINARY_INTERPRETER_MEM_SIZE, ra: 8, err: 0,
+        }, execution.registers);
+        assert_eq!(7, result.to_value()["registers"]["R1"]);
+        assert_eq!(result, serde_json::from_str::<RunResult>(&result.to_string()).unwrap());
+
+        let result = run("ji %mian", &RunOptions::default());
+        assert!(!result.success);
+        assert_eq!(None, result.execution);
+        assert_eq!("E0016", result.diagnostics[0].code);
+        // Only schema, success, errors and diagnostics
+        assert_eq!(4, result.to_value().as_object().unwrap().len());
+    }
+
+    #[test]
+    fn options() {
+        let program = "li $r1, %msg\nli $r2, 6\nsyscalli 1\nsyscalli 1\nli $r1, 0\nsyscalli 0\nmsg:\n.str \"Hello\\n\"";
+        let result = run_with_options(program, &RunOptions::default());
+        assert_eq!("Hello\nHello\n", result["stdout"]);
+        assert!(result["stdout_truncated"].is_null());
+
+        let result = run_with_options(program, &RunOptions { stdout_limit: Some(8), ..RunOptions::default() });
+        assert_eq!(0, result["exit_code"]);
+        assert_eq!("Hello\nHe", result["stdout"]);
+        assert_eq!(true, result["stdout_truncated"]);
+
+        let result = run_with_options(program, &RunOptions { allowed_syscalls: Some(vec![0]), ..RunOptions::default() });
+        assert_eq!("Syscall", result["fault"]["kind"]);
+        assert_eq!("", result["stdout"]);
+
+        let result = run_with_options(program, &RunOptions { memory_size: Some(4096), ..RunOptions::default() });
+        assert_eq!(4096, result["registers"]["SP"]);
+        assert_eq!(false, run_with_options(program, &RunOptions { memory_size: Some(16), ..RunOptions::default() })["success"]);
+
+        let result = run_with_options("loop:\nji %loop", &RunOptions { max_steps: Some(10), ..RunOptions::default() });
+        assert_eq!(true, result["step_limit_exceeded"]);
+        assert!(result["trace"].is_null());
+    }
+
+    #[test]
+    fn binary() {
+        let program = "ji %main\nmsg:\n.str \"Hi\"\n.entry main\nmain:\nli $r1, %msg\nli $r2, 2\nsyscalli 1\nli $r1, 3\nsyscalli 0";
+        let mut parser = libcustomvmcpu::parser::parse_str(program);
+        let output = libcustomvmcpu::compiler::compile_output(&mut parser, &Default::default()).expect("Compiles");
+        let bytes = libcustomvmcpu::image::Image::new(output.binary, output.entry).to_bytes();
+
+        let expected = interpreter_to_json(program);
+        let result = binary_to_json(&bytes);
+        assert_eq!(3, result["exit_code"]);
+        assert_eq!("Hi", result["stdout"]);
+        assert_eq!(expected["registers"], result["registers"]);
+        assert_eq!(object!{}, result["symbols"]);
+        assert_eq!(0, result["diagnostics"].len());
+
+        let result = run_binary(&bytes, &RunOptions { stdout_limit: Some(1), ..RunOptions::default() });
+        assert_eq!("H", result.execution.unwrap().stdout);
+        assert!(!run_binary(&bytes, &RunOptions { memory_size: Some(4), ..RunOptions::default() }).success);
+    }
 }
@@ -0,0 +1,138 @@
+//! Node.js bindings (feature `node`) for graders and visualizers: the addon
+//! is built with `napi build --release -- --features node` in this directory
+
+use std::cell::RefCell;
+use std::io::{self, Write};
+use napi::bindgen_prelude::Buffer;
+use napi_derive::napi;
+use libcustomvmcpu::builder::VmBuilder;
+use libcustomvmcpu::runtime::{BinaryInterpreter, BinaryVirtualMachine, Interpreter};
+use libcustomvmcpu::{compiler, diagnostic, parser};
+use super::{register_state, RunOptions};
+
+thread_local! {
+    /// Output of the machines of this thread, moved to the machine after
+    /// every call
+    static OUTPUT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Writes to `OUTPUT`. A `Machine` lives as long as its JavaScript object, so
+/// its virtual machine can't borrow the output from anywhere else.
+struct ThreadOutput;
+
+impl Write for ThreadOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        OUTPUT.with(|output| output.borrow_mut().extend_from_slice(buf));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Compiles `source`. Throws the errors of the program, if it can't be
+/// compiled.
+fn compile(source: &str) -> napi::Result<compiler::CompileOutput> {
+    let mut parser = parser::parse_string(&source.to_string());
+    let output = compiler::compile_output(&mut parser, &compiler::CompileOptions::default());
+    output.ok_or_else(|| {
+        let errors: Vec<String> = parser.diagnostics().iter()
+            .filter(|diagnostic| diagnostic.severity == diagnostic::Severity::Error)
+            .map(|diagnostic| diagnostic.render(source))
+            .collect();
+        napi::Error::from_reason(errors.join("\n"))
+    })
+}
+
+/// Compiles `source` to a binary (without an image header)
+#[napi]
+pub fn assemble(source: String) -> napi::Result<Buffer> {
+    Ok(compile(&source)?.binary.into())
+}
+
+/// Compiles and runs `source`. Returns the same object as the JSON output
+/// (`RunResult`).
+#[napi]
+pub fn run(source: String) -> serde_json::Value {
+    super::run(&source, &RunOptions::default()).to_value()
+}
+
+/// Virtual machine executing a program step by step
+#[napi]
+pub struct Machine {
+    vm: BinaryVirtualMachine<'static>,
+    output: Vec<u8>,
+}
+
+#[napi]
+impl Machine {
+    /// Compiles `source` and prepares running it at its entry point
+    #[napi(constructor)]
+    pub fn new(source: String) -> napi::Result<Machine> {
+        let output = compile(&source)?;
+        let mut interpreter = BinaryInterpreter::new();
+        if !interpreter.load_program(&output.binary) {
+            return Err(napi::Error::from_reason("the program doesn't fit into the memory"));
+        }
+
+        // Leaking a zero-sized value doesn't allocate
+        let mut vm = VmBuilder::new(interpreter, Box::leak(Box::new(ThreadOutput))).entry(output.entry).build();
+        vm.start(output.entry);
+
+        Ok(Machine { vm, output: Vec::new() })
+    }
+
+    fn collect_output(&mut self) {
+        OUTPUT.with(|output| self.output.append(&mut output.borrow_mut()));
+    }
+
+    /// Executes the next instruction. Returns false, if the program stopped.
+    #[napi]
+    pub fn step(&mut self) -> bool {
+        let running = self.vm.step();
+        self.collect_output();
+        running
+    }
+
+    /// Executes at most `max_steps` instructions. Returns the exit code (see
+    /// `exitCode`), if the program stopped.
+    #[napi]
+    pub fn run(&mut self, max_steps: u32) -> Option<u32> {
+        let result = self.vm.run_for(max_steps as u64);
+        self.collect_output();
+        result
+    }
+
+    /// True, until the program stopped
+    #[napi(getter)]
+    pub fn running(&self) -> bool {
+        self.vm.is_running()
+    }
+
+    /// `$r1` or the error number plus `ERROR_START_NUM` of the stopped
+    /// program
+    #[napi(getter)]
+    pub fn exit_code(&self) -> u32 {
+        self.vm.get_result()
+    }
+
+    /// Values of all registers (like `registers` of the JSON output)
+    #[napi]
+    pub fn registers(&self) -> serde_json::Value {
+        serde_json::to_value(register_state(&self.vm)).expect("RegisterState is always serializable")
+    }
+
+    /// Reads `length` bytes at `address`. Returns null, if the range is
+    /// outside of the memory.
+    #[napi]
+    pub fn read_memory(&self, address: u32, length: u32) -> Option<Buffer> {
+        self.vm.read_memory(address..address.saturating_add(length)).map(|data| data.to_vec().into())
+    }
+
+    /// Returns the output of the program since the last call
+    #[napi]
+    pub fn take_output(&mut self) -> String {
+        String::from_utf8_lossy(&std::mem::take(&mut self.output)).into_owned()
+    }
+}
@@ -0,0 +1,33 @@
+//! JavaScript bindings (feature `wasm`), so the assembler and the VM can
+//! run in the browser: `wasm-pack build libs/customvmcpu-json -- --features wasm`
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+use super::{run, RunOptions, RunResult};
+
+fn to_js(result: &RunResult) -> JsValue {
+    // Plain objects instead of `Map`s, like `JSON.parse` of the JSON output
+    result.serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .unwrap_or(JsValue::NULL)
+}
+
+/// Compiles and runs `source`. Returns the same object as the JSON output
+/// (`RunResult`).
+#[wasm_bindgen]
+pub fn run_program(source: &str) -> JsValue {
+    to_js(&run(source, &RunOptions::default()))
+}
+
+/// Like `run_program`, but stops after `max_steps` instructions and captures
+/// at most `stdout_limit` bytes of the output (for programs, which may not
+/// terminate)
+#[wasm_bindgen]
+pub fn run_program_with_limits(source: &str, max_steps: u32, stdout_limit: u32) -> JsValue {
+    let options = RunOptions {
+        max_steps: Some(max_steps as u64),
+        stdout_limit: Some(stdout_limit as usize),
+        ..RunOptions::default()
+    };
+
+    to_js(&run(source, &options))
+}
@@ -0,0 +1,41 @@
+// Multiple harts
+//
+// Hart 0 starts hart 1 (run the interpreter with `--harts 2`), both add 100
+// to a shared counter with atomic additions. The program exits with the
+// counter (200) after hart 1 is done.
+
+	li $r1, %worker
+	li $r2, 393216 // Stack of hart 1
+	syscalli 4 // $r0: index of hart 1
+	jzi $r0, %no_hart
+	li $r1, 100
+	jil %count
+wait:
+	lwi $r0, %done
+	jzi $r0, %wait
+	lwi $r1, %counter
+	syscalli 0
+no_hart:
+	li $r1, 0
+	syscalli 0
+
+worker:
+	li $r1, 100
+	jil %count
+	li $r0, 1
+	swi $r0, %done
+	syscalli 0 // Stops hart 1
+
+// Adds 1 to the counter $r1 times
+count:
+	li $r0, 1
+	li $r2, %counter
+	amoadd $r0, $r2
+	subi $r1, 1
+	jnzi $r1, %count
+	j $ra
+
+counter:
+	.i32 0
+done:
+	.i32 0
@@ -0,0 +1,173 @@
+// Time-sliced multitasking (run with --timer)
+//
+// Every task has a task control block (TCB): the context save area
+// (CONTEXT_SAVE_AREA_SIZE = 40 bytes: $r0-$r7, $sp, $ra), the address the
+// task continues at and the address of the next task's TCB. The timer
+// interrupts the running task after SLICE instructions (counted from the
+// switch), the scheduler then continues the next task in the ring.
+
+.equ TIMER_COUNTER, 0xF0000
+.equ TIMER_STATUS, 0xF0004
+.equ SLICE, 100
+
+	li $r0, %vectors
+	mts $r0, 0
+	li $r0, 3 // Interrupts enabled, inside of a trap until the first reti
+	mts $r0, 3
+	ji %restore // Start the first task
+
+// --- Scheduler ---
+
+// Timer interrupt: saves the context of the current task and continues the
+// next one
+preempt:
+	swi $r7, %scratch // Free $r7 for the address of the context
+	lwi $r7, %current
+	sw $r0, $r7
+	addi $r7, 4
+	sw $r1, $r7
+	addi $r7, 4
+	sw $r2, $r7
+	addi $r7, 4
+	sw $r3, $r7
+	addi $r7, 4
+	sw $r4, $r7
+	addi $r7, 4
+	sw $r5, $r7
+	addi $r7, 4
+	sw $r6, $r7
+	addi $r7, 4
+	lwi $r0, %scratch
+	sw $r0, $r7 // Original $r7
+	addi $r7, 4
+	sw $sp, $r7
+	addi $r7, 4
+	sw $ra, $r7
+	addi $r7, 4
+	mfs $r0, 1 // The task continues where it was interrupted
+	sw $r0, $r7
+	addi $r7, 4
+	lw $r7, $r7 // Next task
+	swi $r7, %current
+	li $r0, 1
+	swi $r0, %TIMER_STATUS // Acknowledge the interrupt
+
+// Starts the time slice, restores the context of the current task and
+// continues it
+restore:
+	li $r0, %SLICE
+	swi $r0, %TIMER_COUNTER // One-shot
+	lwi $r7, %current
+	addi $r7, 40
+	lw $r0, $r7
+	mts $r0, 1
+	subi $r7, 4
+	lw $ra, $r7
+	subi $r7, 4
+	lw $sp, $r7
+	subi $r7, 8
+	lw $r6, $r7
+	subi $r7, 4
+	lw $r5, $r7
+	subi $r7, 4
+	lw $r4, $r7
+	subi $r7, 4
+	lw $r3, $r7
+	subi $r7, 4
+	lw $r2, $r7
+	subi $r7, 4
+	lw $r1, $r7
+	subi $r7, 4
+	lw $r0, $r7
+	addi $r7, 28
+	lw $r7, $r7
+	reti
+
+// Ends the current task, the program exits after the last one
+exit_task:
+	di // Not preempted while updating the counter
+	lwi $r0, %alive
+	subi $r0, 1
+	swi $r0, %alive
+	ei
+	jzi $r0, %all_done
+idle:
+	ji %idle // Until the timer preempts the task
+all_done:
+	li $r1, 0
+	syscalli 0
+
+// --- Tasks ---
+
+task_a:
+	li $r6, 3 // Iterations (saved across preemptions)
+task_a_loop:
+	li $r1, %msg_a
+	li $r2, 2
+	syscalli 1
+	li $r5, 50
+task_a_work:
+	subi $r5, 1
+	jnzi $r5, %task_a_work
+	subi $r6, 1
+	jnzi $r6, %task_a_loop
+	ji %exit_task
+
+task_b:
+	li $r6, 3
+task_b_loop:
+	li $r1, %msg_b
+	li $r2, 2
+	syscalli 1
+	li $r5, 50
+task_b_work:
+	subi $r5, 1
+	jnzi $r5, %task_b_work
+	subi $r6, 1
+	jnzi $r6, %task_b_loop
+	ji %exit_task
+
+// --- Scheduler state ---
+
+vectors:
+	.i32 %preempt // Interrupts
+
+current:
+	.i32 %tcb_a
+alive:
+	.i32 2
+scratch:
+	.i32 0
+
+tcb_a:
+	.i32 0 // $r0
+	.i32 0
+	.i32 0
+	.i32 0
+	.i32 0
+	.i32 0
+	.i32 0
+	.i32 0 // $r7
+	.i32 458752 // $sp
+	.i32 0 // $ra
+	.i32 %task_a // Continues at
+	.i32 %tcb_b // Next task
+
+tcb_b:
+	.i32 0 // $r0
+	.i32 0
+	.i32 0
+	.i32 0
+	.i32 0
+	.i32 0
+	.i32 0
+	.i32 0 // $r7
+	.i32 393216 // $sp
+	.i32 0 // $ra
+	.i32 %task_b // Continues at
+	.i32 %tcb_a // Next task
+
+msg_a:
+	.str "A\n"
+msg_b:
+	.str "B\n"
@@ -0,0 +1,247 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Control channel for monitoring a running program. Clients send one JSON
+//! request per line (like `{"command": "registers"}`) and receive one JSON
+//! response per line.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::mpsc;
+use std::thread;
+
+use num_traits::FromPrimitive;
+use libcustomvmcpu::common::{Register, LAST_REGISTER};
+use libcustomvmcpu::runtime::{Interpreter, VirtualMachine};
+
+/// Instructions executed between checking for requests
+const SLICE_INSTRUCTIONS: u64 = 1000;
+
+/// Maximum number of bytes a memory request may read
+const MAX_MEMORY_LENGTH: u32 = 64 * 1024;
+
+/// Request of a client together with the channel for the response
+pub struct ControlRequest {
+    pub request: json::JsonValue,
+    pub response: mpsc::Sender<json::JsonValue>,
+}
+
+fn error_response(message: &str) -> json::JsonValue {
+    object!{
+        "ok" => false,
+        "error" => message,
+    }
+}
+
+/// Answers a request. Commands are `status`, `pause`, `resume`, `registers`
+/// and `memory` (with `address` and `length`).
+pub fn handle_request<I: Interpreter>(request: &json::JsonValue, vm: &mut VirtualMachine<I>, paused: &mut bool) -> json::JsonValue {
+    match request["command"].as_str() {
+        Some("status") => object!{
+            "ok" => true,
+            "state" => if *paused { "paused" } else { "running" },
+            "ip" => vm.read_register_value(Register::IP),
+            "instructions" => vm.stats().instructions,
+        },
+        Some("pause") => {
+            *paused = true;
+            object!{ "ok" => true }
+        },
+        Some("resume") => {
+            *paused = false;
+            object!{ "ok" => true }
+        },
+        Some("registers") => {
+            let mut registers = json::JsonValue::new_object();
+            for reg in (0..=LAST_REGISTER as u8).filter_map(Register::from_u8) {
+                registers[format!("{:?}", reg)] = vm.read_register_value(reg).into();
+            }
+
+            object!{
+                "ok" => true,
+                "registers" => registers,
+            }
+        },
+        Some("memory") => {
+            let address = request["address"].as_u32();
+            let length = request["length"].as_u32().filter(|length| *length <= MAX_MEMORY_LENGTH);
+            match (address, length) {
+                (Some(address), Some(length)) => match vm.get_interpreter().read_chunk(address, length) {
+                    Some(memory) => object!{
+                        "ok" => true,
+                        "memory" => memory,
+                    },
+                    None => error_response("invalid memory range"),
+                },
+                _ => error_response("expected address and length"),
+            }
+        },
+        _ => error_response("unknown command"),
+    }
+}
+
+/// Forwards the requests of a client to `requests` and writes the responses
+fn serve<R: BufRead, W: Write>(reader: R, mut writer: W, requests: &mpsc::Sender<ControlRequest>) -> io::Result<()> {
+    for line in reader.lines() {
+        let line = line?;
+        let response = match json::parse(&line) {
+            Ok(request) => {
+                let (sender, receiver) = mpsc::channel();
+                if requests.send(ControlRequest { request, response: sender }).is_err() {
+                    break; // Program stopped
+                }
+
+                match receiver.recv() {
+                    Ok(response) => response,
+                    Err(_) => break,
+                }
+            },
+            Err(_) => error_response("invalid JSON"),
+        };
+
+        writeln!(writer, "{}", response.dump())?;
+    }
+
+    Ok(())
+}
+
+/// Accepts clients at `address` (`HOST:PORT` for TCP or `unix:PATH` for a
+/// Unix socket) in the background
+pub fn listen(address: &str) -> io::Result<mpsc::Receiver<ControlRequest>> {
+    let (sender, receiver) = mpsc::channel();
+
+    if let Some(path) = address.strip_prefix("unix:") {
+        #[cfg(unix)]
+        {
+            let listener = std::os::unix::net::UnixListener::bind(path)?;
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let sender = sender.clone();
+                    thread::spawn(move || {
+                        let reader = BufReader::new(stream.try_clone()?);
+                        serve(reader, stream, &sender)
+                    });
+                }
+            });
+        }
+
+        #[cfg(not(unix))]
+        return Err(io::Error::new(io::ErrorKind::Unsupported, format!("Unix sockets aren't supported: {}", path)));
+    }
+    else {
+        let listener = TcpListener::bind(address)?;
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let sender = sender.clone();
+                thread::spawn(move || {
+                    let reader = BufReader::new(stream.try_clone()?);
+                    serve(reader, stream, &sender)
+                });
+            }
+        });
+    }
+
+    Ok(receiver)
+}
+
+/// Executes the program with its entry point and answers the requests
+/// between slices of instructions. While paused, only requests are answered.
+pub fn run_controlled<I: Interpreter>(vm: &mut VirtualMachine<I>, requests: &mpsc::Receiver<ControlRequest>) -> u32 {
+    let mut paused = false;
+    vm.start(vm.get_entry());
+    loop {
+        loop {
+            let request = if paused {
+                requests.recv().ok()
+            }
+            else {
+                requests.try_recv().ok()
+            };
+
+            match request {
+                Some(request) => {
+                    let response = handle_request(&request.request, vm, &mut paused);
+                    let _ = request.response.send(response);
+                },
+                None => break,
+            }
+        }
+
+        if let Some(result) = vm.run_for(SLICE_INSTRUCTIONS) {
+            break result;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::mpsc;
+    use std::thread;
+    use libcustomvmcpu::common::{OpCode, Register};
+    use libcustomvmcpu::runtime::{BinaryInterpreter, BinaryVirtualMachine, utils};
+    use super::{handle_request, serve};
+
+    #[test]
+    fn requests() {
+        let program = [utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 42)];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut stdout = Vec::new();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.start(0);
+        assert!(vm.step());
+
+        let mut paused = false;
+        let response = handle_request(&object!{ "command" => "registers" }, &mut vm, &mut paused);
+        assert_eq!(Some(42), response["registers"]["R0"].as_u32());
+        assert_eq!(Some(4), response["registers"]["IP"].as_u32());
+
+        let response = handle_request(&object!{ "command" => "pause" }, &mut vm, &mut paused);
+        assert_eq!(Some(true), response["ok"].as_bool());
+        assert!(paused);
+        let response = handle_request(&object!{ "command" => "status" }, &mut vm, &mut paused);
+        assert_eq!(Some("paused"), response["state"].as_str());
+        handle_request(&object!{ "command" => "resume" }, &mut vm, &mut paused);
+        assert!(!paused);
+
+        let response = handle_request(&object!{ "command" => "memory", "address" => 0, "length" => 4 }, &mut vm, &mut paused);
+        assert_eq!(json::JsonValue::from(program[0].to_le_bytes().to_vec()), response["memory"]);
+
+        let response = handle_request(&object!{ "command" => "memory", "address" => 0 }, &mut vm, &mut paused);
+        assert_eq!(Some(false), response["ok"].as_bool());
+        let response = handle_request(&object!{ "command" => "unknown" }, &mut vm, &mut paused);
+        assert_eq!(Some(false), response["ok"].as_bool());
+    }
+
+    #[test]
+    fn serve_lines() {
+        let (sender, receiver) = mpsc::channel::<super::ControlRequest>();
+        let answer = thread::spawn(move || {
+            let request = receiver.recv().expect("Expected request");
+            assert_eq!(Some("status"), request.request["command"].as_str());
+            request.response.send(object!{ "ok" => true }).expect("Expected");
+        });
+
+        let mut output = Vec::new();
+        serve(Cursor::new("{\"command\": \"status\"}\nnot json\n"), &mut output, &sender).expect("Expected");
+        answer.join().expect("Expected");
+
+        assert_eq!("{\"ok\":true}\n{\"ok\":false,\"error\":\"invalid JSON\"}\n", String::from_utf8(output).expect("Expected"));
+    }
+}
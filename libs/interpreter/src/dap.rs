@@ -0,0 +1,633 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Debug Adapter Protocol server (used by editors like VS Code): the
+//! messages are JSON with a `Content-Length` header on the standard input and
+//! output. The program is compiled with a line table, which maps breakpoints
+//! and stopped instructions to lines of the source.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+
+use num_traits::FromPrimitive;
+use libcustomvmcpu::common::{Register, LAST_REGISTER};
+use libcustomvmcpu::builder::VmBuilder;
+use libcustomvmcpu::compiler::{self, CompileOutput};
+use libcustomvmcpu::debuginfo::LineTable;
+use libcustomvmcpu::parser;
+use libcustomvmcpu::runtime::BinaryInterpreter;
+use libcustomvmcpu::symbols::MemoryMap;
+
+/// Instructions executed between checking for requests
+const SLICE_INSTRUCTIONS: u64 = 1000;
+
+/// The only thread: hart 0
+const THREAD_ID: u32 = 1;
+
+/// `variablesReference` of the registers
+const REGISTERS_REFERENCE: u32 = 1;
+
+/// Reads a message (`Content-Length: N`, an empty line and N bytes of JSON).
+/// Returns None at the end of the input.
+pub fn read_message<R: BufRead>(reader: &mut R) -> io::Result<Option<json::JsonValue>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut content = vec![0; length];
+    reader.read_exact(&mut content)?;
+    let content = String::from_utf8(content).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    json::parse(&content).map(Some).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// Writes the messages to the client and numbers them
+struct Transport<W: Write> {
+    writer: W,
+    seq: u64,
+}
+
+impl<W: Write> Transport<W> {
+    fn send(&mut self, mut message: json::JsonValue) {
+        self.seq += 1;
+        message["seq"] = self.seq.into();
+        let content = message.dump();
+        // The client is gone, if this fails: the session ends with the input
+        let message = format!("Content-Length: {}\r\n\r\n{}", content.len(), content);
+        let _ = self.writer.write_all(message.as_bytes()).and_then(|_| self.writer.flush());
+    }
+
+    fn respond(&mut self, request: &json::JsonValue, body: json::JsonValue) {
+        self.send(object!{
+            "type" => "response",
+            "request_seq" => request["seq"].clone(),
+            "success" => true,
+            "command" => request["command"].clone(),
+            "body" => body,
+        });
+    }
+
+    fn respond_error(&mut self, request: &json::JsonValue, message: &str) {
+        self.send(object!{
+            "type" => "response",
+            "request_seq" => request["seq"].clone(),
+            "success" => false,
+            "command" => request["command"].clone(),
+            "message" => message,
+        });
+    }
+
+    fn event(&mut self, event: &str, body: json::JsonValue) {
+        self.send(object!{
+            "type" => "event",
+            "event" => event,
+            "body" => body,
+        });
+    }
+
+    fn stopped(&mut self, reason: &str) {
+        self.event("stopped", object!{
+            "reason" => reason,
+            "threadId" => THREAD_ID,
+            "allThreadsStopped" => true,
+        });
+    }
+}
+
+/// Output of the program: every write is sent as `output` event
+struct GuestOutput<W: Write> {
+    transport: Rc<RefCell<Transport<W>>>,
+}
+
+impl<W: Write> Write for GuestOutput<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.transport.borrow_mut().event("output", object!{
+            "category" => "stdout",
+            "output" => String::from_utf8_lossy(buf).to_string(),
+        });
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The launched program
+struct Program {
+    path: String,
+    output: CompileOutput,
+    line_table: LineTable,
+    memory_map: MemoryMap,
+}
+
+impl Program {
+    fn compile(path: &str) -> Result<Program, String> {
+        let source = fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path, err))?;
+        let mut parser = parser::parse_string(&source);
+        let output = compiler::compile_output(&mut parser, &compiler::CompileOptions::default());
+        match output {
+            Some(output) => Ok(Program {
+                path: path.to_string(),
+                line_table: LineTable::new(path, &source, &output),
+                memory_map: MemoryMap::from_symbols(&output.symbols, output.binary.len() as u32),
+                output,
+            }),
+            None => Err(parser.diagnostics().iter()
+                .map(|diagnostic| diagnostic.render(&source))
+                .collect::<Vec<String>>()
+                .join("\n")),
+        }
+    }
+
+    /// Address of the first instruction of the line
+    fn line_address(&self, line: u32) -> Option<u32> {
+        self.line_table.entries.iter()
+            .filter(|entry| entry.line == line)
+            .map(|entry| entry.address.start)
+            .min()
+    }
+
+    /// Whether `path` is the file of the program (the same file, if both
+    /// exist)
+    fn is_file(&self, path: &str) -> bool {
+        match (fs::canonicalize(path), fs::canonicalize(&self.path)) {
+            (Ok(path), Ok(own_path)) => path == own_path,
+            _ => path == self.path,
+        }
+    }
+
+    fn line(&self, address: u32) -> Option<u32> {
+        self.line_table.find(address).map(|entry| entry.line)
+    }
+
+    fn source(&self) -> json::JsonValue {
+        let name = std::path::Path::new(&self.path).file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| self.path.clone());
+        object!{
+            "name" => name,
+            "path" => self.path.clone(),
+        }
+    }
+}
+
+/// Answers `setBreakpoints`: breakpoints are set at the first instruction of
+/// their line, lines without instructions and breakpoints in other files
+/// than the program are unverified
+fn set_breakpoints<W: Write>(request: &json::JsonValue, program: &Program, breakpoints: &mut HashSet<u32>, transport: &mut Transport<W>) {
+    let in_program = program.is_file(request["arguments"]["source"]["path"].as_str().unwrap_or(""));
+    if in_program {
+        breakpoints.clear();
+    }
+
+    let mut result = json::JsonValue::new_array();
+    for breakpoint in request["arguments"]["breakpoints"].members() {
+        let line = breakpoint["line"].as_u32().unwrap_or(0);
+        let address = if in_program { program.line_address(line) } else { None };
+        if let Some(address) = address {
+            breakpoints.insert(address);
+        }
+        let _ = result.push(object!{
+            "verified" => address.is_some(),
+            "line" => line,
+        });
+    }
+
+    transport.respond(request, object!{ "breakpoints" => result });
+}
+
+/// How the program continues
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Stopped,
+    Continue,
+    /// Until another line is reached (`next` and `stepIn`)
+    Line(Option<u32>),
+    /// Until the address is reached (`stepOut`: the return address)
+    Until(u32),
+}
+
+/// Handles the requests before the program runs: `initialize`, `launch`,
+/// `setBreakpoints` and `configurationDone`. Returns None, if the client
+/// disconnected.
+fn configure<W: Write>(requests: &mpsc::Receiver<json::JsonValue>, transport: &mut Transport<W>, breakpoints: &mut HashSet<u32>) -> Option<(Program, bool)> {
+    let mut launched: Option<(Program, bool)> = None;
+    loop {
+        let request = requests.recv().ok()?;
+        match (request["command"].as_str(), &launched) {
+            (Some("initialize"), _) => {
+                transport.respond(&request, object!{
+                    "supportsConfigurationDoneRequest" => true,
+                    "supportsTerminateRequest" => true,
+                });
+            },
+            (Some("launch"), _) => {
+                let path = request["arguments"]["program"].as_str().unwrap_or("");
+                match Program::compile(path) {
+                    Ok(program) => {
+                        launched = Some((program, request["arguments"]["stopOnEntry"].as_bool().unwrap_or(false)));
+                        transport.respond(&request, json::JsonValue::Null);
+                        // The client sends the breakpoints now
+                        transport.event("initialized", json::JsonValue::Null);
+                    },
+                    Err(message) => transport.respond_error(&request, &message),
+                }
+            },
+            (Some("setBreakpoints"), Some((program, _))) => set_breakpoints(&request, program, breakpoints, transport),
+            (Some("configurationDone"), Some(_)) => {
+                transport.respond(&request, json::JsonValue::Null);
+                return launched;
+            },
+            (Some("threads"), _) => transport.respond(&request, object!{ "threads" => array![] }),
+            (Some("disconnect"), _) => {
+                transport.respond(&request, json::JsonValue::Null);
+                return None;
+            },
+            _ => transport.respond_error(&request, "not launched"),
+        }
+    }
+}
+
+/// Debugs a program: answers the requests and writes the responses and
+/// events to `writer` until the client disconnects
+pub fn run_session<W: Write>(requests: mpsc::Receiver<json::JsonValue>, writer: W) {
+    let transport = Rc::new(RefCell::new(Transport { writer, seq: 0 }));
+    let mut breakpoints = HashSet::new();
+    let (program, stop_on_entry) = match configure(&requests, &mut transport.borrow_mut(), &mut breakpoints) {
+        Some(launched) => launched,
+        None => return,
+    };
+
+    let interpreter = match BinaryInterpreter::new_with_initial(&program.output.binary) {
+        Some(interpreter) => interpreter,
+        None => {
+            transport.borrow_mut().event("output", object!{ "category" => "stderr", "output" => "The program doesn't fit into the memory\n" });
+            transport.borrow_mut().event("terminated", json::JsonValue::Null);
+            return;
+        },
+    };
+    let mut guest_output = GuestOutput { transport: transport.clone() };
+    let mut vm = VmBuilder::new(interpreter, &mut guest_output).entry(program.output.entry).build();
+    vm.start(program.output.entry);
+
+    let mut mode = Mode::Continue;
+    // Address execution resumed from, its breakpoint was already hit
+    let mut resumed_from: Option<u32> = None;
+    if stop_on_entry {
+        mode = Mode::Stopped;
+        transport.borrow_mut().stopped("entry");
+    }
+
+    let mut terminated = false;
+    loop {
+        let request = if mode == Mode::Stopped || terminated {
+            match requests.recv() {
+                Ok(request) => Some(request),
+                Err(_) => return,
+            }
+        }
+        else {
+            requests.try_recv().ok()
+        };
+
+        if let Some(request) = request {
+            let ip = vm.read_register_value(Register::IP);
+            let mut transport = transport.borrow_mut();
+            match request["command"].as_str() {
+                Some("threads") => transport.respond(&request, object!{
+                    "threads" => array![object!{ "id" => THREAD_ID, "name" => "hart 0" }],
+                }),
+                Some("stackTrace") => {
+                    let mut frame = object!{
+                        "id" => 0,
+                        "name" => program.memory_map.format_address(ip),
+                        "line" => 0,
+                        "column" => 0,
+                        "instructionPointerReference" => format!("0x{:X}", ip),
+                    };
+                    if let Some(entry) = program.line_table.find(ip) {
+                        frame["source"] = program.source();
+                        frame["line"] = entry.line.into();
+                        frame["column"] = entry.column.into();
+                    }
+
+                    transport.respond(&request, object!{
+                        "stackFrames" => array![frame],
+                        "totalFrames" => 1,
+                    });
+                },
+                Some("scopes") => transport.respond(&request, object!{
+                    "scopes" => array![object!{
+                        "name" => "Registers",
+                        "variablesReference" => REGISTERS_REFERENCE,
+                        "expensive" => false,
+                    }],
+                }),
+                Some("variables") => {
+                    let mut variables = json::JsonValue::new_array();
+                    if request["arguments"]["variablesReference"].as_u32() == Some(REGISTERS_REFERENCE) {
+                        for reg in (0..=LAST_REGISTER as u8).filter_map(Register::from_u8) {
+                            let value = vm.read_register_value(reg);
+                            let _ = variables.push(object!{
+                                "name" => reg.to_string(),
+                                "value" => format!("{} (0x{:08X})", value, value),
+                                "variablesReference" => 0,
+                            });
+                        }
+                    }
+
+                    transport.respond(&request, object!{ "variables" => variables });
+                },
+                Some("setBreakpoints") => set_breakpoints(&request, &program, &mut breakpoints, &mut transport),
+                Some("continue") if !terminated => {
+                    mode = Mode::Continue;
+                    resumed_from = Some(ip);
+                    transport.respond(&request, object!{ "allThreadsContinued" => true });
+                },
+                Some("next") | Some("stepIn") if !terminated => {
+                    mode = Mode::Line(program.line(ip));
+                    resumed_from = Some(ip);
+                    transport.respond(&request, json::JsonValue::Null);
+                },
+                Some("stepOut") if !terminated => {
+                    mode = Mode::Until(vm.read_register_value(Register::RA));
+                    resumed_from = Some(ip);
+                    transport.respond(&request, json::JsonValue::Null);
+                },
+                Some("pause") => {
+                    transport.respond(&request, json::JsonValue::Null);
+                    if mode != Mode::Stopped && !terminated {
+                        mode = Mode::Stopped;
+                        transport.stopped("pause");
+                    }
+                },
+                Some("disconnect") | Some("terminate") => {
+                    transport.respond(&request, json::JsonValue::Null);
+                    if !terminated {
+                        transport.event("terminated", json::JsonValue::Null);
+                    }
+                    return;
+                },
+                _ => transport.respond_error(&request, "unsupported request"),
+            }
+
+            continue;
+        }
+
+        // Executes a slice of the program
+        for _ in 0..SLICE_INSTRUCTIONS {
+            // Stops before the instruction at a breakpoint
+            let ip = vm.read_register_value(Register::IP);
+            if resumed_from.take() != Some(ip) && breakpoints.contains(&ip) {
+                mode = Mode::Stopped;
+                transport.borrow_mut().stopped("breakpoint");
+                break;
+            }
+
+            if !vm.step() {
+                let exit_code = match vm.result() {
+                    Ok(status) => status.code,
+                    Err(err) => {
+                        transport.borrow_mut().event("output", object!{
+                            "category" => "stderr",
+                            "output" => format!("Error: {:?} at {}\n", err.kind, program.memory_map.format_address(err.ip)),
+                        });
+                        libcustomvmcpu::common::ERROR_START_NUM + err.kind as u32
+                    },
+                };
+
+                let mut transport = transport.borrow_mut();
+                transport.event("exited", object!{ "exitCode" => exit_code });
+                transport.event("terminated", json::JsonValue::Null);
+                terminated = true;
+                break;
+            }
+
+            let ip = vm.read_register_value(Register::IP);
+            let reason = match mode {
+                Mode::Line(line) if program.line(ip).is_some() && program.line(ip) != line => Some("step"),
+                Mode::Until(address) if ip == address => Some("step"),
+                _ => None,
+            };
+
+            if let Some(reason) = reason {
+                mode = Mode::Stopped;
+                transport.borrow_mut().stopped(reason);
+                break;
+            }
+        }
+    }
+}
+
+/// Debugs a program with the client on the standard input and output
+pub fn run() {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut reader = stdin.lock();
+        while let Ok(Some(message)) = read_message(&mut reader) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    run_session(receiver, io::stdout());
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Write};
+    use std::sync::mpsc;
+    use std::thread;
+    use super::{read_message, run_session};
+
+    /// Sends every written message to the test
+    struct Messages(mpsc::Sender<json::JsonValue>);
+
+    impl Write for Messages {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let message = read_message(&mut Cursor::new(buf))?.expect("Expected message");
+            let _ = self.0.send(message);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Receives messages until the response to `command` or the event
+    fn expect(messages: &mpsc::Receiver<json::JsonValue>, name: &str) -> json::JsonValue {
+        loop {
+            let message = messages.recv().expect("Expected message");
+            if message["command"].as_str() == Some(name) || message["event"].as_str() == Some(name) {
+                return message;
+            }
+        }
+    }
+
+    #[test]
+    fn messages() {
+        let mut input = Cursor::new("Content-Length: 9\r\n\r\n{\"seq\":1}Content-Length: 2\r\n\r\n{}");
+        assert_eq!(Some(object!{ "seq" => 1 }), read_message(&mut input).expect("Expected"));
+        assert_eq!(Some(object!{}), read_message(&mut input).expect("Expected"));
+        assert_eq!(None, read_message(&mut input).expect("Expected"));
+        assert!(read_message(&mut Cursor::new("\r\n{}")).is_err());
+    }
+
+    #[test]
+    fn session() {
+        let path = std::env::temp_dir().join("customvmcpu_dap_session.asm");
+        std::fs::write(&path, "li $r0, 1\naddi $r0, 2\nli $r1, %msg\nli $r2, 2\nsyscalli 1\nli $r1, 7\nsyscalli 0\nmsg:\n.str \"Hi\"\n").expect("Expected");
+        let path = path.to_string_lossy().to_string();
+
+        let (requests, receiver) = mpsc::channel();
+        let (sender, messages) = mpsc::channel();
+        let session = thread::spawn(move || run_session(receiver, Messages(sender)));
+        let mut seq = 0;
+        let mut request = |command: &str, arguments: json::JsonValue| {
+            seq += 1;
+            requests.send(object!{ "seq" => seq, "type" => "request", "command" => command, "arguments" => arguments }).expect("Expected");
+        };
+
+        request("initialize", object!{});
+        assert_eq!(Some(true), expect(&messages, "initialize")["success"].as_bool());
+        request("launch", object!{ "program" => path.clone(), "stopOnEntry" => true });
+        assert_eq!(Some(true), expect(&messages, "launch")["success"].as_bool());
+        expect(&messages, "initialized");
+        request("setBreakpoints", object!{ "source" => object!{ "path" => path.clone() }, "breakpoints" => array![object!{ "line" => 2 }, object!{ "line" => 8 }] });
+        let response = expect(&messages, "setBreakpoints");
+        assert_eq!(Some(true), response["body"]["breakpoints"][0]["verified"].as_bool());
+        assert_eq!(Some(false), response["body"]["breakpoints"][1]["verified"].as_bool());
+        request("configurationDone", object!{});
+        expect(&messages, "configurationDone");
+        assert_eq!(Some("entry"), expect(&messages, "stopped")["body"]["reason"].as_str());
+
+        request("stackTrace", object!{ "threadId" => 1 });
+        let frame = expect(&messages, "stackTrace")["body"]["stackFrames"][0].clone();
+        assert_eq!(Some(1), frame["line"].as_u32());
+        assert_eq!(Some(path.as_str()), frame["source"]["path"].as_str());
+
+        request("continue", object!{ "threadId" => 1 });
+        assert_eq!(Some("breakpoint"), expect(&messages, "stopped")["body"]["reason"].as_str());
+        request("next", object!{ "threadId" => 1 });
+        assert_eq!(Some("step"), expect(&messages, "stopped")["body"]["reason"].as_str());
+        request("stackTrace", object!{ "threadId" => 1 });
+        assert_eq!(Some(3), expect(&messages, "stackTrace")["body"]["stackFrames"][0]["line"].as_u32());
+
+        request("variables", object!{ "variablesReference" => 1 });
+        let variables = expect(&messages, "variables")["body"]["variables"].clone();
+        assert_eq!(Some("$r0"), variables[0]["name"].as_str());
+        assert_eq!(Some("3 (0x00000003)"), variables[0]["value"].as_str());
+
+        request("continue", object!{ "threadId" => 1 });
+        assert_eq!(Some("Hi"), expect(&messages, "output")["body"]["output"].as_str());
+        assert_eq!(Some(7), expect(&messages, "exited")["body"]["exitCode"].as_u32());
+        expect(&messages, "terminated");
+        request("disconnect", object!{});
+        expect(&messages, "disconnect");
+        session.join().expect("Expected");
+    }
+
+    #[test]
+    fn breakpoints() {
+        let path = std::env::temp_dir().join("customvmcpu_dap_breakpoints.asm");
+        std::fs::write(&path, "li $r0, 3\nloop:\nsubi $r0, 1\njnzi $r0, %loop\nsyscalli 0\n").expect("Expected");
+        let path = path.to_string_lossy().to_string();
+
+        let (requests, receiver) = mpsc::channel();
+        let (sender, messages) = mpsc::channel();
+        let session = thread::spawn(move || run_session(receiver, Messages(sender)));
+        let mut seq = 0;
+        let mut request = |command: &str, arguments: json::JsonValue| {
+            seq += 1;
+            requests.send(object!{ "seq" => seq, "type" => "request", "command" => command, "arguments" => arguments }).expect("Expected");
+        };
+        let line = |request: &mut dyn FnMut(&str, json::JsonValue)| {
+            request("stackTrace", object!{ "threadId" => 1 });
+            expect(&messages, "stackTrace")["body"]["stackFrames"][0]["line"].as_u32()
+        };
+
+        request("launch", object!{ "program" => path.clone() });
+        expect(&messages, "launch");
+        request("setBreakpoints", object!{ "source" => object!{ "path" => path.clone() }, "breakpoints" => array![object!{ "line" => 1 }, object!{ "line" => 3 }] });
+        expect(&messages, "setBreakpoints");
+        // Another file doesn't change the breakpoints of the program
+        request("setBreakpoints", object!{ "source" => object!{ "path" => "other.asm" }, "breakpoints" => array![object!{ "line" => 1 }] });
+        assert_eq!(Some(false), expect(&messages, "setBreakpoints")["body"]["breakpoints"][0]["verified"].as_bool());
+        request("configurationDone", object!{});
+
+        // Also the breakpoint at the entry point is hit
+        assert_eq!(Some("breakpoint"), expect(&messages, "stopped")["body"]["reason"].as_str());
+        assert_eq!(Some(1), line(&mut request));
+
+        // Every iteration stops at the breakpoint in the loop
+        for _ in 0..3 {
+            request("continue", object!{ "threadId" => 1 });
+            assert_eq!(Some("breakpoint"), expect(&messages, "stopped")["body"]["reason"].as_str());
+            assert_eq!(Some(3), line(&mut request));
+        }
+
+        request("setBreakpoints", object!{ "source" => object!{ "path" => path.clone() }, "breakpoints" => array![] });
+        expect(&messages, "setBreakpoints");
+        request("continue", object!{ "threadId" => 1 });
+        expect(&messages, "exited");
+        request("disconnect", object!{});
+        expect(&messages, "disconnect");
+        session.join().expect("Expected");
+    }
+
+    #[test]
+    fn launch_errors() {
+        let path = std::env::temp_dir().join("customvmcpu_dap_errors.asm");
+        std::fs::write(&path, "li $r9, 1\n").expect("Expected");
+
+        let (requests, receiver) = mpsc::channel();
+        let (sender, messages) = mpsc::channel();
+        requests.send(object!{ "seq" => 1, "command" => "launch", "arguments" => object!{ "program" => path.to_string_lossy().to_string() } }).expect("Expected");
+        requests.send(object!{ "seq" => 2, "command" => "configurationDone" }).expect("Expected");
+        drop(requests);
+        run_session(receiver, Messages(sender));
+
+        let response = expect(&messages, "launch");
+        assert_eq!(Some(false), response["success"].as_bool());
+        assert_eq!(Some("error[E0003] at line 1: unknown register"), response["message"].as_str());
+        assert_eq!(Some(false), expect(&messages, "configurationDone")["success"].as_bool());
+    }
+}
@@ -0,0 +1,455 @@
+//! Command line of the interpreter (the `interpreter` binary)
+
+extern crate libcustomvmcpu;
+
+#[macro_use]
+extern crate json;
+
+mod control;
+pub mod dap;
+
+use std::str;
+use std::convert::TryInto;
+use std::{fs, process::exit};
+use std::io::{self, Read};
+use clap::{CommandFactory, Parser, ValueEnum};
+use clap::error::ErrorKind;
+
+use libcustomvmcpu::runtime::Interpreter;
+use libcustomvmcpu::{runtime, parser, compiler, common, symbols, timer, disassembler, builder, lint, diagnostic, debuginfo, profile};
+
+/// Compiles and runs a program for the custom virtual CPU
+#[derive(Parser)]
+#[command(name = "interpreter", version)]
+pub struct Cli {
+    /// Program to run (`-` is the standard input)
+    #[arg(required_unless_present = "dap")]
+    file: Option<String>,
+    /// Prints the value of the register after the run (like `r0` or `$sp`)
+    #[arg(long, value_name = "REGISTER", value_parser = parse_register)]
+    print_register: Vec<common::Register>,
+    /// Format of the registers printed by `--print-register`
+    #[arg(long, value_enum, default_value = "plain")]
+    format: RegisterFormat,
+    /// Prints all registers after the run
+    #[arg(long)]
+    register_table: bool,
+    /// Prints the log of the compiler
+    #[arg(long)]
+    verbose: bool,
+    /// Prints every executed instruction with the changed registers
+    #[arg(long)]
+    trace: bool,
+    /// Executes the instruction after a jump before jumping
+    #[arg(long)]
+    delay_slot: bool,
+    /// Makes the loaded program read-only
+    #[arg(long)]
+    protect_code: bool,
+    /// Makes the loaded program read-only and only it executable
+    #[arg(long)]
+    write_xor_execute: bool,
+    /// What happens on invalid opcodes
+    #[arg(long, value_enum, value_name = "POLICY", default_value = "trap")]
+    invalid_opcode: InvalidOpcode,
+    /// Maps the timer device
+    #[arg(long)]
+    timer: bool,
+    /// Keeps decoded instructions
+    #[arg(long)]
+    decode_cache: bool,
+    /// Prints how often every opcode was executed
+    #[arg(long)]
+    profile: bool,
+    /// Prints the N most executed instruction addresses
+    #[arg(long, value_name = "N")]
+    hot: Option<usize>,
+    /// Prints the words of the program, which weren't executed
+    #[arg(long)]
+    coverage: bool,
+    /// Fails, if there are warnings
+    #[arg(long)]
+    deny_warnings: bool,
+    /// Checks the program for suspicious code
+    #[arg(long)]
+    lint: bool,
+    /// Colors diagnostics (auto, always or never)
+    #[arg(long, value_name = "WHEN", default_value = "auto")]
+    color: diagnostic::ColorChoice,
+    /// Number of harts
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    harts: usize,
+    /// Accepts monitoring clients (`HOST:PORT` or `unix:PATH`)
+    #[arg(long, value_name = "ADDRESS")]
+    control: Option<String>,
+    /// Input of the program (instead of the standard input)
+    #[arg(long, value_name = "FILE")]
+    stdin_file: Option<String>,
+    /// Size of the memory (like `64K` or `16M`)
+    #[arg(long, value_name = "SIZE", default_value = "4M", value_parser = parse_memory_size)]
+    memory_size: u32,
+    /// Serves the Debug Adapter Protocol on the standard input and output
+    /// (the client selects the program)
+    #[arg(long, conflicts_with = "control")]
+    dap: bool,
+    /// Aborts the program after N executed instructions
+    #[arg(long, value_name = "N", conflicts_with = "control")]
+    max_steps: Option<u64>,
+    /// Prints the memory at START after the run (decimal or hexadecimal with 0x)
+    #[arg(long, num_args = 2, value_names = ["START", "LENGTH"], value_parser = parse_number)]
+    dump: Vec<u32>,
+    /// Names a memory region (used by traces and errors)
+    #[arg(long, num_args = 3, value_names = ["NAME", "START", "SIZE"])]
+    region: Vec<String>,
+}
+
+/// Exit code, if the program exceeds `--max-steps` (like `timeout`)
+const STEP_LIMIT_EXIT_CODE: i32 = 124;
+
+/// `--invalid-opcode` (see `runtime::InvalidOpcodePolicy`)
+#[derive(Clone, Copy, ValueEnum)]
+enum InvalidOpcode {
+    /// Invokes the trap handler of the error (without one the hart stops)
+    Trap,
+    /// Stops the hart, even if a trap handler is installed
+    Stop,
+    /// Executes them as NOP
+    Skip,
+}
+
+/// `--format` of the printed registers
+#[derive(Debug, PartialEq, Clone, Copy, ValueEnum)]
+enum RegisterFormat {
+    /// `$r0: 5`, one register per line
+    Plain,
+    /// `register,value` header, then one register per line
+    Csv,
+    /// One object with the registers as keys
+    Json,
+}
+
+/// Formats the registers and their values (in the order of `--print-register`)
+fn format_registers(registers: &[(common::Register, u32)], format: RegisterFormat) -> String {
+    match format {
+        RegisterFormat::Plain => registers.iter().map(|(reg, value)| format!("{}: {}\n", reg, value)).collect(),
+        RegisterFormat::Csv => {
+            let rows: String = registers.iter().map(|(reg, value)| format!("{},{}\n", reg, value)).collect();
+            format!("register,value\n{}", rows)
+        },
+        RegisterFormat::Json => {
+            let mut result = json::JsonValue::new_object();
+            for (reg, value) in registers {
+                result[reg.to_string()] = (*value).into();
+            }
+
+            format!("{}\n", result.dump())
+        },
+    }
+}
+
+/// Parses a decimal or hexadecimal (0x prefix) number
+fn parse_number(value: &str) -> Result<u32, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse::<u32>(),
+    }.map_err(|err| err.to_string())
+}
+
+/// Parses a memory size (see `runtime::parse_memory_size`)
+fn parse_memory_size(value: &str) -> Result<u32, String> {
+    match runtime::parse_memory_size(value) {
+        Some(size) if size > 0 && size.is_multiple_of(4) => Ok(size),
+        Some(_) => Err("must be a positive multiple of 4".to_string()),
+        None => Err("expected a size like 4096, 64K or 16M".to_string()),
+    }
+}
+
+/// Parses a register name (with or without `$`, in any case)
+fn parse_register(name: &str) -> Result<common::Register, String> {
+    parser::get_register_by_name(&name.trim_start_matches('$').to_lowercase())
+        .ok_or_else(|| format!("unknown register `{}`", name))
+}
+
+/// Compiles and runs the program (or serves the Debug Adapter Protocol)
+pub fn run(cli: Cli) {
+    if cli.dap {
+        dap::run();
+        return;
+    }
+
+    if cli.verbose {
+        env_logger::Builder::new().filter_level(log::LevelFilter::Debug).parse_default_env().init();
+    }
+
+    let registers_to_print = cli.print_register;
+    let register_format = cli.format;
+    let pretty_print_registers = cli.register_table;
+    let trace = cli.trace;
+    let delay_slot = cli.delay_slot;
+    let protect_code = cli.protect_code || cli.write_xor_execute;
+    let write_xor_execute = cli.write_xor_execute;
+    let invalid_opcode_policy = match cli.invalid_opcode {
+        InvalidOpcode::Trap => runtime::InvalidOpcodePolicy::Trap,
+        InvalidOpcode::Stop => runtime::InvalidOpcodePolicy::Stop,
+        InvalidOpcode::Skip => runtime::InvalidOpcodePolicy::Skip,
+    };
+    let control_address = cli.control;
+    let max_steps = cli.max_steps;
+    let map_timer = cli.timer;
+    let harts = cli.harts;
+    let decode_cache = cli.decode_cache;
+    let profile = cli.profile;
+    // The profile includes the hottest addresses
+    let hot_addresses = cli.hot.or(if profile { Some(profile::DEFAULT_HOT_ADDRESSES) } else { None });
+    let coverage = cli.coverage;
+    let deny_warnings = cli.deny_warnings;
+    let lint_program = cli.lint;
+    let dumps: Vec<(u32, u32)> = cli.dump.chunks(2).map(|dump| (dump[0], dump[1])).collect();
+    let regions: Vec<(String, u32, u32)> = cli.region.chunks(3).map(|region| {
+        match (parse_number(&region[1]), parse_number(&region[2])) {
+            (Ok(start), Ok(size)) => (region[0].clone(), start, size),
+            (Err(err), _) | (_, Err(err)) => Cli::command().error(ErrorKind::ValueValidation, format!("invalid region {}: {}", region[0], err)).exit(),
+        }
+    }).collect();
+
+    let file = cli.file.as_deref().expect("Made sure by clap");
+    let mut guest_input: Box<dyn Read> = match &cli.stdin_file {
+        Some(path) => match fs::File::open(path) {
+            Ok(input) => Box::new(input),
+            Err(_) => {
+                eprintln!("Error: Could not read file \"{}\"", path);
+                exit(1);
+            }
+        },
+        None => Box::new(io::stdin()),
+    };
+    let input: String = if file != "-" {
+        if let Ok(data) = fs::read(file) {
+            let result = str::from_utf8(&data[0..]);
+            if let Ok(result) = result {
+                result.to_string()
+            }
+            else {
+                eprintln!("Error: Could not read from standard input");
+                exit(1);
+            }
+        }
+        else {
+            eprintln!("Error: Could not read file \"{}\"", file);
+            exit(1);
+        }
+    } else {
+        let mut result: Vec<u8> = Vec::new();
+        if let Err(_) = io::stdin().lock().read_to_end(&mut result) {
+            eprintln!("Error: Could not read from standard input");
+            exit(1);
+        }
+        let result = str::from_utf8(&result[0..]);
+        if let Ok(result) = result {
+            result.to_string()
+        }
+        else {
+            eprintln!("Error: Could not read from standard input");
+            exit(1);
+        }
+    };
+
+    let color = cli.color.use_color();
+    let mut parser = parser::parse_string(&input);
+    let output = compiler::compile_output(&mut parser, &compiler::CompileOptions::default());
+    for diagnostic in parser.diagnostics() {
+        eprintln!("{}", diagnostic.render_pretty(&input, color));
+    }
+
+    let lint_options = lint::LintOptions {
+        branch_mode: if delay_slot { runtime::BranchMode::DelaySlot } else { runtime::BranchMode::Immediate },
+    };
+    let lints = if lint_program { lint::lint(&parser.program, &lint_options) } else { Vec::new() };
+    for lint in &lints {
+        eprintln!("{}", diagnostic::Diagnostic::from(lint).render_pretty(&input, color));
+    }
+
+    if deny_warnings && (!parser.warnings.is_empty() || !lints.is_empty()) {
+        eprintln!("Error: Warnings are denied");
+        exit(1);
+    }
+
+    if let Some(output) = output {
+        let file_name = if file == "-" { "<stdin>" } else { file };
+        let line_table = debuginfo::LineTable::new(file_name, &input, &output);
+        let program = output.binary;
+        let mut memory_map = symbols::MemoryMap::from_symbols(&compiler::get_symbols(&parser), program.len() as u32);
+        for (name, start, size) in &regions {
+            memory_map.add_region(name, *start, *size);
+        }
+
+        let interpreter = runtime::BinaryInterpreter::with_memory_size(cli.memory_size).and_then(|mut interpreter| {
+            if interpreter.load_program(&program) { Some(interpreter) } else { None }
+        });
+        if let Some(mut interpreter) = interpreter {
+            if map_timer {
+                interpreter.map_device(timer::TIMER_DEFAULT_ADDRESS, timer::TIMER_SIZE, Box::new(timer::Timer::new()));
+                memory_map.add_region("timer", timer::TIMER_DEFAULT_ADDRESS, timer::TIMER_SIZE);
+            }
+
+            let mut stdout = std::io::stdout();
+            let mut stderr = std::io::stderr();
+            let mut trace_sink = runtime::WriteTraceSink::with_memory_map(std::io::stderr(), memory_map.clone()).line_table(line_table.clone());
+            let mut builder = builder::VmBuilder::new(interpreter, &mut stdout)
+                .stdin(&mut guest_input)
+                .stderr(&mut stderr)
+                .harts(harts)
+                .decode_cache(decode_cache)
+                .write_xor_execute(write_xor_execute)
+                .invalid_opcode_policy(invalid_opcode_policy)
+                .opcode_profiling(profile)
+                .address_profiling(hot_addresses.is_some())
+                .coverage(coverage)
+                .entry(output.entry);
+            if trace {
+                builder = builder.tracer(&mut trace_sink);
+            }
+            if delay_slot {
+                builder = builder.branch_mode(runtime::BranchMode::DelaySlot);
+            }
+            if protect_code {
+                builder = builder.write_protected(0..program.len() as u32);
+            }
+            let mut vm = builder.build();
+
+            let result = if let Some(control_address) = &control_address {
+                match control::listen(control_address) {
+                    Ok(requests) => {
+                        control::run_controlled(&mut vm, &requests);
+                        Some(vm.result())
+                    },
+                    Err(err) => {
+                        eprintln!("Error: Could not listen on \"{}\": {}", control_address, err);
+                        exit(1);
+                    }
+                }
+            }
+            else if let Some(max_steps) = max_steps {
+                vm.start(vm.get_entry());
+                vm.run_for(max_steps).map(|_| vm.result())
+            }
+            else {
+                Some(vm.run(vm.get_entry()))
+            };
+            let exit_code = match result {
+                None => {
+                    eprintln!("Error: Exceeded the step limit at {}", memory_map.format_address(vm.read_register_value(common::Register::IP)));
+                    STEP_LIMIT_EXIT_CODE as u32
+                },
+                Some(Ok(status)) => status.code,
+                Some(Err(err)) => {
+                    eprintln!("Error: {:?} at {}", err.kind, memory_map.format_address(err.ip));
+                    if let Some(location) = line_table.location(err.ip) {
+                        eprintln!("Location: {}", location);
+                    }
+                    if let Some(address) = err.address {
+                        eprintln!("Faulting address: {}", memory_map.format_address(address));
+                    }
+                    if let Some(word) = vm.last_fault().and_then(|fault| fault.instruction) {
+                        let text = disassembler::disassemble_instruction(word).unwrap_or_else(|| "?".to_string());
+                        eprintln!("Instruction: 0x{:08X} ({})", word, text);
+                    }
+
+                    common::ERROR_START_NUM + err.kind as u32
+                }
+            } as i32;
+
+            if !registers_to_print.is_empty() {
+                let registers: Vec<(common::Register, u32)> = registers_to_print.iter().map(|reg| (*reg, vm.read_register_value(*reg))).collect();
+                print!("{}", format_registers(&registers, register_format));
+            }
+
+            if pretty_print_registers {
+                println!("R0: {}\nR1: {}\nR2: {}\nR3: {}\nR4: {}\nR5: {}\nR6: {}\nR7: {}\nIP: {}\nSP: {}\nRA: {}\nERR: {}\n",
+                    vm.read_register_value(common::Register::R0),
+                    vm.read_register_value(common::Register::R1),
+                    vm.read_register_value(common::Register::R2),
+                    vm.read_register_value(common::Register::R3),
+                    vm.read_register_value(common::Register::R4),
+                    vm.read_register_value(common::Register::R5),
+                    vm.read_register_value(common::Register::R6),
+                    vm.read_register_value(common::Register::R7),
+                    vm.read_register_value(common::Register::IP),
+                    vm.read_register_value(common::Register::SP),
+                    vm.read_register_value(common::Register::RA),
+                    vm.read_register_value(common::Register::ERR),
+                );
+            }
+
+            if let Some(profile) = vm.opcode_profile() {
+                eprint!("{}", profile::format_opcode_profile(&profile));
+            }
+
+            if let Some(addresses) = hot_addresses.and_then(|n| vm.hot_addresses(n)) {
+                eprint!("{}", profile::format_hot_addresses(&addresses, &memory_map));
+            }
+
+            if let Some(executed) = vm.coverage() {
+                // The program contains its data, too
+                let words: Vec<u32> = (0..program.len() as u32).step_by(4).collect();
+                let uncovered: Vec<u32> = words.iter().copied().filter(|address| !executed.contains(address)).collect();
+                eprintln!("Coverage: {} of {} words executed", words.len() - uncovered.len(), words.len());
+                for address in uncovered {
+                    let word = program.get(address as usize..address as usize + 4)
+                        .and_then(|bytes| bytes.try_into().ok())
+                        .map(u32::from_le_bytes);
+                    let text = word.and_then(disassembler::disassemble_instruction).unwrap_or_else(|| "?".to_string());
+                    eprintln!("{} {}", memory_map.format_address(address), text);
+                }
+            }
+
+            for (start, length) in &dumps {
+                match vm.hexdump(*start..start.saturating_add(*length)) {
+                    Some(dump) => print!("{}", dump),
+                    None => eprintln!("Error: Can't dump 0x{:X} bytes at 0x{:X}", length, start),
+                }
+            }
+
+            exit(exit_code);
+        }
+        else {
+            eprintln!("Error: The program ({} bytes) doesn't fit into the memory ({} bytes)", program.len(), cli.memory_size);
+            exit(common::ERROR_START_NUM as i32 + common::Error::Memory as i32);
+        }
+    }
+    else {
+        exit(32000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+    use libcustomvmcpu::common::Register;
+    use super::{Cli, RegisterFormat, format_registers};
+
+    #[test]
+    fn cli() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from(["interpreter", "--print-register", "r0", "--print-register", "$SP", "program.asm"]).unwrap();
+        assert_eq!(vec![Register::R0, Register::SP], cli.print_register);
+        assert_eq!(RegisterFormat::Plain, cli.format);
+
+        let cli = Cli::try_parse_from(["interpreter", "--print-register", "$a0", "--format", "json", "program.asm"]).unwrap();
+        assert_eq!(vec![Register::R1], cli.print_register);
+        assert_eq!(RegisterFormat::Json, cli.format);
+
+        assert!(Cli::try_parse_from(["interpreter", "--print-register", "r8", "program.asm"]).is_err());
+        assert!(Cli::try_parse_from(["interpreter", "--format", "xml", "program.asm"]).is_err());
+    }
+
+    #[test]
+    fn registers() {
+        let registers = [(Register::R0, 5), (Register::SP, 0x70000)];
+        assert_eq!("$r0: 5\n$sp: 458752\n", format_registers(&registers, RegisterFormat::Plain));
+        assert_eq!("register,value\n$r0,5\n$sp,458752\n", format_registers(&registers, RegisterFormat::Csv));
+        assert_eq!("{\"$r0\":5,\"$sp\":458752}\n", format_registers(&registers, RegisterFormat::Json));
+        assert_eq!("register,value\n", format_registers(&[], RegisterFormat::Csv));
+    }
+}
@@ -0,0 +1,82 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Measures how long the assembler takes for large programs
+//! (`cargo bench -p libcustomvmcpu --bench compiler`)
+
+use std::time::Instant;
+use libcustomvmcpu::compiler::compile;
+use libcustomvmcpu::incremental::IncrementalCompiler;
+use libcustomvmcpu::parser::parse_string;
+
+const RUNS: u32 = 5;
+
+/// Program with `count` blocks, which jump backwards through the program and
+/// load a constant defined by the next constant
+fn generate(count: usize) -> String {
+    let mut result = String::new();
+    for i in 0..count {
+        result += &format!(".equ C{}, %C{} + 1\n", i, i + 1);
+    }
+    result += &format!(".equ C{}, %end\n", count);
+
+    for i in 0..count {
+        result += &format!("l{}:\n\tji %l{}\n\tli $r0, %C{}\n", i, count - 1 - i, i);
+    }
+    result += "end:\n\tsyscalli 0\n";
+    result
+}
+
+fn bench(count: usize) {
+    let source = generate(count);
+    let mut best = f64::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let mut parser = parse_string(&source);
+        compile(&mut parser).expect("Benchmark program must compile");
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+
+    println!("{} lines: {:.3} ms", source.lines().count(), best * 1000.0);
+}
+
+/// Compiles the program again after `edit` changed it (with the run)
+fn bench_incremental(count: usize, name: &str, edit: impl Fn(&str, u32) -> String) {
+    let source = generate(count);
+    let mut best = f64::MAX;
+    for i in 0..RUNS {
+        let mut compiler = IncrementalCompiler::default();
+        compiler.compile(&source);
+        let changed = edit(&source, i);
+
+        let start = Instant::now();
+        assert!(compiler.compile(&changed).is_ok(), "Benchmark program must compile");
+        best = best.min(start.elapsed().as_secs_f64());
+    }
+
+    println!("{} lines, incremental ({}): {:.3} ms", source.lines().count(), name, best * 1000.0);
+}
+
+fn main() {
+    bench(1000);
+    bench(10000);
+    bench_incremental(10000, "changed line", |source, i| source.replacen("li $r0, %C5000\n", &format!("li $r0, {}\n", i), 1));
+    bench_incremental(10000, "inserted line", |source, i| source.replacen("l5000:\n", &format!("l5000:\n\tli $r1, {}\n", i), 1));
+}
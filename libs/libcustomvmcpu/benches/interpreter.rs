@@ -0,0 +1,81 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Measures the instruction throughput of the interpreter loop
+//! (`cargo bench -p libcustomvmcpu`)
+
+use std::time::Instant;
+use libcustomvmcpu::compiler::parse_and_compile_str;
+use libcustomvmcpu::runtime::{BinaryInterpreter, BinaryVirtualMachine};
+
+const RUNS: u32 = 5;
+
+/// Counts down from 524287 with arithmetic in the loop body
+const ARITHMETIC_LOOP: &str = "
+	li $r0, 524287
+	li $r1, 0
+loop:
+	addi $r1, 3
+	muli $r2, 2
+	xor $r2, $r1
+	subi $r0, 1
+	jnzi $r0, %loop
+	syscalli 0
+";
+
+/// Sums up a memory region (256 KiB) with loads and stores in the loop body
+const MEMORY_LOOP: &str = "
+	li $r0, 65536
+	li $r3, 262144
+loop:
+	lw $r1, $r3
+	add $r1, $r0
+	sw $r1, $r3
+	addi $r3, 4
+	subi $r0, 1
+	jnzi $r0, %loop
+	syscalli 0
+";
+
+fn bench(name: &str, source: &'static str, decode_cache: bool) {
+    let program = parse_and_compile_str(source).expect("Benchmark program must compile");
+    let mut stdout = std::io::sink();
+    let mut best = f64::MAX;
+    let mut instructions = 0;
+    for _ in 0..RUNS {
+        let interpreter = BinaryInterpreter::new_with_initial(&program).expect("Program fits into memory");
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.set_decode_cache(decode_cache);
+        let start = Instant::now();
+        vm.execute_first();
+        best = best.min(start.elapsed().as_secs_f64());
+        instructions = vm.stats().instructions;
+    }
+
+    println!("{}: {} instructions in {:.3} ms ({:.1} M instructions/s)",
+        name, instructions, best * 1000.0, instructions as f64 / best / 1_000_000.0);
+}
+
+fn main() {
+    bench("arithmetic loop", ARITHMETIC_LOOP, false);
+    bench("arithmetic loop (decode cache)", ARITHMETIC_LOOP, true);
+    bench("memory loop", MEMORY_LOOP, false);
+    bench("memory loop (decode cache)", MEMORY_LOOP, true);
+}
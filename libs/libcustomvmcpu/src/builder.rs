@@ -0,0 +1,257 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Configures a virtual machine with chainable setters (see `VmBuilder`)
+
+use std::ops::Range;
+use super::common::IsaVersion;
+use super::fault::FaultInjector;
+use super::runtime::{BranchMode, Interpreter, InvalidOpcodePolicy, TraceSink, VirtualMachine};
+
+/// Collects the configuration of a virtual machine, `build()` creates it.
+/// Options, which aren't set, keep the defaults of `VirtualMachine::new`.
+pub struct VmBuilder<'source, InterpreterImpl: Interpreter> {
+    interpreter: InterpreterImpl,
+    stdin: Option<&'source mut dyn std::io::Read>,
+    stdout: &'source mut dyn std::io::Write,
+    stderr: Option<&'source mut dyn std::io::Write>,
+    tracer: Option<&'source mut dyn TraceSink>,
+    isa_version: Option<IsaVersion>,
+    branch_mode: BranchMode,
+    write_protected: Option<Range<u32>>,
+    write_xor_execute: bool,
+    invalid_opcode_policy: InvalidOpcodePolicy,
+    allowed_syscalls: Option<Vec<u32>>,
+    decode_cache: bool,
+    harts: usize,
+    fault_injector: Option<FaultInjector>,
+    opcode_profiling: bool,
+    address_profiling: bool,
+    coverage: bool,
+    entry: u32,
+}
+
+impl<'source, InterpreterImpl: Interpreter> VmBuilder<'source, InterpreterImpl> {
+    /// Virtual machine executing the program of `interpreter`, which prints
+    /// to `stdout`
+    pub fn new(interpreter: InterpreterImpl, stdout: &'source mut dyn std::io::Write) -> VmBuilder<'source, InterpreterImpl> {
+        VmBuilder {
+            interpreter,
+            stdin: None,
+            stdout,
+            stderr: None,
+            tracer: None,
+            isa_version: None,
+            branch_mode: BranchMode::default(),
+            write_protected: None,
+            write_xor_execute: false,
+            invalid_opcode_policy: InvalidOpcodePolicy::default(),
+            allowed_syscalls: None,
+            decode_cache: false,
+            harts: 1,
+            fault_injector: None,
+            opcode_profiling: false,
+            address_profiling: false,
+            coverage: false,
+            entry: 0,
+        }
+    }
+
+    /// Input of the program (syscall 6)
+    pub fn stdin(mut self, stdin: &'source mut dyn std::io::Read) -> Self {
+        self.stdin = Some(stdin);
+        self
+    }
+
+    /// Error stream of the program (syscall 7)
+    pub fn stderr(mut self, stderr: &'source mut dyn std::io::Write) -> Self {
+        self.stderr = Some(stderr);
+        self
+    }
+
+    /// See `VirtualMachine::set_tracer`
+    pub fn tracer(mut self, tracer: &'source mut dyn TraceSink) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// See `VirtualMachine::set_isa_version`
+    pub fn isa_version(mut self, isa_version: IsaVersion) -> Self {
+        self.isa_version = Some(isa_version);
+        self
+    }
+
+    /// See `VirtualMachine::set_branch_mode`
+    pub fn branch_mode(mut self, branch_mode: BranchMode) -> Self {
+        self.branch_mode = branch_mode;
+        self
+    }
+
+    /// See `VirtualMachine::set_write_protected`
+    pub fn write_protected(mut self, range: Range<u32>) -> Self {
+        self.write_protected = Some(range);
+        self
+    }
+
+    /// See `VirtualMachine::set_write_xor_execute`
+    pub fn write_xor_execute(mut self, enabled: bool) -> Self {
+        self.write_xor_execute = enabled;
+        self
+    }
+
+    /// See `VirtualMachine::set_invalid_opcode_policy`
+    pub fn invalid_opcode_policy(mut self, policy: InvalidOpcodePolicy) -> Self {
+        self.invalid_opcode_policy = policy;
+        self
+    }
+
+    /// See `VirtualMachine::set_allowed_syscalls`
+    pub fn allowed_syscalls(mut self, syscalls: Vec<u32>) -> Self {
+        self.allowed_syscalls = Some(syscalls);
+        self
+    }
+
+    /// See `VirtualMachine::set_decode_cache`
+    pub fn decode_cache(mut self, enabled: bool) -> Self {
+        self.decode_cache = enabled;
+        self
+    }
+
+    /// See `VirtualMachine::set_hart_count`
+    pub fn harts(mut self, count: usize) -> Self {
+        self.harts = count;
+        self
+    }
+
+    /// See `VirtualMachine::set_fault_injector`
+    pub fn fault_injector(mut self, fault_injector: FaultInjector) -> Self {
+        self.fault_injector = Some(fault_injector);
+        self
+    }
+
+    /// See `VirtualMachine::set_opcode_profiling`
+    pub fn opcode_profiling(mut self, enabled: bool) -> Self {
+        self.opcode_profiling = enabled;
+        self
+    }
+
+    /// See `VirtualMachine::set_address_profiling`
+    pub fn address_profiling(mut self, enabled: bool) -> Self {
+        self.address_profiling = enabled;
+        self
+    }
+
+    /// See `VirtualMachine::set_coverage`
+    pub fn coverage(mut self, enabled: bool) -> Self {
+        self.coverage = enabled;
+        self
+    }
+
+    /// See `VirtualMachine::set_entry`
+    pub fn entry(mut self, entry: u32) -> Self {
+        self.entry = entry;
+        self
+    }
+
+    /// Creates the configured virtual machine
+    pub fn build(self) -> VirtualMachine<'source, InterpreterImpl> {
+        let mut vm = VirtualMachine::new_with_streams(self.interpreter, self.stdin, self.stdout, self.stderr);
+        vm.set_tracer(self.tracer);
+        if let Some(isa_version) = self.isa_version {
+            vm.set_isa_version(isa_version);
+        }
+        vm.set_branch_mode(self.branch_mode);
+        vm.set_write_protected(self.write_protected);
+        vm.set_write_xor_execute(self.write_xor_execute);
+        vm.set_invalid_opcode_policy(self.invalid_opcode_policy);
+        vm.set_allowed_syscalls(self.allowed_syscalls);
+        vm.set_decode_cache(self.decode_cache);
+        vm.set_hart_count(self.harts);
+        vm.set_fault_injector(self.fault_injector);
+        vm.set_opcode_profiling(self.opcode_profiling);
+        vm.set_address_profiling(self.address_profiling);
+        vm.set_coverage(self.coverage);
+        vm.set_entry(self.entry);
+        vm
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VmBuilder;
+    use crate::common::{IsaVersion, OpCode, Register};
+    use crate::runtime::{utils, BinaryInterpreter, BranchMode, InvalidOpcodePolicy};
+
+    #[test]
+    fn defaults() {
+        let interpreter = BinaryInterpreter::new_with_program(&[utils::create_instruction_immediate(OpCode::SYSCALLI, 0)]).expect("Expected");
+        let mut stdout = Vec::new();
+        let mut vm = VmBuilder::new(interpreter, &mut stdout).build();
+        assert_eq!(BranchMode::default(), vm.get_branch_mode());
+        assert_eq!(InvalidOpcodePolicy::default(), vm.get_invalid_opcode_policy());
+        assert_eq!(None, vm.get_allowed_syscalls());
+        assert_eq!(None, vm.get_write_protected());
+        assert!(!vm.get_decode_cache());
+        assert_eq!(1, vm.get_hart_count());
+        assert_eq!(None, vm.opcode_profile());
+        assert_eq!(0, vm.get_entry());
+        assert_eq!(0, vm.execute_first());
+    }
+
+    #[test]
+    fn configured() {
+        let program = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 1),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 0),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 0),
+        ];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut stdin: &[u8] = b"";
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let mut vm = VmBuilder::new(interpreter, &mut stdout)
+            .stdin(&mut stdin)
+            .stderr(&mut stderr)
+            .isa_version(IsaVersion::V2)
+            .branch_mode(BranchMode::DelaySlot)
+            .write_protected(0..8)
+            .invalid_opcode_policy(InvalidOpcodePolicy::Stop)
+            .allowed_syscalls(vec![0])
+            .decode_cache(true)
+            .harts(2)
+            .opcode_profiling(true)
+            .address_profiling(true)
+            .coverage(true)
+            .entry(4)
+            .build();
+        assert_eq!(IsaVersion::V2, vm.get_isa_version());
+        assert_eq!(BranchMode::DelaySlot, vm.get_branch_mode());
+        assert_eq!(Some(0..8), vm.get_write_protected());
+        assert_eq!(InvalidOpcodePolicy::Stop, vm.get_invalid_opcode_policy());
+        assert_eq!(Some(&[0][..]), vm.get_allowed_syscalls());
+        assert!(vm.get_decode_cache());
+        assert_eq!(2, vm.get_hart_count());
+        assert_eq!(4, vm.get_entry());
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(Some(2), vm.opcode_profile().map(|profile| profile.len()));
+        assert_eq!(Some(2), vm.coverage().map(|coverage| coverage.len()));
+    }
+}
@@ -17,10 +17,12 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::fmt;
 use num_derive::FromPrimitive;    
 
 /// Registers
-#[derive(PartialEq, PartialOrd, Debug, Clone, Copy, FromPrimitive)]
+#[derive(PartialEq, Eq, Hash, PartialOrd, Debug, Clone, Copy, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum Register
 {
@@ -49,7 +51,82 @@ pub enum Register
 
 pub const LAST_REGISTER: Register = Register::ERR;
 
+/// Assembler name of the register. The alternate form (`{:#}`) uses the ABI
+/// aliases of the general purpose registers: `$rv` (return value, $r0),
+/// `$a0`-`$a1` (arguments, $r1-$r2), `$t0`-`$t2` (temporaries, $r3-$r5) and
+/// `$s0`-`$s1` (saved, $r6-$r7).
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if f.alternate() {
+            let alias = match self {
+                Self::R0 => Some("$rv"),
+                Self::R1 => Some("$a0"),
+                Self::R2 => Some("$a1"),
+                Self::R3 => Some("$t0"),
+                Self::R4 => Some("$t1"),
+                Self::R5 => Some("$t2"),
+                Self::R6 => Some("$s0"),
+                Self::R7 => Some("$s1"),
+                _ => None,
+            };
+
+            if let Some(alias) = alias {
+                return f.write_str(alias);
+            }
+        }
+
+        f.write_str(match self {
+            Self::R0 => "$r0",
+            Self::R1 => "$r1",
+            Self::R2 => "$r2",
+            Self::R3 => "$r3",
+            Self::R4 => "$r4",
+            Self::R5 => "$r5",
+            Self::R6 => "$r6",
+            Self::R7 => "$r7",
+            Self::SP => "$sp",
+            Self::IP => "$ip",
+            Self::RA => "$ra",
+            Self::ERR => "$err",
+        })
+    }
+}
+
+/// Registers of the trap architecture, accessed with `mfs` and `mts`
 #[derive(PartialEq, PartialOrd, Debug, Clone, Copy, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum SystemRegister {
+    /// Address of the vector table (0 disables traps). Entry 0 is the
+    /// interrupt handler, entry n the handler for `Error` n. An entry of 0
+    /// means no handler.
+    VectorTable,
+
+    /// Address, where `reti` continues
+    Epc,
+
+    /// Cause of the current trap: the `Error` or, for interrupts,
+    /// `CAUSE_INTERRUPT`
+    Cause,
+
+    /// Trap status (`STATUS_INTERRUPTS_ENABLED`, `STATUS_IN_TRAP`)
+    Status,
+
+    /// Address of the last faulting memory access - read-only
+    FaultAddress,
+}
+
+/// Cause of traps raised by an interrupt
+pub const CAUSE_INTERRUPT: u32 = 0x80000000;
+
+/// Status bit: interrupts are delivered
+pub const STATUS_INTERRUPTS_ENABLED: u32 = 1;
+
+/// Status bit: a trap handler is running (cleared by `reti`)
+pub const STATUS_IN_TRAP: u32 = 2;
+
+#[derive(PartialEq, Eq, Hash, PartialOrd, Debug, Clone, Copy, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u8)]
 pub enum OpCode {
     /// Copy from register to register
@@ -428,11 +505,84 @@ pub enum OpCode {
     /// sh $r0, %label
     /// y
     SBI,
+
+    /// Move from system register
+    ///
+    /// # Example
+    ///
+    /// Copy the trap cause (see `SystemRegister`) into register `$r0`:
+    ///
+    /// 
+    /// mfs $r0, 2
+    /// 
+    MFS,
+
+    /// Move to system register
+    ///
+    /// # Example
+    ///
+    /// Install the vector table at the address in register `$r0`:
+    ///
+    /// 
+    /// mts $r0, 0
+    /// 
+    MTS,
+
+    /// Enable interrupts
+    ///
+    /// # Example
+    ///
+    /// 
+    /// ei
+    /// 
+    EI,
+
+    /// Disable interrupts
+    ///
+    /// # Example
+    ///
+    /// 
+    /// di
+    /// 
+    DI,
+
+    /// Return from a trap handler to the saved instruction pointer (`EPC`)
+    ///
+    /// # Example
+    ///
+    /// 
+    /// reti
+    /// 
+    RETI,
+
+    /// Atomically swap register and memory
+    ///
+    /// # Example
+    ///
+    /// Write `$r0` into memory at value of register `$r1` and load the old
+    /// value into `$r0`:
+    ///
+    /// 
+    /// amoswap $r0, $r1
+    /// 
+    AMOSWAP,
+
+    /// Atomically add register to memory
+    ///
+    /// # Example
+    ///
+    /// Add `$r0` to the 32-bit value in memory at value of register `$r1`
+    /// and load the old value into `$r0`:
+    ///
+    /// 
+    /// amoadd $r0, $r1
+    /// 
+    AMOADD,
 }
 
-impl ToString for OpCode {
-    fn to_string(&self) -> String {
-        (match self {
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
             Self::CPY => "cpy",
             Self::LW => "lw",
             Self::SW => "sw",
@@ -471,14 +621,124 @@ impl ToString for OpCode {
             Self::SHI => "shi",
             Self::LBI => "lbi",
             Self::SBI => "sbi",
-        }).to_string()
+            Self::MFS => "mfs",
+            Self::MTS => "mts",
+            Self::EI => "ei",
+            Self::DI => "di",
+            Self::RETI => "reti",
+            Self::AMOSWAP => "amoswap",
+            Self::AMOADD => "amoadd",
+        })
     }
 }
 
 pub const LAST_OP_CODE: OpCode = OpCode::SYSCALLI;
 
+/// Operands of an instruction format
+#[derive(Debug, PartialEq)]
+pub enum InstructionParseType {
+    TwoRegisters,
+    RegisterAndImmediate,
+    Register,
+    Immediate,
+    TwoRegistersAndImmediate,
+    NoOperands,
+}
+
+pub fn get_instruction_parse_type(op_code: OpCode) -> InstructionParseType {
+    match op_code {
+        OpCode::CPY
+            | OpCode::LW
+            | OpCode::SW
+            | OpCode::LH
+            | OpCode::SH
+            | OpCode::LB
+            | OpCode::SB 
+            | OpCode::ADD
+            | OpCode::SUB
+            | OpCode::MUL
+            | OpCode::DIV
+            | OpCode::AND
+            | OpCode::OR
+            | OpCode::XOR
+            | OpCode::SRL
+            | OpCode::SLL
+            | OpCode::AMOSWAP
+            | OpCode::AMOADD => InstructionParseType::TwoRegisters,
+        OpCode::SRLI
+            | OpCode::SLLI
+            | OpCode::JZI
+            | OpCode::JNZI
+            | OpCode::JLZI
+            | OpCode::JGZI
+            | OpCode::LI
+            | OpCode::ADDI
+            | OpCode::SUBI
+            | OpCode::MULI
+            | OpCode::DIVI 
+            | OpCode::SWI
+            | OpCode::LWI
+            | OpCode::SHI
+            | OpCode::LHI
+            | OpCode::SBI
+            | OpCode::LBI
+            | OpCode::MFS
+            | OpCode::MTS => InstructionParseType::RegisterAndImmediate,
+        OpCode::NOT
+            | OpCode::J => InstructionParseType::Register,
+        OpCode::SYSCALLI
+            | OpCode::JI
+            | OpCode::JIL => InstructionParseType::Immediate,
+        OpCode::EI
+            | OpCode::DI
+            | OpCode::RETI => InstructionParseType::NoOperands,
+    }
+}
+
+/// Instruction set versions
+///
+/// Every version contains all opcodes of the versions before it, so a binary
+/// compiled for an older version runs on every newer virtual machine.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum IsaVersion {
+    /// Base instruction set (`cpy` up to `syscalli`)
+    V1 = 1,
+
+    /// Shift, immediate arithmetic and immediate load/store extensions
+    V2 = 2,
+
+    /// Traps: system registers, interrupt enable/disable and `reti`
+    V3 = 3,
+
+    /// Atomic memory operations for multiple harts
+    V4 = 4,
+}
+
+pub const LATEST_ISA_VERSION: IsaVersion = IsaVersion::V4;
+
+impl OpCode {
+    /// Returns the first instruction set version containing the opcode
+    pub const fn isa_version(&self) -> IsaVersion {
+        if *self as u8 <= LAST_OP_CODE as u8 {
+            IsaVersion::V1
+        }
+        else if *self as u8 <= OpCode::SBI as u8 {
+            IsaVersion::V2
+        }
+        else if *self as u8 <= OpCode::RETI as u8 {
+            IsaVersion::V3
+        }
+        else {
+            IsaVersion::V4
+        }
+    }
+}
+
 /// Errors that can occur
 #[derive(PartialEq, PartialOrd, Debug, Clone, Copy, FromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[repr(u32)]
 pub enum Error {
     /// No error occured
@@ -514,6 +774,16 @@ pub enum Error {
 
     /// Divisor cannot be 0
     DivisorNotZero,
+
+    /// Store into write-protected memory (like the program code)
+    WriteProtected,
+
+    /// Access to a virtual address, which isn't mapped by the MMU (or a
+    /// write to a page, which isn't writable)
+    PageFault,
+
+    /// Instruction fetched from writable memory, while W^X is enabled
+    ExecuteProtected,
 }
 
 pub const ERROR_START_NUM: u32 = 32000;
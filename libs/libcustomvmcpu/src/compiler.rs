@@ -17,12 +17,15 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::iter::Iterator;
 use std::mem::size_of;
-use super::common::{OpCode, Register, Error, LAST_REGISTER, ERROR_START_NUM};
+use super::common::{OpCode, Register, Error, LAST_REGISTER, ERROR_START_NUM, IsaVersion, LATEST_ISA_VERSION};
 use super::runtime::utils;
-use super::parser::{Expr, ImmediateExpr, ParserExpr, ParserResult, ParserError, ParserErrorType, parse_str};
+use super::image::Image;
+use super::host::HOST_SYSCALL_START;
+use super::diagnostic::Diagnostic;
+use super::parser::{Expr, ImmediateExpr, Section, ParserExpr, ParserResult, ParserWarning, ParserWarningType, InstructionParseType, get_instruction_parse_type, parse_str};
 
 fn filter_errors(program: &mut Vec<ParserExpr>) {
     program.retain(|x| x.expr != Expr::Error());
@@ -30,13 +33,21 @@ fn filter_errors(program: &mut Vec<ParserExpr>) {
 
 fn calc_expr_size(expr: &Expr) -> u32 {
     return match expr {
-        Expr::InstructionTwoRegisters(_, _, _)
+        Expr::Instruction(_)
+            | Expr::InstructionTwoRegisters(_, _, _)
             | Expr::InstructionRegisterAndImmediate(_, _, _)
             | Expr::InstructionRegister(_, _)
             | Expr::InstructionImmediate(_, _) => size_of::<u32>() as u32,
         Expr::StoreI32(_) => size_of::<i32>() as u32,
+        Expr::StoreI8(_) => size_of::<i8>() as u32,
         Expr::StoreStr(string) => string.bytes().len() as u32,
+        Expr::StoreSpace(size) => *size,
+        Expr::Constant(_, _) => 0,
+        Expr::Import(_) => 0,
         Expr::Label(_) => 0,
+        Expr::Isa(_) => 0,
+        Expr::Section(_) => 0,
+        Expr::Entry(_) => 0,
         Expr::Error() => 0,
         _ => {
             panic!("Not a top level expression: {:?}", expr);
@@ -44,6 +55,37 @@ fn calc_expr_size(expr: &Expr) -> u32 {
     };
 }
 
+/// Checks, if the statement `new` can replace `old` without changing the
+/// layout of the program: both have the same size, are instructions or data
+/// and don't define symbols
+pub(crate) fn same_layout(old: &Expr, new: &Expr) -> bool {
+    let defines_layout = |expr: &Expr| matches!(expr, Expr::Label(_) | Expr::Constant(_, _) | Expr::Import(_) | Expr::Isa(_) | Expr::Section(_) | Expr::Entry(_) | Expr::Error());
+    !defines_layout(old) && !defines_layout(new)
+        && calc_expr_size(old) == calc_expr_size(new)
+        && get_expr_op_code(old).is_some() == get_expr_op_code(new).is_some()
+}
+
+/// Values an immediate of `op_code` can have. Negative values are the
+/// two's complement (`0 - 1`). Returns None, if the instruction has no
+/// immediate.
+pub fn immediate_range(op_code: OpCode) -> Option<std::ops::RangeInclusive<i64>> {
+    match get_instruction_parse_type(op_code) {
+        // Only li sign-extends its immediate
+        InstructionParseType::RegisterAndImmediate if op_code == OpCode::LI => Some(-0x00080000..=0x0007FFFF),
+        InstructionParseType::RegisterAndImmediate => Some(0..=0x000FFFFF),
+        InstructionParseType::Immediate => Some(0..=0x00FFFFFF),
+        InstructionParseType::TwoRegistersAndImmediate => Some(0..=0x0000FFFF),
+        _ => None,
+    }
+}
+
+/// Checks, if `imm` fits into the immediate of `op_code` (see
+/// `immediate_range`)
+pub fn immediate_fits(op_code: OpCode, imm: u32) -> bool {
+    let value = if imm > i32::MAX as u32 { imm as i32 as i64 } else { imm as i64 };
+    immediate_range(op_code).is_some_and(|range| range.contains(&value))
+}
+
 #[derive(Debug)]
 enum CompileExprResult {
     CompileToNone,
@@ -53,52 +95,143 @@ enum CompileExprResult {
 
 struct Compiler<'source> {
     label_map: HashMap<String, u32>,
-    parser: &'source mut ParserResult,
+    entry: u32,
+    /// Names of the imports in the order of the program
+    imports: Vec<String>,
+    errors: &'source mut Vec<Diagnostic>,
+    warnings: &'source mut Vec<ParserWarning>,
 }
 
 impl<'source> Compiler<'source> {
-    fn interpret_immediate(&mut self, expr: &ImmediateExpr) -> Option<u32> {
-        match expr {
-            ImmediateExpr::Int(result) => Some(*result),
-            ImmediateExpr::AddrToLabel(label) => {
-                if let Some(result) = self.label_map.get(label) {
-                    Some(*result)
+    fn interpret_immediate(&self, expr: &ImmediateExpr) -> Option<u32> {
+        expr.evaluate(&self.label_map)
+    }
+
+    /// Pass 1: addresses of all labels and syscall numbers of all imports,
+    /// then the values of all constants
+    fn collect_symbols(&mut self, program: &[ParserExprWithPos]) {
+        let mut constants: HashMap<&String, &ImmediateExpr> = HashMap::new();
+        for expr in program {
+            match &expr.expr.expr {
+                Expr::Label(label) => {
+                    self.label_map.insert(label.clone(), expr.pos);
+                },
+                Expr::Import(name) => {
+                    let syscall = HOST_SYSCALL_START + self.imports.len() as u32;
+                    self.label_map.insert(name.clone(), syscall);
+                    self.imports.push(name.clone());
+                },
+                Expr::Constant(name, imm) => {
+                    constants.insert(name, imm);
+                },
+                _ => {}
+            }
+        }
+
+        self.resolve_constants(&constants);
+    }
+
+    /// Resolves the constants depth-first: a constant after the constants it
+    /// depends on. Constants, which depend on a missing symbol or on
+    /// themselves, stay unresolved.
+    fn resolve_constants(&mut self, constants: &HashMap<&String, &ImmediateExpr>) {
+        let mut failed: HashSet<&String> = HashSet::new();
+        for name in constants.keys() {
+            let mut stack: Vec<&String> = vec![name];
+            let mut on_stack: HashSet<&String> = stack.iter().copied().collect();
+            while let Some(current) = stack.last().copied() {
+                if self.label_map.contains_key(current) || failed.contains(current) {
+                    stack.pop();
+                    on_stack.remove(current);
+                    continue;
                 }
-                else {
-                    None
+
+                let imm = constants[current];
+                let pending = imm.labels().into_iter().find(|dependency| constants.contains_key(dependency)
+                    && !self.label_map.contains_key(*dependency)
+                    && !failed.contains(dependency));
+                match pending {
+                    Some(dependency) if on_stack.contains(dependency) => {
+                        failed.insert(current); // Cycle
+                    },
+                    Some(dependency) => {
+                        stack.push(dependency);
+                        on_stack.insert(dependency);
+                    },
+                    None => {
+                        match self.interpret_immediate(imm) {
+                            Some(value) => {
+                                self.label_map.insert(current.clone(), value);
+                            },
+                            None => {
+                                failed.insert(current);
+                            },
+                        }
+                    },
                 }
-            },
-            ImmediateExpr::Add(expr0, expr1) =>
-                self.interpret_immediate_biop(expr0, expr1, |x, y| x.wrapping_add(y)),
-            ImmediateExpr::Sub(expr0, expr1) =>
-                self.interpret_immediate_biop(expr0, expr1, |x, y| x.wrapping_sub(y)),
-            ImmediateExpr::Mul(expr0, expr1) =>
-                self.interpret_immediate_biop(expr0, expr1, |x, y| x.wrapping_mul(y)),
-            ImmediateExpr::Div(expr0, expr1) =>
-                self.interpret_immediate_biop(expr0, expr1, |x, y| x.wrapping_div(y)),
+            }
         }
     }
 
-    fn interpret_immediate_biop(&mut self, expr0: &ImmediateExpr, expr1: &ImmediateExpr, fn_bi_op: fn (u32, u32) -> u32) -> Option<u32> {
-        let result0 = self.interpret_immediate(expr0)?;
-        let result1 = self.interpret_immediate(expr1)?;
-        Some(fn_bi_op(result0, result1))
+    fn warn(&mut self, expr: &ParserExpr, warn_type: ParserWarningType) {
+        self.warnings.push(ParserWarning { pos: expr.pos.clone(), warn_type });
     }
 
-    fn compile_expr(&mut self, expr: &ParserExpr, prog_pos: u32) -> CompileExprResult {
+    /// Warns, if the store instruction `expr` writes into one of the
+    /// instructions at `code`
+    fn check_store_to_code(&mut self, expr: &ParserExpr, code: &[std::ops::Range<u32>]) {
+        let (size, imm) = match &expr.expr {
+            Expr::InstructionRegisterAndImmediate(OpCode::SWI, _, imm) => (size_of::<u32>() as u32, imm),
+            Expr::InstructionRegisterAndImmediate(OpCode::SHI, _, imm) => (size_of::<u16>() as u32, imm),
+            Expr::InstructionRegisterAndImmediate(OpCode::SBI, _, imm) => (size_of::<u8>() as u32, imm),
+            _ => return
+        };
+
+        if let Some(address) = self.interpret_immediate(imm) {
+            let address = address & 0x000FFFFF;
+            let end = address.saturating_add(size);
+            if code.iter().any(|instruction| address < instruction.end && instruction.start < end) {
+                self.warn(expr, ParserWarningType::StoreToCode);
+            }
+        }
+    }
+
+    fn compile_expr(&mut self, expr: &ParserExpr) -> CompileExprResult {
         match &expr.expr {
-            Expr::Label(label) => {
-                self.label_map.insert(label.clone(), prog_pos);
-                CompileExprResult::CompileToNone
+            Expr::Label(_) | Expr::Import(_) | Expr::Isa(_) | Expr::Section(_) => CompileExprResult::CompileToNone,
+            Expr::Entry(imm) => {
+                if let Some(entry) = self.interpret_immediate(imm) {
+                    self.entry = entry;
+                    CompileExprResult::CompileToNone
+                }
+                else {
+                    CompileExprResult::CompileToError
+                }
+            },
+            Expr::Constant(name, _) => {
+                if self.label_map.contains_key(name) {
+                    CompileExprResult::CompileToNone
+                }
+                else {
+                    CompileExprResult::CompileToError
+                }
             },
             Expr::InstructionTwoRegisters(op_code, reg0, reg1) => {
                 CompileExprResult::CompileToResult(utils::create_instruction_two_registers(*op_code, *reg0, *reg1).to_le_bytes().to_vec())
             },
+            Expr::Instruction(op_code) => {
+                CompileExprResult::CompileToResult(utils::create_instruction(*op_code).to_le_bytes().to_vec())
+            },
             Expr::InstructionRegister(op_code, reg) => {
                 CompileExprResult::CompileToResult(utils::create_instruction_register(*op_code, *reg).to_le_bytes().to_vec())
             },
             Expr::InstructionRegisterAndImmediate(op_code, reg, imm) => {
                 if let Some(imm) = self.interpret_immediate(&imm) {
+                    if !immediate_fits(*op_code, imm) {
+                        self.errors.push(Diagnostic::immediate_out_of_range(expr.pos.clone(), *op_code, imm));
+                        return CompileExprResult::CompileToNone;
+                    }
+
                     CompileExprResult::CompileToResult(utils::create_instruction_register_and_immediate(*op_code, *reg, imm).to_le_bytes().to_vec())
                 }
                 else {
@@ -107,6 +240,11 @@ impl<'source> Compiler<'source> {
             },
             Expr::InstructionImmediate(op_code, imm) => {
                 if let Some(imm) = self.interpret_immediate(&imm) {
+                    if !immediate_fits(*op_code, imm) {
+                        self.errors.push(Diagnostic::immediate_out_of_range(expr.pos.clone(), *op_code, imm));
+                        return CompileExprResult::CompileToNone;
+                    }
+
                     CompileExprResult::CompileToResult(utils::create_instruction_immediate(*op_code, imm).to_le_bytes().to_vec())
                 }
                 else {
@@ -121,9 +259,26 @@ impl<'source> Compiler<'source> {
                     CompileExprResult::CompileToError
                 }
             },
+            Expr::StoreI8(imm) => {
+                if let Some(imm) = self.interpret_immediate(imm) {
+                    // Signed (-128 to -1) or unsigned (0 to 255)
+                    if imm > 0xFF && imm < 0xFFFFFF80 {
+                        self.errors.push(Diagnostic::byte_out_of_range(expr.pos.clone(), imm));
+                        return CompileExprResult::CompileToNone;
+                    }
+
+                    CompileExprResult::CompileToResult(vec![imm as u8])
+                }
+                else {
+                    CompileExprResult::CompileToError
+                }
+            },
             Expr::StoreStr(string) => {
                 CompileExprResult::CompileToResult(string.as_bytes().to_vec())
             },
+            Expr::StoreSpace(size) => {
+                CompileExprResult::CompileToResult(vec![0; *size as usize])
+            },
             _ => {
                 // Cannot compile expr
                 CompileExprResult::CompileToError
@@ -132,61 +287,538 @@ impl<'source> Compiler<'source> {
     }
 }
 
+/// Options changing how a program is compiled
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileOptions {
+    /// Newest instruction set version the program may use. An `.isa`
+    /// directive in the program can restrict it further.
+    pub isa_version: IsaVersion,
+    /// Address of the `.data` section. If None, it follows the `.text`
+    /// section.
+    pub data_address: Option<u32>,
+    /// Removes data after labels, which are never referenced (see
+    /// `strip_unused_data`)
+    pub strip_unused: bool,
+}
+
+impl Default for CompileOptions {
+    fn default() -> CompileOptions {
+        CompileOptions { isa_version: LATEST_ISA_VERSION, data_address: None, strip_unused: false }
+    }
+}
+
+fn get_expr_op_code(expr: &Expr) -> Option<OpCode> {
+    match expr {
+        Expr::Instruction(op_code)
+            | Expr::InstructionRegister(op_code, _)
+            | Expr::InstructionImmediate(op_code, _)
+            | Expr::InstructionTwoRegisters(op_code, _, _)
+            | Expr::InstructionRegisterAndImmediate(op_code, _, _) => Some(*op_code),
+        _ => None
+    }
+}
+
+/// Reports an error for every constant and import, which has the name of
+/// another constant, import or label
+fn check_redefinitions(program: &[ParserExpr], errors: &mut Vec<Diagnostic>) {
+    let labels: HashSet<&String> = program.iter().filter_map(|expr| match &expr.expr {
+        Expr::Label(label) => Some(label),
+        _ => None
+    }).collect();
+
+    let mut constants: HashSet<&String> = HashSet::new();
+    for expr in program {
+        if let Expr::Constant(name, _) | Expr::Import(name) = &expr.expr {
+            if labels.contains(name) || !constants.insert(name) {
+                errors.push(Diagnostic::symbol_redefined(expr.pos.clone()));
+            }
+        }
+    }
+}
+
+/// Reports an error for every statement except `syscalli`, which uses an
+/// import (their values are syscall numbers)
+fn check_imports(program: &[ParserExpr], imports: &HashSet<String>, errors: &mut Vec<Diagnostic>) {
+    for expr in program {
+        if let Expr::InstructionImmediate(OpCode::SYSCALLI, _) = expr.expr {
+            continue;
+        }
+
+        if let Some(imm) = get_expr_immediate(&expr.expr) {
+            let mut reported: Vec<&String> = Vec::new();
+            for label in imm.labels() {
+                if imports.contains(label) && !reported.contains(&label) {
+                    errors.push(Diagnostic::import_not_called(expr.pos.clone(), label));
+                    reported.push(label);
+                }
+            }
+        }
+    }
+}
+
+/// Names of the imports of `program`
+fn get_imports(program: &[ParserExpr]) -> HashSet<String> {
+    program.iter().filter_map(|expr| match &expr.expr {
+        Expr::Import(name) => Some(name.clone()),
+        _ => None
+    }).collect()
+}
+
+pub(crate) fn get_expr_immediate(expr: &Expr) -> Option<&ImmediateExpr> {
+    match expr {
+        Expr::InstructionImmediate(_, imm)
+            | Expr::InstructionRegisterAndImmediate(_, _, imm)
+            | Expr::StoreI32(imm)
+            | Expr::StoreI8(imm)
+            | Expr::Constant(_, imm)
+            | Expr::Entry(imm) => Some(imm),
+        _ => None
+    }
+}
+
+/// Removes the data blocks (labels directly followed by `.i32`, `.i8`, `.str`
+/// or `.space`), whose labels are never referenced, with a warning for each of
+/// them. Data, which is only referenced by removed data, is removed too.
+/// Data accessed relative to another label isn't detected.
+fn strip_unused_data(program: &mut Vec<ParserExpr>, warnings: &mut Vec<ParserWarning>) {
+    fn is_data(expr: &Expr) -> bool {
+        matches!(expr, Expr::StoreI32(_) | Expr::StoreI8(_) | Expr::StoreStr(_) | Expr::StoreSpace(_))
+    }
+
+    loop {
+        let referenced: HashSet<String> = program.iter()
+            .filter_map(|expr| get_expr_immediate(&expr.expr))
+            .flat_map(|imm| imm.labels())
+            .cloned()
+            .collect();
+
+        // Statements of unused data blocks (labels and their data)
+        let mut unused: Vec<std::ops::Range<usize>> = Vec::new();
+        let mut i = 0;
+        while i < program.len() {
+            let labels_end = i + program[i..].iter().take_while(|expr| matches!(expr.expr, Expr::Label(_))).count();
+            let data_end = labels_end + program[labels_end..].iter().take_while(|expr| is_data(&expr.expr)).count();
+            let used = program[i..labels_end].iter().any(|expr| match &expr.expr {
+                Expr::Label(label) => referenced.contains(label),
+                _ => false,
+            });
+
+            if labels_end > i && data_end > labels_end && !used {
+                unused.push(i..data_end);
+            }
+
+            i = data_end.max(i + 1);
+        }
+
+        if unused.is_empty() {
+            return;
+        }
+
+        for range in unused.iter().rev() {
+            let label = program[range.start].clone();
+            if let Expr::Label(name) = &label.expr {
+                log::debug!("Removing the unused data of {}", name);
+                warnings.push(ParserWarning { pos: label.pos.clone(), warn_type: ParserWarningType::UnusedDataRemoved(name.clone()) });
+            }
+
+            program.drain(range.clone());
+        }
+    }
+}
+
+/// Labels referenced by `imm`, which aren't in `symbols`
+fn find_undefined_labels(imm: &ImmediateExpr, symbols: &HashSet<&String>) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+    for label in imm.labels() {
+        if !symbols.contains(label) && !result.contains(label) {
+            result.push(label.clone());
+        }
+    }
+
+    result
+}
+
+/// Number of inserted, removed, replaced or swapped (neighbouring)
+/// characters to get from `a` to `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+/// Symbol, which differs from `label` only by case or a typo
+fn suggest_label(label: &str, symbols: &HashSet<&String>) -> Option<String> {
+    let max_distance = (label.chars().count() / 3).clamp(1, 2);
+    symbols.iter()
+        .map(|symbol| (if symbol.eq_ignore_ascii_case(label) { 0 } else { edit_distance(label, symbol) }, *symbol))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, symbol)| symbol.clone())
+}
+
+/// Reports an error for every entry point after the first one
+fn check_entries(program: &[ParserExpr], errors: &mut Vec<Diagnostic>) {
+    for expr in program.iter().filter(|expr| matches!(expr.expr, Expr::Entry(_))).skip(1) {
+        errors.push(Diagnostic::entry_redefined(expr.pos.clone()));
+    }
+}
+
+/// Reports an error for every instruction, which isn't part of the selected
+/// instruction set version. Returns the selected version.
+fn check_isa_version(program: &[ParserExpr], isa_version: IsaVersion, errors: &mut Vec<Diagnostic>) -> IsaVersion {
+    let isa_version = program.iter().filter_map(|expr| match expr.expr {
+        Expr::Isa(version) => Some(version),
+        _ => None
+    }).fold(isa_version, std::cmp::min);
+
+    for expr in program {
+        if let Some(op_code) = get_expr_op_code(&expr.expr) {
+            if op_code.isa_version() > isa_version {
+                errors.push(Diagnostic::instruction_not_in_isa(expr.pos.clone()));
+            }
+        }
+    }
+
+    isa_version
+}
+
 #[derive(Debug, Clone)]
 struct ParserExprWithPos {
     pub pos: u32,
     pub expr: ParserExpr,
+    /// Index of the expression in the program
+    pub index: usize,
+}
+
+/// Places the `.text` section at address 0 and the `.data` section after it
+/// (or at `options.data_address`). Returns the expressions with their
+/// addresses and the size of the program, or None if the sections overlap.
+fn layout(program: &[ParserExpr], options: &CompileOptions) -> Option<(Vec<ParserExprWithPos>, u32)> {
+    let mut section = Section::Text;
+    let mut text: Vec<(usize, &ParserExpr)> = Vec::new();
+    let mut data: Vec<(usize, &ParserExpr)> = Vec::new();
+    for (index, expr) in program.iter().enumerate() {
+        if let Expr::Section(new_section) = expr.expr {
+            section = new_section;
+        }
+
+        match section {
+            Section::Text => text.push((index, expr)),
+            Section::Data => data.push((index, expr)),
+        }
+    }
+
+    let text_size: u32 = text.iter().map(|(_, expr)| calc_expr_size(&expr.expr)).sum();
+    let data_address = options.data_address.unwrap_or(text_size);
+    if data_address < text_size && !data.is_empty() {
+        return None;
+    }
+
+    let mut result: Vec<ParserExprWithPos> = Vec::with_capacity(program.len());
+    let mut pos: u32 = 0;
+    for (index, expr) in text {
+        result.push(ParserExprWithPos { pos, expr: expr.clone(), index });
+        pos += calc_expr_size(&expr.expr);
+    }
+
+    if !data.is_empty() {
+        pos = data_address;
+    }
+
+    for (index, expr) in data {
+        result.push(ParserExprWithPos { pos, expr: expr.clone(), index });
+        pos += calc_expr_size(&expr.expr);
+    }
+
+    Some((result, pos))
 }
 
 pub fn compile(parser_result: &mut ParserResult) -> Option<Vec<u8>> {
-    let mut program = parser_result.program.clone();
+    compile_with_options(parser_result, &CompileOptions::default())
+}
+
+pub fn compile_with_options(parser_result: &mut ParserResult, options: &CompileOptions) -> Option<Vec<u8>> {
+    compile_image(parser_result, options).map(|image| image.program)
+}
+
+/// Compiles the program with its entry point (`.entry`, 0 by default)
+pub fn compile_image(parser_result: &mut ParserResult, options: &CompileOptions) -> Option<Image> {
+    compile_output(parser_result, options).map(|output| Image::new(output.binary, output.entry).with_imports(output.imports))
+}
+
+/// Compiled program with everything a debugger needs
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileOutput {
+    pub binary: Vec<u8>,
+    /// Entry point (`.entry`, 0 by default)
+    pub entry: u32,
+    /// Final addresses of all labels
+    pub symbols: HashMap<String, u32>,
+    /// Where the bytes of every statement are (in the order of the
+    /// binary)
+    pub mappings: Vec<SourceMapping>,
+    /// Host functions (`.import`) in the order of their syscall numbers,
+    /// see `VirtualMachine::link_imports`
+    pub imports: Vec<String>,
+}
+
+/// Statement of the program and the bytes it was compiled to
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceMapping {
+    /// Bytes of the program source
+    pub source: std::ops::Range<usize>,
+    /// Addresses in the binary
+    pub address: std::ops::Range<u32>,
+}
+
+/// Compiles the program and returns it with its symbols
+pub fn compile_output(parser_result: &mut ParserResult, options: &CompileOptions) -> Option<CompileOutput> {
+    compile_program(&parser_result.program, options, &mut parser_result.errors, &mut parser_result.warnings).map(|(output, _)| output)
+}
+
+/// Result of `compile2`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileResult {
+    /// The compiled program, None if there are errors
+    pub bytes: Option<Vec<u8>>,
+    /// Final addresses of all labels (empty, if there are errors)
+    pub symbols: HashMap<String, u32>,
+    /// Errors and warnings of the parser and the compiler (in this order)
+    pub diagnostics: Vec<Diagnostic>,
+    /// Entry point (`.entry`, 0 by default)
+    pub entry: u32,
+}
+
+impl CompileResult {
+    /// Checks, if the program was compiled
+    pub fn is_ok(&self) -> bool {
+        self.bytes.is_some()
+    }
+}
+
+/// Compiles the program without changing `parser_result` (unlike
+/// `compile`, which adds the errors and warnings of the compiler to it)
+pub fn compile2(parser_result: &ParserResult) -> CompileResult {
+    compile2_with_options(parser_result, &CompileOptions::default())
+}
+
+/// `compile2` with `options`
+pub fn compile2_with_options(parser_result: &ParserResult, options: &CompileOptions) -> CompileResult {
+    compile_result(parser_result, options).0
+}
+
+/// `compile2_with_options`, which also returns the layout of the program,
+/// if it was compiled
+pub(crate) fn compile_result(parser_result: &ParserResult, options: &CompileOptions) -> (CompileResult, Option<ProgramLayout>) {
+    let mut errors = parser_result.errors.clone();
+    let mut warnings = parser_result.warnings.clone();
+    let output = compile_program(&parser_result.program, options, &mut errors, &mut warnings);
+    let diagnostics = errors.into_iter()
+        .chain(warnings.iter().map(Diagnostic::from))
+        .collect();
+
+    match output {
+        Some((output, layout)) => (CompileResult { bytes: Some(output.binary), symbols: output.symbols, diagnostics, entry: output.entry }, Some(layout)),
+        None => (CompileResult { bytes: None, symbols: HashMap::new(), diagnostics, entry: 0 }, None),
+    }
+}
+
+/// Addresses and symbols of a compiled program (see
+/// `incremental::IncrementalCompiler`)
+pub(crate) struct ProgramLayout {
+    /// Address of every statement (in the order of the program)
+    pub(crate) addresses: Vec<u32>,
+    /// Values of the labels, constants and imports
+    symbols: HashMap<String, u32>,
+    /// Instruction set version selected by the program and the options
+    isa_version: IsaVersion,
+    /// Addresses of the instructions
+    code: Vec<std::ops::Range<u32>>,
+    /// Names of the imports
+    imports: HashSet<String>,
+}
+
+/// Encodes the statements `changed` of `program` again into `binary`, which
+/// was compiled with `layout`. The statements must not change the layout
+/// (they have the same sizes and kinds as the ones they replaced and don't
+/// define symbols). Adds the warnings of the compiler for the whole program
+/// to `warnings`. Returns false, if a statement has an error (the program
+/// has to be compiled again to report it).
+pub(crate) fn recompile(program: &[ParserExpr], changed: std::ops::Range<usize>, layout: &ProgramLayout, binary: &mut [u8], warnings: &mut Vec<ParserWarning>) -> bool {
+    let mut errors = Vec::new();
+    check_imports(&program[changed.clone()], &layout.imports, &mut errors);
+    let mut compiler = Compiler { label_map: layout.symbols.clone(), entry: 0, imports: Vec::new(), errors: &mut errors, warnings };
+    for (expr, &address) in program[changed.clone()].iter().zip(&layout.addresses[changed]) {
+        if get_expr_op_code(&expr.expr).is_some_and(|op_code| op_code.isa_version() > layout.isa_version) {
+            return false;
+        }
+
+        match compiler.compile_expr(expr) {
+            CompileExprResult::CompileToResult(bytes) => {
+                binary[address as usize..(address as usize + bytes.len())].copy_from_slice(&bytes);
+            },
+            CompileExprResult::CompileToNone => {},
+            CompileExprResult::CompileToError => return false,
+        }
+    }
+
+    let mut stores: Vec<(u32, &ParserExpr)> = program.iter().zip(&layout.addresses)
+        .filter(|(expr, _)| matches!(get_expr_op_code(&expr.expr), Some(OpCode::SWI) | Some(OpCode::SHI) | Some(OpCode::SBI)))
+        .map(|(expr, &address)| (address, expr))
+        .collect();
+    // Like the compiler: the text section before the data section
+    stores.sort_by_key(|(address, _)| *address);
+    for (_, store) in stores {
+        compiler.check_store_to_code(store, &layout.code);
+    }
+
+    compiler.errors.is_empty()
+}
+
+/// Compiles `program`, the errors and warnings are added to `errors` and
+/// `warnings`. Fails, if there are any errors (also ones from before).
+fn compile_program(program: &[ParserExpr], options: &CompileOptions, errors: &mut Vec<Diagnostic>, warnings: &mut Vec<ParserWarning>) -> Option<(CompileOutput, ProgramLayout)> {
+    let mut program = program.to_vec();
     filter_errors(&mut program);
+    let isa_version = check_isa_version(&program, options.isa_version, errors);
+    check_redefinitions(&program, errors);
+    let imports = get_imports(&program);
+    check_imports(&program, &imports, errors);
+    check_entries(&program, errors);
+    if options.strip_unused {
+        strip_unused_data(&mut program, warnings);
+    }
 
-    let result_size: u32 = program.iter().map(|expr| calc_expr_size(&expr.expr)).sum();
+    let (program_with_pos, result_size) = if let Some(layout) = layout(&program, options) {
+        layout
+    }
+    else {
+        let pos = program.iter().find(|expr| expr.expr == Expr::Section(Section::Data)).map(|expr| expr.pos.clone()).unwrap_or(0..0);
+        errors.push(Diagnostic::section_overlap(pos));
+        return None;
+    };
 
     let mut result: Vec<u8> = vec![0; result_size as usize];
-    let mut compiler = Compiler { label_map: HashMap::new(), parser: parser_result };
+    let mut compiler = Compiler { label_map: HashMap::new(), entry: 0, imports: Vec::new(), errors, warnings };
 
-    let mut filtered_program_with_pos: Vec<ParserExprWithPos> = Vec::with_capacity(program.len());
-    let mut work_on_storage_pos: u32 = 0;
-    for expr in &program {
-        filtered_program_with_pos.push(ParserExprWithPos { pos: work_on_storage_pos, expr: expr.clone() });
-        work_on_storage_pos += calc_expr_size(&expr.expr);
-    }
+    let code: Vec<std::ops::Range<u32>> = program_with_pos.iter()
+        .filter(|expr| get_expr_op_code(&expr.expr.expr).is_some())
+        .map(|expr| expr.pos..(expr.pos + size_of::<u32>() as u32))
+        .collect();
+    let stores: Vec<ParserExpr> = program_with_pos.iter()
+        .filter(|expr| matches!(get_expr_op_code(&expr.expr.expr), Some(OpCode::SWI) | Some(OpCode::SHI) | Some(OpCode::SBI)))
+        .map(|expr| expr.expr.clone())
+        .collect();
 
-    // The following loop tries to reduce filtered_program_with_pos as long as its possible
-    let mut old_len = filtered_program_with_pos.len();
-    loop {
-        filtered_program_with_pos.retain(|expr| {
-            let expr_result = compiler.compile_expr(&expr.expr, expr.pos);
-            return !(match expr_result {
-                    CompileExprResult::CompileToResult(expr_to_bytes) => {
-                    result.get_mut(expr.pos as usize..(expr.pos as usize + expr_to_bytes.len())).expect("Made sure").copy_from_slice(expr_to_bytes.as_slice());
-                    true
-                }
-                CompileExprResult::CompileToNone => true,
-                CompileExprResult::CompileToError => false
-            });
-        });
+    compiler.collect_symbols(&program_with_pos);
 
-        let new_len = filtered_program_with_pos.len();
-        if old_len == new_len {
-            break; // Cannot reduce (or 0)
+    // Pass 2: encode every expression with the final symbols
+    let mut failed: Vec<&ParserExprWithPos> = Vec::new();
+    for expr in &program_with_pos {
+        match compiler.compile_expr(&expr.expr) {
+            CompileExprResult::CompileToResult(expr_to_bytes) => {
+                result.get_mut(expr.pos as usize..(expr.pos as usize + expr_to_bytes.len())).expect("Made sure").copy_from_slice(expr_to_bytes.as_slice());
+            },
+            CompileExprResult::CompileToNone => {},
+            CompileExprResult::CompileToError => failed.push(expr),
         }
+    }
 
-        old_len = new_len;
+    for store in &stores {
+        compiler.check_store_to_code(store, &code);
     }
 
-    for expr in filtered_program_with_pos {
-        parser_result.errors.push(ParserError { pos: expr.expr.pos.clone(), err_type: ParserErrorType::CannotCompileExpression });
+    let entry = compiler.entry;
+
+    let symbols: HashSet<&String> = program.iter().filter_map(|expr| match &expr.expr {
+        Expr::Label(name) | Expr::Constant(name, _) | Expr::Import(name) => Some(name),
+        _ => None
+    }).collect();
+    for expr in failed {
+        log::debug!("Cannot compile {:?}", expr.expr.expr);
+        let undefined = get_expr_immediate(&expr.expr.expr)
+            .map(|imm| find_undefined_labels(imm, &symbols))
+            .unwrap_or_default();
+
+        if undefined.is_empty() {
+            compiler.errors.push(Diagnostic::cannot_compile_expression(expr.expr.pos.clone()));
+        }
+
+        for label in undefined {
+            let suggestion = suggest_label(&label, &symbols);
+            compiler.errors.push(Diagnostic::undefined_label(expr.expr.pos.clone(), &label, suggestion.as_deref()));
+        }
     }
 
 
-    if !parser_result.errors.is_empty() {
+    if !compiler.errors.is_empty() {
+        log::debug!("Compiling failed with {} errors", compiler.errors.len());
         return None;
     }
 
-    return Some(result);
+    let symbols = program_with_pos.iter().filter_map(|expr| match &expr.expr.expr {
+        Expr::Label(label) => Some((label.clone(), expr.pos)),
+        _ => None
+    }).collect();
+
+    let mappings = program_with_pos.iter()
+        .map(|expr| (expr, calc_expr_size(&expr.expr.expr)))
+        .filter(|(_, size)| *size > 0)
+        .map(|(expr, size)| SourceMapping { source: expr.expr.pos.clone(), address: expr.pos..(expr.pos + size) })
+        .collect();
+
+    let mut addresses = vec![0; program.len()];
+    for expr in &program_with_pos {
+        addresses[expr.index] = expr.pos;
+    }
+    let layout = ProgramLayout { addresses, symbols: compiler.label_map, isa_version, code, imports };
+
+    log::debug!("Compiled {} bytes", result.len());
+    Some((CompileOutput { binary: result, entry, symbols, mappings, imports: compiler.imports }, layout))
+}
+
+/// Addresses of all labels in the program
+pub fn get_symbols(parser_result: &ParserResult) -> HashMap<String, u32> {
+    get_symbols_with_options(parser_result, &CompileOptions::default())
+}
+
+/// Addresses of all labels in the program compiled with `options`
+pub fn get_symbols_with_options(parser_result: &ParserResult, options: &CompileOptions) -> HashMap<String, u32> {
+    let mut program = parser_result.program.clone();
+    filter_errors(&mut program);
+
+    let mut result = HashMap::new();
+    if let Some((program, _)) = layout(&program, options) {
+        for expr in program {
+            if let Expr::Label(label) = expr.expr.expr {
+                result.insert(label, expr.pos);
+            }
+        }
+    }
+
+    result
 }
 
 pub fn parse_and_compile_str(program: &'static str) -> Option<Vec<u8>> {
@@ -196,8 +828,12 @@ pub fn parse_and_compile_str(program: &'static str) -> Option<Vec<u8>> {
 
 #[cfg(test)]
 mod tests_compiler {
-    use super::{compile, parse_and_compile_str, utils, Register, OpCode};
-    use super::super::runtime;
+    use super::{compile, compile2, compile2_with_options, compile_with_options, compile_image, compile_output, get_symbols, get_symbols_with_options, parse_and_compile_str, immediate_range, immediate_fits, utils, Register, OpCode, IsaVersion, CompileOptions, HOST_SYSCALL_START};
+    use super::super::parser::{parse_str, ParserWarningType};
+    use super::super::diagnostic::codes;
+    use super::super::{runtime, timer};
+    use super::super::image::Image;
+    use super::super::builder::VmBuilder;
 
     #[test]
     fn cpy() {
@@ -264,6 +900,223 @@ mod tests_compiler {
         assert_eq!(Some([i32::to_le_bytes(20)].concat().to_vec()), result, "Operator precedence error");
     }
 
+    #[test]
+    fn store_space() {
+        let result = parse_and_compile_str(".i32 %after\n.space 6\n.zero 2\nafter:\n.i32 1");
+        assert_eq!(Some([&i32::to_le_bytes(12)[..], &[0; 8], &i32::to_le_bytes(1)].concat().to_vec()), result);
+
+        let result = parse_and_compile_str(".space 0");
+        assert_eq!(Some(Vec::new()), result);
+    }
+
+    #[test]
+    fn store_i8() {
+        let result = parse_and_compile_str(".i8 0xFF
+.i8 0 - 128
+.i8 %after
+after:");
+        assert_eq!(Some(vec![0xFF, 0x80, 3]), result);
+
+        for program in [".i8 256", ".i8 0 - 129"] {
+            let mut parser = parse_str(program);
+            assert_eq!(None, compile(&mut parser), "{}", program);
+            assert_eq!(vec![codes::IMMEDIATE_OUT_OF_RANGE], parser.errors.iter().map(|error| error.code).collect::<Vec<_>>(), "{}", program);
+        }
+    }
+
+    #[test]
+    fn constants() {
+        let result = parse_and_compile_str(".equ COUNT, 3\n.equ LENGTH, %end - %start\nstart:\nli $r0, %COUNT * 2\n.i32 %LENGTH\nend:");
+        assert_eq!(Some([
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 6).to_le_bytes(),
+            i32::to_le_bytes(8),
+        ].concat().to_vec()), result);
+
+        let mut parser = parse_str(".equ A, 1\n.equ A, 2\nB:\n.set B, 3\n.equ C, %missing");
+        assert_eq!(None, compile(&mut parser));
+        let errors: Vec<&str> = parser.errors.iter().map(|error| error.code).collect();
+        assert_eq!(vec![codes::SYMBOL_REDEFINED, codes::SYMBOL_REDEFINED, codes::UNDEFINED_LABEL], errors);
+
+        // Cyclic constants
+        let mut parser = parse_str(".equ A, %B\n.equ B, %A");
+        assert_eq!(None, compile(&mut parser));
+        let errors: Vec<&str> = parser.errors.iter().map(|error| error.code).collect();
+        assert_eq!(vec![codes::CANNOT_COMPILE_EXPRESSION, codes::CANNOT_COMPILE_EXPRESSION], errors);
+
+        // Constants depending on constants defined later
+        let result = parse_and_compile_str(".equ A, %B + 1\n.equ B, %C * 2\n.equ C, %end\nli $r0, %A\nend:");
+        assert_eq!(Some(utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 9).to_le_bytes().to_vec()), result);
+
+        let mut parser = parse_str(".equ A, 1 / 0\nli $r0, 4 / %A");
+        assert_eq!(None, compile(&mut parser));
+        assert_eq!(2, parser.errors.len());
+    }
+
+    #[test]
+    fn imports() {
+        let mut parser = parse_str(".import print_int\n.import draw_pixel\nsyscalli %draw_pixel\nsyscalli %print_int");
+        let output = compile_output(&mut parser, &CompileOptions::default()).expect("Expected");
+        assert_eq!(vec!["print_int".to_string(), "draw_pixel".to_string()], output.imports);
+        assert_eq!([
+            utils::create_instruction_immediate(OpCode::SYSCALLI, HOST_SYSCALL_START + 1).to_le_bytes(),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, HOST_SYSCALL_START).to_le_bytes(),
+        ].concat().to_vec(), output.binary);
+        assert!(output.symbols.is_empty());
+
+        let mut parser = parse_str(".import A\n.import A\nB:\n.import B\n.equ C, 1\n.import C");
+        assert_eq!(None, compile(&mut parser));
+        let errors: Vec<&str> = parser.errors.iter().map(|error| error.code).collect();
+        assert_eq!(vec![codes::SYMBOL_REDEFINED; 3], errors);
+
+        // Imports are syscall numbers, not addresses
+        for program in [".import foo\nji %foo", ".import foo\nli $r0, %foo + 4", ".import foo\n.i32 %foo", ".import foo\n.equ F, %foo\nsyscalli %F", ".import foo\n.entry %foo"] {
+            let mut parser = parse_str(program);
+            assert_eq!(None, compile(&mut parser), "{}", program);
+            let errors: Vec<&str> = parser.errors.iter().map(|error| error.code).collect();
+            assert_eq!(vec![codes::IMPORT_NOT_CALLED], errors, "{}", program);
+        }
+    }
+
+    #[test]
+    fn bitwise_constants() {
+        let result = parse_and_compile_str(".equ FLAGS, (1 << 3) | (1 << 5)\nli $r0, %FLAGS\nli $r1, ~%FLAGS & 0xFF\n.i32 %end >> 2 ^ 1\nend:");
+        assert_eq!(Some([
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 0x28).to_le_bytes(),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 0xD7).to_le_bytes(),
+            i32::to_le_bytes(2),
+        ].concat().to_vec()), result);
+
+        let mut parser = parse_str("li $r0, ~%missing");
+        assert_eq!(None, compile(&mut parser));
+        assert_eq!(Some(codes::UNDEFINED_LABEL), parser.errors.first().map(|error| error.code));
+    }
+
+    #[test]
+    fn output_symbols() {
+        let mut parser = parse_str(".equ SIZE, 4\nmain:\n  li $r1, %string\n  syscalli 0\n.data\nstring:\n  .space %SIZE\n.text\nend:\n  reti\n.entry main");
+        let output = compile_output(&mut parser, &CompileOptions { data_address: Some(32), ..CompileOptions::default() }).expect("Compiles");
+        assert_eq!(36, output.binary.len());
+        assert_eq!(0, output.entry);
+        assert_eq!(3, output.symbols.len());
+        assert_eq!(Some(&0), output.symbols.get("main"));
+        assert_eq!(Some(&8), output.symbols.get("end"));
+        assert_eq!(Some(&32), output.symbols.get("string"));
+        assert_eq!(&get_symbols_with_options(&parser, &CompileOptions { data_address: Some(32), ..CompileOptions::default() }), &output.symbols);
+    }
+
+    #[test]
+    fn warnings() {
+        fn warning_types(program: &'static str) -> Vec<ParserWarningType> {
+            let mut parser = parse_str(program);
+            assert!(compile(&mut parser).is_some());
+            parser.warnings.into_iter().map(|warning| warning.warn_type).collect()
+        }
+
+        assert_eq!(Vec::<ParserWarningType>::new(), warning_types("li $r0, 524287\nli $r0, -524288\naddi $r0, 1048575\nji 16777215\nswi $r0, %data\ndata: .i32 0"));
+        assert_eq!(vec![ParserWarningType::StoreToCode], warning_types("start: swi $r0, %start"));
+        assert_eq!(vec![ParserWarningType::StoreToCode], warning_types("sbi $r0, %code + 3\ncode: reti"));
+        assert_eq!(vec![ParserWarningType::LabelShadowsRegister], warning_types("sp: reti"));
+    }
+
+    #[test]
+    fn compile_result() {
+        let program = ".entry main\ndata: .i32 7\nmain: lwi $r0, %data\nswi $r0, %main\nsp: syscalli 0";
+        let parser = parse_str(program);
+        let result = compile2(&parser);
+        assert!(result.is_ok());
+        assert_eq!(parse_and_compile_str(program), result.bytes);
+        assert_eq!(4, result.entry);
+        assert_eq!(Some(&0), result.symbols.get("data"));
+        assert_eq!(vec!["W0002", "W0003"], result.diagnostics.iter().map(|diagnostic| diagnostic.code).collect::<Vec<&str>>());
+        // The parser result isn't changed
+        assert_eq!(1, parser.warnings.len());
+        assert_eq!(result, compile2(&parser));
+
+        let options = CompileOptions { strip_unused: true, ..CompileOptions::default() };
+        assert_eq!(Some(8), compile2_with_options(&parse_str("unused: .i32 1\nmain: reti\nreti"), &options).bytes.map(|bytes| bytes.len()));
+
+        // Errors of the parser and the compiler
+        let parser = parse_str("li $r0\nji %mian\nmain: reti");
+        let result = compile2(&parser);
+        assert!(!result.is_ok());
+        assert!(result.symbols.is_empty());
+        assert_eq!(vec!["E0008", "E0016"], result.diagnostics.iter().map(|diagnostic| diagnostic.code).collect::<Vec<&str>>());
+        assert_eq!(1, parser.errors.len());
+    }
+
+    #[test]
+    fn strip_unused_data() {
+        let options = CompileOptions { strip_unused: true, ..CompileOptions::default() };
+        let program = "li $r0, %used\nsyscalli 0\nunused: .i32 %table\ntable: .i32 1\n.i32 2\nused: .str \"Hi\"\nalias:\nalso: .space 8\ncode_label: reti";
+        let mut parser = parse_str(program);
+        let output = compile_output(&mut parser, &options).expect("Expected valid program");
+        assert_eq!([
+            &utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 8).to_le_bytes()[..],
+            &utils::create_instruction_immediate(OpCode::SYSCALLI, 0).to_le_bytes(),
+            b"Hi",
+            &utils::create_instruction(OpCode::RETI).to_le_bytes(),
+        ].concat(), output.binary);
+        let warnings: Vec<ParserWarningType> = parser.warnings.into_iter().map(|warning| warning.warn_type).collect();
+        assert_eq!(vec![
+            ParserWarningType::UnusedDataRemoved("alias".to_string()),
+            ParserWarningType::UnusedDataRemoved("unused".to_string()),
+            // Only referenced by removed data
+            ParserWarningType::UnusedDataRemoved("table".to_string()),
+        ], warnings);
+
+        // Without the option nothing is removed
+        assert_eq!(Some(34), parse_and_compile_str(program).map(|binary| binary.len()));
+
+        // The entry point and constants are references
+        let mut parser = parse_str(".equ SIZE, %end - %data\n.entry start\ndata: .i32 1\nend:\nstart: reti");
+        assert_eq!(Some(8), compile_output(&mut parser, &options).map(|output| output.binary.len()));
+        assert!(parser.warnings.is_empty());
+    }
+
+    #[test]
+    fn immediates_out_of_range() {
+        fn errors(program: &'static str) -> Vec<String> {
+            let mut parser = parse_str(program);
+            assert_eq!(None, compile(&mut parser));
+            parser.errors.iter().map(|error| error.describe(program)).collect()
+        }
+
+        assert_eq!(vec!["the immediate 2097152 (0x200000) is out of range at line 1 (the immediate of `li` must be between -524288 and 524287)"], errors("li $r0, 0x200000"));
+        assert_eq!(vec!["the immediate 524288 (0x80000) is out of range at line 1 (the immediate of `li` must be between -524288 and 524287)"], errors("li $r0, 524288"));
+        assert_eq!(vec!["the immediate 1048576 (0x100000) is out of range at line 1 (the immediate of `addi` must be between 0 and 1048575)"], errors("addi $r0, 1048576"));
+        assert_eq!(vec!["the immediate 4294967295 (0xFFFFFFFF) is out of range at line 1 (the immediate of `addi` must be between 0 and 1048575)"], errors("addi $r0, 0 - 1"));
+        assert_eq!(vec!["the immediate 4293918719 (0xFFEFFFFF) is out of range at line 1 (the immediate of `li` must be between -524288 and 524287)"], errors("li $r0, 0 - 1048577"));
+        assert_eq!(vec!["the immediate 16777216 (0x1000000) is out of range at line 1 (the immediate of `syscalli` must be between 0 and 16777215)"], errors("syscalli 16777216"));
+        // Labels are checked with their address
+        assert_eq!(vec!["the immediate 16777216 (0x1000000) is out of range at line 2 (the immediate of `ji` must be between 0 and 16777215)"], errors(".equ FAR, 0x01000000\nji %FAR"));
+
+        assert!(immediate_fits(OpCode::LI, 0xFFF80000));
+        assert!(!immediate_fits(OpCode::LI, 0xFFF7FFFF));
+        assert!(immediate_fits(OpCode::LI, 0x0007FFFF));
+        assert!(!immediate_fits(OpCode::LI, 0x00080000));
+        assert!(immediate_fits(OpCode::JI, 0x00FFFFFF));
+        assert_eq!(None, immediate_range(OpCode::ADD));
+    }
+
+    #[test]
+    fn undefined_labels() {
+        fn errors(program: &'static str) -> Vec<String> {
+            let mut parser = parse_str(program);
+            assert_eq!(None, compile(&mut parser));
+            parser.errors.iter().map(|error| error.describe(program)).collect()
+        }
+
+        assert_eq!(vec!["undefined label `loop` referenced at line 1"], errors("main: ji %loop"));
+        assert_eq!(vec!["undefined label `Loop` referenced at line 1 (did you mean `loop`?)"], errors("loop: ji %Loop"));
+        assert_eq!(vec!["undefined label `lop` referenced at line 1 (did you mean `loop`?)"], errors("loop: ji %lop"));
+        assert_eq!(vec!["undefined label `COUNTR` referenced at line 2 (did you mean `COUNT`?)"], errors(".equ COUNT, 1\nli $r0, %COUNTR"));
+        assert_eq!(vec![
+            "undefined label `a` referenced at line 1",
+            "undefined label `b` referenced at line 1",
+        ], errors("main: .i32 %a + (%b * %a) + %main"));
+        assert_eq!(vec!["undefined label `mian` referenced at line 3 (did you mean `main`?)"], errors("main:\n  li $r0, 1\n  jnzi $r0, %mian"));
+    }
+
     #[test]
     fn label_standalone() {
         let result = parse_and_compile_str("label:");
@@ -284,6 +1137,96 @@ mod tests_compiler {
                 utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R4).to_le_bytes()].concat().to_vec()), result);
     }
 
+    #[test]
+    fn isa_version() {
+        let options = CompileOptions { isa_version: IsaVersion::V1, ..CompileOptions::default() };
+        let mut parser = parse_str("li $r0, 1\nsyscalli 0");
+        assert!(compile_with_options(&mut parser, &options).is_some());
+
+        let mut parser = parse_str("li $r0, 1\naddi $r0, 1");
+        assert_eq!(None, compile_with_options(&mut parser, &options));
+        assert_eq!(1, parser.errors.len());
+        assert_eq!(codes::INSTRUCTION_NOT_IN_ISA, parser.errors[0].code);
+
+        let result = parse_and_compile_str(".isa 1\naddi $r0, 1");
+        assert_eq!(None, result);
+
+        let result = parse_and_compile_str(".isa 2\naddi $r0, 1");
+        assert_eq!(Some(utils::create_instruction_register_and_immediate(OpCode::ADDI, Register::R0, 1).to_le_bytes().to_vec()), result);
+    }
+
+    #[test]
+    fn symbols() {
+        let parser = parse_str("main: li $r0, 1\nloop: ji %loop\nstring: .str \"Hi\"\nend:");
+        let symbols = get_symbols(&parser);
+        assert_eq!(4, symbols.len());
+        assert_eq!(Some(&0), symbols.get("main"));
+        assert_eq!(Some(&4), symbols.get("loop"));
+        assert_eq!(Some(&8), symbols.get("string"));
+        assert_eq!(Some(&10), symbols.get("end"));
+    }
+
+    #[test]
+    fn sections() {
+        let program = ".data\ncount: .i32 7\n.text\nlwi $r0, %count\n.data\nname: .str \"Hi\"\n.text\nend: reti";
+        let result = parse_and_compile_str(program);
+        assert_eq!(Some([
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 8).to_le_bytes().to_vec(),
+            utils::create_instruction(OpCode::RETI).to_le_bytes().to_vec(),
+            i32::to_le_bytes(7).to_vec(),
+            b"Hi".to_vec(),
+        ].concat()), result);
+
+        let symbols = get_symbols(&parse_str(program));
+        assert_eq!(Some(&4), symbols.get("end"));
+        assert_eq!(Some(&8), symbols.get("count"));
+        assert_eq!(Some(&12), symbols.get("name"));
+
+        // Data at a fixed address
+        let options = CompileOptions { data_address: Some(16), ..CompileOptions::default() };
+        let mut parser = parse_str(program);
+        let result = compile_with_options(&mut parser, &options).expect("Expected program");
+        assert_eq!(22, result.len());
+        assert_eq!(utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 16).to_le_bytes(), result[0..4]);
+        assert_eq!(i32::to_le_bytes(7), result[16..20]);
+        assert_eq!(Some(&20), get_symbols_with_options(&parser, &options).get("name"));
+
+        let options = CompileOptions { data_address: Some(4), ..CompileOptions::default() };
+        let mut parser = parse_str(program);
+        assert_eq!(None, compile_with_options(&mut parser, &options));
+        assert_eq!(vec![codes::SECTION_OVERLAP], parser.errors.iter().map(|error| error.code).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn entry() {
+        let options = CompileOptions::default();
+        let mut parser = parse_str("li $r1, 1\nsyscalli 0\n.entry main\nmain: li $r1, 0\nsyscalli 0");
+        let image = compile_image(&mut parser, &options).expect("Expected program");
+        assert_eq!(8, image.entry);
+        assert_eq!(16, image.program.len());
+
+        let mut parser = parse_str("li $r1, 0\nsyscalli 0");
+        assert_eq!(0, compile_image(&mut parser, &options).expect("Expected program").entry);
+
+        let mut parser = parse_str(".entry main\n.entry main\nmain: reti");
+        assert_eq!(None, compile_image(&mut parser, &options));
+        assert_eq!(vec![codes::ENTRY_REDEFINED], parser.errors.iter().map(|error| error.code).collect::<Vec<_>>());
+
+        let mut parser = parse_str(".entry missing");
+        assert_eq!(None, compile_image(&mut parser, &options));
+        assert_eq!(vec![codes::UNDEFINED_LABEL], parser.errors.iter().map(|error| error.code).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn execute_entry() {
+        let mut parser = parse_str(".entry main\nli $r1, 1\nsyscalli 0\nmain: li $r1, 42\nsyscalli 0");
+        let image = Image::from_bytes(&compile_image(&mut parser, &CompileOptions::default()).expect("Expected program").to_bytes());
+        let interpreter = runtime::BinaryInterpreter::new_with_initial(&image.program).expect("Expected interpreter");
+        let mut stdout = Vec::new();
+        let mut vm = VmBuilder::new(interpreter, &mut stdout).entry(image.entry).build();
+        assert_eq!(42, vm.execute_first());
+    }
+
     #[test]
     fn execute_syscall_print() {
         const PROGRAM: &'static str = concat!(
@@ -303,4 +1246,89 @@ mod tests_compiler {
 
         assert_eq!(b"Hello, world!\n", buffer.as_slice());
     }
+
+    #[test]
+    fn execute_pseudo_instructions() {
+        const PROGRAM: &str = concat!(
+            "li $r1, 5\n",
+            "call %square\n",
+            "syscalli 0\n",
+            "square:\n", // $r1 = $r1 * $r1 + 1, $r6 is preserved
+            "push $r6\n",
+            "mov $r6, $r1\n",
+            "mul $r1, $r6\n",
+            "inc $r1\n",
+            "inc $r1\n",
+            "dec $r1\n",
+            "pop $r6\n",
+            "ret\n",
+        );
+        let compile_result = parse_and_compile_str(PROGRAM).expect("Should compile");
+
+        let interpreter = runtime::BinaryInterpreter::new_with_initial(&compile_result).unwrap();
+        let mut buffer = Vec::new();
+        let mut vm = runtime::BinaryVirtualMachine::new(interpreter, &mut buffer);
+        vm.write_register_value(Register::R6, 42);
+        assert_eq!(26, vm.execute_first());
+        assert_eq!(42, vm.read_register_value(Register::R6));
+        assert_eq!(runtime::BINARY_INTERPRETER_MEM_SIZE, vm.read_register_value(Register::SP));
+    }
+
+    #[test]
+    fn execute_threads() {
+        let compile_result = parse_and_compile_str(include_str!("../../interpreter/program/threads.asm")).expect("Should compile");
+
+        let mut interpreter = runtime::BinaryInterpreter::new_with_initial(&compile_result).unwrap();
+        assert!(interpreter.map_device(timer::TIMER_DEFAULT_ADDRESS, timer::TIMER_SIZE, Box::new(timer::Timer::new())));
+        let mut buffer = Vec::new();
+        let mut vm = runtime::BinaryVirtualMachine::new(interpreter, &mut buffer);
+        assert_eq!(0, vm.execute_first());
+        assert!(vm.stats().traps > 3);
+
+        // The tasks are preempted while working
+        assert_eq!(b"A\nB\nA\nB\nA\nB\n", buffer.as_slice());
+    }
+
+    #[test]
+    fn execute_trap_handler() {
+        let compile_result = parse_and_compile_str("
+	li $r0, %vectors
+	mts $r0, 0
+	li $r1, 1
+	divi $r1, 0
+	syscalli 0
+div_by_zero:
+	li $r1, 42
+	mfs $r0, 1
+	addi $r0, 4
+	mts $r0, 1
+	reti
+vectors:
+	.i32 0
+	.i32 0
+	.i32 0
+	.i32 0
+	.i32 0
+	.i32 0
+	.i32 %div_by_zero
+").expect("Should compile");
+
+        let interpreter = runtime::BinaryInterpreter::new_with_initial(&compile_result).unwrap();
+        let mut stdout = std::io::stdout();
+        let mut vm = runtime::BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(42, vm.execute_first());
+    }
+
+    #[test]
+    fn execute_harts() {
+        let compile_result = parse_and_compile_str(include_str!("../../interpreter/program/harts.asm")).expect("Should compile");
+
+        let interpreter = runtime::BinaryInterpreter::new_with_initial(&compile_result).unwrap();
+        let mut stdout = std::io::stdout();
+        let mut vm = runtime::BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(0, vm.execute_first());
+
+        vm.set_hart_count(2);
+        assert_eq!(200, vm.execute_first());
+    }
 }
@@ -0,0 +1,161 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Debug information: a line table mapping addresses of the compiled
+//! program back to the source
+
+use std::convert::TryInto;
+use std::ops::Range;
+#[cfg(feature = "assembler")]
+use super::compiler::CompileOutput;
+
+/// Source location of the bytes at `address`
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineEntry {
+    pub address: Range<u32>,
+    /// Starting with 1
+    pub line: u32,
+    /// Starting with 1 (in characters)
+    pub column: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LineTable {
+    /// Name of the source file
+    pub file: String,
+    /// Sorted by address
+    pub entries: Vec<LineEntry>,
+}
+
+impl LineTable {
+    /// Line table of the program `output` compiled from `source`
+    #[cfg(feature = "assembler")]
+    pub fn new(file: &str, source: &str, output: &CompileOutput) -> LineTable {
+        let line_starts: Vec<usize> = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(pos, _)| pos + 1))
+            .collect();
+        let mut entries: Vec<LineEntry> = output.mappings.iter().map(|mapping| {
+            let pos = mapping.source.start.min(source.len());
+            let line = line_starts.partition_point(|start| *start <= pos);
+            LineEntry {
+                address: mapping.address.clone(),
+                line: line as u32,
+                column: source[line_starts[line - 1]..pos].chars().count() as u32 + 1,
+            }
+        }).collect();
+        entries.sort_by_key(|entry| entry.address.start);
+
+        LineTable { file: file.to_string(), entries }
+    }
+
+    /// Entry containing the address
+    pub fn find(&self, address: u32) -> Option<&LineEntry> {
+        let index = self.entries.partition_point(|entry| entry.address.start <= address);
+        self.entries[..index].last().filter(|entry| entry.address.contains(&address))
+    }
+
+    /// Source location of the address, like `hello_world.asm:3:2`
+    pub fn location(&self, address: u32) -> Option<String> {
+        self.find(address).map(|entry| format!("{}:{}:{}", self.file, entry.line, entry.column))
+    }
+
+    /// Serialized table: length and bytes of the file name, number of
+    /// entries and the entries (start, end, line, column). Everything is
+    /// 32-bit little-endian.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(&(self.file.len() as u32).to_le_bytes());
+        result.extend_from_slice(self.file.as_bytes());
+        result.extend_from_slice(&(self.entries.len() as u32).to_le_bytes());
+        for entry in &self.entries {
+            for value in [entry.address.start, entry.address.end, entry.line, entry.column] {
+                result.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        result
+    }
+
+    /// Reads a table written by `to_bytes`. Returns None, if `data` isn't a
+    /// complete table.
+    pub fn from_bytes(data: &[u8]) -> Option<LineTable> {
+        let mut reader = Reader { data, pos: 0 };
+        let file_len = reader.u32()? as usize;
+        let file = String::from_utf8(reader.bytes(file_len)?.to_vec()).ok()?;
+        let count = reader.u32()?;
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            let address = reader.u32()?..reader.u32()?;
+            entries.push(LineEntry { address, line: reader.u32()?, column: reader.u32()? });
+        }
+
+        if reader.pos != data.len() {
+            return None;
+        }
+
+        Some(LineTable { file, entries })
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    pub(crate) data: &'a [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let result = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(result)
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+}
+
+#[cfg(all(test, feature = "assembler"))]
+mod tests {
+    use super::{LineEntry, LineTable};
+    use crate::compiler::{compile_output, CompileOptions};
+    use crate::parser::parse_str;
+
+    #[test]
+    fn line_table() {
+        let program = "main:\n\tli $r1, 1 ; syscalli 0\n\t.str \"ä\" ; .str \"B\"";
+        let mut parser = parse_str(program);
+        let output = compile_output(&mut parser, &CompileOptions::default()).expect("Compiles");
+        let table = LineTable::new("test.asm", program, &output);
+        assert_eq!(vec![
+            LineEntry { address: 0..4, line: 2, column: 2 },
+            LineEntry { address: 4..8, line: 2, column: 14 },
+            LineEntry { address: 8..10, line: 3, column: 7 },
+            LineEntry { address: 10..11, line: 3, column: 18 },
+        ], table.entries);
+
+        assert_eq!(Some("test.asm:2:14".to_string()), table.location(7));
+        assert_eq!(Some("test.asm:3:18".to_string()), table.location(10));
+        assert_eq!(None, table.location(11));
+
+        assert_eq!(Some(table.clone()), LineTable::from_bytes(&table.to_bytes()));
+        let bytes = table.to_bytes();
+        assert_eq!(None, LineTable::from_bytes(&bytes[..bytes.len() - 1]));
+    }
+}
@@ -0,0 +1,71 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::fmt;
+
+/// Memory-mapped device (like a UART or a timer). Offsets are relative to
+/// the start of the address window the device is mapped into (see
+/// `BinaryInterpreter::map_device`).
+pub trait Device: fmt::Debug {
+    /// Read the byte at `offset`. Returns None, if the offset is invalid.
+    fn read_u8(&self, offset: u32) -> Option<u8>;
+
+    /// Write the byte at `offset`. Returns false, if the offset is invalid
+    /// or the byte is read-only.
+    fn write_u8(&mut self, offset: u32, value: u8) -> bool;
+
+    /// Called after every executed instruction
+    fn tick(&mut self) {}
+
+    /// True, if the device requests an interrupt
+    fn interrupt_pending(&self) -> bool {
+        false
+    }
+}
+
+/// Start of the address range the standard devices are mapped into by
+/// default (inside of the range reachable by immediate loads/stores)
+pub const DEVICE_AREA_START: u32 = 0x000F0000;
+
+/// Device mapped into an address window
+#[derive(Debug)]
+pub(crate) struct DeviceWindow {
+    pub start: u32,
+    pub size: u32,
+    pub device: Box<dyn Device>,
+}
+
+/// Devices can't be compared, so only the address windows are
+impl PartialEq for DeviceWindow {
+    fn eq(&self, other: &DeviceWindow) -> bool {
+        self.start == other.start && self.size == other.size
+    }
+}
+
+impl DeviceWindow {
+    pub fn contains(&self, address: u32) -> bool {
+        address >= self.start && address - self.start < self.size
+    }
+
+    /// Check if the range of `len` bytes at `address` overlaps the window
+    pub fn overlaps(&self, address: u32, len: u32) -> bool {
+        (address as u64) < self.start as u64 + self.size as u64
+            && address as u64 + len as u64 > self.start as u64
+    }
+}
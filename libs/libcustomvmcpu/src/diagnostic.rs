@@ -0,0 +1,557 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Diagnostics of the parser and compiler in one format (for the command line
+//! tools, the JSON crate and editors)
+
+use std::env;
+use std::fmt;
+use std::io::IsTerminal;
+use std::ops::Range;
+use std::str::FromStr;
+use super::common::OpCode;
+use super::compiler::immediate_range;
+use super::lint::{Lint, LintType};
+use super::parser::{ParserWarning, ParserWarningType, Token, line_of};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Severity {
+    /// The program can't be compiled
+    Error,
+    /// Suspicious code, which still compiles
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+
+impl Severity {
+    fn color(&self) -> &'static str {
+        match self {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        }
+    }
+}
+
+/// When diagnostics are colored (`--color auto|always|never`)
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ColorChoice {
+    /// Colors, if the standard error is a terminal and `NO_COLOR` isn't set
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Whether diagnostics written to the standard error should be colored
+    pub fn use_color(self) -> bool {
+        match self {
+            ColorChoice::Auto => env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()) && std::io::stderr().is_terminal(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<ColorChoice, String> {
+        match value {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err("expected auto, always or never".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Stable, machine-readable code (like `E0016` or `W0002`)
+    pub code: &'static str,
+    /// Bytes of the program
+    pub span: Range<usize>,
+    pub message: String,
+    /// Additional hints (like a suggestion)
+    pub notes: Vec<String>,
+}
+
+/// Defines the constants in `codes` and the list of all codes (each code is
+/// written once)
+macro_rules! codes {
+    ($($(#[$attr:meta])* $name:ident = $code:literal;)*) => {
+        /// Codes of the diagnostics (errors, warnings and lints) of the
+        /// parser and the compiler
+        pub mod codes {
+            $($(#[$attr])* pub const $name: &str = $code;)*
+        }
+
+        /// Codes of all diagnostics
+        #[cfg(feature = "serde")]
+        const CODES: &[&str] = &[$(codes::$name),*];
+    };
+}
+
+codes! {
+    // Errors
+    /// The statement can't be parsed
+    CANNOT_PARSE = "E0001";
+    EXPECTED_REGISTER = "E0002";
+    EXPECTED_VALID_REGISTER = "E0003";
+    EXPECTED_IMMEDIATE = "E0004";
+    EXPECTED_VALID_IMMEDIATE = "E0005";
+    EXPECTED_LABEL = "E0006";
+    EXPECTED_NEW_LINE = "E0007";
+    EXPECTED_TOKEN = "E0008";
+    CANNOT_COMPILE_EXPRESSION = "E0009";
+    INVALID_ESCAPE_SEQUENCE = "E0010";
+    INVALID_ISA_VERSION = "E0011";
+    INSTRUCTION_NOT_IN_ISA = "E0012";
+    /// A constant has the name of another constant or label
+    SYMBOL_REDEFINED = "E0013";
+    /// The data section starts inside the code
+    SECTION_OVERLAP = "E0014";
+    /// The program has more than one entry point
+    ENTRY_REDEFINED = "E0015";
+    /// A label, which isn't defined, is referenced
+    UNDEFINED_LABEL = "E0016";
+    /// The value of an immediate doesn't fit into the instruction (see
+    /// `compiler::immediate_range`)
+    IMMEDIATE_OUT_OF_RANGE = "E0017";
+    /// A `\\x` escape sequence isn't ASCII (above `\\x7F`)
+    BYTE_ESCAPE_OUT_OF_RANGE = "E0018";
+    /// An import is used anywhere else than as the operand of `syscalli`
+    IMPORT_NOT_CALLED = "E0019";
+
+    // Warnings (see `ParserWarningType`)
+    LABEL_SHADOWS_REGISTER = "W0002";
+    STORE_TO_CODE = "W0003";
+    UNUSED_DATA_REMOVED = "W0004";
+
+    // Lints (see `LintType`)
+    UNUSED_LABEL = "L0001";
+    UNREACHABLE_CODE = "L0002";
+    DEAD_REGISTER_WRITE = "L0003";
+    JUMP_INTO_DATA = "L0004";
+}
+
+/// Deserialized `Diagnostic` with an owned code
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+struct DiagnosticData {
+    severity: Severity,
+    code: String,
+    span: Range<usize>,
+    message: String,
+    notes: Vec<String>,
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Diagnostic {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Diagnostic, D::Error> {
+        let data = DiagnosticData::deserialize(deserializer)?;
+        let code = CODES.iter().find(|code| **code == data.code)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown diagnostic code `{}`", data.code)))?;
+        Ok(Diagnostic { severity: data.severity, code, span: data.span, message: data.message, notes: data.notes })
+    }
+}
+
+impl Diagnostic {
+    fn error(code: &'static str, span: Range<usize>, message: impl Into<String>) -> Diagnostic {
+        Diagnostic { severity: Severity::Error, code, span, message: message.into(), notes: Vec::new() }
+    }
+
+    pub(crate) fn cannot_parse(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::CANNOT_PARSE, span, "cannot parse the statement")
+    }
+
+    pub(crate) fn expected_register(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::EXPECTED_REGISTER, span, "expected a register")
+    }
+
+    pub(crate) fn expected_valid_register(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::EXPECTED_VALID_REGISTER, span, "unknown register")
+    }
+
+    pub(crate) fn expected_immediate(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::EXPECTED_IMMEDIATE, span, "expected an immediate")
+    }
+
+    pub(crate) fn expected_valid_immediate(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::EXPECTED_VALID_IMMEDIATE, span, "invalid immediate")
+    }
+
+    pub(crate) fn expected_label(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::EXPECTED_LABEL, span, "expected a label")
+    }
+
+    pub(crate) fn expected_new_line(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::EXPECTED_NEW_LINE, span, "expected the end of the statement")
+    }
+
+    pub(crate) fn expected_token(span: Range<usize>, tok: &Token) -> Diagnostic {
+        Diagnostic::error(codes::EXPECTED_TOKEN, span, format!("expected {}", describe_token(tok)))
+    }
+
+    pub(crate) fn cannot_compile_expression(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::CANNOT_COMPILE_EXPRESSION, span, "cannot compile the expression")
+    }
+
+    pub(crate) fn invalid_escape_sequence(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::INVALID_ESCAPE_SEQUENCE, span, "invalid escape sequence")
+    }
+
+    pub(crate) fn byte_escape_out_of_range(span: Range<usize>, value: u8) -> Diagnostic {
+        let mut result = Diagnostic::error(codes::BYTE_ESCAPE_OUT_OF_RANGE, span, format!("the escape sequence `\\x{:02X}` is out of range", value));
+        result.notes.push(format!("`\\x` escapes are ASCII (`\\x00` to `\\x7F`), the character U+00{:02X} is `\\u{{{:X}}}`", value, value));
+        result
+    }
+
+    pub(crate) fn invalid_isa_version(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::INVALID_ISA_VERSION, span, "invalid instruction set version")
+    }
+
+    pub(crate) fn instruction_not_in_isa(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::INSTRUCTION_NOT_IN_ISA, span, "the instruction isn't part of the selected instruction set version")
+    }
+
+    pub(crate) fn symbol_redefined(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::SYMBOL_REDEFINED, span, "the symbol is already defined")
+    }
+
+    pub(crate) fn section_overlap(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::SECTION_OVERLAP, span, "the data section starts inside the code")
+    }
+
+    pub(crate) fn entry_redefined(span: Range<usize>) -> Diagnostic {
+        Diagnostic::error(codes::ENTRY_REDEFINED, span, "the entry point is already defined")
+    }
+
+    /// `label` isn't defined, `suggestion` is a similar label
+    pub(crate) fn undefined_label(span: Range<usize>, label: &str, suggestion: Option<&str>) -> Diagnostic {
+        let mut result = Diagnostic::error(codes::UNDEFINED_LABEL, span, format!("undefined label `{}`", label));
+        if let Some(suggestion) = suggestion {
+            result.notes.push(format!("did you mean `{}`?", suggestion));
+        }
+
+        result
+    }
+
+    /// The import `name` is used by another statement than `syscalli`
+    pub(crate) fn import_not_called(span: Range<usize>, name: &str) -> Diagnostic {
+        let mut result = Diagnostic::error(codes::IMPORT_NOT_CALLED, span, format!("the import `{}` is used outside of `syscalli`", name));
+        result.notes.push(format!("imports are called with `syscalli %{}`", name));
+        result
+    }
+
+    pub(crate) fn immediate_out_of_range(span: Range<usize>, op_code: OpCode, imm: u32) -> Diagnostic {
+        let mut result = Diagnostic::error(codes::IMMEDIATE_OUT_OF_RANGE, span, format!("the immediate {} (0x{:X}) is out of range", imm, imm));
+        if let Some(range) = immediate_range(op_code) {
+            result.notes.push(format!("the immediate of `{}` must be between {} and {}", op_code, range.start(), range.end()));
+        }
+
+        result
+    }
+
+    pub(crate) fn byte_out_of_range(span: Range<usize>, imm: u32) -> Diagnostic {
+        let mut result = Diagnostic::error(codes::IMMEDIATE_OUT_OF_RANGE, span, format!("the immediate {} (0x{:X}) is out of range", imm, imm));
+        result.notes.push("the value of `.i8` must be between -128 and 255".to_string());
+
+        result
+    }
+
+    /// The message with its line in `source` and the notes in parentheses,
+    /// like ``undefined label `mian` referenced at line 3 (did you mean
+    /// `main`?)``
+    pub fn describe(&self, source: &str) -> String {
+        let at = if self.code == codes::UNDEFINED_LABEL { "referenced at" } else { "at" };
+        let mut result = format!("{} {} line {}", self.message, at, self.line(source));
+        if !self.notes.is_empty() {
+            result += &format!(" ({})", self.notes.join("; "));
+        }
+
+        result
+    }
+
+    /// Line (starting with 1) of the diagnostic in `source`
+    pub fn line(&self, source: &str) -> usize {
+        line_of(source, self.span.start)
+    }
+
+    /// Column (in characters, starting with 1) of the diagnostic in `source`
+    pub fn column(&self, source: &str) -> usize {
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |pos| pos + 1);
+        source[line_start..start].chars().count() + 1
+    }
+
+    /// The source code of the span (empty, if it's outside of `source`)
+    pub fn excerpt<'a>(&self, source: &'a str) -> &'a str {
+        source.get(self.span.clone()).unwrap_or("")
+    }
+
+    /// Formats the diagnostic for the command line, like
+    /// `error[E0016] at line 3: undefined label `mian``, every note on a line
+    /// of its own
+    pub fn render(&self, source: &str) -> String {
+        let mut result = format!("{}[{}] at line {}: {}", self.severity, self.code, self.line(source), self.message);
+        for note in &self.notes {
+            result += &format!("\n  note: {}", note);
+        }
+
+        result
+    }
+
+    /// Formats the diagnostic with the source line and the span underlined,
+    /// like
+    ///
+    /// ```text
+    /// error[E0016]: undefined label `mian`
+    ///  --> line 3, column 3
+    ///   |
+    /// 3 |   jnzi $r0, %mian
+    ///   |   ^^^^^^^^^^^^^^^
+    ///   = note: did you mean `main`?
+    /// ```
+    ///
+    /// With `color` the output contains ANSI escape sequences.
+    pub fn render_pretty(&self, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| if color { format!("{}{}{}", style, text, RESET) } else { text.to_string() };
+
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |pos| pos + 1);
+        let line_end = source[start..].find('\n').map_or(source.len(), |pos| start + pos);
+        let line = &source[line_start..line_end];
+        let prefix = &source[line_start..start];
+        let underlined = self.span.end.min(line_end).saturating_sub(start);
+        let underline_len = source[start..start + underlined].chars().count().max(1);
+        // Keep tabs, so the underline lines up with the source line
+        let indent: String = prefix.chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+
+        let line_number = self.line(source).to_string();
+        let gutter = " ".repeat(line_number.len());
+        let bar = paint(BLUE, "|");
+
+        let mut result = format!("{}{}\n",
+            paint(self.severity.color(), &format!("{}[{}]", self.severity, self.code)),
+            paint(BOLD, &format!(": {}", self.message)));
+        result += &format!("{}{} line {}, column {}\n", gutter, paint(BLUE, "-->"), line_number, self.column(source));
+        result += &format!("{} {}\n", gutter, bar);
+        result += &format!("{} {} {}\n", paint(BLUE, &line_number), bar, line);
+        result += &format!("{} {} {}{}", gutter, bar, indent, paint(self.severity.color(), &"^".repeat(underline_len)));
+        for note in &self.notes {
+            result += &format!("\n{} {} {}: {}", gutter, paint(BLUE, "="), paint(BOLD, "note"), note);
+        }
+
+        result
+    }
+}
+
+fn describe_token(tok: &Token) -> String {
+    match tok {
+        Token::Comma => "`,`".to_string(),
+        Token::OpCloseBracket => "`)`".to_string(),
+        tok => format!("{:?}", tok),
+    }
+}
+
+impl ParserWarningType {
+    /// Stable code of the warning
+    pub fn code(&self) -> &'static str {
+        use ParserWarningType::*;
+        match self {
+            LabelShadowsRegister => codes::LABEL_SHADOWS_REGISTER,
+            StoreToCode => codes::STORE_TO_CODE,
+            UnusedDataRemoved(_) => codes::UNUSED_DATA_REMOVED,
+        }
+    }
+}
+
+impl LintType {
+    /// Stable code of the lint
+    pub fn code(&self) -> &'static str {
+        use LintType::*;
+        match self {
+            UnusedLabel(_) => codes::UNUSED_LABEL,
+            UnreachableCode => codes::UNREACHABLE_CODE,
+            DeadRegisterWrite(_) => codes::DEAD_REGISTER_WRITE,
+            JumpIntoData(_) => codes::JUMP_INTO_DATA,
+        }
+    }
+}
+
+impl From<&ParserWarning> for Diagnostic {
+    fn from(warning: &ParserWarning) -> Diagnostic {
+        use ParserWarningType::*;
+        let message = match &warning.warn_type {
+            LabelShadowsRegister => "the label has the name of a register".to_string(),
+            StoreToCode => "the store writes into the code of the program".to_string(),
+            UnusedDataRemoved(label) => format!("the data of the unused label `{}` is removed", label),
+        };
+
+        Diagnostic { severity: Severity::Warning, code: warning.warn_type.code(), span: warning.pos.clone(), message, notes: Vec::new() }
+    }
+}
+
+impl From<&Lint> for Diagnostic {
+    fn from(lint: &Lint) -> Diagnostic {
+        use LintType::*;
+        let message = match &lint.lint_type {
+            UnusedLabel(label) => format!("the label `{}` is never used", label),
+            UnreachableCode => "the instruction is never executed".to_string(),
+            DeadRegisterWrite(reg) => format!("the value written to `{}` is never read", reg),
+            JumpIntoData(label) => format!("the jump target `{}` is data", label),
+        };
+
+        Diagnostic { severity: Severity::Warning, code: lint.lint_type.code(), span: lint.pos.clone(), message, notes: Vec::new() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorChoice, Diagnostic, Severity};
+    use crate::compiler::compile;
+    use crate::lint::{lint, LintOptions};
+    use crate::parser::parse_str;
+
+    #[test]
+    fn undefined_label() {
+        let program = "main:\n  li $r0, 1\n  jnzi $r0, %mian";
+        let mut parser = parse_str(program);
+        assert_eq!(None, compile(&mut parser));
+        let diagnostics = parser.diagnostics();
+        assert_eq!(vec![Diagnostic {
+            severity: Severity::Error,
+            code: "E0016",
+            span: 20..35,
+            message: "undefined label `mian`".to_string(),
+            notes: vec!["did you mean `main`?".to_string()],
+        }], diagnostics);
+        assert_eq!(3, diagnostics[0].line(program));
+        assert_eq!(3, diagnostics[0].column(program));
+        assert_eq!("jnzi $r0, %mian", diagnostics[0].excerpt(program));
+        assert_eq!("error[E0016] at line 3: undefined label `mian`\n  note: did you mean `main`?", diagnostics[0].render(program));
+    }
+
+    #[test]
+    fn errors_and_warnings() {
+        let program = "sp: li $r0\naddi $r0, 1048576";
+        let mut parser = parse_str(program);
+        assert_eq!(None, compile(&mut parser));
+        let rendered: Vec<String> = parser.diagnostics().iter().map(|diagnostic| diagnostic.render(program)).collect();
+        assert_eq!(vec![
+            "error[E0008] at line 1: expected `,`".to_string(),
+            "error[E0017] at line 2: the immediate 1048576 (0x100000) is out of range\n  note: the immediate of `addi` must be between 0 and 1048575".to_string(),
+            "warning[W0002] at line 1: the label has the name of a register".to_string(),
+        ], rendered);
+    }
+
+    #[test]
+    fn byte_escape() {
+        let program = ".str \"\\xE9\"";
+        let rendered: Vec<String> = parse_str(program).diagnostics().iter().map(|diagnostic| diagnostic.render(program)).collect();
+        assert_eq!(vec![
+            "error[E0018] at line 1: the escape sequence `\\xE9` is out of range\n  note: `\\x` escapes are ASCII (`\\x00` to `\\x7F`), the character U+00E9 is `\\u{E9}`".to_string(),
+        ], rendered);
+    }
+
+    #[test]
+    fn lints() {
+        let program = "li $r0, 1\nli $r0, 2\nsyscalli 0";
+        let parser = parse_str(program);
+        let rendered: Vec<String> = lint(&parser.program, &LintOptions::default()).iter().map(|lint| Diagnostic::from(lint).render(program)).collect();
+        assert_eq!(vec!["warning[L0003] at line 1: the value written to `$r0` is never read".to_string()], rendered);
+    }
+
+    #[test]
+    fn render_pretty() {
+        let program = "main:\n  li $r0, 1\n  jnzi $r0, %mian";
+        let mut parser = parse_str(program);
+        assert_eq!(None, compile(&mut parser));
+        let diagnostics = parser.diagnostics();
+        assert_eq!(
+            "error[E0016]: undefined label `mian`\n \
+             --> line 3, column 3\n  \
+               |\n\
+             3 |   jnzi $r0, %mian\n  \
+               |   ^^^^^^^^^^^^^^^\n  \
+               = note: did you mean `main`?",
+            diagnostics[0].render_pretty(program, false));
+        assert_eq!(
+            "\x1b[1;31merror[E0016]\x1b[0m\x1b[1m: undefined label `mian`\x1b[0m\n \
+             \x1b[1;34m-->\x1b[0m line 3, column 3\n  \
+               \x1b[1;34m|\x1b[0m\n\
+             \x1b[1;34m3\x1b[0m \x1b[1;34m|\x1b[0m   jnzi $r0, %mian\n  \
+               \x1b[1;34m|\x1b[0m   \x1b[1;31m^^^^^^^^^^^^^^^\x1b[0m\n  \
+               \x1b[1;34m=\x1b[0m \x1b[1mnote\x1b[0m: did you mean `main`?",
+            diagnostics[0].render_pretty(program, true));
+
+        // Tabs are kept, empty spans and spans at the end are underlined
+        let program = "\tli $r0\n";
+        let mut parser = parse_str(program);
+        assert_eq!(None, compile(&mut parser));
+        let rendered = parser.diagnostics()[0].render_pretty(program, false);
+        assert!(rendered.ends_with("1 | \tli $r0\n  | \t      ^"), "{}", rendered);
+    }
+
+    #[test]
+    fn color_choice() {
+        assert_eq!(Ok(ColorChoice::Auto), "auto".parse());
+        assert_eq!(Ok(ColorChoice::Never), "never".parse());
+        assert!("yes".parse::<ColorChoice>().is_err());
+        assert!(ColorChoice::Always.use_color());
+        assert!(!ColorChoice::Never.use_color());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize() {
+        let program = "li $r0\nli $r1, 5000000\nji %mian";
+        let mut parser = parse_str(program);
+        assert_eq!(None, compile(&mut parser));
+        let diagnostics = parser.diagnostics();
+        assert_eq!(vec!["E0008", "E0017", "E0016"], diagnostics.iter().map(|diagnostic| diagnostic.code).collect::<Vec<&str>>());
+
+        let bytes = bincode::serialize(&diagnostics).expect("Expected serializable diagnostics");
+        let deserialized: Vec<Diagnostic> = bincode::deserialize(&bytes).expect("Expected deserializable diagnostics");
+        assert_eq!(diagnostics, deserialized);
+
+        // Only known codes
+        let unknown = Diagnostic { code: "X0001", ..diagnostics[0].clone() };
+        assert!(bincode::deserialize::<Diagnostic>(&bincode::serialize(&unknown).expect("Expected serializable diagnostic")).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Differential testing: runs the same program on two virtual machines in
+//! lockstep (different `Interpreter` implementations or configurations, like
+//! with and without the decode cache) and reports the first difference.
+
+use std::fmt;
+use num_traits::FromPrimitive;
+use super::common::{Register, LAST_REGISTER};
+use super::disassembler::disassemble_instruction;
+use super::runtime::{BinaryInterpreter, BinaryVirtualMachine, Interpreter, VirtualMachine};
+
+/// First difference between two virtual machines running the same program
+#[derive(PartialEq, Debug, Clone)]
+pub enum Divergence {
+    /// The registers differed after executing the instruction at `ip` (the
+    /// instruction word, if the address is inside of the memory)
+    Registers {
+        step: u64,
+        ip: u32,
+        instruction: Option<u32>,
+        /// The registers, which differ, with the values of both machines
+        registers: Vec<(Register, u32, u32)>,
+    },
+    /// Only one machine stopped after executing the instruction at `ip`
+    Stopped {
+        step: u64,
+        ip: u32,
+        instruction: Option<u32>,
+        left_running: bool,
+    },
+    /// The memory differed at the end (the first differing byte; None is
+    /// outside of the memory of the machine)
+    Memory {
+        address: u32,
+        left: Option<u8>,
+        right: Option<u8>,
+    },
+}
+
+fn format_instruction(ip: u32, instruction: Option<u32>) -> String {
+    match instruction {
+        Some(instruction) => {
+            let mnemonic = disassemble_instruction(instruction).unwrap_or_else(|| format!("<invalid 0x{:08x}>", instruction));
+            format!("0x{:08x}: {}", ip, mnemonic)
+        },
+        None => format!("0x{:08x}", ip),
+    }
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergence::Registers { step, ip, instruction, registers } => {
+                write!(f, "registers differ after step {} ({}):", step, format_instruction(*ip, *instruction))?;
+                for (reg, left, right) in registers {
+                    write!(f, " {}={}/{}", reg, left, right)?;
+                }
+
+                Ok(())
+            },
+            Divergence::Stopped { step, ip, instruction, left_running } => {
+                let stopped = if *left_running { "right" } else { "left" };
+                write!(f, "only the {} machine stopped after step {} ({})", stopped, step, format_instruction(*ip, *instruction))
+            },
+            Divergence::Memory { address, left, right } => {
+                let format_byte = |byte: &Option<u8>| byte.map_or_else(|| "-".to_string(), |byte| format!("{:02X}", byte));
+                write!(f, "memory differs at 0x{:08x}: {}/{}", address, format_byte(left), format_byte(right))
+            },
+        }
+    }
+}
+
+fn read_instruction<I: Interpreter>(vm: &VirtualMachine<I>, ip: u32) -> Option<u32> {
+    let bytes = vm.read_memory(ip..ip.checked_add(4)?)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// Starts both machines at `entry` and executes at most `max_steps`
+/// instructions on each, comparing the registers after every instruction
+/// and the memory at the end. Returns the number of executed instructions or
+/// the first difference.
+pub fn compare<A: Interpreter, B: Interpreter>(left: &mut VirtualMachine<A>, right: &mut VirtualMachine<B>, entry: u32, max_steps: u64) -> Result<u64, Divergence> {
+    left.start(entry);
+    right.start(entry);
+
+    let mut steps = 0;
+    while steps < max_steps {
+        let ip = left.read_register_value(Register::IP);
+        let instruction = read_instruction(left, ip);
+        let (left_running, right_running) = (left.step(), right.step());
+        steps += 1;
+
+        let registers: Vec<(Register, u32, u32)> = (0..=LAST_REGISTER as u8)
+            .filter_map(Register::from_u8)
+            .map(|reg| (reg, left.read_register_value(reg), right.read_register_value(reg)))
+            .filter(|(_, left, right)| left != right)
+            .collect();
+        if !registers.is_empty() {
+            return Err(Divergence::Registers { step: steps, ip, instruction, registers });
+        }
+        if left_running != right_running {
+            return Err(Divergence::Stopped { step: steps, ip, instruction, left_running });
+        }
+        if !left_running {
+            break;
+        }
+    }
+
+    let (left_memory, right_memory) = (left.snapshot().memory().to_vec(), right.snapshot().memory().to_vec());
+    if left_memory != right_memory {
+        let address = (0..left_memory.len().max(right_memory.len()))
+            .find(|i| left_memory.get(*i) != right_memory.get(*i))
+            .expect("Different memories differ at an address");
+        return Err(Divergence::Memory { address: address as u32, left: left_memory.get(address).copied(), right: right_memory.get(address).copied() });
+    }
+
+    Ok(steps)
+}
+
+/// Compares running `program` (loaded at address 0) with and without the
+/// decode cache (see `compare`)
+pub fn compare_decode_cache(program: &[u8], entry: u32, max_steps: u64) -> Result<u64, Divergence> {
+    let create = || {
+        let mut interpreter = BinaryInterpreter::new();
+        assert!(interpreter.load_program(program), "Expected the program to fit into the memory");
+        interpreter
+    };
+    let (mut left_stdout, mut right_stdout) = (Vec::new(), Vec::new());
+    let mut left = BinaryVirtualMachine::new(create(), &mut left_stdout);
+    let mut right = BinaryVirtualMachine::new(create(), &mut right_stdout);
+    right.set_decode_cache(true);
+
+    compare(&mut left, &mut right, entry, max_steps)
+}
+
+#[cfg(all(test, feature = "assembler"))]
+mod tests {
+    use super::{compare, compare_decode_cache, Divergence};
+    use crate::common::Register;
+    use crate::compiler::compile;
+    use crate::parser::parse_str;
+    use crate::runtime::{BinaryInterpreter, BinaryVirtualMachine, BranchMode, Interpreter};
+    use crate::testing::InstructionGenerator;
+
+    #[test]
+    fn decode_cache() {
+        let program = compile(&mut parse_str("li $r0, 3\nloop:\nsubi $r0, 1\nswi $r0, %data\njnzi $r0, %loop\nli $r1, 0\nsyscalli 0\ndata:\n.i32 7"))
+            .expect("Expected valid program");
+        assert_eq!(Ok(12), compare_decode_cache(&program, 0, 1000));
+
+        let mut generator = InstructionGenerator::new(6);
+        for _ in 0..100 {
+            let program: Vec<u8> = generator.program(64).iter().flat_map(|word| word.to_le_bytes()).collect();
+            let result = compare_decode_cache(&program, 0, 1000);
+            assert!(result.is_ok(), "{}", result.unwrap_err());
+        }
+    }
+
+    #[test]
+    fn divergence() {
+        let program = compile(&mut parse_str("li $r0, 1\nji %end\nli $r0, 2\nend:\nli $r1, 0\nsyscalli 0")).expect("Expected valid program");
+        let create = || {
+            let mut interpreter = BinaryInterpreter::new();
+            assert!(interpreter.load_program(&program));
+            interpreter
+        };
+        let (mut left_stdout, mut right_stdout) = (Vec::new(), Vec::new());
+        let mut left = BinaryVirtualMachine::new(create(), &mut left_stdout);
+        let mut right = BinaryVirtualMachine::new(create(), &mut right_stdout);
+        right.set_branch_mode(BranchMode::DelaySlot);
+
+        // The delay slot after `ji` is executed before the jump
+        let divergence = compare(&mut left, &mut right, 0, 100).expect_err("Expected a divergence");
+        assert_eq!(Divergence::Registers { step: 2, ip: 4, instruction: Some(0x1100000C), registers: vec![(Register::IP, 12, 8)] }, divergence);
+        assert_eq!("registers differ after step 2 (0x00000004: ji 12): $ip=12/8", divergence.to_string());
+
+        let mut memory = program.clone();
+        memory[0] ^= 1;
+        let mut interpreter = BinaryInterpreter::new();
+        assert!(interpreter.load_program(&memory));
+        let mut left = BinaryVirtualMachine::new(create(), &mut left_stdout);
+        let mut right = BinaryVirtualMachine::new(interpreter, &mut right_stdout);
+        assert_eq!(Err(Divergence::Registers { step: 1, ip: 0, instruction: Some(0x07000001), registers: vec![(Register::R0, 1, 0)] }),
+            compare(&mut left, &mut right, 0, 100));
+
+        // Data, which isn't read
+        let mut memory = program.clone();
+        memory.extend_from_slice(&[1, 2]);
+        let mut interpreter = BinaryInterpreter::new();
+        assert!(interpreter.load_program(&memory));
+        let mut left = BinaryVirtualMachine::new(create(), &mut left_stdout);
+        let mut right = BinaryVirtualMachine::new(interpreter, &mut right_stdout);
+        let divergence = compare(&mut left, &mut right, 0, 100).expect_err("Expected a divergence");
+        assert_eq!(Divergence::Memory { address: 20, left: Some(0), right: Some(1) }, divergence);
+        assert_eq!("memory differs at 0x00000014: 00/01", divergence.to_string());
+    }
+}
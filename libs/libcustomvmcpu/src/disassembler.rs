@@ -0,0 +1,372 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::{BTreeSet, HashMap};
+use num_traits::FromPrimitive;
+use super::common::{OpCode, Register, InstructionParseType, get_instruction_parse_type};
+use super::runtime::utils;
+use super::symbols::MemoryMap;
+
+fn get_register(value: u32) -> Option<String> {
+    Register::from_u32(value).map(|reg| reg.to_string())
+}
+
+/// Disassembles a single instruction into its assembler representation
+/// (like `li $r0, 10`). Returns None, if the opcode or a register is invalid.
+pub fn disassemble_instruction(instruction: u32) -> Option<String> {
+    let op_code = OpCode::from_u32(instruction >> (3 * 8))?;
+    let reg0 = (instruction & 0x00F00000) >> (2 * 8 + 4);
+    let reg1 = instruction & 0x0000000F;
+
+    let operands = match get_instruction_parse_type(op_code) {
+        InstructionParseType::Register => get_register(reg1)?,
+        InstructionParseType::Immediate => (instruction & 0x00FFFFFF).to_string(),
+        InstructionParseType::TwoRegisters => format!("{}, {}", get_register(reg0)?, get_register(reg1)?),
+        InstructionParseType::RegisterAndImmediate => format!("{}, {}", get_register(reg0)?, format_immediate(op_code, instruction & 0x000FFFFF)),
+        InstructionParseType::TwoRegistersAndImmediate => format!("{}, {}, {}",
+            get_register(reg0)?,
+            get_register((instruction & 0x000F0000) >> (2 * 8))?,
+            instruction & 0x0000FFFF),
+        InstructionParseType::NoOperands => return Some(op_code.to_string()),
+    };
+
+    Some(format!("{} {}", op_code, operands))
+}
+
+/// A decoded word of a program
+enum Word {
+    Instruction(OpCode, Vec<Register>, Option<u32>),
+    Data(u32),
+}
+
+/// Decodes `word`. Words, which wouldn't be assembled to the same value
+/// again (invalid opcodes, set unused bits), are data.
+fn decode_word(word: u32) -> Word {
+    let decode = || -> Option<Word> {
+        let op_code = OpCode::from_u32(word >> (3 * 8))?;
+        let reg0 = || Register::from_u32((word & 0x00F00000) >> (2 * 8 + 4));
+        let reg1 = || Register::from_u32(word & 0x0000000F);
+        let (encoded, registers, imm) = match get_instruction_parse_type(op_code) {
+            InstructionParseType::NoOperands => (utils::create_instruction(op_code), vec![], None),
+            InstructionParseType::Register => {
+                let reg = reg1()?;
+                (utils::create_instruction_register(op_code, reg), vec![reg], None)
+            },
+            InstructionParseType::Immediate => {
+                let imm = word & 0x00FFFFFF;
+                (utils::create_instruction_immediate(op_code, imm), vec![], Some(imm))
+            },
+            InstructionParseType::TwoRegisters => {
+                let (reg0, reg1) = (reg0()?, reg1()?);
+                (utils::create_instruction_two_registers(op_code, reg0, reg1), vec![reg0, reg1], None)
+            },
+            InstructionParseType::RegisterAndImmediate => {
+                let (reg, imm) = (reg0()?, word & 0x000FFFFF);
+                (utils::create_instruction_register_and_immediate(op_code, reg, imm), vec![reg], Some(imm))
+            },
+            // No instruction of this type can be assembled
+            InstructionParseType::TwoRegistersAndImmediate => return None,
+        };
+
+        if encoded == word {
+            Some(Word::Instruction(op_code, registers, imm))
+        }
+        else {
+            None
+        }
+    };
+
+    decode().unwrap_or(Word::Data(word))
+}
+
+/// Formats the immediate of `op_code`: signed for `li`, which sign-extends
+/// its 20-bit immediate
+fn format_immediate(op_code: OpCode, imm: u32) -> String {
+    if op_code == OpCode::LI && imm & 0x00080000 != 0 {
+        // Two's complement
+        ((imm | 0xFFF00000) as i32).to_string()
+    }
+    else {
+        imm.to_string()
+    }
+}
+
+/// Returns the target of a jump with an immediate address
+fn jump_target(op_code: OpCode, imm: Option<u32>) -> Option<u32> {
+    match op_code {
+        OpCode::JI | OpCode::JIL | OpCode::JZI | OpCode::JNZI | OpCode::JLZI | OpCode::JGZI => imm,
+        _ => None,
+    }
+}
+
+fn label_name(addr: u32) -> String {
+    format!("loc_{:x}", addr)
+}
+
+/// Formats the trailing bytes of a program as `.str` directive or, if they
+/// aren't valid UTF-8, as one `.i8` directive for each byte
+fn format_trailing_bytes(bytes: &[u8]) -> Vec<String> {
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(_) => return bytes.iter().map(|byte| format!(".i8 0x{:02X}", byte)).collect(),
+    };
+
+    let mut result = String::from(".str \"");
+    for c in text.chars() {
+        match c {
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            '\0' => result.push_str("\\0"),
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if c.is_ascii_control() => result.push_str(&format!("\\x{:02X}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+
+    vec![result]
+}
+
+/// Disassembles a whole program into assembler source, which compiles to
+/// the same binary again. Targets of jumps with immediate addresses get
+/// labels (`loc_<address>`), words which aren't valid instructions are
+/// emitted as `.i32`, the trailing bytes (less than a word) as `.str` or
+/// `.i8`.
+pub fn disassemble_program(program: &[u8], entry: u32) -> String {
+    let words: Vec<Word> = program.chunks_exact(4)
+        .map(|chunk| decode_word(u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])))
+        .collect();
+    let code_size = (words.len() * 4) as u32;
+    let is_label_target = |addr: u32| addr.is_multiple_of(4) && addr < code_size;
+
+    let mut labels: BTreeSet<u32> = words.iter()
+        .filter_map(|word| match word {
+            Word::Instruction(op_code, _, imm) => jump_target(*op_code, *imm),
+            Word::Data(_) => None,
+        })
+        .filter(|addr| is_label_target(*addr))
+        .collect();
+
+    let mut result = String::new();
+    if entry != 0 {
+        if is_label_target(entry) {
+            labels.insert(entry);
+            result.push_str(&format!(".entry {}\n", label_name(entry)));
+        }
+        else {
+            result.push_str(&format!(".entry {}\n", entry));
+        }
+    }
+
+    for (i, word) in words.iter().enumerate() {
+        let addr = (i * 4) as u32;
+        if labels.contains(&addr) {
+            result.push_str(&format!("{}:\n", label_name(addr)));
+        }
+
+        let line = match word {
+            Word::Instruction(op_code, registers, imm) => {
+                let mut operands: Vec<String> = registers.iter().map(|reg| reg.to_string()).collect();
+                match (jump_target(*op_code, *imm), imm) {
+                    (Some(target), _) if labels.contains(&target) => operands.push(format!("%{}", label_name(target))),
+                    (_, Some(imm)) => operands.push(format_immediate(*op_code, *imm)),
+                    _ => {},
+                }
+
+                if operands.is_empty() {
+                    op_code.to_string()
+                }
+                else {
+                    format!("{} {}", op_code, operands.join(", "))
+                }
+            },
+            Word::Data(value) => format!(".i32 0x{:X}", value),
+        };
+        result.push_str(&format!("\t{}\n", line));
+    }
+
+    let trailing = &program[words.len() * 4..];
+    if !trailing.is_empty() {
+        for line in format_trailing_bytes(trailing) {
+            result.push_str(&format!("\t{}\n", line));
+        }
+    }
+
+    result
+}
+
+/// Disassembles a program like objdump: a line with the address, the word
+/// and the instruction for every word. Labels of `symbols` are printed before
+/// their address and jumps to immediate addresses show the label of the
+/// target (like `ji 8 <loop>`).
+pub fn disassemble_listing(program: &[u8], symbols: &HashMap<String, u32>) -> String {
+    let memory_map = MemoryMap::from_symbols(symbols, program.len() as u32);
+    let mut labels: Vec<(u32, &String)> = symbols.iter().map(|(name, address)| (*address, name)).collect();
+    labels.sort();
+
+    let mut result = String::new();
+    let mut next_label = 0;
+    let mut print_labels = |result: &mut String, end: u32| {
+        while next_label < labels.len() && labels[next_label].0 < end {
+            result.push_str(&format!("{}:\n", labels[next_label].1));
+            next_label += 1;
+        }
+    };
+
+    for (i, chunk) in program.chunks_exact(4).enumerate() {
+        let addr = (i * 4) as u32;
+        print_labels(&mut result, addr + 4);
+
+        let word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        let text = match decode_word(word) {
+            Word::Instruction(op_code, _, imm) => {
+                let text = disassemble_instruction(word).unwrap_or_default();
+                match jump_target(op_code, imm).and_then(|target| memory_map.find(target).map(|region| (target, region))) {
+                    Some((target, region)) if target == region.start => format!("{} <{}>", text, region.name),
+                    Some((target, region)) => format!("{} <{}+0x{:X}>", text, region.name, target - region.start),
+                    None => text,
+                }
+            },
+            Word::Data(value) => format!(".i32 0x{:X}", value),
+        };
+        result.push_str(&format!("{:08X}  {:08X}  {}\n", addr, word, text));
+    }
+
+    let trailing_start = program.len() / 4 * 4;
+    let trailing = &program[trailing_start..];
+    if !trailing.is_empty() {
+        print_labels(&mut result, program.len() as u32);
+        let bytes: Vec<String> = trailing.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let text = format_trailing_bytes(trailing).join(" ; ");
+        result.push_str(format!("{:08X}  {:<8}  {}", trailing_start, bytes.join(""), text).trim_end());
+        result.push('\n');
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "assembler")]
+    use std::collections::HashMap;
+    use super::disassemble_instruction;
+    #[cfg(feature = "assembler")]
+    use super::{disassemble_program, disassemble_listing};
+    use num_traits::FromPrimitive;
+    use crate::common::{OpCode, Register};
+    #[cfg(feature = "assembler")]
+    use crate::compiler::{compile, compile_image, CompileOptions};
+    #[cfg(feature = "assembler")]
+    use crate::parser::parse_string;
+    use crate::runtime::utils;
+
+    #[test]
+    fn disassemble() {
+        assert_eq!(Some("li $r0, 10".to_string()), disassemble_instruction(utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 10)));
+        assert_eq!(Some("li $r1, -1".to_string()), disassemble_instruction(utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 0x000FFFFF)));
+        assert_eq!(Some("addi $sp, 4".to_string()), disassemble_instruction(utils::create_instruction_register_and_immediate(OpCode::ADDI, Register::SP, 4)));
+        assert_eq!(Some("add $r0, $ra".to_string()), disassemble_instruction(utils::create_instruction_two_registers(OpCode::ADD, Register::R0, Register::RA)));
+        assert_eq!(Some("j $ra".to_string()), disassemble_instruction(utils::create_instruction_register(OpCode::J, Register::RA)));
+        assert_eq!(Some("syscalli 1".to_string()), disassemble_instruction(utils::create_instruction_immediate(OpCode::SYSCALLI, 1)));
+        assert_eq!(Some("mfs $r0, 2".to_string()), disassemble_instruction(utils::create_instruction_register_and_immediate(OpCode::MFS, Register::R0, 2)));
+        assert_eq!(Some("reti".to_string()), disassemble_instruction(utils::create_instruction(OpCode::RETI)));
+    }
+
+    #[test]
+    fn disassemble_invalid() {
+        assert_eq!(None, disassemble_instruction(0xFF000000));
+        assert_eq!(None, disassemble_instruction(utils::create_instruction_immediate(OpCode::J, 0x0F)));
+    }
+
+    #[test]
+    #[cfg(feature = "assembler")]
+    fn disassemble_program_labels() {
+        let program = "li $r0, 3\nloop:\nsubi $r0, 1\njnzi $r0, %loop\nji %end\n.i32 0xFFFFFFFF\nend:\nli $r1, 0 - 1\nsyscalli 0\n";
+        let binary = compile(&mut parse_string(&program.to_string())).expect("Expected valid program");
+        assert_eq!("\tli $r0, 3\nloc_4:\n\tsubi $r0, 1\n\tjnzi $r0, %loc_4\n\tji %loc_14\n\t.i32 0xFFFFFFFF\nloc_14:\n\tli $r1, -1\n\tsyscalli 0\n",
+            disassemble_program(&binary, 0));
+
+        assert_eq!(".entry loc_4\n\treti\nloc_4:\n\treti\n\t.str \"a\\n\"\n",
+            disassemble_program(&[0, 0, 0, OpCode::RETI as u8, 0, 0, 0, OpCode::RETI as u8, 0x61, 0x0A], 4));
+
+        // Not UTF-8
+        let program = [0, 0, 0, OpCode::RETI as u8, 0x61, 0xFF];
+        assert_eq!("\treti\n\t.i8 0x61\n\t.i8 0xFF\n", disassemble_program(&program, 0));
+        assert_eq!(Some(program.to_vec()), compile(&mut parse_string(&disassemble_program(&program, 0))));
+    }
+
+    #[test]
+    #[cfg(feature = "assembler")]
+    fn disassemble_with_addresses() {
+        let program = "main:\nli $r0, 3\nloop:\nsubi $r0, 1\njnzi $r0, %loop\nji %main + 8\n.i32 0xFFFFFFFF\nmsg:\n.str \"Hi\"\n";
+        let binary = compile(&mut parse_string(&program.to_string())).expect("Expected valid program");
+        let mut symbols = HashMap::new();
+        symbols.insert("main".to_string(), 0);
+        symbols.insert("loop".to_string(), 4);
+        symbols.insert("msg".to_string(), 20);
+        assert_eq!(concat!(
+            "main:\n",
+            "00000000  07000003  li $r0, 3\n",
+            "loop:\n",
+            "00000004  1D000001  subi $r0, 1\n",
+            "00000008  14000004  jnzi $r0, 4 <loop>\n",
+            "0000000C  11000008  ji 8 <loop+0x4>\n",
+            "00000010  FFFFFFFF  .i32 0xFFFFFFFF\n",
+            "msg:\n",
+            "00000014  4869      .str \"Hi\"\n"), disassemble_listing(&binary, &symbols));
+
+        assert_eq!("00000000  11000004  ji 4\n00000004  FF        .i8 0xFF\n", disassemble_listing(&[4, 0, 0, OpCode::JI as u8, 0xFF], &HashMap::new()));
+    }
+
+    #[test]
+    #[cfg(feature = "assembler")]
+    fn disassemble_example_programs() {
+        let programs = [
+            include_str!("../../interpreter/program/add.asm"),
+            include_str!("../../interpreter/program/add_mem.asm"),
+            include_str!("../../interpreter/program/threads.asm"),
+            include_str!("../../interpreter/program/harts.asm"),
+            include_str!("../../interpreter/program/hello_world.asm"),
+            include_str!("../../interpreter/program/jump_with_label.asm"),
+        ];
+
+        for program in programs {
+            let image = compile_image(&mut parse_string(&program.to_string()), &CompileOptions::default()).expect("Expected valid program");
+            let source = disassemble_program(&image.program, image.entry);
+            let reassembled = compile_image(&mut parse_string(&source), &CompileOptions::default()).expect("Expected valid disassembly");
+            assert_eq!(image.program, reassembled.program);
+            assert_eq!(image.entry, reassembled.entry);
+        }
+    }
+
+    #[test]
+    fn readme_opcodes() {
+        let readme = include_str!("../../../README.md");
+        let table: Vec<&str> = readme.split("## Opcodes\n").nth(1).expect("Expected opcode table")
+            .lines()
+            .filter(|line| line.starts_with("- "))
+            .collect();
+        let expected: Vec<String> = (0..=u8::MAX)
+            .filter_map(|value| OpCode::from_u8(value).map(|op_code| format!("- {}: 0x{:02X}", op_code, value)))
+            .collect();
+        assert_eq!(expected, table);
+    }
+}
@@ -0,0 +1,202 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Minimal ELF container (32-bit, little-endian) for compiled programs: one
+//! loadable segment at address 0 with the program, a `.text` section and
+//! the labels as symbols
+
+use std::collections::HashMap;
+
+/// Machine type of the ELF header (not assigned officially, the bytes `CV`)
+pub const EM_CUSTOMVMCPU: u16 = 0x5643;
+
+const ELF_HEADER_SIZE: usize = 52;
+const PROGRAM_HEADER_SIZE: usize = 32;
+const SECTION_HEADER_SIZE: usize = 40;
+const SYMBOL_SIZE: usize = 16;
+
+const ET_EXEC: u16 = 2;
+const PT_LOAD: u32 = 1;
+const PF_RWX: u32 = 0x7;
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHF_WRITE_ALLOC_EXECINSTR: u32 = 0x7;
+const STB_GLOBAL: u8 = 1;
+
+/// Index of the sections
+const SECTION_TEXT: u16 = 1;
+const SECTION_STRTAB: u32 = 3;
+const SECTION_SHSTRTAB: u16 = 4;
+const SECTION_COUNT: u16 = 5;
+
+/// Section names (`.shstrtab`) and the offsets of the names
+const SECTION_NAMES: &[u8] = b"\0.text\0.symtab\0.strtab\0.shstrtab\0";
+const NAME_TEXT: u32 = 1;
+const NAME_SYMTAB: u32 = 7;
+const NAME_STRTAB: u32 = 15;
+const NAME_SHSTRTAB: u32 = 23;
+
+fn push_u16(result: &mut Vec<u8>, value: u16) {
+    result.extend_from_slice(&value.to_le_bytes());
+}
+
+fn push_u32(result: &mut Vec<u8>, value: u32) {
+    result.extend_from_slice(&value.to_le_bytes());
+}
+
+fn align(result: &mut Vec<u8>) {
+    while !result.len().is_multiple_of(4) {
+        result.push(0);
+    }
+}
+
+struct SectionHeader {
+    name: u32,
+    section_type: u32,
+    flags: u32,
+    offset: usize,
+    size: usize,
+    link: u32,
+    info: u32,
+    entry_size: u32,
+}
+
+impl SectionHeader {
+    fn write(&self, result: &mut Vec<u8>) {
+        for value in [self.name, self.section_type, self.flags, 0, self.offset as u32, self.size as u32, self.link, self.info] {
+            push_u32(result, value);
+        }
+        push_u32(result, if self.section_type == SHT_STRTAB { 1 } else { 4 });
+        push_u32(result, self.entry_size);
+    }
+}
+
+/// The program (loaded at address 0) as ELF executable with the entry point
+/// and the symbols (like `CompileOutput::symbols`)
+pub fn to_elf(program: &[u8], entry: u32, symbols: &HashMap<String, u32>) -> Vec<u8> {
+    let mut symbols: Vec<(&String, &u32)> = symbols.iter().collect();
+    symbols.sort_by(|(name0, address0), (name1, address1)| address0.cmp(address1).then(name0.cmp(name1)));
+
+    let mut result = Vec::new();
+    // ELF header
+    result.extend_from_slice(&[0x7F, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    push_u16(&mut result, ET_EXEC);
+    push_u16(&mut result, EM_CUSTOMVMCPU);
+    push_u32(&mut result, 1); // Version
+    push_u32(&mut result, entry);
+    push_u32(&mut result, ELF_HEADER_SIZE as u32);
+    let section_headers_offset_pos = result.len();
+    push_u32(&mut result, 0); // Offset of the section headers, see below
+    push_u32(&mut result, 0); // Flags
+    push_u16(&mut result, ELF_HEADER_SIZE as u16);
+    push_u16(&mut result, PROGRAM_HEADER_SIZE as u16);
+    push_u16(&mut result, 1);
+    push_u16(&mut result, SECTION_HEADER_SIZE as u16);
+    push_u16(&mut result, SECTION_COUNT);
+    push_u16(&mut result, SECTION_SHSTRTAB);
+
+    // Program header
+    let program_offset = ELF_HEADER_SIZE + PROGRAM_HEADER_SIZE;
+    for value in [PT_LOAD, program_offset as u32, 0, 0, program.len() as u32, program.len() as u32, PF_RWX, 4] {
+        push_u32(&mut result, value);
+    }
+
+    result.extend_from_slice(program);
+    align(&mut result);
+
+    // Symbols and their names, the first symbol is the undefined symbol
+    let mut names = vec![0];
+    let symtab_offset = result.len();
+    result.extend_from_slice(&[0; SYMBOL_SIZE]);
+    for (name, address) in &symbols {
+        push_u32(&mut result, names.len() as u32);
+        push_u32(&mut result, **address);
+        push_u32(&mut result, 0); // Size
+        result.push(STB_GLOBAL << 4);
+        result.push(0);
+        push_u16(&mut result, SECTION_TEXT);
+        names.extend_from_slice(name.as_bytes());
+        names.push(0);
+    }
+
+    let strtab_offset = result.len();
+    result.extend_from_slice(&names);
+    let shstrtab_offset = result.len();
+    result.extend_from_slice(SECTION_NAMES);
+    align(&mut result);
+
+    let section_headers_offset = result.len() as u32;
+    result[section_headers_offset_pos..section_headers_offset_pos + 4].copy_from_slice(&section_headers_offset.to_le_bytes());
+    result.extend_from_slice(&[0; SECTION_HEADER_SIZE]);
+    let sections = [
+        SectionHeader { name: NAME_TEXT, section_type: SHT_PROGBITS, flags: SHF_WRITE_ALLOC_EXECINSTR, offset: program_offset, size: program.len(), link: 0, info: 0, entry_size: 0 },
+        SectionHeader { name: NAME_SYMTAB, section_type: SHT_SYMTAB, flags: 0, offset: symtab_offset, size: strtab_offset - symtab_offset, link: SECTION_STRTAB, info: 1, entry_size: SYMBOL_SIZE as u32 },
+        SectionHeader { name: NAME_STRTAB, section_type: SHT_STRTAB, flags: 0, offset: strtab_offset, size: names.len(), link: 0, info: 0, entry_size: 0 },
+        SectionHeader { name: NAME_SHSTRTAB, section_type: SHT_STRTAB, flags: 0, offset: shstrtab_offset, size: SECTION_NAMES.len(), link: 0, info: 0, entry_size: 0 },
+    ];
+    for section in &sections {
+        section.write(&mut result);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::convert::TryInto;
+    use super::{to_elf, EM_CUSTOMVMCPU};
+
+    fn u16_at(data: &[u8], pos: usize) -> u16 {
+        u16::from_le_bytes(data[pos..pos + 2].try_into().expect("Expected"))
+    }
+
+    fn u32_at(data: &[u8], pos: usize) -> u32 {
+        u32::from_le_bytes(data[pos..pos + 4].try_into().expect("Expected"))
+    }
+
+    #[test]
+    fn header() {
+        let mut symbols = HashMap::new();
+        symbols.insert("main".to_string(), 4);
+        let elf = to_elf(&[1, 2, 3, 4, 5, 6], 4, &symbols);
+        assert_eq!(b"\x7FELF", &elf[0..4]);
+        assert_eq!(EM_CUSTOMVMCPU, u16_at(&elf, 18));
+        assert_eq!(4, u32_at(&elf, 24)); // Entry
+
+        // The loadable segment contains the program
+        let program_header = u32_at(&elf, 28) as usize;
+        let offset = u32_at(&elf, program_header + 4) as usize;
+        let size = u32_at(&elf, program_header + 16) as usize;
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], elf[offset..offset + size].to_vec());
+
+        // Section headers at the end of the file
+        let section_headers = u32_at(&elf, 32) as usize;
+        assert_eq!(elf.len(), section_headers + 5 * 40);
+
+        // The symbol `main` (after the undefined symbol)
+        let symtab = u32_at(&elf, section_headers + 2 * 40 + 16) as usize;
+        let strtab = u32_at(&elf, section_headers + 3 * 40 + 16) as usize;
+        let name = u32_at(&elf, symtab + 16) as usize;
+        assert_eq!(b"main\0", &elf[strtab + name..strtab + name + 5]);
+        assert_eq!(4, u32_at(&elf, symtab + 20));
+    }
+}
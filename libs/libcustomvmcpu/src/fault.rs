@@ -0,0 +1,191 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::common::Register;
+
+/// Registers, whose bits can be flipped
+const FLIPPABLE_REGISTERS: [Register; 10] = [
+    Register::R0, Register::R1, Register::R2, Register::R3,
+    Register::R4, Register::R5, Register::R6, Register::R7,
+    Register::SP, Register::RA,
+];
+
+/// Deterministic pseudo random number generator (xorshift64*)
+#[derive(PartialEq, Debug, Clone)]
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // The state must not be 0
+        Rng { state: seed ^ 0x9E3779B97F4A7C15 }
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545F4914F6CDD1D) >> 32) as u32
+    }
+
+    fn below(&mut self, max: u32) -> u32 {
+        self.next_u32() % max
+    }
+}
+
+/// Fault, which was injected into a virtual machine
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum InjectedFault {
+    /// A bit of a register was flipped after the instruction with the
+    /// (1-based) index `instruction`
+    RegisterBitFlip { instruction: u64, register: Register, bit: u32 },
+    /// The store with the (1-based) index `write` failed with a memory error
+    FailedWrite { write: u64 },
+    /// A bit of the word read by the load with the (1-based) index `load`
+    /// was flipped
+    CorruptedLoad { load: u64, bit: u32 },
+}
+
+/// Injects faults into a running virtual machine. All random choices are
+/// made by a seeded generator, so runs with the same seed and program are
+/// reproducible.
+#[derive(PartialEq, Debug, Clone)]
+pub struct FaultInjector {
+    rng: Rng,
+    flip_register_every: Option<u64>,
+    fail_write: Option<u64>,
+    corrupt_load: Option<u64>,
+    instructions: u64,
+    writes: u64,
+    loads: u64,
+    injected: Vec<InjectedFault>,
+}
+
+impl FaultInjector {
+    pub fn new(seed: u64) -> FaultInjector {
+        FaultInjector {
+            rng: Rng::new(seed),
+            flip_register_every: None,
+            fail_write: None,
+            corrupt_load: None,
+            instructions: 0,
+            writes: 0,
+            loads: 0,
+            injected: Vec::new(),
+        }
+    }
+
+    /// Flip a random bit of a random register after every `n`-th instruction
+    pub fn flip_register_bit_every(mut self, n: u64) -> FaultInjector {
+        self.flip_register_every = Some(n).filter(|n| *n > 0);
+        self
+    }
+
+    /// Let the `n`-th (1-based) store fail with a memory error
+    pub fn fail_memory_write(mut self, n: u64) -> FaultInjector {
+        self.fail_write = Some(n);
+        self
+    }
+
+    /// Flip a random bit of the word read by the `n`-th (1-based) word load
+    pub fn corrupt_loaded_word(mut self, n: u64) -> FaultInjector {
+        self.corrupt_load = Some(n);
+        self
+    }
+
+    /// All faults injected so far
+    pub fn injected(&self) -> &[InjectedFault] {
+        &self.injected
+    }
+
+    /// Called after every instruction. Returns the register and the bit to
+    /// flip.
+    pub(crate) fn on_instruction(&mut self) -> Option<(Register, u32)> {
+        self.instructions += 1;
+        let every = self.flip_register_every?;
+        if !self.instructions.is_multiple_of(every) {
+            return None;
+        }
+
+        let register = FLIPPABLE_REGISTERS[self.rng.below(FLIPPABLE_REGISTERS.len() as u32) as usize];
+        let bit = self.rng.below(32);
+        self.injected.push(InjectedFault::RegisterBitFlip { instruction: self.instructions, register, bit });
+        Some((register, bit))
+    }
+
+    /// Called before every store. Returns true, if the store must fail.
+    pub(crate) fn on_write(&mut self) -> bool {
+        self.writes += 1;
+        let fail = self.fail_write == Some(self.writes);
+        if fail {
+            self.injected.push(InjectedFault::FailedWrite { write: self.writes });
+        }
+
+        fail
+    }
+
+    /// Called after every word load with the loaded value. Returns the
+    /// (possibly corrupted) value.
+    pub(crate) fn on_load(&mut self, value: u32) -> u32 {
+        self.loads += 1;
+        if self.corrupt_load != Some(self.loads) {
+            return value;
+        }
+
+        let bit = self.rng.below(32);
+        self.injected.push(InjectedFault::CorruptedLoad { load: self.loads, bit });
+        value ^ (1 << bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FaultInjector, InjectedFault};
+
+    #[test]
+    fn deterministic() {
+        let mut injector0 = FaultInjector::new(42).flip_register_bit_every(3);
+        let mut injector1 = FaultInjector::new(42).flip_register_bit_every(3);
+        for _ in 0..30 {
+            assert_eq!(injector0.on_instruction(), injector1.on_instruction());
+        }
+
+        assert_eq!(10, injector0.injected().len());
+        assert_eq!(injector0.injected(), injector1.injected());
+        assert!(matches!(injector0.injected()[0], InjectedFault::RegisterBitFlip { instruction: 3, .. }));
+    }
+
+    #[test]
+    fn fail_write() {
+        let mut injector = FaultInjector::new(0).fail_memory_write(2);
+        assert!(!injector.on_write());
+        assert!(injector.on_write());
+        assert!(!injector.on_write());
+        assert_eq!(&[InjectedFault::FailedWrite { write: 2 }], injector.injected());
+    }
+
+    #[test]
+    fn corrupt_load() {
+        let mut injector = FaultInjector::new(7).corrupt_loaded_word(1);
+        let value = injector.on_load(0);
+        assert_eq!(1, value.count_ones());
+        assert_eq!(5, injector.on_load(5));
+    }
+}
@@ -0,0 +1,295 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Canonical formatting of assembly programs (see `format_source`)
+
+use logos::Logos;
+use super::diagnostic::Diagnostic;
+use super::parser::{Token, parse_string};
+
+/// A line of the formatted program
+#[derive(Debug, PartialEq)]
+enum Line {
+    Blank,
+    /// Comment on a line of its own (indented, if it was indented)
+    Comment(String, bool),
+    Label(String, Option<String>),
+    Statement(String, Option<String>),
+}
+
+/// Comments (with their offset) between two tokens (`gap` only contains
+/// whitespace and comments)
+fn find_comments(gap: &str) -> Vec<(usize, &str)> {
+    let mut result = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = gap[offset..].find("//").into_iter().chain(gap[offset..].find("/*")).min() {
+        let comment = &gap[(offset + start)..];
+        let end = if comment.starts_with("//") {
+            comment.len()
+        }
+        else {
+            comment.find("*/").map(|end| end + 2).unwrap_or_else(|| comment.len())
+        };
+
+        result.push((offset + start, comment[..end].trim_end()));
+        offset += start + end;
+    }
+
+    result
+}
+
+/// Text of a token in the formatted program
+fn format_token(tok: Token, slice: &str) -> String {
+    match tok {
+        Token::Hex => format!("0x{}", slice[2..].to_uppercase()),
+        _ => slice.to_string(),
+    }
+}
+
+/// Checks, if `tok` can be the last token of an operand (a following `-`
+/// subtracts instead of negating)
+fn ends_operand(tok: Token) -> bool {
+    matches!(tok, Token::AddrToLabel | Token::Reg | Token::Hex | Token::Bin | Token::Oct | Token::Int | Token::Char | Token::OpCloseBracket)
+}
+
+/// Joins the tokens of a statement with canonical spacing (`li $r0, (1 + 2) * 3`)
+fn format_statement(tokens: &[(Token, String)]) -> String {
+    let mut result = String::new();
+    let mut prev: Option<Token> = None;
+    // The previous token is a unary minus
+    let mut negative = false;
+    for (tok, text) in tokens {
+        let space = match (prev, tok) {
+            (None, _) => false,
+            (_, Token::Comma) | (_, Token::OpCloseBracket) => false,
+            (Some(Token::OpOpenBracket), _) | (Some(Token::OpNot), _) => false,
+            // Negative
+            (Some(Token::OpSub), _) if negative => false,
+            _ => true,
+        };
+        negative = *tok == Token::OpSub && !prev.is_some_and(ends_operand);
+
+        if space {
+            result.push(' ');
+        }
+        result += text;
+        prev = Some(*tok);
+    }
+
+    result
+}
+
+struct Formatter {
+    lines: Vec<Line>,
+    statement: Vec<(Token, String)>,
+    /// Comment after the current statement
+    statement_comment: Option<String>,
+    /// Something was added on the current line of the source
+    line_has_content: bool,
+}
+
+impl Formatter {
+    fn push_comment(&mut self, comment: &str, indented: bool) {
+        if !self.statement.is_empty() {
+            self.statement_comment = Some(match self.statement_comment.take() {
+                Some(previous) => format!("{} {}", previous, comment),
+                None => comment.to_string(),
+            });
+        }
+        else if let (true, Some(Line::Label(_, label_comment @ None))) = (self.line_has_content, self.lines.last_mut()) {
+            *label_comment = Some(comment.to_string());
+        }
+        else {
+            self.lines.push(Line::Comment(comment.to_string(), indented));
+        }
+
+        self.line_has_content = true;
+    }
+
+    fn flush_statement(&mut self) {
+        if !self.statement.is_empty() {
+            let statement = format_statement(&self.statement);
+            self.lines.push(Line::Statement(statement, self.statement_comment.take()));
+            self.statement.clear();
+        }
+    }
+
+    fn end_line(&mut self) {
+        if !self.line_has_content && self.statement.is_empty() && self.lines.last() != Some(&Line::Blank) {
+            self.lines.push(Line::Blank);
+        }
+
+        self.flush_statement();
+        self.line_has_content = false;
+    }
+}
+
+/// Formats an assembly program: one statement per line, labels on lines of
+/// their own, statements indented with a tab, operands separated by `, `,
+/// operators surrounded by spaces, aligned comments after statements and
+/// at most one blank line in a row. Returns the errors, if the program
+/// can't be parsed.
+pub fn format_source(source: &str) -> Result<String, Vec<Diagnostic>> {
+    let parsed = parse_string(&source.to_string());
+    if !parsed.errors.is_empty() {
+        return Err(parsed.errors);
+    }
+
+    let mut formatter = Formatter { lines: Vec::new(), statement: Vec::new(), statement_comment: None, line_has_content: false };
+    let mut prev_end = 0;
+    let mut lex = Token::lexer(source).spanned();
+    loop {
+        let next = lex.next();
+        let gap_end = next.as_ref().map(|(_, span)| span.start).unwrap_or_else(|| source.len());
+        let gap = &source[prev_end..gap_end];
+        for (offset, comment) in find_comments(gap) {
+            let comment_start = prev_end + offset;
+            let line_start = source[..comment_start].rfind('\n').map(|pos| pos + 1).unwrap_or(0);
+            formatter.push_comment(comment, comment_start > line_start);
+        }
+
+        let (tok, span) = match next {
+            Some(next) => next,
+            None => break,
+        };
+        prev_end = span.end;
+
+        match tok {
+            Token::NewLine if source[span.clone()].starts_with(';') => formatter.flush_statement(),
+            Token::NewLine => formatter.end_line(),
+            Token::Label => {
+                formatter.flush_statement();
+                formatter.lines.push(Line::Label(source[span].to_string(), None));
+                formatter.line_has_content = true;
+            },
+            _ => {
+                formatter.statement.push((tok, format_token(tok, &source[span])));
+                formatter.line_has_content = true;
+            },
+        }
+    }
+    formatter.flush_statement();
+
+    while formatter.lines.first() == Some(&Line::Blank) {
+        formatter.lines.remove(0);
+    }
+    while formatter.lines.last() == Some(&Line::Blank) {
+        formatter.lines.pop();
+    }
+
+    Ok(render(&formatter.lines))
+}
+
+fn render(lines: &[Line]) -> String {
+    let mut result = String::new();
+    let mut i = 0;
+    while i < lines.len() {
+        // Comments of neighbouring statements start in the same column
+        let group_end = lines[i..].iter().position(|line| !matches!(line, Line::Statement(_, _))).map(|len| i + len).unwrap_or(lines.len());
+        let column = lines[i..group_end].iter().filter_map(|line| match line {
+            Line::Statement(statement, Some(_)) => Some(statement.chars().count()),
+            _ => None,
+        }).max().unwrap_or(0);
+
+        for line in &lines[i..group_end.max(i + 1)] {
+            match line {
+                Line::Blank => {},
+                Line::Comment(comment, true) => result += &format!("\t{}", comment),
+                Line::Comment(comment, false) => result += comment,
+                Line::Label(label, None) => result += label,
+                Line::Label(label, Some(comment)) => result += &format!("{} {}", label, comment),
+                Line::Statement(statement, None) => result += &format!("\t{}", statement),
+                Line::Statement(statement, Some(comment)) => result += &format!("\t{:width$} {}", statement, comment, width = column),
+            }
+
+            result.push('\n');
+        }
+
+        i = group_end.max(i + 1);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_source;
+    use crate::compiler::compile;
+    use crate::diagnostic::codes;
+    use crate::parser::parse_string;
+
+    #[test]
+    fn canonical() {
+        let source = "\n\nstart:   li $r0,1 // Counter\nli $r1 , ( 2+3 )*0xff// Value\n\n\n\nloop: subi $r0,1; jnzi $r0,%loop\n// Done\n  /* exit */\nsyscalli   0\n\n";
+        assert_eq!(Ok("start:\n\
+            \tli $r0, 1              // Counter\n\
+            \tli $r1, (2 + 3) * 0xFF // Value\n\
+            \n\
+            loop:\n\
+            \tsubi $r0, 1\n\
+            \tjnzi $r0, %loop\n\
+            // Done\n\
+            \t/* exit */\n\
+            \tsyscalli 0\n".to_string()), format_source(source));
+    }
+
+    #[test]
+    fn labels_and_data() {
+        let source = "msg: // Greeting\n.str \"Hi; // there\"\n.equ  SIZE ,4\nbuffer: .space %SIZE";
+        assert_eq!(Ok("msg: // Greeting\n\
+            \t.str \"Hi; // there\"\n\
+            \t.equ SIZE, 4\n\
+            buffer:\n\
+            \t.space %SIZE\n".to_string()), format_source(source));
+    }
+
+    #[test]
+    fn bitwise_operators() {
+        assert_eq!(Ok("\t.equ FLAGS, (1 << 3) | (1 << 5)\n\tli $r0, ~%FLAGS & 0xFF\n".to_string()),
+            format_source(".equ FLAGS,(1<<3)|(1<<5)\nli $r0,~ %FLAGS&0xff"));
+        assert_eq!(Ok("\tli $r0, -1\n\t.i32 2 - -3\n\t.i32 (-%end)\nend:\n".to_string()),
+            format_source("li $r0,- 1\n.i32 2--3\n.i32 ( - %end)\nend:"));
+    }
+
+    #[test]
+    fn errors() {
+        let result = format_source("li $r0\nreti");
+        assert_eq!(Some(codes::EXPECTED_TOKEN), result.err().and_then(|errors| errors.first().map(|error| error.code)));
+    }
+
+    #[test]
+    fn example_programs() {
+        let programs = [
+            include_str!("../../interpreter/program/add.asm"),
+            include_str!("../../interpreter/program/add_mem.asm"),
+            include_str!("../../interpreter/program/threads.asm"),
+            include_str!("../../interpreter/program/harts.asm"),
+            include_str!("../../interpreter/program/hello_world.asm"),
+            include_str!("../../interpreter/program/jump_with_label.asm"),
+        ];
+
+        for program in programs {
+            let formatted = format_source(program).expect("Expected valid program");
+            // Idempotent and the same program
+            assert_eq!(Ok(formatted.clone()), format_source(&formatted));
+            assert_eq!(compile(&mut parse_string(&program.to_string())), compile(&mut parse_string(&formatted)));
+        }
+    }
+}
@@ -0,0 +1,230 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Executes a virtual machine on a background thread, which is controlled
+//! over a channel (see `VmHandle`)
+
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use num_traits::FromPrimitive;
+use super::common::{Register, LAST_REGISTER};
+use super::runtime::{ExitStatus, Interpreter, VirtualMachine, VmError};
+
+/// Instructions executed between checking for commands
+const SLICE_INSTRUCTIONS: u64 = 1000;
+
+/// Values of all registers (indexed by `Register`)
+pub type Registers = [u32; LAST_REGISTER as usize + 1];
+
+enum Command {
+    Pause,
+    Resume,
+    Interrupt,
+    ReadRegisters(Sender<Registers>),
+    Kill,
+}
+
+/// Virtual machine executing a program on its own thread. The program
+/// starts at address 0 immediately. Dropping the handle kills the machine.
+pub struct VmHandle {
+    commands: Sender<Command>,
+    thread: Option<JoinHandle<Option<Result<ExitStatus, VmError>>>>,
+}
+
+impl VmHandle {
+    /// Spawns a thread, which creates the interpreter with `create` and
+    /// executes its program. The program prints to `stdout`.
+    pub fn spawn<I, F>(create: F, stdout: Box<dyn Write + Send>) -> VmHandle
+        where I: Interpreter, F: FnOnce() -> I + Send + 'static
+    {
+        Self::spawn_with(create, stdout, |_| {})
+    }
+
+    /// Like `spawn`, but `setup` configures the virtual machine (like the
+    /// number of harts) before the program starts
+    pub fn spawn_with<I, F, S>(create: F, mut stdout: Box<dyn Write + Send>, setup: S) -> VmHandle
+        where I: Interpreter, F: FnOnce() -> I + Send + 'static, S: FnOnce(&mut VirtualMachine<I>) + Send + 'static
+    {
+        let (commands, receiver) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let mut vm = VirtualMachine::new(create(), &mut *stdout);
+            setup(&mut vm);
+            run(&mut vm, &receiver)
+        });
+
+        VmHandle { commands, thread: Some(thread) }
+    }
+
+    /// Stops executing instructions until `resume`. Returns false, if the
+    /// machine already stopped.
+    pub fn pause(&self) -> bool {
+        self.commands.send(Command::Pause).is_ok()
+    }
+
+    /// Continues a paused machine. Returns false, if the machine already
+    /// stopped.
+    pub fn resume(&self) -> bool {
+        self.commands.send(Command::Resume).is_ok()
+    }
+
+    /// Requests an interrupt (see `VirtualMachine::raise_interrupt`).
+    /// Returns false, if the machine already stopped.
+    pub fn inject_interrupt(&self) -> bool {
+        self.commands.send(Command::Interrupt).is_ok()
+    }
+
+    /// Registers of the hart executing the next instruction. Returns None,
+    /// if the machine already stopped.
+    pub fn read_registers(&self) -> Option<Registers> {
+        let (sender, receiver) = mpsc::channel();
+        self.commands.send(Command::ReadRegisters(sender)).ok()?;
+        receiver.recv().ok()
+    }
+
+    /// True, if the program stopped (or the machine was killed)
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(JoinHandle::is_finished)
+    }
+
+    /// Waits until the program stops and returns its result
+    pub fn join(mut self) -> Result<ExitStatus, VmError> {
+        let thread = self.thread.take().expect("Thread is only taken by join");
+        match thread.join() {
+            Ok(result) => result.expect("Only kill stops the machine without a result"),
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+
+    /// Stops the machine and waits for its thread
+    pub fn kill(mut self) {
+        let _ = self.commands.send(Command::Kill);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for VmHandle {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Kill);
+    }
+}
+
+/// Executes the program in slices of instructions and handles the commands
+/// in between. Returns None, if the machine was killed.
+fn run<I: Interpreter>(vm: &mut VirtualMachine<I>, commands: &Receiver<Command>) -> Option<Result<ExitStatus, VmError>> {
+    let mut paused = false;
+    vm.start(0);
+    loop {
+        loop {
+            let command = if paused {
+                commands.recv().unwrap_or(Command::Kill)
+            }
+            else {
+                match commands.try_recv() {
+                    Ok(command) => command,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => Command::Kill,
+                }
+            };
+
+            match command {
+                Command::Pause => paused = true,
+                Command::Resume => paused = false,
+                Command::Interrupt => vm.raise_interrupt(),
+                Command::ReadRegisters(reply) => {
+                    let mut registers = [0; LAST_REGISTER as usize + 1];
+                    for reg in (0..=LAST_REGISTER as u8).filter_map(Register::from_u8) {
+                        registers[reg as usize] = vm.read_register_value(reg);
+                    }
+
+                    let _ = reply.send(registers);
+                },
+                Command::Kill => {
+                    log::debug!("Virtual machine killed");
+                    return None;
+                },
+            }
+        }
+
+        if vm.run_for(SLICE_INSTRUCTIONS).is_some() {
+            return Some(vm.result());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VmHandle;
+    use crate::common::{OpCode, Register, SystemRegister};
+    use crate::runtime::{BinaryInterpreter, ExitStatus, utils};
+
+    /// Counts in $r1 until the interrupt handler sets $r3, exits with $r3
+    const INTERRUPT_PROGRAM: [u32; 11] = [
+        utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 10 * 4),
+        utils::create_instruction_register_and_immediate(OpCode::MTS, Register::R0, SystemRegister::VectorTable as u32),
+        utils::create_instruction(OpCode::EI),
+        utils::create_instruction_register_and_immediate(OpCode::ADDI, Register::R1, 1), // wait:
+        utils::create_instruction_register_and_immediate(OpCode::JZI, Register::R3, 3 * 4),
+        utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R3),
+        utils::create_instruction_immediate(OpCode::SYSCALLI, 0),
+        utils::create_instruction_register_and_immediate(OpCode::LI, Register::R3, 7), // handler:
+        utils::create_instruction(OpCode::RETI),
+        0,
+        7 * 4, // Vector table: interrupt handler
+    ];
+
+    fn spawn() -> VmHandle {
+        VmHandle::spawn(|| BinaryInterpreter::new_with_program(&INTERRUPT_PROGRAM).expect("Expected"), Box::new(std::io::sink()))
+    }
+
+    #[test]
+    fn inject_interrupt() {
+        let handle = spawn();
+        assert!(handle.read_registers().is_some());
+        assert!(handle.inject_interrupt());
+        assert_eq!(Ok(ExitStatus { code: 7 }), handle.join());
+    }
+
+    #[test]
+    fn pause_resume() {
+        let handle = spawn();
+        assert!(handle.pause());
+        let registers = handle.read_registers().expect("Expected");
+        assert_eq!(Some(registers), handle.read_registers());
+
+        assert!(handle.resume());
+        assert!(!handle.is_finished());
+        handle.kill();
+    }
+
+    #[test]
+    fn read_registers_setup() {
+        let handle = VmHandle::spawn_with(
+            || BinaryInterpreter::new_with_program(&INTERRUPT_PROGRAM).expect("Expected"),
+            Box::new(std::io::sink()),
+            |vm| vm.write_register_value(Register::R5, 42));
+        assert!(handle.pause());
+        assert_eq!(Some(42), handle.read_registers().map(|registers| registers[Register::R5 as usize]));
+        handle.kill();
+    }
+}
@@ -0,0 +1,71 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Classic hexdump layout of memory: offset, 16 bytes in hex and the
+//! printable ASCII characters
+
+use std::fmt::Write;
+
+/// Bytes per line
+const LINE_SIZE: usize = 16;
+
+/// Formats `data` starting at `address` like `hexdump -C`:
+///
+/// ```text
+/// 00000000  48 65 6c 6c 6f 0a 00 00  00 00 00 00 00 00 00 00  |Hello...........|
+/// ```
+pub fn hexdump(data: &[u8], address: u32) -> String {
+    let mut result = String::new();
+    for (i, line) in data.chunks(LINE_SIZE).enumerate() {
+        let offset = address.wrapping_add((i * LINE_SIZE) as u32);
+        write!(result, "{:08x} ", offset).expect("Writing to a String can't fail");
+        for column in 0..LINE_SIZE {
+            if column % 8 == 0 {
+                result.push(' ');
+            }
+
+            match line.get(column) {
+                Some(byte) => write!(result, "{:02x} ", byte).expect("Writing to a String can't fail"),
+                None => result.push_str("   "),
+            }
+        }
+
+        let ascii: String = line.iter()
+            .map(|byte| if byte.is_ascii_graphic() || *byte == b' ' { *byte as char } else { '.' })
+            .collect();
+        writeln!(result, " |{}|", ascii).expect("Writing to a String can't fail");
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::hexdump;
+
+    #[test]
+    fn lines() {
+        assert_eq!("", hexdump(&[], 0));
+        assert_eq!(
+            "00000010  48 65 6c 6c 6f 2c 20 57  6f 72 6c 64 0a 00 ff 7f  |Hello, World....|\n\
+             00000020  41 42                                             |AB|\n",
+            hexdump(b"Hello, World\n\0\xff\x7fAB", 0x10));
+    }
+}
@@ -0,0 +1,155 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Text formats of compiled programs: Intel HEX, plain hex bytes and JSON
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Data bytes per record (or line)
+const RECORD_SIZE: usize = 16;
+
+const RECORD_DATA: u8 = 0x00;
+const RECORD_END_OF_FILE: u8 = 0x01;
+const RECORD_EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const RECORD_START_LINEAR_ADDRESS: u8 = 0x05;
+
+fn write_record(result: &mut String, address: u16, record_type: u8, data: &[u8]) {
+    let mut checksum = (data.len() as u8)
+        .wrapping_add((address >> 8) as u8)
+        .wrapping_add(address as u8)
+        .wrapping_add(record_type);
+    write!(result, ":{:02X}{:04X}{:02X}", data.len(), address, record_type).expect("Writing to a String can't fail");
+    for byte in data {
+        checksum = checksum.wrapping_add(*byte);
+        write!(result, "{:02X}", byte).expect("Writing to a String can't fail");
+    }
+
+    writeln!(result, "{:02X}", checksum.wrapping_neg()).expect("Writing to a String can't fail");
+}
+
+/// The program (loaded at address 0) as Intel HEX records. Addresses above
+/// 0xFFFF use extended linear address records, an entry point other than 0
+/// is written as start linear address record.
+pub fn to_intel_hex(program: &[u8], entry: u32) -> String {
+    let mut result = String::new();
+    let mut upper_address = 0;
+    for (i, data) in program.chunks(RECORD_SIZE).enumerate() {
+        let address = (i * RECORD_SIZE) as u32;
+        if address >> 16 != upper_address {
+            upper_address = address >> 16;
+            write_record(&mut result, 0, RECORD_EXTENDED_LINEAR_ADDRESS, &(upper_address as u16).to_be_bytes());
+        }
+
+        write_record(&mut result, address as u16, RECORD_DATA, data);
+    }
+
+    if entry != 0 {
+        write_record(&mut result, 0, RECORD_START_LINEAR_ADDRESS, &entry.to_be_bytes());
+    }
+
+    write_record(&mut result, 0, RECORD_END_OF_FILE, &[]);
+    result
+}
+
+/// The bytes of the program in hex, 16 per line (separated by spaces)
+pub fn to_hex(program: &[u8]) -> String {
+    let mut result = String::new();
+    for line in program.chunks(RECORD_SIZE) {
+        let bytes: Vec<String> = line.iter().map(|byte| format!("{:02X}", byte)).collect();
+        writeln!(result, "{}", bytes.join(" ")).expect("Writing to a String can't fail");
+    }
+
+    result
+}
+
+/// The program as JSON object with the entry point, the bytes of the
+/// program as hex string and the addresses of the labels (sorted by address).
+/// Label names are identifiers, so they don't need escaping.
+pub fn to_json(program: &[u8], entry: u32, symbols: &HashMap<String, u32>) -> String {
+    let mut symbols: Vec<(&String, &u32)> = symbols.iter().collect();
+    symbols.sort_by(|(name0, address0), (name1, address1)| address0.cmp(address1).then(name0.cmp(name1)));
+
+    let mut result = String::new();
+    writeln!(result, "{{").expect("Writing to a String can't fail");
+    writeln!(result, "  \"entry\": {},", entry).expect("Writing to a String can't fail");
+    write!(result, "  \"program\": \"").expect("Writing to a String can't fail");
+    for byte in program {
+        write!(result, "{:02X}", byte).expect("Writing to a String can't fail");
+    }
+
+    writeln!(result, "\",").expect("Writing to a String can't fail");
+    if symbols.is_empty() {
+        writeln!(result, "  \"symbols\": {{}}").expect("Writing to a String can't fail");
+    }
+    else {
+        writeln!(result, "  \"symbols\": {{").expect("Writing to a String can't fail");
+        for (i, (name, address)) in symbols.iter().enumerate() {
+            let separator = if i + 1 < symbols.len() { "," } else { "" };
+            writeln!(result, "    \"{}\": {}{}", name, address, separator).expect("Writing to a String can't fail");
+        }
+
+        writeln!(result, "  }}").expect("Writing to a String can't fail");
+    }
+
+    writeln!(result, "}}").expect("Writing to a String can't fail");
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::{to_intel_hex, to_hex, to_json};
+
+    #[test]
+    fn intel_hex() {
+        assert_eq!(":00000001FF\n", to_intel_hex(&[], 0));
+        assert_eq!(
+            ":0400000002001007E3\n\
+             :0400000500000004F3\n\
+             :00000001FF\n",
+            to_intel_hex(&[0x02, 0x00, 0x10, 0x07], 4));
+
+        // Extended linear address after 64 KiB
+        let hex = to_intel_hex(&vec![0; 0x10010], 0);
+        let lines: Vec<&str> = hex.lines().collect();
+        assert_eq!(4099, lines.len());
+        assert_eq!(":020000040001F9", lines[4096]);
+        assert_eq!(":1000000000000000000000000000000000000000F0", lines[4097]);
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!("", to_hex(&[]));
+        assert_eq!("48 65 6C 6C 6F 2C 20 57 6F 72 6C 64 0A 00 FF 7F\n41 42\n", to_hex(b"Hello, World\n\0\xff\x7fAB"));
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!("{\n  \"entry\": 0,\n  \"program\": \"\",\n  \"symbols\": {}\n}\n", to_json(&[], 0, &HashMap::new()));
+
+        let mut symbols = HashMap::new();
+        symbols.insert("msg".to_string(), 4);
+        symbols.insert("main".to_string(), 0);
+        assert_eq!(
+            "{\n  \"entry\": 4,\n  \"program\": \"0200100748690A00\",\n  \"symbols\": {\n    \"main\": 0,\n    \"msg\": 4\n  }\n}\n",
+            to_json(&[0x02, 0x00, 0x10, 0x07, b'H', b'i', b'\n', 0], 4, &symbols));
+    }
+}
@@ -0,0 +1,216 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Typed host functions: Rust closures called by programs with `syscall`.
+//! A program declares a host function with `.import name` and calls it
+//! with `syscalli %name`. The arguments are passed in `$r1` to `$r4`, the
+//! result is written into `$r0`.
+
+/// Syscall number of the first import, the compiler numbers the imports
+/// in the order of the program
+pub const HOST_SYSCALL_START: u32 = 0x100;
+
+/// Maximum number of arguments of a host function
+pub const MAX_HOST_ARGUMENTS: usize = 4;
+
+/// Argument of a host function
+pub trait FromRegister {
+    fn from_register(value: u32) -> Self;
+}
+
+impl FromRegister for u32 {
+    fn from_register(value: u32) -> Self {
+        value
+    }
+}
+
+impl FromRegister for i32 {
+    fn from_register(value: u32) -> Self {
+        value as i32
+    }
+}
+
+/// Every value other than 0 is true
+impl FromRegister for bool {
+    fn from_register(value: u32) -> Self {
+        value != 0
+    }
+}
+
+/// Result of a host function. Returns None, if `$r0` isn't changed.
+pub trait IntoRegister {
+    fn into_register(self) -> Option<u32>;
+}
+
+impl IntoRegister for () {
+    fn into_register(self) -> Option<u32> {
+        None
+    }
+}
+
+impl IntoRegister for u32 {
+    fn into_register(self) -> Option<u32> {
+        Some(self)
+    }
+}
+
+impl IntoRegister for i32 {
+    fn into_register(self) -> Option<u32> {
+        Some(self as u32)
+    }
+}
+
+impl IntoRegister for bool {
+    fn into_register(self) -> Option<u32> {
+        Some(self as u32)
+    }
+}
+
+/// Closure, which can be bound as host function. `Args` are the types of
+/// its arguments (as tuple), so closures with different arguments don't
+/// conflict.
+pub trait HostFunction<Args> {
+    /// Calls the function with the values of `$r1` to `$r4`
+    fn call(&mut self, args: [u32; MAX_HOST_ARGUMENTS]) -> Option<u32>;
+}
+
+macro_rules! impl_host_function {
+    ($($arg:ident => $index:tt),*) => {
+        impl<F, R, $($arg),*> HostFunction<($($arg,)*)> for F
+        where
+            F: FnMut($($arg),*) -> R,
+            R: IntoRegister,
+            $($arg: FromRegister),*
+        {
+            #[allow(unused_variables)]
+            fn call(&mut self, args: [u32; MAX_HOST_ARGUMENTS]) -> Option<u32> {
+                self($($arg::from_register(args[$index])),*).into_register()
+            }
+        }
+    };
+}
+
+impl_host_function!();
+impl_host_function!(A => 0);
+impl_host_function!(A => 0, B => 1);
+impl_host_function!(A => 0, B => 1, C => 2);
+impl_host_function!(A => 0, B => 1, C => 2, D => 3);
+
+type BoxedHostFunction = Box<dyn FnMut([u32; MAX_HOST_ARGUMENTS]) -> Option<u32>>;
+
+/// Host functions bound to a virtual machine and the imports of the loaded
+/// program. The functions can't borrow anything (`'static`), otherwise the
+/// virtual machine would keep its streams borrowed until it's dropped.
+#[derive(Default)]
+pub(crate) struct HostFunctions {
+    functions: Vec<(String, BoxedHostFunction)>,
+    /// Index of the function of every import
+    imports: Vec<usize>,
+}
+
+impl HostFunctions {
+    /// Binds `function` to `name`, a function bound to the same name before
+    /// is replaced
+    pub fn bind<Args, F: HostFunction<Args> + 'static>(&mut self, name: &str, mut function: F) {
+        let function: BoxedHostFunction = Box::new(move |args| function.call(args));
+        match self.functions.iter_mut().find(|(bound, _)| bound == name) {
+            Some(entry) => entry.1 = function,
+            None => self.functions.push((name.to_string(), function)),
+        }
+    }
+
+    /// Resolves the imports of a program (the syscall numbers starting at
+    /// `HOST_SYSCALL_START`). Returns the names of the imports, which aren't
+    /// bound.
+    pub fn link(&mut self, imports: &[String]) -> Result<(), Vec<String>> {
+        let mut missing = Vec::new();
+        let mut linked = Vec::with_capacity(imports.len());
+        for name in imports {
+            match self.functions.iter().position(|(bound, _)| bound == name) {
+                Some(index) => linked.push(index),
+                None => missing.push(name.clone()),
+            }
+        }
+
+        if !missing.is_empty() {
+            return Err(missing);
+        }
+
+        self.imports = linked;
+        Ok(())
+    }
+
+    /// Calls the function of the import `index`. Returns None, if there's
+    /// no such import.
+    pub fn call(&mut self, index: u32, args: [u32; MAX_HOST_ARGUMENTS]) -> Option<Option<u32>> {
+        let function = *self.imports.get(index as usize)?;
+        Some((self.functions[function].1)(args))
+    }
+
+    /// Names of all bound functions
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.iter().map(|(name, _)| name.as_str())
+    }
+}
+
+impl std::fmt::Debug for HostFunctions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HostFunctions")
+            .field("functions", &self.names().collect::<Vec<_>>())
+            .field("imports", &self.imports)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn typed_arguments() {
+        let mut host = HostFunctions::default();
+        host.bind("add", |a: u32, b: u32| a + b);
+        host.bind("negative", |value: i32| value < 0);
+        host.bind("nothing", || ());
+        assert_eq!(Ok(()), host.link(&["nothing".to_string(), "add".to_string(), "negative".to_string()]));
+
+        assert_eq!(Some(None), host.call(0, [1, 2, 3, 4]));
+        assert_eq!(Some(Some(3)), host.call(1, [1, 2, 3, 4]));
+        assert_eq!(Some(Some(1)), host.call(2, [-5i32 as u32, 0, 0, 0]));
+        assert_eq!(None, host.call(3, [0; MAX_HOST_ARGUMENTS]));
+    }
+
+    #[test]
+    fn link() {
+        let calls = Rc::new(Cell::new(0));
+        let mut host = HostFunctions::default();
+        host.bind("count", || 1u32);
+        let counter = calls.clone();
+        host.bind("count", move || counter.set(counter.get() + 1));
+        assert_eq!(vec!["count"], host.names().collect::<Vec<_>>());
+        assert_eq!(Err(vec!["draw_pixel".to_string()]), host.link(&["count".to_string(), "draw_pixel".to_string()]));
+        assert_eq!(None, host.call(0, [0; MAX_HOST_ARGUMENTS]));
+
+        assert_eq!(Ok(()), host.link(&["count".to_string()]));
+        assert_eq!(Some(None), host.call(0, [0; MAX_HOST_ARGUMENTS]));
+        assert_eq!(1, calls.get());
+    }
+}
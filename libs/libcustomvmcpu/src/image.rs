@@ -0,0 +1,230 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Binary images: a program with its entry point. Images with an entry point
+//! other than 0 start with a header (`HEADER_MAGIC` and the entry address,
+//! little-endian), the program always starts at address 0. Images with debug
+//! information start with `DEBUG_HEADER_MAGIC`, the entry address and the
+//! size of the program; the line table follows the program. Images with
+//! imports (`.import`) start with `IMPORTS_HEADER_MAGIC`, the entry address
+//! and the size of the program; the imports (their number, then the length
+//! and the name of each import) and the line table (if any) follow the
+//! program.
+
+use std::convert::TryInto;
+use super::debuginfo::{LineTable, Reader};
+
+/// First bytes of an image with header (no valid instruction starts with
+/// them)
+pub const HEADER_MAGIC: [u8; 4] = *b"CVMC";
+/// Size of the header in bytes
+pub const HEADER_SIZE: usize = 8;
+/// First bytes of an image with debug information
+pub const DEBUG_HEADER_MAGIC: [u8; 4] = *b"CVMD";
+/// Size of the header of an image with debug information in bytes
+pub const DEBUG_HEADER_SIZE: usize = 12;
+/// First bytes of an image with imports
+pub const IMPORTS_HEADER_MAGIC: [u8; 4] = *b"CVMI";
+/// Size of the header of an image with imports in bytes
+pub const IMPORTS_HEADER_SIZE: usize = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    /// Loaded at address 0
+    pub program: Vec<u8>,
+    /// Address of the first executed instruction
+    pub entry: u32,
+    pub debug: Option<LineTable>,
+    /// Host functions of the program (`CompileOutput::imports`)
+    pub imports: Vec<String>,
+}
+
+impl Image {
+    pub fn new(program: Vec<u8>, entry: u32) -> Image {
+        Image { program, entry, debug: None, imports: Vec::new() }
+    }
+
+    /// Image with debug information
+    pub fn with_debug(mut self, line_table: LineTable) -> Image {
+        self.debug = Some(line_table);
+        self
+    }
+
+    /// Image with imports
+    pub fn with_imports(mut self, imports: Vec<String>) -> Image {
+        self.imports = imports;
+        self
+    }
+
+    /// Reads an image with or without header (without a header the entry
+    /// point is 0)
+    pub fn from_bytes(data: &[u8]) -> Image {
+        if let Some(image) = Self::from_bytes_with_imports(data) {
+            image
+        }
+        else if let Some(image) = Self::from_bytes_with_debug(data) {
+            image
+        }
+        else if data.len() >= HEADER_SIZE && data[0..4] == HEADER_MAGIC {
+            let entry = u32::from_le_bytes(data[4..HEADER_SIZE].try_into().expect("Made sure"));
+            Image::new(data[HEADER_SIZE..].to_vec(), entry)
+        }
+        else {
+            Image::new(data.to_vec(), 0)
+        }
+    }
+
+    fn from_bytes_with_debug(data: &[u8]) -> Option<Image> {
+        if data.len() < DEBUG_HEADER_SIZE || data[0..4] != DEBUG_HEADER_MAGIC {
+            return None;
+        }
+
+        let entry = u32::from_le_bytes(data[4..8].try_into().expect("Made sure"));
+        let size = u32::from_le_bytes(data[8..DEBUG_HEADER_SIZE].try_into().expect("Made sure")) as usize;
+        let program = data.get(DEBUG_HEADER_SIZE..DEBUG_HEADER_SIZE.checked_add(size)?)?;
+        let line_table = LineTable::from_bytes(&data[DEBUG_HEADER_SIZE + size..])?;
+        Some(Image::new(program.to_vec(), entry).with_debug(line_table))
+    }
+
+    fn from_bytes_with_imports(data: &[u8]) -> Option<Image> {
+        if data.len() < IMPORTS_HEADER_SIZE || data[0..4] != IMPORTS_HEADER_MAGIC {
+            return None;
+        }
+
+        let mut reader = Reader { data, pos: 4 };
+        let entry = reader.u32()?;
+        let size = reader.u32()? as usize;
+        let program = reader.bytes(size)?;
+        let mut imports = Vec::new();
+        for _ in 0..reader.u32()? {
+            let len = reader.u32()? as usize;
+            imports.push(String::from_utf8(reader.bytes(len)?.to_vec()).ok()?);
+        }
+
+        let mut image = Image::new(program.to_vec(), entry).with_imports(imports);
+        if reader.pos < data.len() {
+            image = image.with_debug(LineTable::from_bytes(&data[reader.pos..])?);
+        }
+
+        Some(image)
+    }
+
+    /// Bytes of the image. The header is only written, if the entry point
+    /// isn't 0 or the image has debug information or imports (so plain
+    /// programs stay plain).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        if !self.imports.is_empty() {
+            let mut result = Vec::with_capacity(IMPORTS_HEADER_SIZE + self.program.len());
+            result.extend_from_slice(&IMPORTS_HEADER_MAGIC);
+            result.extend_from_slice(&self.entry.to_le_bytes());
+            result.extend_from_slice(&(self.program.len() as u32).to_le_bytes());
+            result.extend_from_slice(&self.program);
+            result.extend_from_slice(&(self.imports.len() as u32).to_le_bytes());
+            for import in &self.imports {
+                result.extend_from_slice(&(import.len() as u32).to_le_bytes());
+                result.extend_from_slice(import.as_bytes());
+            }
+            if let Some(debug) = &self.debug {
+                result.extend_from_slice(&debug.to_bytes());
+            }
+            return result;
+        }
+
+        if let Some(debug) = &self.debug {
+            let mut result = Vec::with_capacity(DEBUG_HEADER_SIZE + self.program.len());
+            result.extend_from_slice(&DEBUG_HEADER_MAGIC);
+            result.extend_from_slice(&self.entry.to_le_bytes());
+            result.extend_from_slice(&(self.program.len() as u32).to_le_bytes());
+            result.extend_from_slice(&self.program);
+            result.extend_from_slice(&debug.to_bytes());
+            return result;
+        }
+
+        if self.entry == 0 {
+            return self.program.clone();
+        }
+
+        let mut result = Vec::with_capacity(HEADER_SIZE + self.program.len());
+        result.extend_from_slice(&HEADER_MAGIC);
+        result.extend_from_slice(&self.entry.to_le_bytes());
+        result.extend_from_slice(&self.program);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Image, HEADER_MAGIC, HEADER_SIZE, DEBUG_HEADER_MAGIC, DEBUG_HEADER_SIZE, IMPORTS_HEADER_MAGIC, IMPORTS_HEADER_SIZE};
+    use crate::common::OpCode;
+    use crate::debuginfo::{LineEntry, LineTable};
+    use num_traits::FromPrimitive;
+
+    #[test]
+    fn round_trip() {
+        let image = Image::new(vec![1, 2, 3, 4], 0);
+        assert_eq!(vec![1, 2, 3, 4], image.to_bytes());
+        assert_eq!(image, Image::from_bytes(&image.to_bytes()));
+
+        let image = Image::new(vec![1, 2, 3, 4], 0x10);
+        let bytes = image.to_bytes();
+        assert_eq!(HEADER_SIZE + 4, bytes.len());
+        assert_eq!(HEADER_MAGIC, bytes[0..4]);
+        assert_eq!(image, Image::from_bytes(&bytes));
+    }
+
+    #[test]
+    fn debug_info() {
+        let line_table = LineTable { file: "test.asm".to_string(), entries: vec![LineEntry { address: 0..4, line: 1, column: 1 }] };
+        let image = Image::new(vec![1, 2, 3, 4], 0).with_debug(line_table);
+        let bytes = image.to_bytes();
+        assert_eq!(DEBUG_HEADER_MAGIC, bytes[0..4]);
+        assert_eq!(vec![1, 2, 3, 4], bytes[DEBUG_HEADER_SIZE..DEBUG_HEADER_SIZE + 4].to_vec());
+        assert_eq!(image, Image::from_bytes(&bytes));
+
+        // Truncated debug information: a program without header
+        assert_eq!(None, Image::from_bytes(&bytes[..bytes.len() - 1]).debug);
+    }
+
+    #[test]
+    fn imports() {
+        let image = Image::new(vec![1, 2, 3, 4], 0x10).with_imports(vec!["print_int".to_string(), "draw_pixel".to_string()]);
+        let bytes = image.to_bytes();
+        assert_eq!(IMPORTS_HEADER_MAGIC, bytes[0..4]);
+        assert_eq!(vec![1, 2, 3, 4], bytes[IMPORTS_HEADER_SIZE..IMPORTS_HEADER_SIZE + 4].to_vec());
+        assert_eq!(image, Image::from_bytes(&bytes));
+
+        let line_table = LineTable { file: "test.asm".to_string(), entries: vec![LineEntry { address: 0..4, line: 1, column: 1 }] };
+        let image = image.with_debug(line_table);
+        assert_eq!(image, Image::from_bytes(&image.to_bytes()));
+
+        // Truncated imports: a program without header
+        assert!(Image::from_bytes(&bytes[..bytes.len() - 1]).imports.is_empty());
+    }
+
+    #[test]
+    fn magic_isnt_an_instruction() {
+        let word = u32::from_le_bytes(HEADER_MAGIC);
+        assert_eq!(None, OpCode::from_u32(word >> 24));
+        let word = u32::from_le_bytes(DEBUG_HEADER_MAGIC);
+        assert_eq!(None, OpCode::from_u32(word >> 24));
+        let word = u32::from_le_bytes(IMPORTS_HEADER_MAGIC);
+        assert_eq!(None, OpCode::from_u32(word >> 24));
+    }
+}
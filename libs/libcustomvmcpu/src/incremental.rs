@@ -0,0 +1,354 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Incremental compilation for editors: a program, which is compiled again
+//! after a small change, reuses the parsed statements and the layout of the
+//! last compilation
+
+use std::collections::HashMap;
+use std::ops::Range;
+use super::compiler::{compile_result, recompile, same_layout, CompileOptions, CompileResult, ProgramLayout};
+use super::diagnostic::Diagnostic;
+use super::parser::{evaluate_constants, parse_from, parse_with_checkpoints, Checkpoint, ParserExpr, ParserResult};
+
+/// Compiles new versions of the same program. Only the changed lines are
+/// parsed again, the statements before and after them are taken from the
+/// last compilation. If the changed statements have the same sizes and
+/// don't define symbols (like an edited operand), the addresses and symbols
+/// of the last compilation are kept and only these statements are encoded
+/// again; otherwise the program is laid out and encoded again.
+pub struct IncrementalCompiler {
+    options: CompileOptions,
+    last: Option<Compilation>,
+    reused: usize,
+}
+
+/// Result of the last `compile`
+struct Compilation {
+    source: String,
+    parsed: ParserResult,
+    checkpoints: Vec<Checkpoint>,
+    result: CompileResult,
+    /// None, if the program has errors
+    layout: Option<ProgramLayout>,
+}
+
+/// Statements of a program, which was parsed again after a change
+struct Reparsed {
+    parsed: ParserResult,
+    checkpoints: Vec<Checkpoint>,
+    /// Statements, which were parsed again (in the new program)
+    changed: Range<usize>,
+    /// Statements they replace (in the last program)
+    replaced: Vec<ParserExpr>,
+}
+
+impl IncrementalCompiler {
+    pub fn new(options: CompileOptions) -> IncrementalCompiler {
+        IncrementalCompiler { options, last: None, reused: 0 }
+    }
+
+    /// Compiles `source` like `compiler::compile2_with_options`
+    pub fn compile(&mut self, source: &str) -> CompileResult {
+        let compilation = match self.last.take() {
+            Some(last) if last.source == source => {
+                self.reused = last.parsed.program.len();
+                last
+            },
+            Some(last) => self.compile_changed(last, source),
+            None => {
+                let (parsed, checkpoints) = parse_with_checkpoints(source);
+                let (result, layout) = compile_result(&parsed, &self.options);
+                self.reused = 0;
+                Compilation { source: source.to_string(), parsed, checkpoints, result, layout }
+            },
+        };
+
+        let result = compilation.result.clone();
+        self.last = Some(compilation);
+        result
+    }
+
+    /// Number of statements the last `compile` took from the earlier
+    /// compilation instead of parsing them again
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+
+    fn compile_changed(&mut self, last: Compilation, source: &str) -> Compilation {
+        let Compilation { source: last_source, parsed, checkpoints, result: last_result, layout } = last;
+        let reparsed = reparse(&last_source, parsed, &checkpoints, source);
+        self.reused = reparsed.parsed.program.len() - reparsed.changed.len();
+
+        let Reparsed { parsed, checkpoints, changed, replaced } = reparsed;
+        let unchanged_layout = !self.options.strip_unused
+            && parsed.errors.is_empty()
+            && replaced.len() == changed.len()
+            && replaced.iter().zip(&parsed.program[changed.clone()]).all(|(old, new)| same_layout(&old.expr, &new.expr));
+        if let (true, Some(layout), Some(mut bytes)) = (unchanged_layout, layout, last_result.bytes) {
+            let mut warnings = parsed.warnings.clone();
+            if recompile(&parsed.program, changed, &layout, &mut bytes, &mut warnings) {
+                let diagnostics = warnings.iter().map(Diagnostic::from).collect();
+                let result = CompileResult { bytes: Some(bytes), symbols: last_result.symbols, diagnostics, entry: last_result.entry };
+                return Compilation { source: source.to_string(), parsed, checkpoints, result, layout: Some(layout) };
+            }
+        }
+
+        let (result, layout) = compile_result(&parsed, &self.options);
+        Compilation { source: source.to_string(), parsed, checkpoints, result, layout }
+    }
+}
+impl Default for IncrementalCompiler {
+    fn default() -> IncrementalCompiler {
+        IncrementalCompiler::new(CompileOptions::default())
+    }
+}
+
+/// Parses `source`, a changed version of `last_source`: from the last
+/// checkpoint before the first changed byte until a checkpoint after the
+/// last changed byte, at which the parser is in the same state as it was
+/// for `last_source`
+fn reparse(last_source: &str, mut parsed: ParserResult, checkpoints: &[Checkpoint], source: &str) -> Reparsed {
+    let (old, new) = (last_source.as_bytes(), source.as_bytes());
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let suffix_start = new.len() - suffix;
+
+    // The statements before a checkpoint and its token must be unchanged
+    let resume = checkpoints.partition_point(|checkpoint| checkpoint.lookahead <= prefix);
+    let start = resume.checked_sub(1)
+        .map(|i| checkpoints[i])
+        .unwrap_or(Checkpoint { offset: 0, lookahead: 0, statements: 0, errors: 0, warnings: 0 });
+    let mut rest = ParserResult {
+        program: parsed.program.split_off(start.statements),
+        errors: parsed.errors.split_off(start.errors),
+        warnings: parsed.warnings.split_off(start.warnings),
+    };
+
+    let mut constants = HashMap::new();
+    evaluate_constants(&mut constants, &parsed.program);
+    let mut new_checkpoints = checkpoints[..resume].to_vec();
+    // Index of the checkpoint of the last program, at which parsing stopped
+    let mut continued: Option<usize> = None;
+    let mut compared = false;
+    let stopped = parse_from(source, start, constants.clone(), &mut parsed, |checkpoint, new_constants| {
+        // The checkpoint parsing resumed at is already known
+        if resume > 0 && checkpoint.offset == start.offset {
+            return false;
+        }
+
+        // Only the first checkpoint in the unchanged end is compared, the
+        // constants differ at later ones, too
+        if !compared && checkpoint.offset >= suffix_start {
+            let old_offset = checkpoint.offset + old.len() - new.len();
+            if let Ok(i) = checkpoints[resume..].binary_search_by_key(&old_offset, |old| old.offset) {
+                compared = true;
+                evaluate_constants(&mut constants, &rest.program[..checkpoints[resume + i].statements - start.statements]);
+                if constants == *new_constants {
+                    continued = Some(resume + i);
+                    return true;
+                }
+            }
+        }
+
+        new_checkpoints.push(checkpoint);
+        false
+    });
+
+    let changed = start.statements..parsed.program.len();
+    let replaced = match (stopped, continued) {
+        (Some(new_checkpoint), Some(old_end)) => {
+            let old_checkpoint = checkpoints[old_end];
+            let replaced: Vec<ParserExpr> = rest.program.drain(..old_checkpoint.statements - start.statements).collect();
+            rest.errors.drain(..old_checkpoint.errors - start.errors);
+            rest.warnings.drain(..old_checkpoint.warnings - start.warnings);
+
+            // The rest of the program moved
+            let shift = |pos: &mut Range<usize>| *pos = (pos.start + new.len() - old.len())..(pos.end + new.len() - old.len());
+            for expr in &mut rest.program {
+                shift(&mut expr.pos);
+            }
+            for error in &mut rest.errors {
+                shift(&mut error.span);
+            }
+            for warning in &mut rest.warnings {
+                shift(&mut warning.pos);
+            }
+            parsed.program.append(&mut rest.program);
+            parsed.errors.append(&mut rest.errors);
+            parsed.warnings.append(&mut rest.warnings);
+
+            new_checkpoints.extend(checkpoints[old_end..].iter().map(|checkpoint| Checkpoint {
+                offset: checkpoint.offset + new.len() - old.len(),
+                // Conservative, the changed statements may have looked further
+                lookahead: (checkpoint.lookahead + new.len()).saturating_sub(old.len()).max(new_checkpoint.lookahead),
+                statements: checkpoint.statements + new_checkpoint.statements - old_checkpoint.statements,
+                errors: checkpoint.errors + new_checkpoint.errors - old_checkpoint.errors,
+                warnings: checkpoint.warnings + new_checkpoint.warnings - old_checkpoint.warnings,
+            }));
+
+            replaced
+        },
+        _ => rest.program,
+    };
+
+    Reparsed { parsed, checkpoints: new_checkpoints, changed, replaced }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IncrementalCompiler;
+    use crate::compiler::{compile2_with_options, CompileOptions};
+    use crate::parser::{parse_string, parse_with_checkpoints, Checkpoint};
+
+    /// Compiles the versions of a program one after the other, every result
+    /// (and the parsed program) must be the same as the one of a full
+    /// compilation
+    fn compile_versions(options: &CompileOptions, versions: &[&str]) -> IncrementalCompiler {
+        let mut compiler = IncrementalCompiler::new(options.clone());
+        for source in versions {
+            assert_eq!(compile2_with_options(&parse_string(&source.to_string()), options), compiler.compile(source), "{}", source);
+
+            let last = compiler.last.as_ref().expect("Compiled");
+            let (parsed, checkpoints) = parse_with_checkpoints(source);
+            assert_eq!(parsed.program, last.parsed.program, "{}", source);
+            assert_eq!(parsed.errors, last.parsed.errors, "{}", source);
+            assert_eq!(parsed.warnings, last.parsed.warnings, "{}", source);
+            // The lookahead of the reused checkpoints may be larger
+            assert_eq!(checkpoints.len(), last.checkpoints.len(), "{}", source);
+            for (checkpoint, reused) in checkpoints.iter().zip(&last.checkpoints) {
+                assert_eq!(Checkpoint { lookahead: reused.lookahead, ..*checkpoint }, *reused, "{}", source);
+                assert!(checkpoint.lookahead <= reused.lookahead, "{}", source);
+            }
+        }
+
+        compiler
+    }
+
+    #[test]
+    fn reuses_statements() {
+        let source = "main:\n\tli $r0, %data\n\tpush $r0\n\tpush $r0\n\tji %main\ndata:\n\t.i32 %main\n\t.str \"Hi\"\n";
+        let mut compiler = compile_versions(&CompileOptions::default(), &[source]);
+        assert_eq!(0, compiler.reused());
+
+        // Nothing changed
+        compiler.compile(source);
+        assert_eq!(10, compiler.reused());
+
+        // Only the changed line is parsed again (push expands to two
+        // statements)
+        let changed = source.replace("push $r0\n\tji", "push $r1\n\tji");
+        assert_eq!(compile2_with_options(&parse_string(&changed), &CompileOptions::default()), compiler.compile(&changed));
+        assert_eq!(8, compiler.reused());
+
+        // An inserted line moves data (the statement before it is parsed
+        // again, too)
+        let inserted = changed.replace("\tji", "\treti\n\tji");
+        assert_eq!(compile2_with_options(&parse_string(&inserted), &CompileOptions::default()), compiler.compile(&inserted));
+        assert_eq!(8, compiler.reused());
+    }
+
+    #[test]
+    fn edits() {
+        compile_versions(&CompileOptions::default(), &[
+            "main:\n\tli $r0, 1\n\tji %main\n",
+            // Changed operand, label and line
+            "main:\n\tli $r0, 2\n\tji %main\n",
+            "main:\n\tli $r0, 2\n\tji %mian\n",
+            "main:\n\tli $r0, 2\n\tji %main\n\tswi $r0, %main\n",
+            "start:\n\tli $r0, 2\n\tji %start\n\tswi $r0, %start\n",
+            // Imports can only be called
+            ".import foo\nstart:\n\tsyscalli %foo\n",
+            ".import foo\nstart:\n\tji %foo\n",
+            ".import foo\nstart:\n\tsyscalli %foo\n",
+            // Data and sections
+            "start:\n\tli $r0, %data\n.data\ndata: .i32 1\n.text\n\tsbi $r0, %start\n",
+            "start:\n\tli $r0, %data\n.data\ndata: .i32 2\n.text\n\tsbi $r0, %start\n",
+            "start:\n\tli $r0, %data\n.data\ndata: .i32 2\n.text\n\tsbi $r0, %data\n",
+            // Constants change the size of `.space` after them
+            ".equ SIZE, 4\n\tli $r0, %SIZE\nbuffer: .space %SIZE\nend: .i32 %end\n",
+            ".equ SIZE, 8\n\tli $r0, %SIZE\nbuffer: .space %SIZE\nend: .i32 %end\n",
+            ".equ SIZE, 8\n\tli $r0, %SIZE + 1\nbuffer: .space %SIZE\nend: .i32 %end\n",
+            // Comments and strings over several lines
+            "\tli $r0, 1\n\tli $r1, 2\n\tli $r2, 3\n",
+            "\tli $r0, 1\n/*\tli $r1, 2\n\tli $r2, 3\n",
+            "\tli $r0, 1\n/*\tli $r1, 2 */\n\tli $r2, 3\n",
+            "\t.str \"a\n\tli $r1, 2\n\tli $r2, 3\n",
+            "\t.str \"a\"\n\tli $r1, 2\n\tli $r2, 3\n",
+            // Errors
+            "\tli $r0, 1\n\tli $r1\n\tli $r2, 3\n",
+            "\tli $r0, 1\n\tli $r1, 2; reti\n\tli $r2, 3\n",
+            "\tli $r0, 1\n\tli $r1, 0x100000\n\tli $r2, 3\n",
+            "\tli $r0, 1\n\tli $r1, 2\n\tli $r2, 3\n",
+            "\tli $r0, 1\n\tamoadd $r1, $r2\n.isa 1\n",
+            // Removed lines
+            "\tli $r0, 1\n",
+            "",
+            "label:",
+        ]);
+    }
+
+    #[test]
+    fn random_edits() {
+        const LINES: [&str; 16] = [
+            "main:", "\tli $r0, %data", "\tli $r1, 0x10", "\tpush $r0", "\tji %main", "data: .i32 %main + 4",
+            ".equ SIZE, 8", "\t.space %SIZE", "\tswi $r0, %main", ".data", ".text", "\t.str \"a\\nb\"",
+            "// comment", "\tsubi $r0, 1; jnzi $r0, %loop", "loop: reti", ".entry %main",
+        ];
+        const FRAGMENTS: [&str; 10] = ["/*", "*/", "\"", "\n", ";", "0x", "%", "1", " ", "'"];
+
+        // xorshift64*
+        let mut state: u64 = 0x9E3779B97F4A7C15;
+        let mut next = |max: usize| {
+            state ^= state >> 12;
+            state ^= state << 25;
+            state ^= state >> 27;
+            ((state.wrapping_mul(0x2545F4914F6CDD1D) >> 32) as usize) % max
+        };
+
+        let mut source = LINES.join("\n");
+        let mut versions = Vec::new();
+        for _ in 0..300 {
+            let pos = next(source.len() + 1);
+            let pos = (0..=pos).rev().find(|pos| source.is_char_boundary(*pos)).unwrap_or(0);
+            let line_start = source[..pos].rfind('\n').map_or(0, |start| start + 1);
+            let line_end = source[pos..].find('\n').map_or(source.len(), |end| pos + end);
+            match next(4) {
+                // Replaced line
+                0 => source.replace_range(line_start..line_end, LINES[next(LINES.len())]),
+                // Inserted line
+                1 => source.insert_str(line_start, &format!("{}\n", LINES[next(LINES.len())])),
+                // Removed line
+                2 => source.replace_range(line_start..(line_end + 1).min(source.len()), ""),
+                _ => source.insert_str(pos, FRAGMENTS[next(FRAGMENTS.len())]),
+            }
+            versions.push(source.clone());
+        }
+
+        let versions: Vec<&str> = versions.iter().map(|version| version.as_str()).collect();
+        compile_versions(&CompileOptions::default(), &versions);
+    }
+
+    #[test]
+    fn errors() {
+        let options = CompileOptions { strip_unused: true, ..CompileOptions::default() };
+        compile_versions(&options, &["ji %mian\nmain: reti\nunused: .i32 1", "ji %main\nmain: reti\nunused: .i32 1"]);
+    }
+}
@@ -1,4 +1,39 @@
 pub mod common;
+#[cfg(feature = "assembler")]
 pub mod parser;
 pub mod runtime;
+#[cfg(feature = "assembler")]
 pub mod compiler;
+pub mod disassembler;
+pub mod view;
+pub mod mmu;
+pub mod symbols;
+pub mod fault;
+pub mod device;
+pub mod host;
+pub mod shared;
+pub mod timer;
+pub mod handle;
+pub mod testing;
+pub mod hexdump;
+pub mod builder;
+pub mod image;
+#[cfg(feature = "assembler")]
+pub mod diagnostic;
+#[cfg(feature = "assembler")]
+pub mod format;
+#[cfg(feature = "assembler")]
+pub mod lint;
+#[cfg(feature = "assembler")]
+pub mod listing;
+pub mod debuginfo;
+pub mod hexfile;
+pub mod elf;
+#[cfg(feature = "assembler")]
+pub mod incremental;
+#[cfg(feature = "assembler")]
+pub mod repl;
+pub mod profile;
+#[cfg(feature = "assembler")]
+pub mod testkit;
+pub mod differential;
@@ -0,0 +1,287 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Checks for code, which compiles, but probably doesn't do what it should
+
+use std::collections::{HashMap, HashSet};
+use super::common::{OpCode, Register};
+use super::compiler::get_expr_immediate;
+use super::parser::{Expr, ParserExpr, Section};
+use super::runtime::BranchMode;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LintType {
+    /// The label is never referenced
+    UnusedLabel(String),
+    /// The instruction follows an unconditional jump and has no label
+    UnreachableCode,
+    /// The register is written again before the value is read
+    DeadRegisterWrite(Register),
+    /// The jump target is data (the label)
+    JumpIntoData(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Lint {
+    pub pos: std::ops::Range<usize>,
+    pub lint_type: LintType,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct LintOptions {
+    /// With `BranchMode::DelaySlot` the instruction after a jump is executed
+    pub branch_mode: BranchMode,
+}
+
+/// Lints of a parsed program (`ParserResult::program`), ordered by kind
+pub fn lint(program: &[ParserExpr], options: &LintOptions) -> Vec<Lint> {
+    let mut result = Vec::new();
+    unused_labels(program, &mut result);
+    unreachable_code(program, options, &mut result);
+    dead_register_writes(program, &mut result);
+    jumps_into_data(program, &mut result);
+    result
+}
+
+fn is_instruction(expr: &Expr) -> bool {
+    matches!(expr, Expr::Instruction(_)
+        | Expr::InstructionRegister(_, _)
+        | Expr::InstructionImmediate(_, _)
+        | Expr::InstructionTwoRegisters(_, _, _)
+        | Expr::InstructionRegisterAndImmediate(_, _, _))
+}
+
+fn is_data(expr: &Expr) -> bool {
+    matches!(expr, Expr::StoreI32(_) | Expr::StoreI8(_) | Expr::StoreStr(_) | Expr::StoreSpace(_))
+}
+
+/// Instructions, which may continue somewhere else than at the next
+/// instruction
+fn is_jump(expr: &Expr) -> bool {
+    match expr {
+        Expr::Instruction(op_code)
+            | Expr::InstructionRegister(op_code, _)
+            | Expr::InstructionImmediate(op_code, _)
+            | Expr::InstructionRegisterAndImmediate(op_code, _, _) => matches!(op_code,
+                OpCode::J | OpCode::JI | OpCode::JIL | OpCode::JZI | OpCode::JNZI
+                | OpCode::JLZI | OpCode::JGZI | OpCode::RETI),
+        _ => false
+    }
+}
+
+fn is_unconditional_jump(expr: &Expr) -> bool {
+    matches!(expr, Expr::InstructionRegister(OpCode::J, _)
+        | Expr::InstructionImmediate(OpCode::JI, _)
+        | Expr::Instruction(OpCode::RETI))
+}
+
+/// Registers read and the register written by the instruction
+fn register_accesses(expr: &Expr) -> (Vec<Register>, Option<Register>) {
+    match expr {
+        Expr::InstructionTwoRegisters(op_code, reg0, reg1) => match op_code {
+            OpCode::CPY | OpCode::LW | OpCode::LH | OpCode::LB => (vec![*reg1], Some(*reg0)),
+            OpCode::SW | OpCode::SH | OpCode::SB => (vec![*reg0, *reg1], None),
+            _ => (vec![*reg0, *reg1], Some(*reg0)),
+        },
+        Expr::InstructionRegister(OpCode::NOT, reg) => (vec![*reg], Some(*reg)),
+        Expr::InstructionRegister(_, reg) => (vec![*reg], None),
+        Expr::InstructionRegisterAndImmediate(op_code, reg, _) => match op_code {
+            OpCode::LI | OpCode::LWI | OpCode::LHI | OpCode::LBI | OpCode::MFS => (Vec::new(), Some(*reg)),
+            OpCode::SRLI | OpCode::SLLI | OpCode::ADDI | OpCode::SUBI | OpCode::MULI | OpCode::DIVI => (vec![*reg], Some(*reg)),
+            _ => (vec![*reg], None),
+        },
+        _ => (Vec::new(), None)
+    }
+}
+
+fn unused_labels(program: &[ParserExpr], result: &mut Vec<Lint>) {
+    let mut referenced = HashSet::new();
+    for expr in program {
+        if let Some(imm) = get_expr_immediate(&expr.expr) {
+            referenced.extend(imm.labels());
+        }
+    }
+
+    for expr in program {
+        if let Expr::Label(label) = &expr.expr {
+            if !referenced.contains(label) {
+                result.push(Lint { pos: expr.pos.clone(), lint_type: LintType::UnusedLabel(label.clone()) });
+            }
+        }
+    }
+}
+
+/// Only the first unreachable instruction of a block is reported
+fn unreachable_code(program: &[ParserExpr], options: &LintOptions, result: &mut Vec<Lint>) {
+    let delay_slots = if options.branch_mode == BranchMode::DelaySlot { 1 } else { 0 };
+    let mut section = Section::Text;
+    // Instructions after the last unconditional jump, which are still executed
+    let mut executed: Option<usize> = None;
+    let mut unreachable = false;
+    for expr in program {
+        match &expr.expr {
+            Expr::Section(new_section) => section = *new_section,
+            _ if section == Section::Data => {},
+            Expr::Label(_) => {
+                executed = None;
+                unreachable = false;
+            },
+            expr_type if is_instruction(expr_type) && !unreachable => {
+                match executed {
+                    Some(0) => {
+                        result.push(Lint { pos: expr.pos.clone(), lint_type: LintType::UnreachableCode });
+                        unreachable = true;
+                    },
+                    Some(count) => executed = Some(count - 1),
+                    None => {
+                        if is_unconditional_jump(expr_type) {
+                            executed = Some(delay_slots);
+                        }
+                    },
+                }
+            },
+            _ => {}
+        }
+    }
+}
+
+/// Registers are only tracked inside of blocks without labels, jumps and
+/// system calls
+fn dead_register_writes(program: &[ParserExpr], result: &mut Vec<Lint>) {
+    let mut section = Section::Text;
+    let mut written: HashMap<Register, std::ops::Range<usize>> = HashMap::new();
+    for expr in program {
+        if let Expr::Section(new_section) = &expr.expr {
+            section = *new_section;
+        }
+
+        if section == Section::Data || !is_instruction(&expr.expr) {
+            written.clear();
+            continue;
+        }
+
+        let (reads, write) = register_accesses(&expr.expr);
+        for reg in &reads {
+            written.remove(reg);
+        }
+
+        match write {
+            // Writing $ip is a jump, $err is also written by the CPU
+            Some(Register::IP) | Some(Register::ERR) | None => {},
+            Some(reg) => {
+                if let Some(pos) = written.insert(reg, expr.pos.clone()) {
+                    result.push(Lint { pos, lint_type: LintType::DeadRegisterWrite(reg) });
+                }
+            },
+        }
+
+        if is_jump(&expr.expr) || write == Some(Register::IP) || matches!(expr.expr, Expr::InstructionImmediate(OpCode::SYSCALLI, _)) {
+            written.clear();
+        }
+    }
+}
+
+fn jumps_into_data(program: &[ParserExpr], result: &mut Vec<Lint>) {
+    // Labels directly in front of data
+    let mut data_labels = HashSet::new();
+    let mut labels = Vec::new();
+    for expr in program {
+        match &expr.expr {
+            Expr::Label(label) => labels.push(label),
+            expr_type if is_data(expr_type) => data_labels.extend(labels.drain(..)),
+            expr_type if is_instruction(expr_type) => labels.clear(),
+            Expr::Section(_) => labels.clear(),
+            _ => {}
+        }
+    }
+
+    for expr in program {
+        let target = match &expr.expr {
+            Expr::InstructionImmediate(OpCode::JI, imm)
+                | Expr::InstructionImmediate(OpCode::JIL, imm)
+                | Expr::InstructionRegisterAndImmediate(OpCode::JZI, _, imm)
+                | Expr::InstructionRegisterAndImmediate(OpCode::JNZI, _, imm)
+                | Expr::InstructionRegisterAndImmediate(OpCode::JLZI, _, imm)
+                | Expr::InstructionRegisterAndImmediate(OpCode::JGZI, _, imm) => imm,
+            _ => continue,
+        };
+
+        let mut reported = HashSet::new();
+        for label in target.labels().into_iter().filter(|label| data_labels.contains(label)) {
+            if reported.insert(label) {
+                result.push(Lint { pos: expr.pos.clone(), lint_type: LintType::JumpIntoData(label.clone()) });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint, Lint, LintOptions, LintType};
+    use crate::common::Register;
+    use crate::parser::parse_str;
+    use crate::runtime::BranchMode;
+
+    fn lint_types(program: &'static str, options: &LintOptions) -> Vec<LintType> {
+        let parser = parse_str(program);
+        assert_eq!(0, parser.errors.len());
+        lint(&parser.program, options).into_iter().map(|lint| lint.lint_type).collect()
+    }
+
+    #[test]
+    fn unused_labels() {
+        assert_eq!(vec![LintType::UnusedLabel("unused".to_string())],
+            lint_types("main:\n  ji %main\nunused:\n  li $r0, %data\ndata:\n  .i32 0", &LintOptions::default()));
+        assert_eq!(Vec::<LintType>::new(),
+            lint_types(".equ SIZE, %end - %start\nstart:\n  li $r0, %SIZE\nend:\n  li $r1, 0\n.entry start", &LintOptions::default()));
+    }
+
+    #[test]
+    fn unreachable_code() {
+        let program = "main:\n  ji %loop\n  li $r0, 1\n  li $r1, 2\nloop:\n  j $ra\n  .i32 %main\n  reti\n  li $r1, 3";
+        assert_eq!(vec![LintType::UnreachableCode, LintType::UnreachableCode], lint_types(program, &LintOptions::default()));
+
+        let parser = parse_str(program);
+        let lints = lint(&parser.program, &LintOptions::default());
+        assert_eq!(Lint { pos: 19..29, lint_type: LintType::UnreachableCode }, lints[0]);
+
+        // The first instruction after the jump is executed
+        let delay_slot = LintOptions { branch_mode: BranchMode::DelaySlot };
+        assert_eq!(vec![LintType::UnreachableCode, LintType::UnreachableCode], lint_types(program, &delay_slot));
+        let lints = lint(&parser.program, &delay_slot);
+        assert_eq!(31..41, lints[0].pos);
+    }
+
+    #[test]
+    fn dead_register_writes() {
+        assert_eq!(vec![LintType::DeadRegisterWrite(Register::R0), LintType::DeadRegisterWrite(Register::R2)],
+            lint_types("li $r0, 1\nli $r1, 1\nli $r0, 2\nadd $r1, $r0\nlw $r2, $r1\ncpy $r2, $r1\nli $r1, 0\nsyscalli 0", &LintOptions::default()));
+
+        // Labels, jumps and system calls end the block
+        assert_eq!(Vec::<LintType>::new(),
+            lint_types("li $r1, 0\nsyscalli 1\nli $r1, 1\nloop:\nli $r1, 2\njzi $r0, %loop\nli $r1, 3", &LintOptions::default()));
+    }
+
+    #[test]
+    fn jumps_into_data() {
+        assert_eq!(vec![LintType::JumpIntoData("data".to_string()), LintType::JumpIntoData("string".to_string())],
+            lint_types("main:\n  jnzi $r0, %data\n  jil %string + 2\n  ji %main\ndata:\n  .i32 0\nstring:\n  .str \"abc\"", &LintOptions::default()));
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Classic assembler listing: every source line with the address and bytes
+//! it was compiled to
+
+use std::fmt::Write;
+use super::compiler::CompileOutput;
+
+/// Bytes per line
+const LINE_SIZE: usize = 4;
+
+/// Lines of bytes shown per statement (`.space` and long strings are cut)
+const MAX_LINES: usize = 4;
+
+/// Width of the address and the bytes in front of the source
+const PREFIX_WIDTH: usize = 8 + 2 + LINE_SIZE * 3 + 1;
+
+/// Formats the compiled program `output` of `source` as listing:
+///
+/// ```text
+/// 00000000  02 00 10 07      li $r1, 2
+/// 00000004               loop:
+/// 00000004  00 00 00 17      syscalli 0
+/// ```
+pub fn format_listing(source: &str, output: &CompileOutput) -> String {
+    let line_starts: Vec<usize> = std::iter::once(0)
+        .chain(source.match_indices('\n').map(|(pos, _)| pos + 1))
+        .collect();
+    let line_of = |pos: usize| match line_starts.binary_search(&pos) {
+        Ok(line) => line,
+        Err(line) => line - 1,
+    };
+
+    let mut mappings: Vec<Vec<&std::ops::Range<u32>>> = vec![Vec::new(); line_starts.len()];
+    let mut mappings_sorted: Vec<_> = output.mappings.iter().collect();
+    mappings_sorted.sort_by_key(|mapping| mapping.source.start);
+    for mapping in mappings_sorted {
+        mappings[line_of(mapping.source.start)].push(&mapping.address);
+    }
+
+    let mut result = String::new();
+    // Address of the next byte, shown in front of lines without bytes
+    let mut next_address = 0;
+    for (line, text) in source.lines().enumerate() {
+        if mappings[line].is_empty() {
+            writeln!(result, "{:<width$}{}", format!("{:08x}", next_address), text, width = PREFIX_WIDTH).expect("Writing to a String can't fail");
+            continue;
+        }
+
+        let mut text = Some(text);
+        for address in &mappings[line] {
+            let bytes = &output.binary[address.start as usize..address.end as usize];
+            for (i, chunk) in bytes.chunks(LINE_SIZE).enumerate().take(MAX_LINES) {
+                let mut hex = String::new();
+                for byte in chunk {
+                    write!(hex, "{:02x} ", byte).expect("Writing to a String can't fail");
+                }
+
+                let more = i == MAX_LINES - 1 && bytes.len() > MAX_LINES * LINE_SIZE;
+                let prefix = format!("{:08x}  {}{}", address.start as usize + i * LINE_SIZE, hex, if more { "..." } else { "" });
+                match text.take() {
+                    Some(text) => writeln!(result, "{:<width$}{}", prefix, text, width = PREFIX_WIDTH),
+                    None => writeln!(result, "{}", prefix.trim_end()),
+                }.expect("Writing to a String can't fail");
+            }
+
+            next_address = address.end;
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_listing;
+    use crate::compiler::{compile_output, CompileOptions};
+    use crate::parser::parse_str;
+
+    #[test]
+    fn listing() {
+        let program = "main:\n\tli $r1, %string ; syscalli 0\n.data\nstring:\n\t.str \"Hello\"\n\t.space 64";
+        let mut parser = parse_str(program);
+        let output = compile_output(&mut parser, &CompileOptions::default()).expect("Compiles");
+        assert_eq!("\
+00000000               main:
+00000000  08 00 10 07  \tli $r1, %string ; syscalli 0
+00000004  00 00 00 17
+00000008               .data
+00000008               string:
+00000008  48 65 6c 6c  \t.str \"Hello\"
+0000000c  6f
+0000000d  00 00 00 00  \t.space 64
+00000011  00 00 00 00
+00000015  00 00 00 00
+00000019  00 00 00 00 ...
+", format_listing(program, &output));
+    }
+}
@@ -0,0 +1,124 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+
+/// Size of a page in bytes
+pub const PAGE_SIZE: u32 = 4096;
+
+/// Access to an address, which isn't mapped (or isn't writable)
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct PageFault {
+    /// Faulting virtual address
+    pub address: u32,
+    /// True, if the access was a write
+    pub write: bool,
+}
+
+/// Mapping of a virtual page
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct PageTableEntry {
+    /// Physical page number (physical address / PAGE_SIZE)
+    pub physical_page: u32,
+    /// Accesses to pages, which aren't present, are page faults
+    pub present: bool,
+    /// Writes to pages, which aren't writable, are page faults
+    pub writable: bool,
+}
+
+/// Maps virtual pages to physical pages. Unmapped pages aren't accessible.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct PageTable {
+    entries: HashMap<u32, PageTableEntry>,
+}
+
+impl PageTable {
+    pub fn new() -> PageTable {
+        PageTable::default()
+    }
+
+    /// Page table, which maps the first `len` bytes to themselves (writable)
+    pub fn identity(len: u32) -> PageTable {
+        let mut result = PageTable::new();
+        for page in 0..len.div_ceil(PAGE_SIZE) {
+            result.map(page, page, true);
+        }
+
+        result
+    }
+
+    /// Map the virtual page `virtual_page` to `physical_page` (page numbers,
+    /// not addresses)
+    pub fn map(&mut self, virtual_page: u32, physical_page: u32, writable: bool) {
+        self.entries.insert(virtual_page, PageTableEntry { physical_page, present: true, writable });
+    }
+
+    /// Remove the mapping of a virtual page
+    pub fn unmap(&mut self, virtual_page: u32) -> Option<PageTableEntry> {
+        self.entries.remove(&virtual_page)
+    }
+
+    pub fn get_entry(&self, virtual_page: u32) -> Option<&PageTableEntry> {
+        self.entries.get(&virtual_page)
+    }
+
+    pub fn get_entry_mut(&mut self, virtual_page: u32) -> Option<&mut PageTableEntry> {
+        self.entries.get_mut(&virtual_page)
+    }
+
+    /// Translate a virtual address into a physical address
+    pub fn translate(&self, address: u32, write: bool) -> Result<u32, PageFault> {
+        match self.entries.get(&(address / PAGE_SIZE)) {
+            Some(entry) if entry.present && (entry.writable || !write) =>
+                Ok(entry.physical_page.wrapping_mul(PAGE_SIZE) + address % PAGE_SIZE),
+            _ => Err(PageFault { address, write }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PageTable, PageFault, PAGE_SIZE};
+
+    #[test]
+    fn translate() {
+        let mut table = PageTable::new();
+        table.map(1, 3, true);
+        table.map(2, 0, false);
+
+        assert_eq!(Ok(3 * PAGE_SIZE + 8), table.translate(PAGE_SIZE + 8, false));
+        assert_eq!(Ok(3 * PAGE_SIZE + 8), table.translate(PAGE_SIZE + 8, true));
+        assert_eq!(Ok(4), table.translate(2 * PAGE_SIZE + 4, false));
+        assert_eq!(Err(PageFault { address: 2 * PAGE_SIZE + 4, write: true }), table.translate(2 * PAGE_SIZE + 4, true));
+        assert_eq!(Err(PageFault { address: 0, write: false }), table.translate(0, false));
+
+        table.get_entry_mut(1).unwrap().present = false;
+        assert_eq!(Err(PageFault { address: PAGE_SIZE, write: false }), table.translate(PAGE_SIZE, false));
+
+        assert!(table.unmap(2).is_some());
+        assert_eq!(None, table.get_entry(2));
+    }
+
+    #[test]
+    fn identity() {
+        let table = PageTable::identity(PAGE_SIZE + 1);
+        assert_eq!(Ok(PAGE_SIZE + 10), table.translate(PAGE_SIZE + 10, true));
+        assert!(table.translate(2 * PAGE_SIZE, false).is_err());
+    }
+}
@@ -17,14 +17,22 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
-use super::common::{OpCode, Register};
+use std::collections::HashMap;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+use super::common::{OpCode, Register, IsaVersion};
+pub use super::common::{InstructionParseType, get_instruction_parse_type};
+use super::diagnostic::Diagnostic;
+use num_traits::FromPrimitive;
 
 extern crate logos;
-use logos::{Logos, Lexer};
+use logos::{Logos, Lexer, Filter};
 
 use more_asserts::{assert_ge, debug_assert_ge};
 
 #[derive(Logos, Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Token {
     #[regex("[a-zA-Z_][a-zA-Z0-9_]*:")]
     Label,
@@ -35,15 +43,24 @@ pub enum Token {
     #[regex("\\$[a-z]+[0-9]?")]
     Reg,
 
-    #[regex("0x[A-Z0-9]+")]
+    #[regex("0x[0-9a-fA-F]+")]
     Hex,
 
+    #[regex("0b[01]+")]
+    Bin,
+
+    #[regex("0o[0-7]+")]
+    Oct,
+
     #[regex("[1-9][0-9]*|0")]
     Int,
 
     #[regex("\"([^\"\\\\]|\\\\.)*\"")]
     String,
 
+    #[regex("'([^'\\\\]|\\\\.|\\\\x[0-9a-fA-F]*|\\\\u\\{[0-9a-fA-F]*\\})'")]
+    Char,
+
     #[token("cpy")]
     KwCpy,
 
@@ -86,6 +103,27 @@ pub enum Token {
     #[token("sbi")]
     KwSbI,
 
+    #[token("mfs")]
+    KwMfs,
+
+    #[token("mts")]
+    KwMts,
+
+    #[token("ei")]
+    KwEi,
+
+    #[token("di")]
+    KwDi,
+
+    #[token("reti")]
+    KwReti,
+
+    #[token("amoswap")]
+    KwAmoSwap,
+
+    #[token("amoadd")]
+    KwAmoAdd,
+
     #[token("add")]
     KwAdd,
 
@@ -158,12 +196,69 @@ pub enum Token {
     #[token("slli")]
     KwSlli,
 
+    #[token("mov")]
+    KwMov,
+
+    #[token("push")]
+    KwPush,
+
+    #[token("pop")]
+    KwPop,
+
+    #[token("call")]
+    KwCall,
+
+    #[token("ret")]
+    KwRet,
+
+    #[token("la")]
+    KwLa,
+
+    #[token("inc")]
+    KwInc,
+
+    #[token("dec")]
+    KwDec,
+
     #[token(".i32")]
     KwMemI32,
 
+    #[token(".i8")]
+    KwMemI8,
+
     #[token(".str")]
     KwMemStr,
 
+    #[token(".asciiz")]
+    #[token(".strz")]
+    KwMemStrZ,
+
+    #[token(".space")]
+    #[token(".zero")]
+    KwMemSpace,
+
+    #[token(".isa")]
+    KwIsa,
+
+    #[token(".equ")]
+    #[token(".set")]
+    KwEqu,
+
+    #[token(".import")]
+    KwImport,
+
+    #[token(".entry")]
+    KwEntry,
+
+    #[token(".text")]
+    KwText,
+
+    #[token(".data")]
+    KwData,
+
+    #[regex("[a-zA-Z_][a-zA-Z0-9_]*")]
+    Ident,
+
     #[token(",")]
     Comma,
 
@@ -179,85 +274,138 @@ pub enum Token {
     #[token("/")]
     OpDiv,
 
+    #[token("&")]
+    OpAnd,
+
+    #[token("|")]
+    OpOr,
+
+    #[token("^")]
+    OpXor,
+
+    #[token("<<")]
+    OpShl,
+
+    #[token(">>")]
+    OpShr,
+
+    #[token("~")]
+    OpNot,
+
     #[token("(")]
     OpOpenBracket,
 
     #[token(")")]
     OpCloseBracket,
 
+    /// A semicolon separates statements like a new line
     #[regex("\n\r?|\r\n?")]
+    #[token(";")]
     NewLine,
 
     #[error]
     #[regex(r"[ \t\v]|//.*", logos::skip)]
+    #[token("/*", block_comment)]
     Error,
 
 }
 
-#[derive(Debug, PartialEq)]
-pub enum InstructionParseType {
-    TwoRegisters,
-    RegisterAndImmediate,
-    Register,
-    Immediate,
-    TwoRegistersAndImmediate,
+/// Assembler-level instructions, which are expanded to real instructions
+/// (see `PseudoInstruction::expand`)
+#[derive(Debug, PartialEq, Clone)]
+pub enum PseudoInstruction {
+    /// `cpy`
+    Mov(Register, Register),
+    /// `subi $sp, 4` and `sw`
+    Push(Register),
+    /// `lw` and `addi $sp, 4`
+    Pop(Register),
+    /// `jil`
+    Call(ImmediateExpr),
+    /// `j $ra`
+    Ret,
+    /// `li`
+    La(Register, ImmediateExpr),
+    /// `addi 1`
+    Inc(Register),
+    /// `subi 1`
+    Dec(Register),
 }
 
-pub fn get_instruction_parse_type(op_code: OpCode) -> InstructionParseType {
-    match op_code {
-        OpCode::CPY
-            | OpCode::LW
-            | OpCode::SW
-            | OpCode::LH
-            | OpCode::SH
-            | OpCode::LB
-            | OpCode::SB 
-            | OpCode::ADD
-            | OpCode::SUB
-            | OpCode::MUL
-            | OpCode::DIV
-            | OpCode::AND
-            | OpCode::OR
-            | OpCode::XOR
-            | OpCode::SRL
-            | OpCode::SLL => InstructionParseType::TwoRegisters,
-        OpCode::SRLI
-            | OpCode::SLLI
-            | OpCode::JZI
-            | OpCode::JNZI
-            | OpCode::JLZI
-            | OpCode::JGZI
-            | OpCode::LI
-            | OpCode::ADDI
-            | OpCode::SUBI
-            | OpCode::MULI
-            | OpCode::DIVI 
-            | OpCode::SWI
-            | OpCode::LWI
-            | OpCode::SHI
-            | OpCode::LHI
-            | OpCode::SBI
-            | OpCode::LBI => InstructionParseType::RegisterAndImmediate,
-        OpCode::NOT
-            | OpCode::J => InstructionParseType::Register,
-        OpCode::SYSCALLI
-            | OpCode::JI
-            | OpCode::JIL => InstructionParseType::Immediate,
+impl PseudoInstruction {
+    /// The real instructions
+    pub fn expand(&self) -> Vec<Expr> {
+        match self {
+            PseudoInstruction::Mov(reg0, reg1) => vec![Expr::InstructionTwoRegisters(OpCode::CPY, *reg0, *reg1)],
+            PseudoInstruction::Push(reg) => vec![
+                Expr::InstructionRegisterAndImmediate(OpCode::SUBI, Register::SP, ImmediateExpr::Int(4)),
+                Expr::InstructionTwoRegisters(OpCode::SW, *reg, Register::SP),
+            ],
+            PseudoInstruction::Pop(reg) => vec![
+                Expr::InstructionTwoRegisters(OpCode::LW, *reg, Register::SP),
+                Expr::InstructionRegisterAndImmediate(OpCode::ADDI, Register::SP, ImmediateExpr::Int(4)),
+            ],
+            PseudoInstruction::Call(imm) => vec![Expr::InstructionImmediate(OpCode::JIL, imm.clone())],
+            PseudoInstruction::Ret => vec![Expr::InstructionRegister(OpCode::J, Register::RA)],
+            PseudoInstruction::La(reg, imm) => vec![Expr::InstructionRegisterAndImmediate(OpCode::LI, *reg, imm.clone())],
+            PseudoInstruction::Inc(reg) => vec![Expr::InstructionRegisterAndImmediate(OpCode::ADDI, *reg, ImmediateExpr::Int(1))],
+            PseudoInstruction::Dec(reg) => vec![Expr::InstructionRegisterAndImmediate(OpCode::SUBI, *reg, ImmediateExpr::Int(1))],
+        }
+    }
+}
+
+/// Skips a block comment (they don't nest). Unterminated block comments are
+/// errors.
+fn block_comment(lex: &mut Lexer<Token>) -> Filter<()> {
+    match lex.remainder().find("*/") {
+        Some(end) => {
+            lex.bump(end + 2);
+            Filter::Skip
+        },
+        None => {
+            lex.bump(lex.remainder().len());
+            Filter::Emit(())
+        },
     }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
+    Instruction(OpCode),
     InstructionRegister(OpCode, Register),
     InstructionImmediate(OpCode, ImmediateExpr),
     InstructionTwoRegisters(OpCode, Register, Register),
     InstructionRegisterAndImmediate(OpCode, Register, ImmediateExpr),
     StoreI32(ImmediateExpr),
+    /// One byte (the value must fit into a signed or unsigned byte)
+    StoreI8(ImmediateExpr),
     StoreStr(String),
+    /// Zero bytes
+    StoreSpace(u32),
+    /// Assemble-time constant (used like a label)
+    Constant(String, ImmediateExpr),
+    /// Host function, the compiler assigns its syscall number (used like a
+    /// constant)
+    Import(String),
+    /// Expanded by the parser, never part of a parsed program
+    Pseudo(PseudoInstruction),
     Label(String),
+    Isa(IsaVersion),
+    /// Following expressions are part of the section
+    Section(Section),
+    /// Entry point of the program
+    Entry(ImmediateExpr),
     Error(),
 }
 
+/// Section of a program, the compiler places the code before the data
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Section {
+    /// Code (default)
+    Text,
+    Data,
+}
+
 #[derive(Debug, PartialEq, PartialOrd, Clone)]
 pub enum ImmediateExpr {
     Int(u32),
@@ -265,63 +413,137 @@ pub enum ImmediateExpr {
     Sub(Box<ImmediateExpr>, Box<ImmediateExpr>),
     Mul(Box<ImmediateExpr>, Box<ImmediateExpr>),
     Div(Box<ImmediateExpr>, Box<ImmediateExpr>),
+    And(Box<ImmediateExpr>, Box<ImmediateExpr>),
+    Or(Box<ImmediateExpr>, Box<ImmediateExpr>),
+    Xor(Box<ImmediateExpr>, Box<ImmediateExpr>),
+    /// Shifts by 32 or more bits result in 0
+    Shl(Box<ImmediateExpr>, Box<ImmediateExpr>),
+    /// Logical shift (zeros are shifted in)
+    Shr(Box<ImmediateExpr>, Box<ImmediateExpr>),
+    Not(Box<ImmediateExpr>),
     AddrToLabel(String),
 }
 
-#[derive(Debug, PartialEq, Clone)]
-#[repr(u32)]
-pub enum ParserErrorType {
-    /// Cannot lex expression
-    CannotParse,
-    /// Expected register token
-    ExpectedRegister,
-    ExpectedValidRegister,
-    ExpectedImmediate,
-    ExpectedValidImmediate,
-    ExpectedLabel,
-    ExpectedNewLine,
-    ExpectedToken(&'static Token),
-    CannotCompileExpression,
-    InvalidEscapeSquence
-}
+impl ImmediateExpr {
+    /// Value of the expression, if it doesn't depend on labels
+    pub fn constant(&self) -> Option<u32> {
+        self.evaluate(&HashMap::new())
+    }
+
+    /// Value of the expression with the values of `symbols`. Returns None,
+    /// if a symbol is missing or it divides by zero.
+    pub fn evaluate(&self, symbols: &HashMap<String, u32>) -> Option<u32> {
+        match self {
+            ImmediateExpr::Int(value) => Some(*value),
+            ImmediateExpr::Add(expr0, expr1) => Some(expr0.evaluate(symbols)?.wrapping_add(expr1.evaluate(symbols)?)),
+            ImmediateExpr::Sub(expr0, expr1) => Some(expr0.evaluate(symbols)?.wrapping_sub(expr1.evaluate(symbols)?)),
+            ImmediateExpr::Mul(expr0, expr1) => Some(expr0.evaluate(symbols)?.wrapping_mul(expr1.evaluate(symbols)?)),
+            ImmediateExpr::Div(expr0, expr1) => expr0.evaluate(symbols)?.checked_div(expr1.evaluate(symbols)?),
+            ImmediateExpr::And(expr0, expr1) => Some(expr0.evaluate(symbols)? & expr1.evaluate(symbols)?),
+            ImmediateExpr::Or(expr0, expr1) => Some(expr0.evaluate(symbols)? | expr1.evaluate(symbols)?),
+            ImmediateExpr::Xor(expr0, expr1) => Some(expr0.evaluate(symbols)? ^ expr1.evaluate(symbols)?),
+            ImmediateExpr::Shl(expr0, expr1) => {
+                let (value, shift) = (expr0.evaluate(symbols)?, expr1.evaluate(symbols)?);
+                Some(value.checked_shl(shift).unwrap_or(0))
+            },
+            ImmediateExpr::Shr(expr0, expr1) => {
+                let (value, shift) = (expr0.evaluate(symbols)?, expr1.evaluate(symbols)?);
+                Some(value.checked_shr(shift).unwrap_or(0))
+            },
+            ImmediateExpr::Not(expr) => Some(!expr.evaluate(symbols)?),
+            ImmediateExpr::AddrToLabel(label) => symbols.get(label).copied(),
+        }
+    }
 
-impl ToString for ParserErrorType {
-    fn to_string(&self) -> String {
-        use ParserErrorType::*;
-        (match self {
-            CannotParse => "CannotParse",
-            ExpectedRegister => "ExpectedRegister",
-            ExpectedValidRegister => "ExpectedValidRegister",
-            ExpectedImmediate => "ExpectedImmediate",
-            ExpectedValidImmediate => "ExpectedValidImmediate",
-            ExpectedLabel => "ExpectedLabel",
-            ExpectedNewLine => "ExpectedNewLine",
-            ExpectedToken(_) => "ExpectedToken",
-            CannotCompileExpression => "CannotCompileExpression",
-            InvalidEscapeSquence => "InvalidEscapeSquence"
-        }).to_string()
+    /// Labels (and constants) referenced by the expression, in source order
+    pub fn labels(&self) -> Vec<&String> {
+        match self {
+            ImmediateExpr::Int(_) => Vec::new(),
+            ImmediateExpr::AddrToLabel(label) => vec![label],
+            ImmediateExpr::Add(expr0, expr1)
+                | ImmediateExpr::Sub(expr0, expr1)
+                | ImmediateExpr::Mul(expr0, expr1)
+                | ImmediateExpr::Div(expr0, expr1)
+                | ImmediateExpr::And(expr0, expr1)
+                | ImmediateExpr::Or(expr0, expr1)
+                | ImmediateExpr::Xor(expr0, expr1)
+                | ImmediateExpr::Shl(expr0, expr1)
+                | ImmediateExpr::Shr(expr0, expr1) => {
+                let mut result = expr0.labels();
+                result.extend(expr1.labels());
+                result
+            },
+            ImmediateExpr::Not(expr) => expr.labels(),
+        }
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct ParserError {
-    pub pos: std::ops::Range<usize>,
-    pub err_type: ParserErrorType,
+/// Line (starting with 1) of the byte `pos` in `source`
+pub fn line_of(source: &str, pos: usize) -> usize {
+    source.bytes().take(pos).filter(|c| *c == b'\n').count() + 1
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ParserExpr {
     pub pos: std::ops::Range<usize>,
     pub expr: Expr
 }
 
+/// Suspicious code, which still compiles
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParserWarningType {
+    /// A label has the name of a register (or register alias)
+    LabelShadowsRegister,
+    /// A store instruction writes into the code of the program
+    StoreToCode,
+    /// The data after a label is removed, because the label is never
+    /// referenced (`CompileOptions::strip_unused`)
+    UnusedDataRemoved(String),
+}
+
+impl fmt::Display for ParserWarningType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use ParserWarningType::*;
+        f.write_str(match self {
+            LabelShadowsRegister => "LabelShadowsRegister",
+            StoreToCode => "StoreToCode",
+            UnusedDataRemoved(_) => "UnusedDataRemoved",
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ParserWarning {
+    pub pos: std::ops::Range<usize>,
+    pub warn_type: ParserWarningType,
+}
+
 pub struct ParserResult {
     pub program: Vec<ParserExpr>,
-    pub errors: Vec<ParserError>
+    pub errors: Vec<Diagnostic>,
+    pub warnings: Vec<ParserWarning>,
+}
+
+impl ParserResult {
+    /// Errors and warnings (in this order) as diagnostics
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        self.errors.iter().cloned()
+            .chain(self.warnings.iter().map(Diagnostic::from))
+            .collect()
+    }
 }
 
 struct Parser {
-    errors: Vec<ParserError>
+    errors: Vec<Diagnostic>,
+    warnings: Vec<ParserWarning>,
+    /// Constants, which don't depend on labels (for `.space`)
+    constants: HashMap<String, u32>,
+    /// Start of the first token after the last statement separator
+    statement_start: usize,
+    /// End of the bytes the lexer looked at so far
+    lookahead: usize,
 }
 
 pub fn parse_str(program: &'static str) -> ParserResult {
@@ -337,13 +559,99 @@ pub fn parse_string(program: &String) -> ParserResult {
 }
 
 pub fn parse(lex: &mut Lexer<Token>) -> ParserResult {
-    let mut program: Vec<ParserExpr> = Vec::new();
-    let mut parser = Parser { errors: Vec::new() };
+    let mut result = ParserResult { program: Vec::new(), errors: Vec::new(), warnings: Vec::new() };
+    parse_statements(lex, &mut result, HashMap::new(), 0, 0, |_, _| false);
+    result
+}
+
+/// Statement boundary of a parsed program, from which parsing can be
+/// resumed (see `incremental::IncrementalCompiler`): the parser was at the
+/// token starting at `offset` after `statements` statements, `errors` errors
+/// and `warnings` warnings. Everything before depends only on the bytes
+/// before `lookahead`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) struct Checkpoint {
+    pub(crate) offset: usize,
+    pub(crate) lookahead: usize,
+    pub(crate) statements: usize,
+    pub(crate) errors: usize,
+    pub(crate) warnings: usize,
+}
 
-    let mut tok = lex.next();
+/// Parses `program` like `parse_string` and returns every statement boundary
+pub(crate) fn parse_with_checkpoints(program: &str) -> (ParserResult, Vec<Checkpoint>) {
+    let mut result = ParserResult { program: Vec::new(), errors: Vec::new(), warnings: Vec::new() };
+    let mut checkpoints = Vec::new();
+    parse_statements(&mut Token::lexer(program), &mut result, HashMap::new(), 0, 0, |checkpoint, _| {
+        checkpoints.push(checkpoint);
+        false
+    });
+
+    (result, checkpoints)
+}
+
+/// Parses `program` from the checkpoint `start` with the `constants` of
+/// the statements before it (`evaluate_constants`) and appends the
+/// statements, errors and warnings to `result`. Every following checkpoint
+/// is given to `stop` with the constants known there, parsing ends at the
+/// first one `stop` accepts (returned).
+pub(crate) fn parse_from(program: &str, start: Checkpoint, constants: HashMap<String, u32>, result: &mut ParserResult, stop: impl FnMut(Checkpoint, &HashMap<String, u32>) -> bool) -> Option<Checkpoint> {
+    let mut lex = Token::lexer(program);
+    lex.bump(start.offset);
+    parse_statements(&mut lex, result, constants, start.offset, start.lookahead, stop)
+}
+
+/// Adds the constants of `program`, which don't depend on labels, to
+/// `constants` (like the parser does)
+pub(crate) fn evaluate_constants(constants: &mut HashMap<String, u32>, program: &[ParserExpr]) {
+    for expr in program {
+        if let Expr::Constant(name, imm) = &expr.expr {
+            if let Some(value) = imm.evaluate(constants) {
+                constants.insert(name.clone(), value);
+            }
+        }
+    }
+}
+
+/// Parses the statements of `lex` into `result`, the parser starts with
+/// `constants` at a statement boundary (`statement_start`), after the lexer
+/// looked at the bytes before `lookahead`
+fn parse_statements(lex: &mut Lexer<Token>, result: &mut ParserResult, constants: HashMap<String, u32>, statement_start: usize, lookahead: usize, mut checkpoint: impl FnMut(Checkpoint, &HashMap<String, u32>) -> bool) -> Option<Checkpoint> {
+    let mut parser = Parser {
+        errors: std::mem::take(&mut result.errors),
+        warnings: std::mem::take(&mut result.warnings),
+        constants,
+        statement_start,
+        lookahead,
+    };
+
+    let mut tok = None;
+    parser.next(&mut tok, lex);
     let mut pos = lex.span();
-    while let Some(expr) = parser.parse_expr(&mut tok, lex) {
-        program.push(expr);
+    let mut stopped = None;
+    loop {
+        // The parser only depends on the constants here (a line break
+        // resets the start of the statement)
+        if tok.is_some() && (tok == Some(Token::NewLine) || lex.span().start == parser.statement_start) {
+            let current = Checkpoint { offset: lex.span().start, lookahead: parser.lookahead, statements: result.program.len(), errors: parser.errors.len(), warnings: parser.warnings.len() };
+            if checkpoint(current, &parser.constants) {
+                stopped = Some(current);
+                break;
+            }
+        }
+
+        let errors_before = parser.errors.len();
+        let expr = match parser.parse_expr(&mut tok, lex) {
+            Some(expr) => expr,
+            None => break,
+        };
+
+        // Also statements, which are followed by garbage, are broken
+        if expr.expr == Expr::Error() || parser.errors.len() > errors_before {
+            parser.recover(errors_before, expr.pos.clone(), &mut tok, lex);
+        }
+
+        result.program.extend(expand_pseudo_instruction(expr));
 
         // Check position to avoid endless loop
         let new_pos = lex.span();
@@ -356,7 +664,19 @@ pub fn parse(lex: &mut Lexer<Token>) -> ParserResult {
         pos = new_pos;
     }
 
-    return ParserResult { program, errors: parser.errors };
+    result.errors = parser.errors;
+    result.warnings = parser.warnings;
+    stopped
+}
+
+/// Replaces a pseudo-instruction with its real instructions
+fn expand_pseudo_instruction(expr: ParserExpr) -> Vec<ParserExpr> {
+    match expr.expr {
+        Expr::Pseudo(ref pseudo) => pseudo.expand().into_iter()
+            .map(|real| ParserExpr { pos: expr.pos.clone(), expr: real })
+            .collect(),
+        _ => vec![expr],
+    }
 }
 
 /// Combine two ranges, range0 is the lower bound and range1 is the upper bound
@@ -364,11 +684,120 @@ fn combine_range<Idx>(range0: std::ops::Range<Idx>, range1: std::ops::Range<Idx>
     return range0.start..range1.end;
 }
 
+/// Register with the name `name` (without `$`, ABI aliases are allowed)
+pub fn get_register_by_name(name: &str) -> Option<Register> {
+    match name {
+        "r0" => Some(Register::R0),
+        "r1" => Some(Register::R1),
+        "r2" => Some(Register::R2),
+        "r3" => Some(Register::R3),
+        "r4" => Some(Register::R4),
+        "r5" => Some(Register::R5),
+        "r6" => Some(Register::R6),
+        "r7" => Some(Register::R7),
+        "ip" => Some(Register::IP),
+        "ra" => Some(Register::RA),
+        "sp" => Some(Register::SP),
+        "err" => Some(Register::ERR),
+        // ABI aliases
+        "rv" => Some(Register::R0),
+        "a0" => Some(Register::R1),
+        "a1" => Some(Register::R2),
+        "t0" => Some(Register::R3),
+        "t1" => Some(Register::R4),
+        "t2" => Some(Register::R5),
+        "s0" => Some(Register::R6),
+        "s1" => Some(Register::R7),
+        _ => None,
+    }
+}
+
+/// Parses the escape sequence after a `\\` (`\\n`, `\\x41` or `\\u{20AC}`,
+/// `\\x` is limited to ASCII)
+fn parse_escape(chars: &mut Peekable<CharIndices>) -> Result<char, EscapeError> {
+    parse_escape_value(chars).ok_or(EscapeError::Invalid).and_then(|c| match c {
+        Escape::Char(c) => Ok(c),
+        Escape::Byte(value) if value <= 0x7F => Ok(char::from(value)),
+        Escape::Byte(value) => Err(EscapeError::NotAscii(value)),
+    })
+}
+
+/// Error of an escape sequence, the caller knows its position
+enum EscapeError {
+    /// Invalid or truncated
+    Invalid,
+    /// `\\x` above `\\x7F`
+    NotAscii(u8),
+}
+
+impl EscapeError {
+    fn diagnostic(self, span: std::ops::Range<usize>) -> Diagnostic {
+        match self {
+            EscapeError::Invalid => Diagnostic::invalid_escape_sequence(span),
+            EscapeError::NotAscii(value) => Diagnostic::byte_escape_out_of_range(span, value),
+        }
+    }
+}
+
+/// Escape sequence before checking the range of `\\x`
+enum Escape {
+    Char(char),
+    Byte(u8),
+}
+
+fn parse_escape_value(chars: &mut Peekable<CharIndices>) -> Option<Escape> {
+    match chars.next()?.1 {
+        'x' => {
+            let high = chars.next()?.1.to_digit(16)?;
+            let low = chars.next()?.1.to_digit(16)?;
+            Some(Escape::Byte((high * 16 + low) as u8))
+        },
+        'u' => {
+            if chars.next()?.1 != '{' {
+                return None;
+            }
+
+            let mut digits = String::new();
+            while let Some((_, c)) = chars.next_if(|(_, c)| c.is_ascii_hexdigit()) {
+                digits.push(c);
+            }
+
+            if digits.is_empty() || digits.len() > 6 || chars.next()?.1 != '}' {
+                return None;
+            }
+
+            u32::from_str_radix(&digits, 16).ok().and_then(char::from_u32).map(Escape::Char)
+        },
+        c => escaped_char(c).map(Escape::Char),
+    }
+}
+
+/// Character of the escape sequence `\\c` (None, if it's invalid)
+fn escaped_char(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        '0' => Some('\0'),
+        '"' => Some('"'),
+        '\'' => Some('\''),
+        '\\' => Some('\\'),
+        _ => None,
+    }
+}
+
 impl Parser {
     /// Advance to next token
     fn next<'source>(&mut self, tok: &'source mut Option<Token>, lex: &mut Lexer<Token>) -> &'source mut Option<Token>
     {
         *tok = lex.next();
+        log::trace!("Token {:?} at {:?}", tok, lex.span());
+        // The lexer looks at up to two bytes after a token (like `0x`
+        // without digits), errors like unterminated strings past the end
+        self.lookahead = self.lookahead.max(match tok {
+            Some(Token::Error) => lex.source().len() + 1,
+            _ => lex.span().end + 2,
+        });
         return tok;
     }
 
@@ -393,6 +822,13 @@ impl Parser {
              Token::KwShI => self.parse_instruction(OpCode::SHI, current, lex),
              Token::KwLbI => self.parse_instruction(OpCode::LBI, current, lex),
              Token::KwSbI => self.parse_instruction(OpCode::SBI, current, lex),
+             Token::KwMfs => self.parse_instruction(OpCode::MFS, current, lex),
+             Token::KwMts => self.parse_instruction(OpCode::MTS, current, lex),
+             Token::KwEi => self.parse_instruction(OpCode::EI, current, lex),
+             Token::KwDi => self.parse_instruction(OpCode::DI, current, lex),
+             Token::KwReti => self.parse_instruction(OpCode::RETI, current, lex),
+             Token::KwAmoSwap => self.parse_instruction(OpCode::AMOSWAP, current, lex),
+             Token::KwAmoAdd => self.parse_instruction(OpCode::AMOADD, current, lex),
              Token::KwAdd => self.parse_instruction(OpCode::ADD, current, lex),
              Token::KwSub => self.parse_instruction(OpCode::SUB, current, lex),
              Token::KwMul => self.parse_instruction(OpCode::MUL, current, lex),
@@ -417,23 +853,47 @@ impl Parser {
              Token::KwJlzi => self.parse_instruction(OpCode::JLZI, current, lex),
              Token::KwJgzi => self.parse_instruction(OpCode::JGZI, current, lex),
              Token::KwSyscalli => self.parse_instruction(OpCode::SYSCALLI, current, lex),
+             Token::KwMov => self.parse_pseudo_instruction(OpCode::CPY, current, lex),
+             Token::KwPush | Token::KwPop | Token::KwInc | Token::KwDec => self.parse_pseudo_instruction(OpCode::J, current, lex),
+             Token::KwCall => self.parse_pseudo_instruction(OpCode::JIL, current, lex),
+             Token::KwRet => self.parse_pseudo_instruction(OpCode::RETI, current, lex),
+             Token::KwLa => self.parse_pseudo_instruction(OpCode::LI, current, lex),
              Token::Label => self.parse_label(current, lex),
              Token::AddrToLabel => ParserExpr { pos: lex.span(), expr : Expr::Error() },
              Token::Reg => ParserExpr { pos: lex.span(), expr: Expr::Error() },
              Token::Hex => ParserExpr { pos: lex.span(), expr: Expr::Error() },
+             Token::Bin => ParserExpr { pos: lex.span(), expr: Expr::Error() },
+             Token::Oct => ParserExpr { pos: lex.span(), expr: Expr::Error() },
              Token::Int => ParserExpr { pos: lex.span(), expr: Expr::Error() },
              Token::String => ParserExpr { pos: lex.span(), expr: Expr::Error() },
+             Token::Ident => ParserExpr { pos: lex.span(), expr: Expr::Error() },
+             Token::Char => ParserExpr { pos: lex.span(), expr: Expr::Error() },
              Token::Comma => ParserExpr { pos: lex.span(), expr: Expr::Error() },
              Token::OpAdd => ParserExpr { pos: lex.span(), expr: Expr::Error() },
              Token::OpSub => ParserExpr { pos: lex.span(), expr: Expr::Error() },
              Token::OpMul => ParserExpr { pos: lex.span(), expr: Expr::Error() },
              Token::OpDiv => ParserExpr { pos: lex.span(), expr: Expr::Error() },
+             Token::OpAnd => ParserExpr { pos: lex.span(), expr: Expr::Error() },
+             Token::OpOr => ParserExpr { pos: lex.span(), expr: Expr::Error() },
+             Token::OpXor => ParserExpr { pos: lex.span(), expr: Expr::Error() },
+             Token::OpShl => ParserExpr { pos: lex.span(), expr: Expr::Error() },
+             Token::OpShr => ParserExpr { pos: lex.span(), expr: Expr::Error() },
+             Token::OpNot => ParserExpr { pos: lex.span(), expr: Expr::Error() },
              Token::OpOpenBracket => ParserExpr { pos: lex.span(), expr: Expr::Error() },
              Token::OpCloseBracket => ParserExpr { pos: lex.span(), expr: Expr::Error() },
              Token::NewLine => ParserExpr { pos: lex.span(), expr: Expr::Error() },
              Token::Error  => ParserExpr { pos: lex.span(), expr: Expr::Error() },
              Token::KwMemI32 => self.parse_mem_i32(current, lex),
+             Token::KwMemI8 => self.parse_mem_i8(current, lex),
              Token::KwMemStr => self.parse_mem_str(current, lex),
+             Token::KwMemStrZ => self.parse_mem_strz(current, lex),
+             Token::KwMemSpace => self.parse_mem_space(current, lex),
+             Token::KwIsa => self.parse_isa(current, lex),
+             Token::KwEqu => self.parse_equ(current, lex),
+             Token::KwImport => self.parse_import(current, lex),
+             Token::KwEntry => self.parse_entry(current, lex),
+             Token::KwText => self.parse_section(Section::Text, current, lex),
+             Token::KwData => self.parse_section(Section::Data, current, lex),
          })
     }
 
@@ -459,34 +919,26 @@ impl Parser {
             let tokstr = lex.slice();
             let tokstr = tokstr.get(1..(tokstr.len() - 1)).expect("Made sure by lexer").to_string();
 
+            let content_start = pos.start + 1; // After the quote
             let mut result = String::with_capacity(tokstr.len());
-            let mut i = 0;
-            while i < tokstr.len() {
-                let c: char = tokstr.chars().nth(i).unwrap();
+            let mut chars = tokstr.char_indices().peekable();
+            while let Some((i, c)) = chars.next() {
                 if c == '\\' {
                     // Escape sequence
-                    i += 1;
-                    let c: char = tokstr.chars().nth(i).unwrap();
-                    let c = match c {
-                        'n' => '\n',
-                        'r' => '\r',
-                        't' => '\t',
-                        '0' => '\0',
-                        '"' => '"',
-                        '\'' => '\'',
-                        _ => {
-                            self.errors.push(ParserError { pos: pos.start+i..pos.start+i, err_type: ParserErrorType::InvalidEscapeSquence });
+                    let c = match parse_escape(&mut chars) {
+                        Ok(c) => c,
+                        Err(err) => {
+                            let end = chars.peek().map(|(end, _)| *end).unwrap_or_else(|| tokstr.len());
+                            self.errors.push(err.diagnostic(content_start+i..content_start+end));
                             '?'
                         }
                     };
 
-                    result += c.to_string().as_str();
+                    result.push(c);
                 }
                 else {
-                    result += c.to_string().as_str();
+                    result.push(c);
                 }
-
-                i += 1;
             }
 
             self.next(tok, lex);
@@ -512,19 +964,202 @@ impl Parser {
         return ParserExpr { pos, expr: result };
     }
 
+    pub fn parse_mem_i8(&mut self, tok: &mut Option<Token>, lex: &mut Lexer<Token>) -> ParserExpr {
+        self.next(tok, lex);
+
+        let pos = lex.span();
+        let result = if let Some(expr) = self.parse_immediate(tok, lex) {
+            self.expect_newline(tok, lex);
+            Expr::StoreI8(expr)
+        }
+        else {
+            Expr::Error()
+        };
+
+        ParserExpr { pos, expr: result }
+    }
+
+    /// Like `.str`, but with a terminating NUL
+    pub fn parse_mem_strz(&mut self, tok: &mut Option<Token>, lex: &mut Lexer<Token>) -> ParserExpr {
+        let mut result = self.parse_mem_str(tok, lex);
+        if let Expr::StoreStr(string) = &mut result.expr {
+            string.push('\0');
+        }
+
+        result
+    }
+
+    /// The size of `.space` must be known before the labels, so it can't
+    /// depend on them (only on constants defined before)
+    pub fn parse_mem_space(&mut self, tok: &mut Option<Token>, lex: &mut Lexer<Token>) -> ParserExpr {
+        self.next(tok, lex);
+
+        let pos = lex.span();
+        let result = match self.parse_immediate(tok, lex) {
+            Some(expr) => {
+                if let Some(size) = expr.evaluate(&self.constants) {
+                    self.expect_newline(tok, lex);
+                    Expr::StoreSpace(size)
+                }
+                else {
+                    self.errors.push(Diagnostic::expected_valid_immediate(combine_range(pos.clone(), lex.span())));
+                    Expr::Error()
+                }
+            },
+            None => Expr::Error(),
+        };
+
+        ParserExpr { pos, expr: result }
+    }
+
+    pub fn parse_equ(&mut self, tok: &mut Option<Token>, lex: &mut Lexer<Token>) -> ParserExpr {
+        self.next(tok, lex);
+
+        let pos = lex.span();
+        if *tok != Some(Token::Ident) {
+            self.errors.push(Diagnostic::expected_label(pos));
+            return ParserExpr { pos: lex.span(), expr: Expr::Error() };
+        }
+
+        let name = lex.slice().to_string();
+        self.next(tok, lex); // eat name
+        if !self.eat_token(tok, lex, &Token::Comma) {
+            return ParserExpr { pos, expr: Expr::Error() };
+        }
+
+        let result = if let Some(expr) = self.parse_immediate(tok, lex) {
+            self.expect_newline(tok, lex);
+            if let Some(value) = expr.evaluate(&self.constants) {
+                self.constants.insert(name.clone(), value);
+            }
+
+            Expr::Constant(name, expr)
+        }
+        else {
+            Expr::Error()
+        };
+
+        ParserExpr { pos, expr: result }
+    }
+
+    pub fn parse_import(&mut self, tok: &mut Option<Token>, lex: &mut Lexer<Token>) -> ParserExpr {
+        self.next(tok, lex);
+
+        let pos = lex.span();
+        if *tok != Some(Token::Ident) {
+            self.errors.push(Diagnostic::expected_label(pos));
+            return ParserExpr { pos: lex.span(), expr: Expr::Error() };
+        }
+
+        let name = lex.slice().to_string();
+        self.next(tok, lex); // eat name
+        let result = if self.expect_newline(tok, lex) {
+            Expr::Import(name)
+        }
+        else {
+            Expr::Error()
+        };
+
+        ParserExpr { pos, expr: result }
+    }
+
+    pub fn parse_entry(&mut self, tok: &mut Option<Token>, lex: &mut Lexer<Token>) -> ParserExpr {
+        let pos = lex.span();
+        self.next(tok, lex);
+
+        // `.entry label` or `.entry immediate`
+        let entry = if let Some(Token::Ident) = tok {
+            let label = lex.slice().to_string();
+            self.next(tok, lex);
+            Some(ImmediateExpr::AddrToLabel(label))
+        }
+        else {
+            self.parse_immediate(tok, lex)
+        };
+
+        let result = match entry {
+            Some(entry) if self.expect_newline(tok, lex) => Expr::Entry(entry),
+            _ => Expr::Error()
+        };
+
+        ParserExpr { pos, expr: result }
+    }
+
+    pub fn parse_section(&mut self, section: Section, tok: &mut Option<Token>, lex: &mut Lexer<Token>) -> ParserExpr {
+        let pos = lex.span();
+        self.next(tok, lex);
+        let result = if self.expect_newline(tok, lex) {
+            Expr::Section(section)
+        }
+        else {
+            Expr::Error()
+        };
+
+        ParserExpr { pos, expr: result }
+    }
+
+    pub fn parse_isa(&mut self, tok: &mut Option<Token>, lex: &mut Lexer<Token>) -> ParserExpr {
+        self.next(tok, lex);
+
+        let pos = lex.span();
+        let version = if let Some(Token::Int) = tok {
+            lex.slice().parse::<u8>().ok().and_then(IsaVersion::from_u8)
+        }
+        else {
+            None
+        };
+
+        let result = if let Some(version) = version {
+            self.next(tok, lex); // eat version
+            self.expect_newline(tok, lex);
+            Expr::Isa(version)
+        }
+        else {
+            self.errors.push(Diagnostic::invalid_isa_version(lex.span()));
+            Expr::Error()
+        };
+
+        ParserExpr { pos, expr: result }
+    }
+
     pub fn parse_label(&mut self, tok: &mut Option<Token>, lex: &mut Lexer<Token>) -> ParserExpr {
         let pos = lex.span();
         if let Some(Token::Label) = tok {
-            let result = Expr::Label(lex.slice().get(0..(lex.slice().len() - 1)).expect("Made sure by lexer").to_string());
+            let label = lex.slice().get(0..(lex.slice().len() - 1)).expect("Made sure by lexer");
+            if get_register_by_name(label).is_some() {
+                self.warnings.push(ParserWarning { pos: pos.clone(), warn_type: ParserWarningType::LabelShadowsRegister });
+            }
+
+            let result = Expr::Label(label.to_string());
             self.next(tok, lex);
             ParserExpr { pos, expr: result }
         }
         else {
-            self.errors.push(ParserError { pos: lex.span(), err_type: ParserErrorType::ExpectedLabel });
+            self.errors.push(Diagnostic::expected_label(lex.span()));
             ParserExpr { pos, expr: Expr::Error() }
         }
     }
     
+    /// Parses the pseudo-instruction `tok`, which has the operands of
+    /// `operands_like`
+    pub fn parse_pseudo_instruction(&mut self, operands_like: OpCode, tok: &mut Option<Token>, lex: &mut Lexer<Token>) -> ParserExpr {
+        let pseudo_tok = *tok;
+        let expr = self.parse_instruction(operands_like, tok, lex);
+        let pseudo = match (pseudo_tok, expr.expr) {
+            (Some(Token::KwMov), Expr::InstructionTwoRegisters(_, reg0, reg1)) => PseudoInstruction::Mov(reg0, reg1),
+            (Some(Token::KwPush), Expr::InstructionRegister(_, reg)) => PseudoInstruction::Push(reg),
+            (Some(Token::KwPop), Expr::InstructionRegister(_, reg)) => PseudoInstruction::Pop(reg),
+            (Some(Token::KwInc), Expr::InstructionRegister(_, reg)) => PseudoInstruction::Inc(reg),
+            (Some(Token::KwDec), Expr::InstructionRegister(_, reg)) => PseudoInstruction::Dec(reg),
+            (Some(Token::KwCall), Expr::InstructionImmediate(_, imm)) => PseudoInstruction::Call(imm),
+            (Some(Token::KwRet), Expr::Instruction(_)) => PseudoInstruction::Ret,
+            (Some(Token::KwLa), Expr::InstructionRegisterAndImmediate(_, reg, imm)) => PseudoInstruction::La(reg, imm),
+            _ => return ParserExpr { pos: expr.pos, expr: Expr::Error() },
+        };
+
+        ParserExpr { pos: expr.pos, expr: Expr::Pseudo(pseudo) }
+    }
+
     pub fn parse_instruction(&mut self, op_code: OpCode, tok: &mut Option<Token>, lex: &mut Lexer<Token>) -> ParserExpr {
         let start = lex.span();
 
@@ -580,7 +1215,11 @@ impl Parser {
             },
             InstructionParseType::TwoRegistersAndImmediate => {
                 ParserExpr { pos: start.clone(), expr: Expr::Error() }
-            }
+            },
+            InstructionParseType::NoOperands => {
+                self.next(tok, lex);
+                ParserExpr { pos: start.clone(), expr: Expr::Instruction(op_code) }
+            },
         };
 
         if !self.expect_newline(tok, lex) {
@@ -592,7 +1231,7 @@ impl Parser {
 
     fn expect_token(&mut self, tok: &mut Option<Token>, lex: &mut Lexer<Token>, expect: &'static Token) -> bool {
         if *tok != Some(*expect) {
-            self.errors.push(ParserError { pos: lex.span(), err_type: ParserErrorType::ExpectedToken(expect) });
+            self.errors.push(Diagnostic::expected_token(lex.span(), expect));
             return false;
         }
 
@@ -609,9 +1248,32 @@ impl Parser {
         return false;
     }
 
+    /// Synchronizes after the broken statement at `pos`: keeps only its first
+    /// error (or reports one, if there's none) and skips the rest of the
+    /// statement (up to the next new line or label)
+    fn recover(&mut self, errors_before: usize, pos: std::ops::Range<usize>, tok: &mut Option<Token>, lex: &mut Lexer<Token>) {
+        if self.errors.len() == errors_before {
+            self.errors.push(Diagnostic::cannot_parse(pos.clone()));
+        }
+        self.errors.truncate(errors_before + 1);
+
+        let progressed = tok.is_some() && lex.span().start > pos.start;
+        if progressed && lex.span().start == self.statement_start {
+            return; // The statement already ended
+        }
+
+        if !progressed && tok.is_some() {
+            self.next(tok, lex);
+        }
+
+        while !matches!(tok, None | Some(Token::NewLine) | Some(Token::Label)) {
+            self.next(tok, lex);
+        }
+    }
+
     fn expect_newline(&mut self, tok: &mut Option<Token>, lex: &mut Lexer<Token>) -> bool {
         if !self.advance_newlines(tok, lex) {
-            self.errors.push(ParserError { pos: lex.span(), err_type: ParserErrorType::ExpectedValidImmediate });
+            self.errors.push(Diagnostic::expected_new_line(lex.span()));
             return false;
         }
 
@@ -628,6 +1290,9 @@ impl Parser {
             self.next(tok, lex);
             result = true;
         }
+        if result {
+            self.statement_start = lex.span().start;
+        }
         return result;
     }
 
@@ -644,11 +1309,60 @@ impl Parser {
                     self.next(current, lex); // eat int
                     result
                 },
+                Token::Hex | Token::Bin | Token::Oct => {
+                    let radix = match tok {
+                        Token::Hex => 16,
+                        Token::Bin => 2,
+                        _ => 8,
+                    };
+
+                    // Skip the prefix (like 0x)
+                    let value = lex.slice().get(2..).and_then(|digits| u32::from_str_radix(digits, radix).ok());
+                    if value.is_none() {
+                        self.errors.push(Diagnostic::expected_valid_immediate(lex.span()));
+                    }
+
+                    self.next(current, lex); // eat number
+                    value.map(ImmediateExpr::Int)
+                },
+                Token::Char => {
+                    let tokstr = lex.slice();
+                    let mut chars = tokstr.get(1..(tokstr.len() - 1)).expect("Made sure by lexer").char_indices().peekable();
+                    let c = match chars.next() {
+                        Some((_, '\\')) => parse_escape(&mut chars)
+                            .and_then(|c| if chars.next().is_none() { Ok(c) } else { Err(EscapeError::Invalid) }),
+                        c => c.map(|(_, c)| c).ok_or(EscapeError::Invalid),
+                    };
+
+                    let result = match c {
+                        Ok(c) if (c as u32) <= u8::MAX as u32 => Some(ImmediateExpr::Int(c as u32)),
+                        Ok(_) => {
+                            self.errors.push(Diagnostic::expected_valid_immediate(lex.span()));
+                            None
+                        },
+                        Err(err) => {
+                            self.errors.push(err.diagnostic(lex.span()));
+                            None
+                        },
+                    };
+
+                    self.next(current, lex); // eat char
+                    result
+                },
                 Token::AddrToLabel => {
                     let result = Some(ImmediateExpr::AddrToLabel(lex.slice().get(1..).expect("Made sure by lexer").into()));
                     self.next(current, lex); // eat addr_to_label
                     result
                 },
+                Token::OpNot => {
+                    self.next(current, lex); // eat ~
+                    self.parse_immediate_primary(current, lex).map(|expr| ImmediateExpr::Not(Box::new(expr)))
+                },
+                Token::OpSub => {
+                    self.next(current, lex); // eat -
+                    // Negative: 0 - expr
+                    self.parse_immediate_primary(current, lex).map(|expr| ImmediateExpr::Sub(Box::new(ImmediateExpr::Int(0)), Box::new(expr)))
+                },
                 Token::OpOpenBracket => {
                     self.next(current, lex); // eat (
                     let result = self.parse_immediate(current, lex);
@@ -657,13 +1371,13 @@ impl Parser {
                     result
                 },
                 _ => {
-                    self.errors.push(ParserError { pos: lex.span(), err_type: ParserErrorType::ExpectedValidImmediate });
+                    self.errors.push(Diagnostic::expected_valid_immediate(lex.span()));
                     None
                 }
             }
         }
         else {
-            self.errors.push(ParserError { pos: lex.span(), err_type: ParserErrorType::ExpectedImmediate });
+            self.errors.push(Diagnostic::expected_immediate(lex.span()));
             None
         }
     }
@@ -684,7 +1398,6 @@ impl Parser {
                 let mut prec_next_op = Self::get_precedence(current);
                 while prec_next_op > prec_cur_op {
                     rhs = self.parse_immediate_expr(current, lex, rhs.clone(), prec_cur_op + 1);
-                    self.next(current, lex);
                     prec_next_op = Self::get_precedence(current);
                 }
 
@@ -710,6 +1423,21 @@ impl Parser {
             Token::OpDiv => {
                 ImmediateExpr::Div(Box::new(lhs), Box::new(rhs))
             },
+            Token::OpAnd => {
+                ImmediateExpr::And(Box::new(lhs), Box::new(rhs))
+            },
+            Token::OpOr => {
+                ImmediateExpr::Or(Box::new(lhs), Box::new(rhs))
+            },
+            Token::OpXor => {
+                ImmediateExpr::Xor(Box::new(lhs), Box::new(rhs))
+            },
+            Token::OpShl => {
+                ImmediateExpr::Shl(Box::new(lhs), Box::new(rhs))
+            },
+            Token::OpShr => {
+                ImmediateExpr::Shr(Box::new(lhs), Box::new(rhs))
+            },
             _ => {
                 panic!("Not implemented for {:?}", op);
             }
@@ -719,8 +1447,13 @@ impl Parser {
     fn get_precedence(tok: &Option<Token>) -> usize {
         return if let Some(tok) = tok {
             match tok  {
-                Token::OpAdd | Token::OpSub => 1,
-                Token::OpMul | Token::OpDiv => 2,
+                // Like in C: | ^ & (<< >>) (+ -) (* /)
+                Token::OpOr => 1,
+                Token::OpXor => 2,
+                Token::OpAnd => 3,
+                Token::OpShl | Token::OpShr => 4,
+                Token::OpAdd | Token::OpSub => 5,
+                Token::OpMul | Token::OpDiv => 6,
                 _ => 0
             }
         }
@@ -732,24 +1465,10 @@ impl Parser {
     fn parse_register(&mut self, tok: &mut Option<Token>, lex: &mut Lexer<Token>) -> Option<Register> {
         return if let Some(Token::Reg) = *tok {
             let reg = lex.slice().get(1..).expect("It starts with $, damit!");
-            let result = match reg {
-                "r0" => Some(Register::R0),
-                "r1" => Some(Register::R1),
-                "r2" => Some(Register::R2),
-                "r3" => Some(Register::R3),
-                "r4" => Some(Register::R4),
-                "r5" => Some(Register::R5),
-                "r6" => Some(Register::R6),
-                "r7" => Some(Register::R7),
-                "ip" => Some(Register::IP),
-                "ra" => Some(Register::RA),
-                "sp" => Some(Register::SP),
-                "err" => Some(Register::ERR),
-                _ => {
-                    self.errors.push(ParserError { pos: lex.span(), err_type: ParserErrorType::ExpectedValidRegister });
-                    None
-                }
-            };
+            let result = get_register_by_name(reg);
+            if result.is_none() {
+                self.errors.push(Diagnostic::expected_valid_register(lex.span()));
+            }
 
             if result != None {
                 self.next(tok, lex); // eat register token
@@ -758,7 +1477,7 @@ impl Parser {
             result
         }
         else {
-            self.errors.push(ParserError { pos: lex.span(), err_type: ParserErrorType::ExpectedRegister });
+            self.errors.push(Diagnostic::expected_register(lex.span()));
             None
         };
     }
@@ -766,8 +1485,9 @@ impl Parser {
 
 #[cfg(test)]
 mod tests {
-    use crate::common::{OpCode, Register};
-    use super::{Token, parse_str, parse_string, ParserResult, Expr, ImmediateExpr};
+    use crate::common::{OpCode, Register, IsaVersion};
+    use super::{Token, parse_str, parse_string, ParserWarningType, Expr, ImmediateExpr, Section};
+    use crate::diagnostic::{codes, Diagnostic};
     use logos::{Logos, Lexer};
 
     #[test]
@@ -1030,6 +1750,266 @@ mod tests {
 
         let mut lex = Token::lexer("0x0123456789");
         assert_eq!(Some(Token::Hex), lex.next());
+
+        let mut lex = Token::lexer("0xabcdef");
+        assert_eq!(Some(Token::Hex), lex.next());
+        assert_eq!(None, lex.next());
+    }
+
+    #[test]
+    fn bin_and_oct() {
+        let mut lex = Token::lexer("0b1010");
+        assert_eq!(Some(Token::Bin), lex.next());
+        assert_eq!(None, lex.next());
+
+        let mut lex = Token::lexer("0o755");
+        assert_eq!(Some(Token::Oct), lex.next());
+        assert_eq!(None, lex.next());
+
+        let result = parse_str("li $r0, 0b1010\nli $r1, 0o755");
+        assert_eq!(Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R0, ImmediateExpr::Int(10)), result.program[0].expr);
+        assert_eq!(Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R1, ImmediateExpr::Int(0o755)), result.program[1].expr);
+
+        // Doesn't fit into 32 bits
+        let result = parse_str(".i32 0b100000000000000000000000000000000");
+        assert_eq!(Some(codes::EXPECTED_VALID_IMMEDIATE), result.errors.first().map(|error| error.code));
+    }
+
+    #[test]
+    fn parse_char_immediates() {
+        let mut lex = Token::lexer("'A'");
+        assert_eq!(Some(Token::Char), lex.next());
+        assert_eq!(None, lex.next());
+
+        let result = parse_str("li $r0, 'A'\nli $r1, '\\n'\nli $r2, '\\''\nli $r3, '\\\\'\nli $r4, ' '");
+        assert_eq!(0, result.errors.len());
+        assert_eq!(Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R0, ImmediateExpr::Int(65)), result.program[0].expr);
+        assert_eq!(Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R1, ImmediateExpr::Int(10)), result.program[1].expr);
+        assert_eq!(Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R2, ImmediateExpr::Int(39)), result.program[2].expr);
+        assert_eq!(Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R3, ImmediateExpr::Int(92)), result.program[3].expr);
+        assert_eq!(Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R4, ImmediateExpr::Int(32)), result.program[4].expr);
+
+        let result = parse_str(".i32 'a' + 1");
+        assert_eq!(Expr::StoreI32(ImmediateExpr::Add(Box::new(ImmediateExpr::Int(97)), Box::new(ImmediateExpr::Int(1)))), result.program[0].expr);
+
+        let result = parse_str("li $r0, '\\q'");
+        assert_eq!(Some(codes::INVALID_ESCAPE_SEQUENCE), result.errors.first().map(|error| error.code));
+
+        // Not a byte
+        let result = parse_str("li $r0, '€'");
+        assert_eq!(Some(codes::EXPECTED_VALID_IMMEDIATE), result.errors.first().map(|error| error.code));
+    }
+
+    #[test]
+    fn string_escapes() {
+        let result = parse_str(".str \"\\x41\\x7f\\u{20AC}\\u{41}\\\\\"");
+        assert_eq!(0, result.errors.len());
+        assert_eq!(Expr::StoreStr("A\x7f€A\\".to_string()), result.program[0].expr);
+
+        // Position of the broken escape sequence (also after multi-byte characters)
+        let result = parse_str(".str \"€\\x4\"");
+        assert_eq!(vec![Diagnostic::invalid_escape_sequence(9..12)], result.errors);
+
+        for program in [".str \"\\x\"", ".str \"\\xg0\"", ".str \"\\u41\"", ".str \"\\u{}\"", ".str \"\\u{41\"", ".str \"\\u{D800}\"", ".str \"\\u{1234567}\""] {
+            let result = parse_str(program);
+            assert_eq!(vec![codes::INVALID_ESCAPE_SEQUENCE], result.errors.into_iter().map(|error| error.code).collect::<Vec<_>>(), "{}", program);
+        }
+
+        let result = parse_str(".str \"a\\x80\"\nli $r0, '\\xFF'");
+        assert_eq!(vec![
+            Diagnostic::byte_escape_out_of_range(7..11, 0x80),
+            Diagnostic::byte_escape_out_of_range(21..27, 0xFF),
+        ], result.errors);
+
+        let result = parse_str("li $r0, '\\x41'\nli $r1, '\\u{7A}'");
+        assert_eq!(0, result.errors.len());
+        assert_eq!(Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R0, ImmediateExpr::Int(65)), result.program[0].expr);
+        assert_eq!(Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R1, ImmediateExpr::Int(122)), result.program[1].expr);
+
+        let result = parse_str("li $r0, '\\x4'");
+        assert_eq!(Some(codes::INVALID_ESCAPE_SEQUENCE), result.errors.first().map(|error| error.code));
+        let result = parse_str("li $r0, '\\u{20AC}'");
+        assert_eq!(Some(codes::EXPECTED_VALID_IMMEDIATE), result.errors.first().map(|error| error.code));
+    }
+
+    #[test]
+    fn parse_mem_strz() {
+        let result = parse_str(".asciiz \"abc\"\n.strz \"\"");
+        assert_eq!(0, result.errors.len());
+        assert_eq!(Expr::StoreStr("abc\0".to_string()), result.program[0].expr);
+        assert_eq!(Expr::StoreStr("\0".to_string()), result.program[1].expr);
+
+        let result = parse_str(".strz 1");
+        assert_eq!(Expr::Error(), result.program[0].expr);
+    }
+
+    #[test]
+    fn error_recovery() {
+        fn error_codes(program: &'static str) -> Vec<&'static str> {
+            parse_str(program).errors.into_iter().map(|error| error.code).collect()
+        }
+
+        // One error per broken line, the other lines are parsed
+        let result = parse_str("li $r0\nli $r1, 1\nadd $r0 $r1 $r2\nfoo bar baz\nlabel: li $r2, @\nsyscalli 0");
+        assert_eq!(4, result.errors.len());
+        let program: Vec<Expr> = result.program.into_iter().map(|expr| expr.expr).filter(|expr| *expr != Expr::Error()).collect();
+        assert_eq!(vec![
+            Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R1, ImmediateExpr::Int(1)),
+            Expr::Label("label".to_string()),
+            Expr::InstructionImmediate(OpCode::SYSCALLI, ImmediateExpr::Int(0)),
+        ], program);
+
+        assert_eq!(vec![codes::EXPECTED_TOKEN], error_codes("li $r0"));
+        assert_eq!(vec![codes::EXPECTED_NEW_LINE], error_codes("li $r0, 1 2 3"));
+        assert_eq!(vec![codes::EXPECTED_NEW_LINE, codes::EXPECTED_NEW_LINE], error_codes("li $r0, 1 2\nli $r1, 2 $r0\nsyscalli 0"));
+        assert_eq!(vec![codes::EXPECTED_NEW_LINE], error_codes(".isa 1 $r0"));
+        assert_eq!(vec![codes::CANNOT_PARSE], error_codes("42"));
+        assert_eq!(vec![codes::CANNOT_PARSE, codes::EXPECTED_REGISTER], error_codes("foo; j 1; j $r0"));
+
+        // A label ends a broken statement, too
+        let result = parse_str("li $r0, $r1 label: syscalli 0");
+        assert_eq!(1, result.errors.len());
+        assert_eq!(Some(&Expr::Label("label".to_string())), result.program.iter().map(|expr| &expr.expr).find(|expr| **expr != Expr::Error()));
+    }
+
+    #[test]
+    fn label_shadows_register() {
+        let result = parse_str("sp:\na0: reti\nstart:\nspace:");
+        assert_eq!(0, result.errors.len());
+        assert_eq!(vec![ParserWarningType::LabelShadowsRegister, ParserWarningType::LabelShadowsRegister],
+            result.warnings.into_iter().map(|warning| warning.warn_type).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn block_comments() {
+        let result = parse_str("/* Start */ li $r0, 1 /* one */\n/*\n * li $r1, 2\n **/\nli $r2, 3 /**/ // End");
+        assert_eq!(0, result.errors.len());
+        let program: Vec<Expr> = result.program.into_iter().map(|expr| expr.expr).collect();
+        assert_eq!(vec![
+            Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R0, ImmediateExpr::Int(1)),
+            Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R2, ImmediateExpr::Int(3)),
+        ], program);
+
+        // Not nesting
+        let mut lex = Token::lexer("/* /* */ 1 */");
+        assert_eq!(Some(Token::Int), lex.next());
+        assert_eq!(Some(Token::OpMul), lex.next());
+        assert_eq!(Some(Token::OpDiv), lex.next());
+
+        let mut lex = Token::lexer("1 /* Unterminated");
+        assert_eq!(Some(Token::Int), lex.next());
+        assert_eq!(Some(Token::Error), lex.next());
+        assert_eq!(None, lex.next());
+
+        // Division and multiplication still work
+        let result = parse_str(".i32 4 / 2 * 3");
+        assert_eq!(0, result.errors.len());
+    }
+
+    #[test]
+    fn parse_semicolons() {
+        let result = parse_str("li $r0, 1; li $r1, 2 ;li $r2, ';'\n.str \";\"; // ;");
+        assert_eq!(0, result.errors.len());
+        let program: Vec<Expr> = result.program.into_iter().map(|expr| expr.expr).collect();
+        assert_eq!(vec![
+            Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R0, ImmediateExpr::Int(1)),
+            Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R1, ImmediateExpr::Int(2)),
+            Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R2, ImmediateExpr::Int(59)),
+            Expr::StoreStr(";".to_string()),
+        ], program);
+    }
+
+    #[test]
+    fn parse_pseudo_instructions() {
+        let result = parse_str("mov $r0, $r1\npush $r2\npop $r3\ncall %func\nret\nla $r4, %data\ninc $r5\ndec $r6");
+        assert_eq!(0, result.errors.len());
+        let program: Vec<Expr> = result.program.into_iter().map(|expr| expr.expr).collect();
+        assert_eq!(vec![
+            Expr::InstructionTwoRegisters(OpCode::CPY, Register::R0, Register::R1),
+            Expr::InstructionRegisterAndImmediate(OpCode::SUBI, Register::SP, ImmediateExpr::Int(4)),
+            Expr::InstructionTwoRegisters(OpCode::SW, Register::R2, Register::SP),
+            Expr::InstructionTwoRegisters(OpCode::LW, Register::R3, Register::SP),
+            Expr::InstructionRegisterAndImmediate(OpCode::ADDI, Register::SP, ImmediateExpr::Int(4)),
+            Expr::InstructionImmediate(OpCode::JIL, ImmediateExpr::AddrToLabel("func".to_string())),
+            Expr::InstructionRegister(OpCode::J, Register::RA),
+            Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R4, ImmediateExpr::AddrToLabel("data".to_string())),
+            Expr::InstructionRegisterAndImmediate(OpCode::ADDI, Register::R5, ImmediateExpr::Int(1)),
+            Expr::InstructionRegisterAndImmediate(OpCode::SUBI, Register::R6, ImmediateExpr::Int(1)),
+        ], program);
+
+        // Labels and immediates keep working
+        let result = parse_str("ret:\nreti\npush 1");
+        assert_eq!(Expr::Label("ret".to_string()), result.program[0].expr);
+        assert_eq!(Expr::Instruction(OpCode::RETI), result.program[1].expr);
+        assert_eq!(Expr::Error(), result.program[2].expr);
+        assert_eq!(1, result.errors.len());
+    }
+
+    #[test]
+    fn parse_equ() {
+        let result = parse_str(".equ SIZE, 4 * 4\n.set NEXT, %SIZE + %label\n.space %SIZE");
+        assert_eq!(0, result.errors.len());
+        assert_eq!(Expr::Constant("SIZE".to_string(), ImmediateExpr::Mul(Box::new(ImmediateExpr::Int(4)), Box::new(ImmediateExpr::Int(4)))), result.program[0].expr);
+        assert_eq!(Expr::Constant("NEXT".to_string(), ImmediateExpr::Add(
+            Box::new(ImmediateExpr::AddrToLabel("SIZE".to_string())),
+            Box::new(ImmediateExpr::AddrToLabel("label".to_string())))), result.program[1].expr);
+        assert_eq!(Expr::StoreSpace(16), result.program[2].expr);
+
+        // Keywords aren't names
+        let mut lex = Token::lexer("li lix");
+        assert_eq!(Some(Token::KwLi), lex.next());
+        assert_eq!(Some(Token::Ident), lex.next());
+
+        let result = parse_str(".equ 1, 2");
+        assert_eq!(Some(codes::EXPECTED_LABEL), result.errors.first().map(|error| error.code));
+    }
+
+    #[test]
+    fn parse_import() {
+        let result = parse_str(".import draw_pixel\nsyscalli %draw_pixel");
+        assert_eq!(0, result.errors.len());
+        assert_eq!(Expr::Import("draw_pixel".to_string()), result.program[0].expr);
+        assert_eq!(Expr::InstructionImmediate(OpCode::SYSCALLI, ImmediateExpr::AddrToLabel("draw_pixel".to_string())), result.program[1].expr);
+
+        let result = parse_str(".import 1");
+        assert_eq!(Some(codes::EXPECTED_LABEL), result.errors.first().map(|error| error.code));
+
+        let result = parse_str(".import a b");
+        assert_eq!(Some(codes::EXPECTED_NEW_LINE), result.errors.first().map(|error| error.code));
+    }
+
+    #[test]
+    fn parse_mem_space() {
+        let result = parse_str(".space 16\n.zero 4 * 2");
+        assert_eq!(0, result.errors.len());
+        assert_eq!(Expr::StoreSpace(16), result.program[0].expr);
+        assert_eq!(Expr::StoreSpace(8), result.program[1].expr);
+
+        let result = parse_str(".space %label");
+        assert_eq!(Some(codes::EXPECTED_VALID_IMMEDIATE), result.errors.first().map(|error| error.code));
+    }
+
+    #[test]
+    fn parse_hex_immediates() {
+        let result = parse_str("li $r0, 0xff");
+        let expr = result.program.first().expect("Expected");
+        assert_eq!(Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R0, ImmediateExpr::Int(0xFF)), expr.expr);
+
+        let result = parse_str(".i32 0xDEADBEEF");
+        let expr = result.program.first().expect("Expected");
+        assert_eq!(Expr::StoreI32(ImmediateExpr::Int(0xDEADBEEF)), expr.expr);
+
+        let result = parse_str(".i8 0xFF");
+        let expr = result.program.first().expect("Expected");
+        assert_eq!(Expr::StoreI8(ImmediateExpr::Int(0xFF)), expr.expr);
+
+        let result = parse_str("ji 0x10 + 4");
+        let expr = result.program.first().expect("Expected");
+        assert_eq!(Expr::InstructionImmediate(OpCode::JI, ImmediateExpr::Add(Box::new(ImmediateExpr::Int(0x10)), Box::new(ImmediateExpr::Int(4)))), expr.expr);
+
+        // Doesn't fit into 32 bits
+        let result = parse_str(".i32 0x100000000");
+        assert_eq!(Some(codes::EXPECTED_VALID_IMMEDIATE), result.errors.first().map(|error| error.code));
     }
 
     #[test]
@@ -1124,6 +2104,47 @@ mod tests {
         assert_eq!(Expr::StoreStr("Hello, world!".to_string()), expr.expr);
     }
 
+    #[test]
+    fn parse_isa() {
+        let result = parse_str(".isa 1");
+        assert_eq!(1, result.program.len());
+        assert!(result.errors.is_empty());
+        assert_eq!(Expr::Isa(IsaVersion::V1), result.program[0].expr);
+
+        let result = parse_str(".isa 99");
+        assert_eq!(vec![codes::INVALID_ISA_VERSION], result.errors.iter().map(|err| err.code).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn parse_sections() {
+        let result = parse_str(".data\ncount: .i32 1\n.text\nreti");
+        assert!(result.errors.is_empty());
+        assert_eq!(vec![
+            Expr::Section(Section::Data),
+            Expr::Label("count".to_string()),
+            Expr::StoreI32(ImmediateExpr::Int(1)),
+            Expr::Section(Section::Text),
+            Expr::Instruction(OpCode::RETI),
+        ], result.program.into_iter().map(|expr| expr.expr).collect::<Vec<_>>());
+
+        let result = parse_str(".data 4");
+        assert_eq!(vec![codes::EXPECTED_NEW_LINE], result.errors.iter().map(|err| err.code).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn parse_entry() {
+        let result = parse_str(".entry main\n.entry %main + 4\n.entry 8");
+        assert!(result.errors.is_empty());
+        assert_eq!(vec![
+            Expr::Entry(ImmediateExpr::AddrToLabel("main".to_string())),
+            Expr::Entry(ImmediateExpr::Add(Box::new(ImmediateExpr::AddrToLabel("main".to_string())), Box::new(ImmediateExpr::Int(4)))),
+            Expr::Entry(ImmediateExpr::Int(8)),
+        ], result.program.into_iter().map(|expr| expr.expr).collect::<Vec<_>>());
+
+        let result = parse_str(".entry");
+        assert_eq!(1, result.errors.len());
+    }
+
     #[test]
     fn parse_instructions_two_registers() {
         let op_codes = [ OpCode::CPY,
@@ -1132,7 +2153,8 @@ mod tests {
             OpCode::LB, OpCode::SB,
             OpCode::ADD, OpCode::SUB, OpCode::MUL, OpCode::DIV,
             OpCode::AND, OpCode::OR, OpCode::XOR,
-            OpCode::SRL, OpCode::SLL ];
+            OpCode::SRL, OpCode::SLL,
+            OpCode::AMOSWAP, OpCode::AMOADD ];
 
         for op_code in op_codes {
             let result = parse_string(&(op_code.to_string() + " $r0, $ra"));
@@ -1150,7 +2172,9 @@ mod tests {
             OpCode::JNZI,
             OpCode::JLZI,
             OpCode::JGZI,
-            OpCode::LI ];
+            OpCode::LI,
+            OpCode::MFS,
+            OpCode::MTS ];
 
         for op_code in op_codes {
             let result = parse_string(&(op_code.to_string() + " $r0, 10"));
@@ -1172,6 +2196,45 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_register_aliases() {
+        let result = parse_str("add $rv, $a0\nadd $a1, $t0\nadd $t1, $t2\nadd $s0, $s1\n");
+        let exprs: Vec<Expr> = result.program.into_iter().map(|expr| expr.expr).collect();
+        assert_eq!(vec![
+            Expr::InstructionTwoRegisters(OpCode::ADD, Register::R0, Register::R1),
+            Expr::InstructionTwoRegisters(OpCode::ADD, Register::R2, Register::R3),
+            Expr::InstructionTwoRegisters(OpCode::ADD, Register::R4, Register::R5),
+            Expr::InstructionTwoRegisters(OpCode::ADD, Register::R6, Register::R7),
+        ], exprs);
+
+        // The alternate form of Display prints the aliases
+        for reg in [Register::R0, Register::R5, Register::R7, Register::SP] {
+            let result = parse_string(&format!("j {:#}\n", reg));
+            assert_eq!(Expr::InstructionRegister(OpCode::J, reg), result.program.first().expect("Expected").expr);
+        }
+        assert_eq!("$a0", format!("{:#}", Register::R1));
+        assert_eq!("$r1", format!("{}", Register::R1));
+        assert_eq!("$sp", format!("{:#}", Register::SP));
+
+        let result = parse_str("j $a2");
+        assert_eq!(1, result.errors.len());
+    }
+
+    #[test]
+    fn parse_instruction_no_operands() {
+        let op_codes = [ OpCode::EI, OpCode::DI, OpCode::RETI ];
+
+        for op_code in op_codes {
+            let result = parse_string(&(op_code.to_string() + "\n"));
+            assert_eq!(1, result.program.len());
+            let expr = result.program.first().expect("Made sure above");
+            assert_eq!(Expr::Instruction(op_code), expr.expr);
+        }
+
+        let result = parse_str("reti $r0");
+        assert_eq!(1, result.errors.len());
+    }
+
     #[test]
     fn parse_instruction_immediate() {
         let op_codes = [ OpCode::SYSCALLI, OpCode::JI, OpCode::JIL ];
@@ -1273,4 +2336,60 @@ mod tests {
             assert_eq!(Expr::InstructionImmediate(op_code, ImmediateExpr::Mul(Box::new(ImmediateExpr::Int(1)), Box::new(ImmediateExpr::Add(Box::new(ImmediateExpr::Int(2)), Box::new(ImmediateExpr::Int(3)))))), expr.expr);
         }
     }
+
+    #[test]
+    fn parse_instruction_immediate_precedence_chain() {
+        let result = parse_string(&"syscalli 1 + 2 * 3 + 4".to_string());
+        assert_eq!(0, result.errors.len());
+        let expr = result.program.first().expect("Expected expression");
+        assert_eq!(Expr::InstructionImmediate(OpCode::SYSCALLI, ImmediateExpr::Add(
+            Box::new(ImmediateExpr::Add(Box::new(ImmediateExpr::Int(1)), Box::new(ImmediateExpr::Mul(Box::new(ImmediateExpr::Int(2)), Box::new(ImmediateExpr::Int(3)))))),
+            Box::new(ImmediateExpr::Int(4)))), expr.expr);
+
+        let result = parse_string(&".i32 2 * 3 + 4 * 5 - 6 / 2".to_string());
+        assert_eq!(0, result.errors.len());
+        match &result.program[0].expr {
+            Expr::StoreI32(imm) => assert_eq!(Some(23), imm.constant()),
+            expr => panic!("Unexpected {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn parse_instruction_immediate_bitwise() {
+        let values = [
+            ("(1 << 3) | (1 << 5)", 0x28),
+            ("1 << 3 | 1 << 5", 0x28),
+            ("0xFF & 0x0F ^ 0x03", 0x0C),
+            ("0xF0 | 0x0F & 0x03", 0xF3),
+            ("1 + 1 << 2", 8),
+            ("0x80 >> 4 + 1", 0x04),
+            ("~0", 0xFFFFFFFF),
+            ("~0 >> 12", 0x000FFFFF),
+            ("~(1 << 2) & 0x0F", 0x0B),
+            ("1 << 32", 0),
+            ("0xFFFFFFFF >> 40", 0),
+            ("-1", 0xFFFFFFFF),
+            ("2 - -3", 5),
+            ("-(1 << 2) & 0xFF", 0xFC),
+        ];
+
+        for (source, value) in values {
+            let result = parse_string(&format!(".i32 {}", source));
+            assert_eq!(0, result.errors.len(), "{}", source);
+            match &result.program[0].expr {
+                Expr::StoreI32(imm) => assert_eq!(Some(value), imm.constant(), "{}", source),
+                expr => panic!("Unexpected {:?}", expr),
+            }
+        }
+
+        let result = parse_string(&".equ FLAGS, (1 << 3) | (1 << 5)\nli $r0, ~%FLAGS & %label\nlabel:".to_string());
+        assert_eq!(0, result.errors.len());
+        assert_eq!(Expr::InstructionRegisterAndImmediate(OpCode::LI, Register::R0, ImmediateExpr::And(
+            Box::new(ImmediateExpr::Not(Box::new(ImmediateExpr::AddrToLabel("FLAGS".to_string())))),
+            Box::new(ImmediateExpr::AddrToLabel("label".to_string())))), result.program[1].expr);
+        assert_eq!(vec!["FLAGS", "label"], match &result.program[1].expr {
+            Expr::InstructionRegisterAndImmediate(_, _, imm) => imm.labels(),
+            _ => vec![],
+        });
+    }
 }
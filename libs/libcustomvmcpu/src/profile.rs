@@ -0,0 +1,64 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Reports of the profiling counters of a virtual machine (see
+//! `VirtualMachine::opcode_profile` and `VirtualMachine::hot_addresses`)
+
+use std::collections::HashMap;
+use super::common::OpCode;
+use super::symbols::MemoryMap;
+
+/// Number of addresses reported by default
+pub const DEFAULT_HOT_ADDRESSES: usize = 10;
+
+/// A line with the count and the opcode for every executed opcode, most
+/// frequent first
+pub fn format_opcode_profile(profile: &HashMap<OpCode, u64>) -> String {
+    let mut profile: Vec<(OpCode, u64)> = profile.iter().map(|(op_code, count)| (*op_code, *count)).collect();
+    profile.sort_by(|a, b| b.1.cmp(&a.1).then((a.0 as u8).cmp(&(b.0 as u8))));
+    profile.iter().map(|(op_code, count)| format!("{:>10} {}\n", count, op_code)).collect()
+}
+
+/// A line with the count and the address (symbolized with `memory_map`,
+/// like `0x14 (func+0x8)`) for every address
+pub fn format_hot_addresses(addresses: &[(u32, u64)], memory_map: &MemoryMap) -> String {
+    addresses.iter().map(|(address, count)| format!("{:>10} {}\n", count, memory_map.format_address(*address))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::{format_opcode_profile, format_hot_addresses};
+    use crate::common::OpCode;
+    use crate::symbols::MemoryMap;
+
+    #[test]
+    fn reports() {
+        let mut profile = HashMap::new();
+        profile.insert(OpCode::ADDI, 10);
+        profile.insert(OpCode::JNZI, 10);
+        profile.insert(OpCode::LI, 1);
+        assert_eq!("        10 jnzi\n        10 addi\n         1 li\n", format_opcode_profile(&profile));
+
+        let mut memory_map = MemoryMap::new();
+        memory_map.add_region("loop", 4, 8);
+        assert_eq!("        10 0x8 (loop+0x4)\n         1 0x0\n", format_hot_addresses(&[(8, 10), (0, 1)], &memory_map));
+    }
+}
@@ -0,0 +1,305 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Interactive assembler: every line is compiled and executed immediately on
+//! a virtual machine, which keeps its registers and memory between lines
+
+use std::collections::HashMap;
+use num_traits::FromPrimitive;
+use super::common::{Register, LAST_REGISTER};
+use super::diagnostic::Diagnostic;
+use super::incremental::IncrementalCompiler;
+use super::parser::{parse_string, Expr};
+use super::runtime::{BinaryInterpreter, BinaryVirtualMachine};
+
+/// Instructions a single line may execute (stops endless loops)
+pub const REPL_MAX_STEPS: u64 = 1_000_000;
+
+/// Number of bytes `:mem` prints without a length
+const DEFAULT_DUMP_LENGTH: u32 = 16;
+
+pub const REPL_HELP: &str = "\
+Lines are compiled and executed immediately, labels can be used in later lines.
+Commands:
+  :regs               prints all registers
+  :mem ADDRESS [LEN]  prints the memory at ADDRESS (number or label)
+  :reset              clears the registers, the memory and all labels
+  :help               prints this help
+  :quit               exits";
+
+/// Compiles lines on top of all earlier lines (so labels of earlier lines can
+/// be used) and executes the instructions of every new line
+pub struct Repl<'source> {
+    vm: BinaryVirtualMachine<'source>,
+    compiler: IncrementalCompiler,
+    /// All accepted lines, each between labels marking its addresses
+    source: String,
+    /// Number of accepted lines
+    lines: usize,
+    /// The compiled program (as written into the memory)
+    program: Vec<u8>,
+    symbols: HashMap<String, u32>,
+    /// `:quit` was entered
+    quit: bool,
+}
+
+fn format_diagnostic(diagnostic: &Diagnostic) -> String {
+    let mut result = format!("{}[{}]: {}", diagnostic.severity, diagnostic.code, diagnostic.message);
+    for note in &diagnostic.notes {
+        result += &format!("\n  note: {}", note);
+    }
+
+    result
+}
+
+fn has_instructions(line: &str) -> bool {
+    parse_string(&line.to_string()).program.iter().any(|expr| matches!(expr.expr,
+        Expr::Instruction(_)
+            | Expr::InstructionRegister(_, _)
+            | Expr::InstructionImmediate(_, _)
+            | Expr::InstructionTwoRegisters(_, _, _)
+            | Expr::InstructionRegisterAndImmediate(_, _, _)))
+}
+
+/// Parses a decimal or hexadecimal (0x prefix) number
+fn parse_number(value: &str) -> Option<u32> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+impl<'source> Repl<'source> {
+    /// Programs write to `stdout`
+    pub fn new(stdout: &'source mut dyn std::io::Write) -> Repl<'source> {
+        Repl {
+            vm: BinaryVirtualMachine::new(BinaryInterpreter::new(), stdout),
+            compiler: IncrementalCompiler::default(),
+            source: String::new(),
+            lines: 0,
+            program: Vec::new(),
+            symbols: HashMap::new(),
+            quit: false,
+        }
+    }
+
+    pub fn get_vm(&self) -> &BinaryVirtualMachine<'source> {
+        &self.vm
+    }
+
+    /// True, after `:quit` (the caller stops reading lines)
+    pub fn has_quit(&self) -> bool {
+        self.quit
+    }
+
+    /// Compiles and executes an assembler line or runs a command (starting
+    /// with `:`). Returns the text for the user: diagnostics, the result of
+    /// the command or how the program stopped (empty, if the line executed
+    /// normally). Lines with errors are discarded.
+    pub fn eval(&mut self, line: &str) -> String {
+        let line = line.trim();
+        if let Some(command) = line.strip_prefix(':') {
+            return self.command(command);
+        }
+        if line.is_empty() {
+            return String::new();
+        }
+
+        let start_label = format!("__repl_{}", self.lines);
+        let end_label = format!("{}_end", start_label);
+        let line_start = self.source.len();
+        let source = format!("{}{}:\n{}\n{}:\n", self.source, start_label, line, end_label);
+        let result = self.compiler.compile(&source);
+        // Earlier lines were accepted, so their diagnostics are warnings,
+        // which were already reported
+        let mut output: Vec<String> = result.diagnostics.iter()
+            .filter(|diagnostic| diagnostic.span.start >= line_start)
+            .map(format_diagnostic)
+            .collect();
+
+        let bytes = match result.bytes {
+            Some(bytes) => bytes,
+            None => return output.join("\n"),
+        };
+        if !self.load(&bytes) {
+            output.push("Error: The program doesn't fit into the memory".to_string());
+            return output.join("\n");
+        }
+
+        self.source = source;
+        self.lines += 1;
+        self.symbols = result.symbols;
+        if has_instructions(line) {
+            let message = self.execute(self.symbols[&start_label], self.symbols[&end_label]);
+            output.extend(message);
+        }
+
+        output.join("\n")
+    }
+
+    /// Writes the bytes of `program`, which changed since the last
+    /// compilation, into the memory. Other bytes keep the values the program
+    /// wrote.
+    fn load(&mut self, program: &[u8]) -> bool {
+        for (addr, byte) in program.iter().enumerate() {
+            if self.program.get(addr) != Some(byte) && self.vm.write_bytes(addr as u32, &[*byte]).is_err() {
+                return false;
+            }
+        }
+
+        self.program = program.to_vec();
+        true
+    }
+
+    /// Executes the instructions from `start` until `end` is reached. Returns
+    /// a message, if the program stopped before.
+    fn execute(&mut self, start: u32, end: u32) -> Option<String> {
+        self.vm.start(start);
+        let mut steps = 0;
+        while self.vm.read_register_value(Register::IP) != end {
+            if steps == REPL_MAX_STEPS {
+                return Some(format!("Stopped after {} instructions at 0x{:X}", steps, self.vm.read_register_value(Register::IP)));
+            }
+
+            if !self.vm.step() {
+                return Some(match self.vm.result() {
+                    Ok(status) => format!("Exited with {}", status.code),
+                    Err(err) => format!("Error: {:?} at 0x{:X}", err.kind, err.ip),
+                });
+            }
+
+            steps += 1;
+        }
+
+        None
+    }
+
+    fn command(&mut self, command: &str) -> String {
+        let args: Vec<&str> = command.split_whitespace().collect();
+        match args.as_slice() {
+            ["regs"] => (0..=LAST_REGISTER as u32)
+                .filter_map(Register::from_u32)
+                .map(|reg| {
+                    let value = self.vm.read_register_value(reg);
+                    format!("{:<5} {:>10} 0x{:08X}", reg.to_string(), value, value)
+                })
+                .collect::<Vec<String>>()
+                .join("\n"),
+            ["mem", addr] => self.dump(addr, DEFAULT_DUMP_LENGTH),
+            ["mem", addr, length] => match parse_number(length) {
+                Some(length) => self.dump(addr, length),
+                None => format!("Error: Invalid length {}", length),
+            },
+            ["reset"] => {
+                self.vm.load_program(&[]);
+                self.compiler = IncrementalCompiler::default();
+                self.source.clear();
+                self.lines = 0;
+                self.program.clear();
+                self.symbols.clear();
+                String::new()
+            },
+            ["help"] => REPL_HELP.to_string(),
+            ["quit"] => {
+                self.quit = true;
+                String::new()
+            },
+            _ => format!("Error: Unknown command :{} (see :help)", command),
+        }
+    }
+
+    fn dump(&self, addr: &str, length: u32) -> String {
+        let start = parse_number(addr).or_else(|| self.symbols.get(addr.trim_start_matches('%')).copied());
+        match start.and_then(|start| self.vm.hexdump(start..start.saturating_add(length))) {
+            Some(dump) => dump.trim_end().to_string(),
+            None => format!("Error: Can't dump {} bytes at {}", length, addr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Repl, REPL_MAX_STEPS};
+    use crate::common::Register;
+
+    #[test]
+    fn eval_lines() {
+        let mut stdout: Vec<u8> = Vec::new();
+        let mut repl = Repl::new(&mut stdout);
+        assert_eq!("", repl.eval("li $r0, 5"));
+        assert_eq!("", repl.eval("addi $r0, 2"));
+        assert_eq!(7, repl.get_vm().read_register_value(Register::R0));
+
+        // Data isn't executed, its label can be used by later lines
+        assert_eq!("", repl.eval("data: .i32 41"));
+        assert_eq!("", repl.eval("lwi $r1, %data"));
+        assert_eq!("", repl.eval("addi $r1, 1"));
+        assert_eq!("", repl.eval("swi $r1, %data"));
+        assert_eq!("", repl.eval("li $r2, 0"));
+        // Compiling later lines doesn't overwrite the written data
+        assert_eq!(Ok(42), repl.get_vm().read_u32(repl.symbols["data"]));
+        assert_eq!(0, repl.get_vm().read_register_value(Register::R2));
+        assert_eq!(7, repl.get_vm().read_register_value(Register::R0));
+
+        assert_eq!("", repl.eval("li $r1, 3"));
+        assert_eq!("Exited with 3", repl.eval("syscalli 0"));
+        // The machine continues with the next line
+        assert_eq!("", repl.eval("li $r1, 4"));
+        assert_eq!(4, repl.get_vm().read_register_value(Register::R1));
+    }
+
+    #[test]
+    fn eval_errors() {
+        let mut stdout: Vec<u8> = Vec::new();
+        let mut repl = Repl::new(&mut stdout);
+        assert_eq!("error[E0003]: unknown register", repl.eval("li $r9, 1"));
+        assert_eq!("error[E0016]: undefined label `missing`", repl.eval("ji %missing"));
+        // Discarded lines don't define labels
+        assert!(repl.eval("ok: li $r0, 1 +").starts_with("error"));
+        assert!(repl.eval("ji %ok").starts_with("error[E0016]"));
+
+        assert_eq!(format!("Stopped after {} instructions at 0x0", REPL_MAX_STEPS), repl.eval("loop: ji %loop"));
+        assert_eq!("", repl.eval("li $r1, 0 - 4"));
+        assert_eq!("Error: Memory at 0x8", repl.eval("lw $r0, $r1"));
+    }
+
+    #[test]
+    fn commands() {
+        let mut stdout: Vec<u8> = Vec::new();
+        let mut repl = Repl::new(&mut stdout);
+        repl.eval("li $r0, 10");
+        assert!(repl.eval(":regs").starts_with("$r0           10 0x0000000A\n$r1            0 0x00000000\n"));
+
+        repl.eval("data: .str \"Hi\"");
+        assert_eq!("00000004  48 69                                             |Hi|", repl.eval(":mem %data 2"));
+        assert_eq!("00000000  0a 00 00 07                                       |....|", repl.eval(":mem 0x0 4"));
+        assert_eq!("Error: Can't dump 16 bytes at other", repl.eval(":mem other"));
+
+        assert_eq!("", repl.eval(":reset"));
+        assert_eq!(0, repl.get_vm().read_register_value(Register::R0));
+        assert_eq!("Error: Can't dump 16 bytes at data", repl.eval(":mem data"));
+        assert!(repl.eval("ji %data").starts_with("error[E0016]"));
+
+        assert_eq!("Error: Unknown command :jump (see :help)", repl.eval(":jump"));
+
+        assert!(!repl.has_quit());
+        assert_eq!("", repl.eval(":quit"));
+        assert!(repl.has_quit());
+    }
+}
@@ -17,10 +17,26 @@
  * along with this program.  If not, see <https://www.gnu.org/licenses/>.
  */
 
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::convert::TryInto;
+use std::fmt;
+use std::future::Future;
+use std::ops::Range;
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use num_traits::FromPrimitive;
-use super::common::{OpCode, Register, Error, LAST_REGISTER, ERROR_START_NUM};
+use super::common::{OpCode, Register, SystemRegister, Error, IsaVersion, LAST_REGISTER, ERROR_START_NUM, LATEST_ISA_VERSION, CAUSE_INTERRUPT, STATUS_IN_TRAP, STATUS_INTERRUPTS_ENABLED};
+use super::disassembler::disassemble_instruction;
+use super::view::{TypedRegion, Value, ViewError};
+use super::mmu::{PageFault, PageTable, PAGE_SIZE};
+use super::symbols::MemoryMap;
+use super::fault::FaultInjector;
+use super::device::{Device, DeviceWindow};
+use super::host::{HostFunction, HostFunctions, HOST_SYSCALL_START, MAX_HOST_ARGUMENTS};
+use super::hexdump::hexdump;
+use super::debuginfo::LineTable;
 
 /// Instruction interpreter (implementation for machine code and assembler)
 pub trait Interpreter {
@@ -52,22 +68,218 @@ pub trait Interpreter {
     #[must_use]
     fn read_chunk(&self, pos: u32, len: u32) -> Option<Vec<u8>>;
 
+    /// Read NUL-terminated string from memory (without the terminating NUL).
+    /// Returns None, if the string doesn't end inside of the memory.
+    #[must_use]
+    fn read_cstr(&self, pos: u32) -> Option<Vec<u8>> {
+        let mut result = Vec::new();
+        let mut current = pos;
+        loop {
+            let c = self.read_u8(current)?;
+            if c == 0 {
+                return Some(result);
+            }
+
+            result.push(c);
+            current = current.checked_add(1)?;
+        }
+    }
+
+    /// Translate a virtual address into a physical address (used by the
+    /// virtual machine for all accesses of the program). Without an MMU
+    /// every address is physical.
+    fn translate(&self, pos: u32, _write: bool) -> Result<u32, PageFault> {
+        Ok(pos)
+    }
+
+    /// Called after every executed instruction (advances devices like
+    /// timers)
+    fn tick(&mut self) {}
+
+    /// True, if a device requests an interrupt
+    fn interrupt_pending(&self) -> bool {
+        false
+    }
+
+    /// True, if the instruction at the physical address `pos` may be kept in
+    /// the decode cache of the virtual machine (it's plain memory)
+    fn is_cacheable(&self, _pos: u32) -> bool {
+        true
+    }
+
+    /// Copy of the whole memory (for snapshots). Devices aren't part of it.
+    fn memory_image(&self) -> Vec<u8> {
+        self.read_chunk(0, self.len()).unwrap_or_default()
+    }
+
+    /// Replaces the whole memory with an image from `memory_image`. Returns
+    /// false, if the image doesn't have the size of the memory.
+    fn restore_memory_image(&mut self, image: &[u8]) -> bool {
+        if image.len() != self.len() as usize {
+            return false;
+        }
+
+        image.iter().enumerate().all(|(pos, value)| self.write_u8(pos as u32, *value))
+    }
+
+    /// Restores the memory to the loaded program (all other bytes are 0)
+    fn reset_memory(&mut self) {
+        self.restore_memory_image(&vec![0; self.len() as usize]);
+    }
+
+    /// Replaces the loaded program: the memory is cleared and `program` is
+    /// copied to address 0. Returns false, if the program doesn't fit into
+    /// the memory.
+    fn load_program(&mut self, program: &[u8]) -> bool {
+        let mut image = vec![0; self.len() as usize];
+        match image.get_mut(0..program.len()) {
+            Some(slice) => slice.copy_from_slice(program),
+            None => return false,
+        }
+
+        self.restore_memory_image(&image)
+    }
+
+    /// Bytes of the plain memory in `range` (without devices), if they can
+    /// be borrowed. Otherwise (the default) `VirtualMachine::read_memory`
+    /// copies them with `read_chunk`.
+    fn memory(&self, _range: Range<u32>) -> Option<&[u8]> {
+        None
+    }
+
     /// Must memory
     fn len(&self) -> u32;
 }
 
+/// Parses a memory size in bytes with an optional binary suffix: `K`, `M`
+/// or `G` (like `64K` or `16M`, also `KiB`, `MiB` and `GiB`). Returns None,
+/// if the size isn't a number or doesn't fit into 32 bits.
+pub fn parse_memory_size(value: &str) -> Option<u32> {
+    let value = value.trim();
+    let digits = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (number, suffix) = value.split_at(digits);
+    let factor: u64 = match suffix.to_ascii_uppercase().as_str() {
+        "" => 1,
+        "K" | "KIB" => 1024,
+        "M" | "MIB" => 1024 * 1024,
+        "G" | "GIB" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+
+    number.parse::<u64>().ok()
+        .and_then(|number| number.checked_mul(factor))
+        .and_then(|size| u32::try_from(size).ok())
+}
+
+/// Size of an instruction in bytes
+pub const INSTRUCTION_SIZE: u32 = 4;
+
 /// 4 MiB is "RAM"
 pub const BINARY_INTERPRETER_MEM_SIZE: u32 = 1024 * 1024 * 4;
 
+/// Registers saved by a context switch, in the order they are stored in a
+/// context save area (starting at offset 0, 4 bytes each). Guest schedulers
+/// like `program/threads.asm` follow this layout.
+pub const CONTEXT_REGISTERS: [Register; 10] = [
+    Register::R0, Register::R1, Register::R2, Register::R3,
+    Register::R4, Register::R5, Register::R6, Register::R7,
+    Register::SP, Register::RA,
+];
+
+/// Size of a context save area in bytes
+pub const CONTEXT_SAVE_AREA_SIZE: u32 = CONTEXT_REGISTERS.len() as u32 * 4;
+
 #[derive(PartialEq, Debug)]
 pub struct BinaryInterpreter {
     memory: Vec<u8>,
+    /// Loaded program, restored by `reset_memory`
+    program: Vec<u8>,
+    page_table: Option<PageTable>,
+    devices: Vec<DeviceWindow>,
 }
 
 impl BinaryInterpreter {
     pub fn new() -> BinaryInterpreter {
         let memory = vec![0; BINARY_INTERPRETER_MEM_SIZE as usize];
-        BinaryInterpreter { memory }
+        BinaryInterpreter { memory, program: Vec::new(), page_table: None, devices: Vec::new() }
+    }
+
+    /// Interpreter with `size` bytes of memory instead of
+    /// `BINARY_INTERPRETER_MEM_SIZE` ($sp starts at the end of the memory).
+    /// Returns None, if the size is 0 or not a multiple of 4. Programs are
+    /// loaded with `load_program`.
+    pub fn with_memory_size(size: u32) -> Option<BinaryInterpreter> {
+        if size == 0 || !size.is_multiple_of(4) {
+            return None;
+        }
+
+        let memory = vec![0; size as usize];
+        Some(BinaryInterpreter { memory, program: Vec::new(), page_table: None, devices: Vec::new() })
+    }
+
+    /// Map `device` into the (physical) address window starting at `start`.
+    /// Accesses to the window are routed to the device instead of the
+    /// memory. Returns false, if the window is empty or overlaps the window
+    /// of another device.
+    pub fn map_device(&mut self, start: u32, size: u32, device: Box<dyn Device>) -> bool {
+        if size == 0 || start.checked_add(size - 1).is_none() || self.overlaps_device(start, size) {
+            return false;
+        }
+
+        self.devices.push(DeviceWindow { start, size, device });
+        true
+    }
+
+    /// Remove the device mapped at `start`
+    pub fn unmap_device(&mut self, start: u32) -> Option<Box<dyn Device>> {
+        let index = self.devices.iter().position(|window| window.start == start)?;
+        Some(self.devices.remove(index).device)
+    }
+
+    fn overlaps_device(&self, pos: u32, len: u32) -> bool {
+        self.devices.iter().any(|window| window.overlaps(pos, len))
+    }
+
+    /// Reads byte by byte, every byte from either a device or the memory
+    fn read_device_bytes<const N: usize>(&self, pos: u32) -> Option<[u8; N]> {
+        let mut result = [0; N];
+        for (i, byte) in result.iter_mut().enumerate() {
+            let pos = pos.checked_add(i as u32)?;
+            *byte = match self.devices.iter().find(|window| window.contains(pos)) {
+                Some(window) => window.device.read_u8(pos - window.start)?,
+                None => *self.memory.get(pos as usize)?,
+            };
+        }
+
+        Some(result)
+    }
+
+    /// Writes byte by byte, every byte to either a device or the memory
+    fn write_device_bytes(&mut self, pos: u32, bytes: &[u8]) -> bool {
+        bytes.iter().enumerate().all(|(i, byte)| {
+            let pos = match pos.checked_add(i as u32) {
+                Some(pos) => pos,
+                None => return false,
+            };
+
+            match self.devices.iter_mut().find(|window| window.contains(pos)) {
+                Some(window) => window.device.write_u8(pos - window.start, *byte),
+                None => self.memory.get_mut(pos as usize).map(|value| *value = *byte).is_some(),
+            }
+        })
+    }
+
+    /// Enable the MMU with `page_table` (None disables it)
+    pub fn set_page_table(&mut self, page_table: Option<PageTable>) {
+        self.page_table = page_table;
+    }
+
+    pub fn get_page_table(&self) -> Option<&PageTable> {
+        self.page_table.as_ref()
+    }
+
+    pub fn get_page_table_mut(&mut self) -> Option<&mut PageTable> {
+        self.page_table.as_mut()
     }
 
     #[allow(unused_must_use)] // Ignoring is evil, but it's checked upfront
@@ -81,6 +293,7 @@ impl BinaryInterpreter {
         for pos in 0..program.len() {
             result.write_u32(pos as u32 * 4 + start_pos, program[pos]);
         }
+        result.program = program.iter().flat_map(|instruction| instruction.to_le_bytes()).collect();
 
         return Some(result);
     }
@@ -91,6 +304,7 @@ impl BinaryInterpreter {
         let slice_from_memory = result.memory.get_mut(0..initial_memory.len());
         return if let Some(slice_from_memory) = slice_from_memory {
             slice_from_memory.copy_from_slice(&initial_memory);
+            result.program = initial_memory.clone();
             Some(result)
         }
         else {
@@ -102,12 +316,20 @@ impl BinaryInterpreter {
 impl Interpreter for BinaryInterpreter {
     #[must_use]
     fn read_u32(&self, pos: u32) -> Option<u32> {
+        if self.overlaps_device(pos, 4) {
+            return self.read_device_bytes(pos).map(u32::from_le_bytes);
+        }
+
         let result = self.memory.get(pos as usize..(pos as usize + 4))?;
         Some(u32::from_le_bytes(result.try_into().expect("Unexpected error")))
     }
 
     #[must_use]
     fn write_u32(&mut self, pos: u32, value: u32) -> bool {
+        if self.overlaps_device(pos, 4) {
+            return self.write_device_bytes(pos, &value.to_le_bytes());
+        }
+
         let result = self.memory.get_mut(pos as usize..pos as usize + 4);
         return if let Some(result) = result {
             result.copy_from_slice(&u32::to_le_bytes(value));
@@ -120,12 +342,20 @@ impl Interpreter for BinaryInterpreter {
 
     #[must_use]
     fn read_u16(&self, pos: u32) -> Option<u16> {
+        if self.overlaps_device(pos, 2) {
+            return self.read_device_bytes(pos).map(u16::from_le_bytes);
+        }
+
         let result = self.memory.get(pos as usize..(pos as usize + 2))?;
         Some(u16::from_le_bytes(result.try_into().expect("Unexpected error")))
     }
 
     #[must_use]
     fn write_u16(&mut self, pos: u32, value: u16) -> bool {
+        if self.overlaps_device(pos, 2) {
+            return self.write_device_bytes(pos, &value.to_le_bytes());
+        }
+
         let result = self.memory.get_mut(pos as usize..pos as usize + 2);
         return if let Some(result) = result {
             result.copy_from_slice(&u16::to_le_bytes(value));
@@ -138,12 +368,20 @@ impl Interpreter for BinaryInterpreter {
 
     #[must_use]
     fn read_u8(&self, pos: u32) -> Option<u8> {
+        if self.overlaps_device(pos, 1) {
+            return self.read_device_bytes(pos).map(u8::from_le_bytes);
+        }
+
         let result = self.memory.get(pos as usize)?;
         Some(*result)
     }
 
     #[must_use]
     fn write_u8(&mut self, pos: u32, value: u8) -> bool {
+        if self.overlaps_device(pos, 1) {
+            return self.write_device_bytes(pos, &[value]);
+        }
+
         let result = self.memory.get_mut(pos as usize);
         return if let Some(result) = result {
             *result = value;
@@ -160,942 +398,3245 @@ impl Interpreter for BinaryInterpreter {
             return None; // Prevent overflow exception/error
         }
 
+        if self.overlaps_device(pos, len) {
+            return (0..len).map(|i| self.read_device_bytes::<1>(pos + i).map(|[byte]| byte)).collect();
+        }
+
         let result =self.memory.get(pos as usize..(pos + len) as usize)?;
         Some(result.to_vec())
     }
 
-    fn len(&self) -> u32 {
-        u32::try_from(self.memory.len()).expect("Less than u32::MAX expected")
+    fn translate(&self, pos: u32, write: bool) -> Result<u32, PageFault> {
+        match &self.page_table {
+            Some(page_table) => page_table.translate(pos, write),
+            None => Ok(pos),
+        }
     }
-}
-
-/// Virtual machine to execute machine code on
-pub struct VirtualMachine<'source, InterpreterImpl: Interpreter>
-{
-    interpreter: InterpreterImpl,
-    registers: [u32; LAST_REGISTER as usize + 1],
-    running: bool,
-    stdout: &'source mut dyn std::io::Write,
-}
 
-impl<'source, InterpreterImpl: Interpreter> VirtualMachine<'source, InterpreterImpl> {
-    /// Create a new virtual machine
-    pub fn new(interpreter: InterpreterImpl, stdout: &'source mut dyn std::io::Write) -> VirtualMachine<'source, InterpreterImpl> {
-        let mut result = VirtualMachine { interpreter, registers: [0; LAST_REGISTER as usize + 1], running: false, stdout };
-        result.write_register_value(Register::SP, result.interpreter.len());
-        result
+    fn tick(&mut self) {
+        for window in self.devices.iter_mut() {
+            window.device.tick();
+        }
     }
 
-    /// Reset all registers (for restarting the machine)
-    pub fn reset(&mut self) {
-        for reg in self.registers.iter_mut() {
-            *reg = 0;
-        }
+    fn interrupt_pending(&self) -> bool {
+        self.devices.iter().any(|window| window.device.interrupt_pending())
     }
 
-    /// Execute program with entry point at 0
-    /// If result is greater than ERROR_START_NUM than it's a CPU error
-    pub fn execute_first(&mut self) -> u32 {
-        self.execute(0)
+    fn is_cacheable(&self, pos: u32) -> bool {
+        !self.overlaps_device(pos, INSTRUCTION_SIZE)
     }
 
-    /// Execute program with entry point at pos
-    /// If result is greater than ERROR_START_NUM than it's a CPU error
-    pub fn execute(&mut self, pos: u32) -> u32 {
-        self.running = true;
-        self.write_register_value(Register::IP, pos);
-        self.write_register_value(Register::ERR, Error::NoError as u32);
+    fn memory(&self, range: Range<u32>) -> Option<&[u8]> {
+        self.memory.get(range.start as usize..range.end as usize)
+    }
 
-        loop {
-            let instruction = self.interpreter.read_u32(self.read_register_value(Register::IP));
-            if let Some(parsed_instruction) = instruction {
-                self.interpret_instruction(parsed_instruction);
-            }
-            else {
-                self.write_register_value(Register::ERR, Error::Memory as u32);
-                break;
-            }
+    fn memory_image(&self) -> Vec<u8> {
+        self.memory.clone()
+    }
 
-            if self.read_register_value(Register::ERR) != Error::NoError as u32 || !self.running {
-                break;
-            }
+    fn restore_memory_image(&mut self, image: &[u8]) -> bool {
+        if image.len() != self.memory.len() {
+            return false;
+        }
 
-            self.write_register_value(Register::IP, self.read_register_value(Register::IP).wrapping_add(4));
-        } 
+        self.memory.copy_from_slice(image);
+        true
+    }
 
-        let error_value = self.read_register_value(Register::ERR);
-        return if error_value == (Error::NoError as u32) {
-            self.read_register_value(Register::R1)
-        }
-        else {
-            error_value + ERROR_START_NUM
-        }
+    fn reset_memory(&mut self) {
+        self.memory.fill(0);
+        self.memory[..self.program.len()].copy_from_slice(&self.program);
     }
 
-    fn interpret_instruction(&mut self, instruction: u32) {
-        let opcode = Self::get_opcode(instruction);
-        let opcode = OpCode::from_u8(opcode);
-        if let Some(opcode) = opcode {
-            match opcode {
-                OpCode::SYSCALLI => {
-                    self.write_next_instruction_address();
-                    self.syscall(Self::get_immediate(instruction))
-                },
-                OpCode::CPY => {
-                    self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
-                        this.write_user_register_value(reg0, this.read_user_register_value(reg1))
-                    );
-                },
-                // Load-store
-                OpCode::LW => {
-                    self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
-                        if let Some(result) = this.interpreter.read_u32(this.read_user_register_value(reg1)) {
-                            this.write_user_register_value(reg0, result);
-                        }
-                        else {
-                            this.write_error(Error::Memory);
-                        }
-                    );
-                },
-                OpCode::SW => {
-                    self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
-                        if !this.interpreter.write_u32(this.read_user_register_value(reg1), this.read_user_register_value(reg0)) {
-                            this.write_error(Error::Memory);
-                        }
-                    );
-                },
-                OpCode::LH => {
-                    self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
-                        if let Some(result) = this.interpreter.read_u16(this.read_user_register_value(reg1)) {
-                            this.write_user_register_value(reg0, result as u32);
-                        }
-                        else {
-                            this.write_error(Error::Memory);
-                        }
-                    );
-                },
-                OpCode::SH => {
-                    self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
-                        if !this.interpreter.write_u16(this.read_user_register_value(reg1), (this.read_user_register_value(reg0) & 0x0000FFFF).try_into().expect("Unexpected error")) {
-                            this.write_error(Error::Memory);
-                        }
-                    );
-                },
-                OpCode::LB => {
-                    self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
-                        if let Some(result) = this.interpreter.read_u8(this.read_user_register_value(reg1)) {
-                            this.write_user_register_value(reg0, result as u32);
-                        }
-                        else {
-                            this.write_error(Error::Memory);
-                        }
-                    );
-                },
-                OpCode::SB => {
-                    self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
-                        if !this.interpreter.write_u8(this.read_user_register_value(reg1), (this.read_user_register_value(reg0) & 0x000000FF).try_into().expect("Unexpected error")) {
-                            this.write_error(Error::Memory);
-                        }
-                    );
-                },
-                OpCode::LI => {
-                    let (reg0, imm1) = Self::get_register_and_twos_complement_immediate(instruction);
-                    if let Some(reg_value0) = Register::from_u8(reg0) {
-                        self.write_user_register_value(reg_value0, imm1);
-                    }
-                    else {
-                        self.write_error(Error::Register);
-                    }
-                },
-                OpCode::LWI => {
-                    self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
-                        if let Some(result) = this.interpreter.read_u32(imm) {
-                            this.write_user_register_value(reg, result);
-                        }
-                        else {
-                            this.write_error(Error::Memory);
-                        }
-                    );
-                },
-                OpCode::SWI => {
-                    self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
-                        if !this.interpreter.write_u32(imm, this.read_user_register_value(reg)) {
-                            this.write_error(Error::Memory);
-                        }
-                    );
-                },
-                OpCode::LHI => {
-                    self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
-                        if let Some(result) = this.interpreter.read_u16(imm) {
-                            this.write_user_register_value(reg, result as u32);
-                        }
-                        else {
-                            this.write_error(Error::Memory);
-                        }
-                    );
-                },
-                OpCode::SHI => {
-                    self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
-                        if !this.interpreter.write_u16(imm, (this.read_user_register_value(reg) & 0x0000FFFF).try_into().expect("Unexpected error")) {
-                            this.write_error(Error::Memory);
-                        }
-                    );
-                },
-                OpCode::LBI => {
-                    self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
-                        if let Some(result) = this.interpreter.read_u8(imm) {
-                            this.write_user_register_value(reg, result as u32);
-                        }
-                        else {
-                            this.write_error(Error::Memory);
-                        }
-                    );
-                },
-                OpCode::SBI => {
-                    self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
-                        if !this.interpreter.write_u8(imm, (this.read_user_register_value(reg) & 0x000000FF).try_into().expect("Unexpected error")) {
-                            this.write_error(Error::Memory);
-                        }
-                    );
-                },
-                // Arithmetics
-                OpCode::ADD => {
-                    self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_add(y));
-                },
-                OpCode::SUB => {
-                    self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_sub(y));
-                },
-                OpCode::MUL => {
-                    self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_mul(y));
-                },
-                OpCode::DIV => {
-                    self.binary_register_operation_write0(instruction,
-                        |this: &mut Self, x, y|
-                            if y == 0 {
-                                this.write_error(Error::DivisorNotZero);
-                                0
-                            } else {
-                                x / y
-                            }
-                    );
-                },
-                OpCode::ADDI => {
-                    self.binary_register_and_immediate_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_add(y));
-                },
-                OpCode::SUBI => {
-                    self.binary_register_and_immediate_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_sub(y));
-                },
-                OpCode::MULI => {
-                    self.binary_register_and_immediate_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_mul(y));
-                },
-                OpCode::DIVI => {
-                    self.binary_register_and_immediate_operation_write0(instruction,
-                        |this: &mut Self, x, y|
-                            if y == 0 {
-                                this.write_error(Error::DivisorNotZero);
-                                0
-                            } else {
-                                x / y
-                            }
-                    );
-                },
-                // Unconditional jumps
-                OpCode::J => {
-                    let reg = Self::get_registers(instruction);
-                    if let Some(reg_value) = Register::from_u8(reg) {
-                        let address = self.read_user_register_value(reg_value);
-                        self.write_register_value(Register::IP, address.wrapping_sub(4)); // Minus 4 because this will be added after every cycle
-                    }
-                    else {
-                        self.write_error(Error::Register);
-                    }
-                },
-                OpCode::JI => {
-                    let address = Self::get_immediate(instruction);
-                    self.write_register_value(Register::IP, address.wrapping_sub(4)); // Minus 4 because this will be added after every cycle
-                }
-                OpCode::JIL => {
-                    let address = Self::get_immediate(instruction);
-                    self.write_register_value(Register::RA, self.read_register_value(Register::IP).wrapping_add(4)); // Plus 4 because it points to the next instruction
-                    self.write_register_value(Register::IP, address.wrapping_sub(4)); // Minus 4 because this will be added after every cycle
-                },
-                OpCode::JZI => {
-                    self.unary_check_write_ip(instruction, |_: &mut Self, x| x == 0);
-                },
-                OpCode::JNZI => {
-                    self.unary_check_write_ip(instruction, |_: &mut Self, x| x != 0);
-                },
-                OpCode::JLZI => {
-                    self.unary_check_write_ip(instruction,
-                        |_: &mut Self, x| i32::from_le_bytes(u32::to_le_bytes(x)) < 0
-                    );
-                },
-                OpCode::JGZI => {
-                    self.unary_check_write_ip(instruction,
-                        |_: &mut Self, x| i32::from_le_bytes(u32::to_le_bytes(x)) > 0
-                    );
-                },
-                OpCode::AND => {
-                    self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x & y);
-                },
-                OpCode::OR => {
-                    self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x | y);
-                },
-                OpCode::XOR => {
-                    self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x ^ y);
-                },
-                OpCode::NOT => {
-                    let reg = Self::get_registers(instruction);
-                    if let Some(reg_value) = Register::from_u8(reg) {
-                        let val = self.read_user_register_value(reg_value);
-                        self.write_user_register_value(reg_value, !val);
-                    }
-                    else {
-                        self.write_error(Error::Register);
-                    }
-                },
-                OpCode::SRL => {
-                    self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x >> y);
-                },
-                OpCode::SLL => {
-                    self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x << y);
-                },
-                OpCode::SRLI => {
-                    self.binary_register_and_immediate_operation_write0(instruction,
-                        |_: &mut Self, x, y| x >> y);
-                },
-                OpCode::SLLI => {
-                    self.binary_register_and_immediate_operation_write0(instruction,
-                        |_: &mut Self, x, y| x << y);
-                }
-            }
-        }
-        else {
-            self.write_error(Error::OpCode);
-            return;
+    fn load_program(&mut self, program: &[u8]) -> bool {
+        if program.len() > self.memory.len() {
+            return false;
         }
-    }
 
-    /// Writes the address - 4 to register $ip, if `unary_op` evaluates to
-    /// true.
-    fn unary_check_write_ip(&mut self, instruction: u32, unary_op: fn (&mut Self, u32) -> bool) {
-      let (reg, imm) = Self::get_register_and_immediate(instruction);
-      if let Some(reg_value) = Register::from_u8(reg) {
-          let val = self.read_user_register_value(reg_value);
-          if unary_op(self, val) {
-            self.write_register_value(Register::IP, imm.wrapping_sub(4));
-          }
-      }
-      else {
-          self.write_error(Error::Register);
-      }
+        self.program = program.to_vec();
+        self.reset_memory();
+        true
     }
 
-    fn binary_register_and_immediate_operation_write0(&mut self, instruction: u32, binary_op: fn (&mut Self, u32, u32) -> u32) {
-      let (reg, imm) = Self::get_register_and_immediate(instruction);
-      if let Some(reg_value) = Register::from_u8(reg) {
-          let val = self.read_user_register_value(reg_value);
-          let result = binary_op(self, val, imm);
-          self.write_user_register_value(reg_value, result);
-      }
-      else {
-          self.write_error(Error::Register);
-      }
+    fn len(&self) -> u32 {
+        u32::try_from(self.memory.len()).expect("Less than u32::MAX expected")
     }
+}
 
-    fn binary_register_and_immediate_operation(&mut self, instruction: u32, binary_op: fn (&mut Self, Register, u32)) {
-      let (reg, imm) = Self::get_register_and_immediate(instruction);
-      if let Some(reg_value) = Register::from_u8(reg) {
-          binary_op(self, reg_value, imm);
-      }
-      else {
-          self.write_error(Error::Register);
-      }
-    }
+/// Performance counters of a virtual machine
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VmStats {
+    /// Executed instructions
+    pub instructions: u64,
 
-    /// Combines both values of the two registers parsed from the instruction with the function
-    /// `binary_op` and writes the result in the first registers
-    fn binary_register_operation_write0(&mut self, instruction: u32, binary_op: fn (&mut Self, u32, u32) -> u32) {
-      let (reg0, reg1) = Self::get_two_registers(instruction);
-      if let (Some(reg_value0), Some(reg_value1)) = (Register::from_u8(reg0), Register::from_u8(reg1)) {
-          let val0 = self.read_user_register_value(reg_value0);
-          let val1 = self.read_user_register_value(reg_value1);
-          let result = binary_op(self, val0, val1);
-          self.write_user_register_value(reg_value0, result);
-      }
-      else {
-          self.write_error(Error::Register);
-      }
-    }
+    /// Memory reads by load instructions
+    pub memory_reads: u64,
 
-    fn binary_register_operation(&mut self, instruction: u32, binary_op: fn (&mut Self, Register, Register)) {
-      let (reg0, reg1) = Self::get_two_registers(instruction);
-      if let (Some(reg_value0), Some(reg_value1)) = (Register::from_u8(reg0), Register::from_u8(reg1)) {
-          binary_op(self, reg_value0, reg_value1);
-      }
-      else {
-          self.write_error(Error::Register);
-      }
-    }
+    /// Memory writes by store instructions
+    pub memory_writes: u64,
 
-    /// Saves the address of the next instruction in $ra
-    #[inline(always)]
-    fn write_next_instruction_address(&mut self) {
-        self.write_register_value(Register::RA, self.read_register_value(Register::IP) + 4);
-    }
+    /// Jumps, which changed the instruction pointer
+    pub jumps_taken: u64,
 
-    /// Check if register is read-only
-    fn is_readonly(reg: Register) -> bool {
-        return match reg {
-            Register::IP | Register::ERR => true,
-            _ => false
-        }
-    }
+    /// System calls
+    pub syscalls: u64,
 
-    #[inline(always)]
-    fn write_error(&mut self, err: Error) {
-        self.write_register_value(Register::ERR, err as u32);
-    }
+    /// Errors and interrupts delivered to a trap handler
+    pub traps: u64,
+}
 
-    /// Write to unkonwn register value
-    #[inline(always)]
-    fn write_unknown_register_value(&mut self, reg: u8, value: u32) {
-        if let Some(reg_value) = Register::from_u8(reg) {
-            self.write_register_value(reg_value, value);
-        }
-        else {
-            self.write_register_value(Register::ERR, Error::Register as u32);
+impl VmStats {
+    /// Counter by the index used by the guest (see syscall 3)
+    pub fn get_counter(&self, index: u32) -> Option<u64> {
+        match index {
+            0 => Some(self.instructions),
+            1 => Some(self.memory_reads),
+            2 => Some(self.memory_writes),
+            3 => Some(self.jumps_taken),
+            4 => Some(self.syscalls),
+            _ => None
         }
     }
+}
 
-    // If the user (program) writes to register reg
-    #[inline(always)]
-    pub fn write_user_register_value(&mut self, reg: Register, value: u32) {
-        if Self::is_readonly(reg) {
-           self.write_error(Error::ReadonlyRegister);
-        }
-        else {
-           self.write_register_value(reg, value);
+/// Normal end of a program (syscall 0)
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct ExitStatus {
+    /// Status value of the program ($r1)
+    pub code: u32,
+}
+
+/// Error, which stopped a program
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct VmError {
+    /// What went wrong
+    pub kind: Error,
+
+    /// Address of the failing instruction
+    pub ip: u32,
+
+    /// Accessed address of memory errors (`Error::Memory`,
+    /// `Error::PageFault`)
+    pub address: Option<u32>,
+}
+
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} at 0x{:X}", self.kind, self.ip)?;
+        if let Some(address) = self.address {
+            write!(f, " (address 0x{:X})", address)?;
         }
+
+        Ok(())
     }
+}
 
-    /// Writes value value to register reg
-    #[inline(always)]
-    pub fn write_register_value(&mut self, reg: Register, value: u32) {
-        self.registers[reg as usize] = value;
-    }
+impl std::error::Error for VmError {}
 
-    #[inline(always)]
-    fn read_user_register_value(&self, reg: Register) -> u32 {
-        self.read_register_value(reg)
-    }
+/// Details of the last error of an instruction (see
+/// `VirtualMachine::last_fault`)
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Fault {
+    /// What went wrong
+    pub kind: Error,
 
-    #[inline(always)]
-    fn read_user_chunk(&self, addr: u32, len: u32) -> Option<Vec<u8>> {
-        self.interpreter.read_chunk(addr, len)
-    }
+    /// Address of the failing instruction
+    pub ip: u32,
 
-    /// Reads value from register reg
-    #[inline(always)]
-    pub fn read_register_value(&self, reg: Register) -> u32 {
-        self.registers[reg as usize]
-    }
+    /// Accessed address of memory errors (`Error::Memory`,
+    /// `Error::PageFault`)
+    pub address: Option<u32>,
 
-    fn syscall(&mut self, syscall: u32) {
-        match syscall {
-            0 => {
-                self.running = false;
-            },
-            1 => {
-                let addr = self.read_user_register_value(Register::R1);
-                let len = self.read_user_register_value(Register::R2);
-                let chunk = self.read_user_chunk(addr, len);
-                if let Some(chunk) = chunk {
-                    let write_result = self.stdout.write(chunk.as_slice()).is_ok();
-                    self.write_user_register_value(Register::R0, write_result as u32);
-                }
-                else {
-                    self.write_error(Error::Memory);
-                }
+    /// The failing instruction word, None if it couldn't be fetched
+    pub instruction: Option<u32>,
+}
+
+/// State of the program after `run_slice`
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum SliceStatus {
+    /// All instructions of the slice were executed, the program continues
+    /// with the next slice
+    Yielded,
+
+    /// The program waits for input: the input stream returned
+    /// `io::ErrorKind::WouldBlock` (syscall 6). The syscall is executed again
+    /// with the next slice.
+    Pending,
+
+    /// The program stopped
+    Finished(Result<ExitStatus, VmError>),
+}
+
+/// Instructions executed by every poll of `ExecuteAsync`
+const ASYNC_SLICE_INSTRUCTIONS: u64 = 10000;
+
+/// Future executing a program (see `VirtualMachine::execute_async`)
+pub struct ExecuteAsync<'vm, 'source, InterpreterImpl: Interpreter> {
+    vm: &'vm mut VirtualMachine<'source, InterpreterImpl>,
+}
+
+impl<InterpreterImpl: Interpreter> Future for ExecuteAsync<'_, '_, InterpreterImpl> {
+    type Output = Result<ExitStatus, VmError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.vm.run_slice(ASYNC_SLICE_INSTRUCTIONS) {
+            SliceStatus::Finished(result) => Poll::Ready(result),
+            SliceStatus::Yielded | SliceStatus::Pending => {
+                // There's no notification for new input, poll again
+                cx.waker().wake_by_ref();
+                Poll::Pending
             },
-            _ => {
-                self.write_register_value(Register::ERR, Error::Syscall as u32);
-            }
         }
     }
+}
 
-    #[inline(always)]
-    fn get_opcode(instruction: u32) -> u8 {
-        u8::try_from((instruction & 0xFF000000) >> (3 * 8)).expect("Unexpected failure!")
-    }
+/// A single executed instruction
+#[derive(PartialEq, Debug, Clone)]
+pub struct TraceEntry {
+    /// Address of the instruction
+    pub ip: u32,
 
+    /// Raw instruction
+    pub instruction: u32,
 
-    #[inline(always)]
-    fn get_immediate(instruction: u32) -> u32 {
-        instruction & 0x00FFFFFF
+    /// Registers written by the instruction with their new values ($ip is
+    /// omitted)
+    pub register_writes: Vec<(Register, u32)>,
+}
+
+impl TraceEntry {
+    /// Assembler representation of the instruction (None if invalid)
+    pub fn mnemonic(&self) -> Option<String> {
+        disassemble_instruction(self.instruction)
     }
+}
 
-    #[inline(always)]
-    fn get_registers(instruction: u32) -> u8 {
-        u8::try_from(instruction & 0x0000000F).expect("Unexpected failure!")
+impl TraceEntry {
+    /// Like the Display representation, but with the address of the
+    /// instruction reported relative to its memory region
+    pub fn to_string_with_memory_map(&self, memory_map: &MemoryMap) -> String {
+        let mut result = format!("{}: {}", memory_map.format_address(self.ip), self.format_instruction());
+        for (reg, value) in &self.register_writes {
+            result += &format!(" {}={}", reg, value);
+        }
+
+        result
     }
 
-    #[inline(always)]
-    fn get_two_registers(instruction: u32) -> (u8, u8) {
-        (
-            u8::try_from((instruction & 0x00F00000) >> (2 * 8 + 4)).expect("Unexpected failure!"),
-            u8::try_from(instruction & 0x0000000F).expect("Unexpected failure!"),
-        )
+    fn format_instruction(&self) -> String {
+        let mnemonic = self.mnemonic().unwrap_or_else(|| format!("<invalid 0x{:08x}>", self.instruction));
+        format!("{:<24}", mnemonic)
     }
+}
 
-    #[inline(always)]
-    fn get_register_and_immediate(instruction: u32) -> (u8, u32) {
-        (
-            u8::try_from((instruction & 0x00F00000) >> (2 * 8 + 4)).expect("Unexpected failure!"),
-            instruction & 0x000FFFFF
-        )
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "0x{:08x}: {}", self.ip, self.format_instruction())?;
+        for (reg, value) in &self.register_writes {
+            write!(f, " {}={}", reg, value)?;
+        }
+
+        Ok(())
     }
+}
 
-    #[inline(always)]
-    fn get_register_and_twos_complement_immediate(instruction: u32) -> (u8, u32) {
-        (
-            u8::try_from((instruction & 0x00F00000) >> (2 * 8 + 4)).expect("Unexpected failure!"),
-            Self::get_u32_from_immediate(instruction & 0x000FFFFF, 0x000FFFFF, 0x00080000)
-        )
+/// Receives the executed instructions of a virtual machine
+pub trait TraceSink {
+    fn trace(&mut self, entry: TraceEntry);
+}
+
+impl TraceSink for Vec<TraceEntry> {
+    fn trace(&mut self, entry: TraceEntry) {
+        self.push(entry);
     }
+}
 
-    #[inline(always)]
-    fn get_two_register_and_immediate(instruction: u32) -> (u8, u8, u32) {
-        (
-            u8::try_from((instruction & 0x00F00000) >> (2 * 8 + 4)).expect("Unexpected failure!"),
-            u8::try_from((instruction & 0x000F0000) >> (2 * 8 + 0)).expect("Unexpected failure!"),
-            instruction & 0x0000FFFF
-        )
+/// Writes every executed instruction as a line to a writer
+pub struct WriteTraceSink<W: std::io::Write> {
+    writer: W,
+    memory_map: Option<MemoryMap>,
+    line_table: Option<LineTable>,
+}
+
+impl<W: std::io::Write> WriteTraceSink<W> {
+    pub fn new(writer: W) -> WriteTraceSink<W> {
+        WriteTraceSink { writer, memory_map: None, line_table: None }
     }
 
-    /// Returns u32 from immediate. Immediate is a twos complement!
-    #[inline(always)]
-    fn get_u32_from_immediate(imm: u32, bitmask: u32, check_negative_bitmask: u32) -> u32 {
-        if imm & check_negative_bitmask == 0 { // Positive
-            imm
-        }
-        else {
-            imm | !bitmask // Two's complement -> Add 1 to the start
-        }
+    /// Reports addresses relative to the regions of `memory_map`
+    pub fn with_memory_map(writer: W, memory_map: MemoryMap) -> WriteTraceSink<W> {
+        WriteTraceSink { writer, memory_map: Some(memory_map), line_table: None }
     }
 
-    pub fn get_interpreter(&mut self) -> &InterpreterImpl {
-        &self.interpreter
+    /// Also reports the source location of every instruction
+    pub fn line_table(mut self, line_table: LineTable) -> WriteTraceSink<W> {
+        self.line_table = Some(line_table);
+        self
     }
+}
 
-    pub fn get_interpreter_mut(&mut self) -> &mut InterpreterImpl {
-        &mut self.interpreter
+impl<W: std::io::Write> TraceSink for WriteTraceSink<W> {
+    fn trace(&mut self, entry: TraceEntry) {
+        let mut line = match &self.memory_map {
+            Some(memory_map) => entry.to_string_with_memory_map(memory_map),
+            None => entry.to_string(),
+        };
+        if let Some(location) = self.line_table.as_ref().and_then(|line_table| line_table.location(entry.ip)) {
+            line += &format!(" at {}", location);
+        }
+
+        // Tracing must not influence the execution, so errors are ignored
+        let _ = writeln!(self.writer, "{}", line);
     }
 }
 
-pub mod utils {
-    use super::{OpCode, Register};
+/// When a taken jump changes the instruction pointer
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum BranchMode {
+    /// The jump target is the next executed instruction
+    #[default]
+    Immediate,
+    /// MIPS-style: the instruction after the jump (the delay slot) is
+    /// executed before continuing at the jump target. Linking jumps save the
+    /// address after the delay slot in $ra.
+    DelaySlot,
+}
 
-    pub const fn create_instruction_register(opcode: OpCode, reg: Register) -> u32
-    {
-        ((opcode as u32) << 3 * 8) | (reg as u32)
+/// What happens, when the virtual machine executes an invalid opcode (or an
+/// opcode, which isn't part of the instruction set version)
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum InvalidOpcodePolicy {
+    /// Fails with `Error::OpCode` like other errors: the trap handler of the
+    /// error is invoked, without one the hart stops
+    #[default]
+    Trap,
+    /// Stops the hart with `Error::OpCode`, even if a trap handler is
+    /// installed
+    Stop,
+    /// The instruction is executed as NOP
+    Skip,
+}
+
+/// State of a single virtual CPU (hart)
+#[derive(PartialEq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Hart {
+    registers: [u32; LAST_REGISTER as usize + 1],
+    running: bool,
+    fault_address: Option<u32>,
+    /// Address of the instruction executed after the current one. Set to the
+    /// following instruction before executing an instruction, control flow
+    /// instructions change it.
+    next_ip: u32,
+    /// Target of a jump, which is taken after the next instruction (the
+    /// delay slot)
+    delayed_branch: Option<u32>,
+    /// Address of the vector table (0 disables traps)
+    vector_table: u32,
+    /// Address, where `reti` continues
+    epc: u32,
+    /// Cause of the current trap
+    cause: u32,
+    /// STATUS_INTERRUPTS_ENABLED and STATUS_IN_TRAP
+    status: u32,
+}
+
+/// Saved state of a virtual machine: the registers and running state of all
+/// harts, the memory and the performance counters. Devices, the MMU and the
+/// configuration (like the branch mode) aren't part of it.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct VmSnapshot {
+    harts: Vec<Hart>,
+    hart_id: usize,
+    memory: Vec<u8>,
+    stats: VmStats,
+}
+
+impl VmSnapshot {
+    /// Saved memory
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
     }
 
-    pub const fn create_instruction_immediate(opcode: OpCode, imm: u32) -> u32
-    {
-        ((opcode as u32) << 3 * 8) | imm
+    /// Saved value of a register of hart 0
+    pub fn read_register_value(&self, reg: Register) -> u32 {
+        self.harts[0].registers[reg as usize]
     }
 
-    pub const fn create_instruction_register_and_immediate(opcode: OpCode, reg: Register, imm: u32) -> u32 {
-        ((opcode as u32)  << 3 * 8) | ((reg as u32) << 2 * 8 + 4) | (imm & 0x000FFFFF)
+    /// Saved performance counters
+    pub fn stats(&self) -> VmStats {
+        self.stats
     }
-    
-    pub const fn create_instruction_two_registers(opcode: OpCode, reg0: Register, reg1: Register) -> u32 {
-        ((opcode as u32)  << 3 * 8) | ((reg0 as u32) << 2 * 8 + 4) | (reg1 as u32)
+
+    /// Serializes the snapshot
+    #[cfg(feature = "serde")]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("Snapshots are always serializable")
     }
-    
-    pub const fn create_instruction_two_registers_and_immediate(opcode: OpCode, reg0: Register, reg1: Register, imm: u32) -> u32 {
-        ((opcode as u32)  << 3 * 8) | ((reg0 as u32) << 2 * 8 + 4) | ((reg1 as u32) << 2 * 8) | (imm & 0x0000FFFF)
+
+    /// Deserializes a snapshot created by `to_bytes`
+    #[cfg(feature = "serde")]
+    pub fn from_bytes(bytes: &[u8]) -> Option<VmSnapshot> {
+        bincode::deserialize(bytes).ok()
     }
 }
 
-pub type BinaryVirtualMachine<'source> = VirtualMachine<'source, BinaryInterpreter>;
+/// Instruction decoded for execution
+#[derive(Clone, Copy)]
+struct Instruction {
+    /// Instruction word
+    word: u32,
+    /// None if the opcode is invalid
+    opcode: Option<OpCode>,
+}
 
-#[cfg(test)]
-mod tests {
-    use super::{OpCode, BinaryInterpreter, BinaryVirtualMachine, Interpreter, Register, utils, Error, ERROR_START_NUM, BINARY_INTERPRETER_MEM_SIZE};
+/// Virtual machine to execute machine code on
+pub struct VirtualMachine<'source, InterpreterImpl: Interpreter>
+{
+    interpreter: InterpreterImpl,
+    /// Hart executing the next instruction
+    hart: Hart,
+    /// Index of `hart`
+    hart_id: usize,
+    /// All harts, the entry of the current hart is outdated
+    harts: Vec<Hart>,
+    stdin: Option<&'source mut dyn std::io::Read>,
+    stdout: &'source mut dyn std::io::Write,
+    stderr: Option<&'source mut dyn std::io::Write>,
+    isa_version: IsaVersion,
+    stats: VmStats,
+    tracer: Option<&'source mut dyn TraceSink>,
+    branch_mode: BranchMode,
+    write_protected: Option<Range<u32>>,
+    fault_injector: Option<FaultInjector>,
+    /// Decoded instructions indexed by physical address / INSTRUCTION_SIZE
+    decode_cache: Option<Vec<Option<Instruction>>>,
+    /// Interrupt requested with `raise_interrupt`, which wasn't taken yet
+    interrupt_requested: bool,
+    /// Executing writable memory fails (W^X)
+    write_xor_execute: bool,
+    invalid_opcode_policy: InvalidOpcodePolicy,
+    /// Syscalls, which may be called (None allows all)
+    allowed_syscalls: Option<Vec<u32>>,
+    /// Functions called with the syscalls starting at `HOST_SYSCALL_START`
+    host_functions: HostFunctions,
+    /// A syscall waits for input (see `SliceStatus::Pending`)
+    input_pending: bool,
+    /// Executions per opcode (indexed by the opcode), if profiling is enabled
+    opcode_counts: Option<Vec<u64>>,
+    /// Executions per instruction address, if profiling is enabled
+    address_counts: Option<HashMap<u32, u64>>,
+    /// Executed instruction addresses, if coverage is enabled
+    coverage: Option<HashSet<u32>>,
+    /// Last error of an instruction (also if a trap handler took it)
+    last_fault: Option<Fault>,
+    /// Entry point of `execute_first`
+    entry: u32,
+}
 
-    const SYSCALLI_EXIT_INSTRUCTION: u32 = u32::to_le((OpCode::SYSCALLI as u32) << 3 * 8);
-    const LOAD_0_IN_R1_INSTRUCTION: u32 = utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 0);
+impl<'source, InterpreterImpl: Interpreter> VirtualMachine<'source, InterpreterImpl> {
+    /// Create a new virtual machine. The program can't read input (syscall
+    /// 6) and can't print to the error stream (syscall 7).
+    pub fn new(interpreter: InterpreterImpl, stdout: &'source mut dyn std::io::Write) -> VirtualMachine<'source, InterpreterImpl> {
+        Self::new_with_streams(interpreter, None, stdout, None)
+    }
 
-    #[test]
-    fn get_opcode() {
-        assert_eq!(OpCode::SYSCALLI as u8, BinaryVirtualMachine::get_opcode(SYSCALLI_EXIT_INSTRUCTION));
+    /// Create a new virtual machine, which reads input of the program from
+    /// `stdin` and writes its output to `stdout` and `stderr`
+    pub fn with_streams(interpreter: InterpreterImpl, stdin: &'source mut dyn std::io::Read, stdout: &'source mut dyn std::io::Write, stderr: &'source mut dyn std::io::Write) -> VirtualMachine<'source, InterpreterImpl> {
+        Self::new_with_streams(interpreter, Some(stdin), stdout, Some(stderr))
     }
 
-    #[test]
-    fn syscall_exit() {
-        let syscode_inst = SYSCALLI_EXIT_INSTRUCTION;
-        let interpreter = BinaryInterpreter::new_with_program(&[syscode_inst]).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    pub(crate) fn new_with_streams(interpreter: InterpreterImpl, stdin: Option<&'source mut dyn std::io::Read>, stdout: &'source mut dyn std::io::Write, stderr: Option<&'source mut dyn std::io::Write>) -> VirtualMachine<'source, InterpreterImpl> {
+        let mut result = VirtualMachine { interpreter, hart: Hart::default(), hart_id: 0, harts: vec![Hart::default()], stdin, stdout, stderr, isa_version: LATEST_ISA_VERSION, stats: VmStats::default(), tracer: None, branch_mode: BranchMode::default(), write_protected: None, fault_injector: None, decode_cache: None, interrupt_requested: false, input_pending: false, write_xor_execute: false, invalid_opcode_policy: InvalidOpcodePolicy::default(), allowed_syscalls: None, host_functions: HostFunctions::default(), opcode_counts: None, address_counts: None, coverage: None, last_fault: None, entry: 0 };
+        result.write_register_value(Register::SP, result.interpreter.len());
+        result
+    }
 
-        assert_eq!(0, vm.read_register_value(Register::IP));
-        assert_eq!(Some(syscode_inst), vm.get_interpreter().read_u32(0));
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(0, vm.read_register_value(Register::IP));
+    /// Restrict the machine to the instructions of an instruction set version.
+    /// Newer instructions are treated as invalid opcodes.
+    pub fn set_isa_version(&mut self, isa_version: IsaVersion) {
+        self.isa_version = isa_version;
+        self.clear_decode_cache();
+    }
 
-        let syscode_inst = SYSCALLI_EXIT_INSTRUCTION;
-        let interpreter = BinaryInterpreter::new_with_program(&[
-            syscode_inst,
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32)
-        ]).expect("Unexpected error!");
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// Instruction set version supported by the machine
+    pub fn get_isa_version(&self) -> IsaVersion {
+        self.isa_version
+    }
 
-        assert_eq!(0, vm.read_register_value(Register::IP));
-        assert_eq!(Some(syscode_inst), vm.get_interpreter().read_u32(0));
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(0, vm.read_register_value(Register::R0));
-        assert_eq!(0, vm.read_register_value(Register::IP));
+    /// Performance counters. The counters aren't reset by executing the
+    /// machine again.
+    pub fn stats(&self) -> VmStats {
+        self.stats
+    }
 
+    /// Reset all performance counters to zero
+    pub fn reset_stats(&mut self) {
+        self.stats = VmStats::default();
     }
 
-    #[test]
-    fn li_r0() {
-        let inst = utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 564);
-        let interpreter = BinaryInterpreter::new_with_program(&[inst, LOAD_0_IN_R1_INSTRUCTION, SYSCALLI_EXIT_INSTRUCTION]).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// Record every executed instruction into `tracer` (None disables
+    /// tracing)
+    pub fn set_tracer(&mut self, tracer: Option<&'source mut dyn TraceSink>) {
+        self.tracer = tracer;
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(564, vm.read_register_value(Register::R0));
+    /// Select when taken jumps change the instruction pointer
+    pub fn set_branch_mode(&mut self, branch_mode: BranchMode) {
+        self.branch_mode = branch_mode;
     }
 
-    #[test]
-    fn li_r1() {
-        let inst = utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 563);
-        let interpreter = BinaryInterpreter::new_with_program(&[inst, SYSCALLI_EXIT_INSTRUCTION]).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// When taken jumps change the instruction pointer
+    pub fn get_branch_mode(&self) -> BranchMode {
+        self.branch_mode
+    }
 
-        assert_eq!(563, vm.execute_first());
-        assert_eq!(563, vm.read_register_value(Register::R1));
+    /// Mark a memory range (like the loaded program) as read-only. Stores into
+    /// it fail with `Error::WriteProtected`. None removes the protection.
+    pub fn set_write_protected(&mut self, range: Option<Range<u32>>) {
+        self.write_protected = range;
     }
 
-    #[test]
-    fn li_r7() {
-        let inst = utils::create_instruction_register_and_immediate(OpCode::LI, Register::R7, 513);
-        let interpreter = BinaryInterpreter::new_with_program(&[inst, LOAD_0_IN_R1_INSTRUCTION, SYSCALLI_EXIT_INSTRUCTION]).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// Read-only memory range
+    pub fn get_write_protected(&self) -> Option<Range<u32>> {
+        self.write_protected.clone()
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(513, vm.read_register_value(Register::R7));
+    /// Memory is either writable or executable (W^X): fetching an
+    /// instruction from writable memory fails with `Error::ExecuteProtected`.
+    /// Only the write-protected range (see `set_write_protected`) and pages,
+    /// which the MMU maps read-only, are executable.
+    pub fn set_write_xor_execute(&mut self, enabled: bool) {
+        self.write_xor_execute = enabled;
     }
 
-    #[test]
-    fn li_ip() {
-        let inst = utils::create_instruction_register_and_immediate(OpCode::LI, Register::IP, 12);
-        let interpreter = BinaryInterpreter::new_with_program(&[inst, LOAD_0_IN_R1_INSTRUCTION, SYSCALLI_EXIT_INSTRUCTION]).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// True, if W^X is enabled
+    pub fn get_write_xor_execute(&self) -> bool {
+        self.write_xor_execute
+    }
 
-        assert_eq!(ERROR_START_NUM + (Error::ReadonlyRegister as u32), vm.execute_first());
+    /// Count how often every opcode is executed (see `opcode_profile`).
+    /// Enabling resets the counts.
+    pub fn set_opcode_profiling(&mut self, enabled: bool) {
+        self.opcode_counts = if enabled {
+            Some(vec![0; u8::MAX as usize + 1])
+        }
+        else {
+            None
+        };
     }
 
-    #[test]
-    fn li_err() {
-        let inst = utils::create_instruction_register_and_immediate(OpCode::LI, Register::ERR, 12);
-        let interpreter = BinaryInterpreter::new_with_program(&[inst, LOAD_0_IN_R1_INSTRUCTION, SYSCALLI_EXIT_INSTRUCTION]).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// Executions of every executed opcode. Returns None, if profiling isn't
+    /// enabled.
+    pub fn opcode_profile(&self) -> Option<HashMap<OpCode, u64>> {
+        let counts = self.opcode_counts.as_ref()?;
+        Some(counts.iter().enumerate()
+            .filter(|(_, count)| **count > 0)
+            .filter_map(|(opcode, count)| Some((OpCode::from_usize(opcode)?, *count)))
+            .collect())
+    }
 
-        assert_eq!(ERROR_START_NUM + (Error::ReadonlyRegister as u32), vm.execute_first());
+    /// Count how often every instruction address is executed (see
+    /// `hot_addresses`). Enabling resets the counts.
+    pub fn set_address_profiling(&mut self, enabled: bool) {
+        self.address_counts = if enabled {
+            Some(HashMap::new())
+        }
+        else {
+            None
+        };
     }
 
-    #[test]
-    fn add() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 16),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 5),
-            utils::create_instruction_two_registers(OpCode::ADD, Register::R0, Register::R1),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION
-        ];
+    /// The n most executed instruction addresses with their number of
+    /// executions, most frequent first. Returns None, if profiling isn't
+    /// enabled.
+    pub fn hot_addresses(&self, n: usize) -> Option<Vec<(u32, u64)>> {
+        let mut addresses: Vec<(u32, u64)> = self.address_counts.as_ref()?
+            .iter()
+            .map(|(address, count)| (*address, *count))
+            .collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        addresses.truncate(n);
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        Some(addresses)
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(21, vm.read_register_value(Register::R0));
+    /// Record which instruction addresses are executed (see `coverage`).
+    /// Enabling resets the recorded addresses.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        self.coverage = if enabled {
+            Some(HashSet::new())
+        }
+        else {
+            None
+        };
     }
 
-    #[test]
-    fn cpy() {
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 16),
-            utils::create_instruction_two_registers(OpCode::CPY, Register::R2, Register::R0),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION
-        ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// The executed instruction addresses. Returns None, if coverage isn't
+    /// enabled.
+    pub fn coverage(&self) -> Option<&HashSet<u32>> {
+        self.coverage.as_ref()
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(16, vm.read_register_value(Register::R2));
+    /// Select the entry point of the program (used by `execute_first`, like
+    /// the entry of a binary image)
+    pub fn set_entry(&mut self, entry: u32) {
+        self.entry = entry;
     }
 
-    #[test]
-    fn sub() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 16),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 5),
-            utils::create_instruction_two_registers(OpCode::SUB, Register::R0, Register::R1),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION
-        ];
+    /// Entry point of the program
+    pub fn get_entry(&self) -> u32 {
+        self.entry
+    }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// Select what happens on invalid opcodes
+    pub fn set_invalid_opcode_policy(&mut self, policy: InvalidOpcodePolicy) {
+        self.invalid_opcode_policy = policy;
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(11, vm.read_register_value(Register::R0));
+    /// What happens on invalid opcodes
+    pub fn get_invalid_opcode_policy(&self) -> InvalidOpcodePolicy {
+        self.invalid_opcode_policy
     }
 
-    #[test]
-    fn mul() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 4),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 5),
-            utils::create_instruction_two_registers(OpCode::MUL, Register::R0, Register::R1),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION
-        ];
+    /// Restrict the syscalls to `syscalls` (None allows all). Other syscalls
+    /// fail like unknown ones (`Error::Syscall`).
+    pub fn set_allowed_syscalls(&mut self, syscalls: Option<Vec<u32>>) {
+        self.allowed_syscalls = syscalls;
+    }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// Binds `function` to the import `name` (`.import name`) of programs.
+    /// The arguments are read from `$r1` to `$r4`, the result (if not `()`)
+    /// is written into `$r0`. A function bound to the same name before is
+    /// replaced. The function can't borrow anything, state is shared with
+    /// e.g. `Rc<RefCell<_>>`.
+    ///
+    /// ```
+    /// # use libcustomvmcpu::runtime::{BinaryInterpreter, BinaryVirtualMachine};
+    /// let mut stdout = std::io::sink();
+    /// let mut vm = BinaryVirtualMachine::new(BinaryInterpreter::new(), &mut stdout);
+    /// vm.bind("draw_pixel", |x: u32, y: u32, color: u32| {
+    ///     println!("{}x{}: {:06x}", x, y, color);
+    /// });
+    /// ```
+    pub fn bind<Args, F: HostFunction<Args> + 'static>(&mut self, name: &str, function: F) {
+        self.host_functions.bind(name, function);
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(20, vm.read_register_value(Register::R0));
+    /// Resolves the imports of the loaded program (`CompileOutput::imports`)
+    /// to the bound functions. Returns the names of the imports, which
+    /// aren't bound; the imports linked before are kept then. Calling an
+    /// import, which isn't linked, fails like an unknown syscall.
+    pub fn link_imports(&mut self, imports: &[String]) -> Result<(), Vec<String>> {
+        self.host_functions.link(imports)
     }
 
-    #[test]
-    fn div() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 20),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 5),
-            utils::create_instruction_two_registers(OpCode::DIV, Register::R0, Register::R1),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION
-        ];
+    /// Syscalls, which may be called (None, if all are allowed)
+    pub fn get_allowed_syscalls(&self) -> Option<&[u32]> {
+        self.allowed_syscalls.as_deref()
+    }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// Virtual address of the last faulting memory access (`Error::PageFault`
+    /// or `Error::Memory`)
+    pub fn get_fault_address(&self) -> Option<u32> {
+        self.hart.fault_address
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(4, vm.read_register_value(Register::R0));
+    /// Details of the last error of an instruction, also if a trap handler
+    /// took it
+    pub fn last_fault(&self) -> Option<Fault> {
+        self.last_fault
+    }
 
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 24),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 5),
-            utils::create_instruction_two_registers(OpCode::DIV, Register::R0, Register::R1),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION
-        ];
+    /// Inject faults while executing (None disables fault injection)
+    pub fn set_fault_injector(&mut self, fault_injector: Option<FaultInjector>) {
+        self.fault_injector = fault_injector;
+    }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// Fault injector with the log of all injected faults
+    pub fn get_fault_injector(&self) -> Option<&FaultInjector> {
+        self.fault_injector.as_ref()
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(4, vm.read_register_value(Register::R0));
+    /// Keep decoded instructions, so loops don't decode them again. Stores
+    /// into the memory invalidate the decoded instructions at their address.
+    /// The cache needs memory for one entry per word of the memory.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache = if enabled {
+            Some(vec![None; (self.interpreter.len() / INSTRUCTION_SIZE) as usize])
+        }
+        else {
+            None
+        };
     }
 
-    #[test]
-    fn div_divisor_zero() {
-      let program: [u32; 5] = [
-          utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 20),
-          utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 0),
-          utils::create_instruction_two_registers(OpCode::DIV, Register::R0, Register::R1),
-          LOAD_0_IN_R1_INSTRUCTION,
-          SYSCALLI_EXIT_INSTRUCTION
-      ];
+    /// Requests an interrupt (like a device). The next hart with enabled
+    /// interrupts takes it, afterwards the request is cleared.
+    pub fn raise_interrupt(&mut self) {
+        self.interrupt_requested = true;
+    }
 
-      let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-      let mut stdout = std::io::stdout();
-      let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// True, if the decode cache is enabled
+    pub fn get_decode_cache(&self) -> bool {
+        self.decode_cache.is_some()
+    }
 
-      assert_eq!(ERROR_START_NUM + Error::DivisorNotZero as u32, vm.execute_first());
-      assert_eq!(0, vm.read_register_value(Register::R0));
+    /// Forgets all decoded instructions (e.g. after the memory was replaced)
+    fn clear_decode_cache(&mut self) {
+        if let Some(cache) = &mut self.decode_cache {
+            cache.fill(None);
+        }
     }
 
-    #[test]
-    fn addi() {
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 16),
-            utils::create_instruction_register_and_immediate(OpCode::ADDI, Register::R0, 5),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION
-        ];
+    /// Forgets the decoded instructions overlapping `size` bytes at the
+    /// physical `address`
+    fn invalidate_decoded(&mut self, address: u32, size: u32) {
+        if let Some(cache) = &mut self.decode_cache {
+            let first = address / INSTRUCTION_SIZE;
+            let last = address.saturating_add(size - 1) / INSTRUCTION_SIZE;
+            for index in first..=last {
+                if let Some(entry) = cache.get_mut(index as usize) {
+                    *entry = None;
+                }
+            }
+        }
+    }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// Number of harts (virtual CPUs) sharing the memory. Hart 0 executes
+    /// the program, the other ones are started by the program (syscall 4).
+    /// The harts are stopped.
+    pub fn set_hart_count(&mut self, count: usize) {
+        self.switch_to_hart(0);
+        self.harts = vec![Hart::default(); count.max(1)];
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(21, vm.read_register_value(Register::R0));
+    /// Number of harts
+    pub fn get_hart_count(&self) -> usize {
+        self.harts.len()
     }
 
-    #[test]
-    fn subi() {
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 16),
-            utils::create_instruction_register_and_immediate(OpCode::SUBI, Register::R0, 5),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION
-        ];
+    /// Index of the hart executing the next instruction. Register accesses
+    /// refer to this hart.
+    pub fn get_hart_id(&self) -> usize {
+        self.hart_id
+    }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// Saves the current state (see `VmSnapshot`)
+    pub fn snapshot(&self) -> VmSnapshot {
+        let mut harts = self.harts.clone();
+        harts[self.hart_id] = self.hart.clone();
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(11, vm.read_register_value(Register::R0));
+        VmSnapshot { harts, hart_id: self.hart_id, memory: self.interpreter.memory_image(), stats: self.stats }
     }
 
-    #[test]
-    fn muli() {
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 4),
-            utils::create_instruction_register_and_immediate(OpCode::MULI, Register::R0, 5),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION
-        ];
+    /// Continues from a saved state. The number of harts is restored, too.
+    /// Returns false (and doesn't change anything), if the memory size of
+    /// the snapshot differs.
+    pub fn restore(&mut self, snapshot: &VmSnapshot) -> bool {
+        if snapshot.memory.len() != self.interpreter.len() as usize || snapshot.hart_id >= snapshot.harts.len() {
+            return false;
+        }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        if !self.interpreter.restore_memory_image(&snapshot.memory) {
+            return false;
+        }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(20, vm.read_register_value(Register::R0));
+        self.clear_decode_cache();
+        self.harts = snapshot.harts.clone();
+        self.hart_id = snapshot.hart_id;
+        self.hart = self.harts[self.hart_id].clone();
+        self.stats = snapshot.stats;
+        true
+    }
+
+    /// Reset the machine to the state after `new`: the registers of all harts
+    /// (with $sp at the end of the memory), the running, error and trap
+    /// state, the performance counters and the memory, which only contains
+    /// the loaded program again. The configuration (like the branch mode) is
+    /// kept.
+    pub fn reset_full(&mut self) {
+        self.stop_secondary_harts();
+        self.hart = Hart::default();
+        self.write_register_value(Register::SP, self.interpreter.len());
+        self.stats = VmStats::default();
+        self.interpreter.reset_memory();
+        self.clear_decode_cache();
+        self.interrupt_requested = false;
+        self.last_fault = None;
+    }
+
+    /// Replaces the program in memory and resets the machine (see
+    /// `reset_full`). Returns false, if the program doesn't fit into the
+    /// memory.
+    pub fn load_program(&mut self, program: &[u8]) -> bool {
+        if !self.interpreter.load_program(program) {
+            return false;
+        }
+
+        self.reset_full();
+        true
     }
 
-    #[test]
-    fn divi() {
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 20),
-            utils::create_instruction_register_and_immediate(OpCode::DIVI, Register::R0, 5),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION
-        ];
+    /// Reset all registers (for restarting the machine)
+    pub fn reset(&mut self) {
+        self.stop_secondary_harts();
+        for reg in self.hart.registers.iter_mut() {
+            *reg = 0;
+        }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        self.hart.delayed_branch = None;
+        self.reset_traps();
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(4, vm.read_register_value(Register::R0));
+    /// Execute program with entry point at `get_entry()` (0 by default)
+    /// If result is greater than ERROR_START_NUM than it's a CPU error
+    pub fn execute_first(&mut self) -> u32 {
+        self.execute(self.entry)
+    }
 
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 24),
-            utils::create_instruction_register_and_immediate(OpCode::DIVI, Register::R0, 5),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION
-        ];
+    /// Execute program with entry point at pos
+    /// If result is greater than ERROR_START_NUM than it's a CPU error
+    ///
+    /// Thin wrapper around `run`, which encodes the result as a number.
+    pub fn execute(&mut self, pos: u32) -> u32 {
+        match self.run(pos) {
+            Ok(status) => status.code,
+            Err(err) => ERROR_START_NUM + err.kind as u32,
+        }
+    }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// Execute program with entry point at pos until it exits or fails
+    pub fn run(&mut self, pos: u32) -> Result<ExitStatus, VmError> {
+        self.start(pos);
+        while self.step() {}
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(4, vm.read_register_value(Register::R0));
+        self.result()
     }
 
-    #[test]
-    fn divi_divisor_zero() {
-      let program: [u32; 4] = [
-          utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 20),
-          utils::create_instruction_register_and_immediate(OpCode::DIVI, Register::R0, 0),
-          LOAD_0_IN_R1_INSTRUCTION,
-          SYSCALLI_EXIT_INSTRUCTION
-      ];
+    /// Prepare executing the program with entry point at pos. The program is
+    /// executed with `step` or `run_for`.
+    pub fn start(&mut self, pos: u32) {
+        self.stop_secondary_harts();
+        self.hart.running = true;
+        self.write_register_value(Register::IP, pos);
+        self.write_register_value(Register::ERR, Error::NoError as u32);
+        self.hart.delayed_branch = None;
+        self.hart.fault_address = None;
+        self.reset_traps();
+    }
 
-      let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-      let mut stdout = std::io::stdout();
-      let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// Continues with hart 0 and stops all other harts
+    fn stop_secondary_harts(&mut self) {
+        self.switch_to_hart(0);
+        for hart in self.harts.iter_mut().skip(1) {
+            *hart = Hart::default();
+        }
+    }
 
-      assert_eq!(ERROR_START_NUM + Error::DivisorNotZero as u32, vm.execute_first());
-      assert_eq!(0, vm.read_register_value(Register::R0));
+    /// Makes hart `hart_id` the current one
+    fn switch_to_hart(&mut self, hart_id: usize) {
+        if hart_id != self.hart_id {
+            std::mem::swap(&mut self.hart, &mut self.harts[self.hart_id]);
+            std::mem::swap(&mut self.hart, &mut self.harts[hart_id]);
+            self.hart_id = hart_id;
+        }
     }
 
-    #[test]
-    fn lw() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 4 * 4),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R2),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            0xFF00FF00
-        ];
+    /// Starts the next stopped hart at `entry`. Returns its index or None, if
+    /// all harts are running.
+    fn start_hart(&mut self, entry: u32, stack_pointer: u32, argument: u32) -> Option<usize> {
+        let hart_id = (1..self.harts.len()).find(|id| *id != self.hart_id && !self.harts[*id].running)?;
+        let hart = &mut self.harts[hart_id];
+        *hart = Hart::default();
+        hart.running = true;
+        hart.registers[Register::IP as usize] = entry;
+        hart.registers[Register::SP as usize] = stack_pointer;
+        hart.registers[Register::R1 as usize] = argument;
+        log::debug!("Hart {} started at 0x{:X}", hart_id, entry);
+
+        Some(hart_id)
+    }
+
+    /// Continues with the next running hart (round-robin)
+    fn next_hart(&mut self) {
+        let count = self.harts.len();
+        let next = (1..count)
+            .map(|offset| (self.hart_id + offset) % count)
+            .find(|id| self.harts[*id].running);
+        if let Some(next) = next {
+            self.switch_to_hart(next);
+        }
+    }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    /// Uninstalls the vector table and leaves a running trap handler
+    fn reset_traps(&mut self) {
+        self.hart.vector_table = 0;
+        self.hart.epc = 0;
+        self.hart.cause = 0;
+        self.hart.status = 0;
+    }
+
+    /// Value of a system register (None, if `index` isn't a system register)
+    pub fn read_system_register(&self, index: u32) -> Option<u32> {
+        Some(match SystemRegister::from_u32(index)? {
+            SystemRegister::VectorTable => self.hart.vector_table,
+            SystemRegister::Epc => self.hart.epc,
+            SystemRegister::Cause => self.hart.cause,
+            SystemRegister::Status => self.hart.status,
+            SystemRegister::FaultAddress => self.hart.fault_address.unwrap_or(0),
+        })
+    }
+
+    /// Writes a system register like `mts`
+    pub fn write_system_register(&mut self, index: u32, value: u32) -> Result<(), Error> {
+        match SystemRegister::from_u32(index) {
+            Some(SystemRegister::VectorTable) => self.hart.vector_table = value,
+            Some(SystemRegister::Epc) => self.hart.epc = value,
+            Some(SystemRegister::Cause) => self.hart.cause = value,
+            Some(SystemRegister::Status) => self.hart.status = value & (STATUS_INTERRUPTS_ENABLED | STATUS_IN_TRAP),
+            Some(SystemRegister::FaultAddress) => return Err(Error::ReadonlyRegister),
+            None => return Err(Error::Register),
+        }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(0xFF00FF00, vm.read_register_value(Register::R0));
+        Ok(())
     }
 
-    #[test]
-    fn sw() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 5 * 4),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1033),
-            utils::create_instruction_two_registers(OpCode::SW, Register::R0, Register::R2),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-        ];
+    /// Continues at the handler of `cause` in the vector table. Returns
+    /// false, if there's no handler or a trap handler is already running.
+    fn trap(&mut self, cause: u32, epc: u32) -> bool {
+        if self.hart.vector_table == 0 || self.hart.status & STATUS_IN_TRAP != 0 {
+            return false;
+        }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        let entry = if cause == CAUSE_INTERRUPT { 0 } else { cause };
+        let handler = self.interpreter.translate(self.hart.vector_table.wrapping_add(entry.wrapping_mul(4)), false).ok()
+            .and_then(|address| self.interpreter.read_u32(address))
+            .unwrap_or(0);
+        if handler == 0 {
+            return false;
+        }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(1033, vm.get_interpreter().read_u32(5 * 4).expect("Cannot read memory address"));
+        log::debug!("Hart {} traps to 0x{:X} (cause 0x{:X}, EPC 0x{:X})", self.hart_id, handler, cause, epc);
+        if cause == CAUSE_INTERRUPT {
+            self.interrupt_requested = false;
+        }
+
+        self.hart.epc = epc;
+        self.hart.cause = cause;
+        self.hart.status |= STATUS_IN_TRAP;
+        self.stats.traps += 1;
+        self.hart.delayed_branch = None;
+        self.write_error(Error::NoError);
+        self.write_register_value(Register::IP, handler);
+
+        true
+    }
+
+    /// Execute a single instruction. Returns false, if the program stopped
+    /// (see `get_result`). With multiple harts, every call executes an
+    /// instruction of the next running hart. The program stops, when hart 0
+    /// stops.
+    pub fn step(&mut self) -> bool {
+        let running = self.step_hart();
+        if !running && self.hart_id == 0 {
+            return false;
+        }
+
+        self.next_hart();
+        true
     }
 
-    #[test]
-    fn lh() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 4 * 4),
-            utils::create_instruction_two_registers(OpCode::LH, Register::R0, Register::R2),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            1032 // Will be stored in [0] and [1] of integer 0124, because little endian
-        ];
+    /// Execute a single instruction of the current hart. Returns false, if
+    /// the hart stopped.
+    fn step_hart(&mut self) -> bool {
+        if !self.hart.running {
+            return false;
+        }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        let ip = self.read_register_value(Register::IP);
+        self.hart.next_ip = self.hart.delayed_branch.take().unwrap_or_else(|| ip.wrapping_add(INSTRUCTION_SIZE));
+        let instruction = self.fetch_instruction(ip);
+        let word = instruction.as_ref().ok().map(|instruction| instruction.word);
+        match instruction {
+            Ok(instruction) => {
+                if let Some(counts) = &mut self.address_counts {
+                    *counts.entry(ip).or_insert(0) += 1;
+                }
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.insert(ip);
+                }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(1032, vm.read_register_value(Register::R0));
-    }
+                if self.tracer.is_some() {
+                    let registers_before = self.hart.registers;
+                    self.execute_instruction(instruction);
+                    self.trace_instruction(ip, instruction.word, &registers_before);
+                }
+                else {
+                    self.execute_instruction(instruction);
+                }
 
-    #[test]
+                if let Some((register, bit)) = self.fault_injector.as_mut().and_then(FaultInjector::on_instruction) {
+                    self.write_register_value(register, self.read_register_value(register) ^ (1 << bit));
+                }
+            },
+            Err(err) => self.write_error(err),
+        }
+
+        self.interpreter.tick();
+
+        let err = self.read_register_value(Register::ERR);
+        if err != Error::NoError as u32 {
+            self.record_fault(err, ip, word);
+            if self.hart.running && self.trap(err, ip) {
+                return true;
+            }
+
+            log::debug!("Hart {} stopped with error {} at 0x{:X}", self.hart_id, err, ip);
+            self.hart.running = false;
+        }
+
+        if !self.hart.running {
+            return false;
+        }
+
+        let next_ip = self.hart.next_ip;
+
+        // Interrupts wait for pending delay slots
+        if self.hart.status & STATUS_INTERRUPTS_ENABLED != 0
+            && self.hart.delayed_branch.is_none()
+            && (self.interrupt_requested || self.interpreter.interrupt_pending())
+            && self.trap(CAUSE_INTERRUPT, next_ip) {
+            return true;
+        }
+
+        self.write_register_value(Register::IP, next_ip);
+
+        true
+    }
+
+    /// Starts the program with entry point at pos. The returned future
+    /// executes it in slices of instructions and yields to the executor in
+    /// between.
+    pub fn execute_async(&mut self, pos: u32) -> ExecuteAsync<'_, 'source, InterpreterImpl> {
+        self.start(pos);
+        ExecuteAsync { vm: self }
+    }
+
+    /// Execute at most `max_instructions` instructions of the started program
+    /// (see `start`) and return to the host. Unlike `run_for`, waiting for
+    /// input returns early (`SliceStatus::Pending`) instead of blocking.
+    pub fn run_slice(&mut self, max_instructions: u64) -> SliceStatus {
+        self.input_pending = false;
+        for _ in 0..max_instructions {
+            if !self.step() {
+                return SliceStatus::Finished(self.result());
+            }
+
+            if self.input_pending {
+                return SliceStatus::Pending;
+            }
+        }
+
+        if self.is_running() {
+            SliceStatus::Yielded
+        }
+        else {
+            SliceStatus::Finished(self.result())
+        }
+    }
+
+    /// Execute at most `max_instructions` instructions. Returns the result,
+    /// if the program stopped.
+    pub fn run_for(&mut self, max_instructions: u64) -> Option<u32> {
+        for _ in 0..max_instructions {
+            if !self.step() {
+                return Some(self.get_result());
+            }
+        }
+
+        if self.is_running() {
+            None
+        }
+        else {
+            Some(self.get_result())
+        }
+    }
+
+    /// True, if the program was started and didn't stop yet
+    pub fn is_running(&self) -> bool {
+        if self.hart_id == 0 {
+            self.hart.running
+        }
+        else {
+            self.harts[0].running
+        }
+    }
+
+    /// Result of the stopped program
+    pub fn result(&self) -> Result<ExitStatus, VmError> {
+        let error_value = self.read_register_value(Register::ERR);
+        match Error::from_u32(error_value) {
+            Some(Error::NoError) => Ok(ExitStatus { code: self.read_register_value(Register::R1) }),
+            kind => {
+                let kind = kind.unwrap_or(Error::Register);
+                let address = match kind {
+                    Error::Memory | Error::PageFault => self.hart.fault_address,
+                    _ => None,
+                };
+
+                Err(VmError { kind, ip: self.read_register_value(Register::IP), address })
+            },
+        }
+    }
+
+    /// Result of the program: $r1 or, if the program stopped because of an
+    /// error, ERROR_START_NUM + $err (numeric variant of `result`)
+    pub fn get_result(&self) -> u32 {
+        let error_value = self.read_register_value(Register::ERR);
+        if error_value == (Error::NoError as u32) {
+            self.read_register_value(Register::R1)
+        }
+        else {
+            error_value + ERROR_START_NUM
+        }
+    }
+
+    /// Decodes the opcode of the instruction word
+    fn decode_instruction(&self, word: u32) -> Instruction {
+        let opcode = OpCode::from_u8(Self::get_opcode(word))
+            .filter(|opcode| opcode.isa_version() <= self.isa_version);
+
+        Instruction { word, opcode }
+    }
+
+    fn execute_instruction(&mut self, instruction: Instruction) {
+        let opcode = match instruction.opcode {
+            Some(opcode) => opcode,
+            None => return self.invalid_opcode(),
+        };
+
+        self.count_instruction(opcode);
+        match opcode {
+            OpCode::SYSCALLI => self.execute_syscalli(instruction.word),
+            OpCode::CPY => self.execute_cpy(instruction.word),
+            OpCode::LW => self.execute_lw(instruction.word),
+            OpCode::SW => self.execute_sw(instruction.word),
+            OpCode::LH => self.execute_lh(instruction.word),
+            OpCode::SH => self.execute_sh(instruction.word),
+            OpCode::LB => self.execute_lb(instruction.word),
+            OpCode::SB => self.execute_sb(instruction.word),
+            OpCode::LI => self.execute_li(instruction.word),
+            OpCode::LWI => self.execute_lwi(instruction.word),
+            OpCode::SWI => self.execute_swi(instruction.word),
+            OpCode::LHI => self.execute_lhi(instruction.word),
+            OpCode::SHI => self.execute_shi(instruction.word),
+            OpCode::LBI => self.execute_lbi(instruction.word),
+            OpCode::SBI => self.execute_sbi(instruction.word),
+            OpCode::ADD => self.execute_add(instruction.word),
+            OpCode::SUB => self.execute_sub(instruction.word),
+            OpCode::MUL => self.execute_mul(instruction.word),
+            OpCode::DIV => self.execute_div(instruction.word),
+            OpCode::ADDI => self.execute_addi(instruction.word),
+            OpCode::SUBI => self.execute_subi(instruction.word),
+            OpCode::MULI => self.execute_muli(instruction.word),
+            OpCode::DIVI => self.execute_divi(instruction.word),
+            OpCode::J => self.execute_j(instruction.word),
+            OpCode::JI => self.execute_ji(instruction.word),
+            OpCode::JIL => self.execute_jil(instruction.word),
+            OpCode::JZI => self.execute_jzi(instruction.word),
+            OpCode::JNZI => self.execute_jnzi(instruction.word),
+            OpCode::JLZI => self.execute_jlzi(instruction.word),
+            OpCode::JGZI => self.execute_jgzi(instruction.word),
+            OpCode::AND => self.execute_and(instruction.word),
+            OpCode::OR => self.execute_or(instruction.word),
+            OpCode::XOR => self.execute_xor(instruction.word),
+            OpCode::NOT => self.execute_not(instruction.word),
+            OpCode::SRL => self.execute_srl(instruction.word),
+            OpCode::SLL => self.execute_sll(instruction.word),
+            OpCode::SRLI => self.execute_srli(instruction.word),
+            OpCode::SLLI => self.execute_slli(instruction.word),
+            OpCode::MFS => self.execute_mfs(instruction.word),
+            OpCode::MTS => self.execute_mts(instruction.word),
+            OpCode::EI => self.execute_ei(instruction.word),
+            OpCode::DI => self.execute_di(instruction.word),
+            OpCode::RETI => self.execute_reti(instruction.word),
+            OpCode::AMOSWAP => self.execute_amoswap(instruction.word),
+            OpCode::AMOADD => self.execute_amoadd(instruction.word),
+        }
+    }
+
+    fn invalid_opcode(&mut self) {
+        match self.invalid_opcode_policy {
+            InvalidOpcodePolicy::Trap => self.write_error(Error::OpCode),
+            InvalidOpcodePolicy::Stop => {
+                self.write_error(Error::OpCode);
+                self.hart.running = false; // Stopped harts don't trap
+            },
+            InvalidOpcodePolicy::Skip => {
+                log::debug!("Hart {} skipped invalid opcode at 0x{:X}", self.hart_id, self.read_register_value(Register::IP));
+                self.stats.instructions += 1;
+            },
+        }
+    }
+
+    fn execute_syscalli(&mut self, instruction: u32) {
+        self.write_next_instruction_address();
+        self.syscall(Self::get_immediate(instruction))
+    }
+
+    fn execute_cpy(&mut self, instruction: u32) {
+        self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
+            this.write_user_register_value(reg0, this.read_user_register_value(reg1))
+        );
+    }
+
+    // Load-store
+    fn execute_lw(&mut self, instruction: u32) {
+        self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
+            match this.load_u32(this.read_user_register_value(reg1)) {
+                Ok(result) => this.write_user_register_value(reg0, result),
+                Err(err) => this.write_error(err),
+            }
+        );
+    }
+
+    fn execute_sw(&mut self, instruction: u32) {
+        self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
+            this.store_u32(this.read_user_register_value(reg1), this.read_user_register_value(reg0))
+        );
+    }
+
+    fn execute_lh(&mut self, instruction: u32) {
+        self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
+            match this.load_u16(this.read_user_register_value(reg1)) {
+                Ok(result) => this.write_user_register_value(reg0, result as u32),
+                Err(err) => this.write_error(err),
+            }
+        );
+    }
+
+    fn execute_sh(&mut self, instruction: u32) {
+        self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
+            this.store_u16(this.read_user_register_value(reg1), (this.read_user_register_value(reg0) & 0x0000FFFF).try_into().expect("Unexpected error"))
+        );
+    }
+
+    fn execute_lb(&mut self, instruction: u32) {
+        self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
+            match this.load_u8(this.read_user_register_value(reg1)) {
+                Ok(result) => this.write_user_register_value(reg0, result as u32),
+                Err(err) => this.write_error(err),
+            }
+        );
+    }
+
+    fn execute_sb(&mut self, instruction: u32) {
+        self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
+            this.store_u8(this.read_user_register_value(reg1), (this.read_user_register_value(reg0) & 0x000000FF).try_into().expect("Unexpected error"))
+        );
+    }
+
+    fn execute_li(&mut self, instruction: u32) {
+        let (reg0, imm1) = Self::get_register_and_twos_complement_immediate(instruction);
+        if let Some(reg_value0) = Register::from_u8(reg0) {
+            self.write_user_register_value(reg_value0, imm1);
+        }
+        else {
+            self.write_error(Error::Register);
+        }
+    }
+
+    fn execute_lwi(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
+            match this.load_u32(imm) {
+                Ok(result) => this.write_user_register_value(reg, result),
+                Err(err) => this.write_error(err),
+            }
+        );
+    }
+
+    fn execute_swi(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
+            this.store_u32(imm, this.read_user_register_value(reg))
+        );
+    }
+
+    fn execute_lhi(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
+            match this.load_u16(imm) {
+                Ok(result) => this.write_user_register_value(reg, result as u32),
+                Err(err) => this.write_error(err),
+            }
+        );
+    }
+
+    fn execute_shi(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
+            this.store_u16(imm, (this.read_user_register_value(reg) & 0x0000FFFF).try_into().expect("Unexpected error"))
+        );
+    }
+
+    fn execute_lbi(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
+            match this.load_u8(imm) {
+                Ok(result) => this.write_user_register_value(reg, result as u32),
+                Err(err) => this.write_error(err),
+            }
+        );
+    }
+
+    fn execute_sbi(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
+            this.store_u8(imm, (this.read_user_register_value(reg) & 0x000000FF).try_into().expect("Unexpected error"))
+        );
+    }
+
+    // Arithmetics
+    fn execute_add(&mut self, instruction: u32) {
+        self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_add(y));
+    }
+
+    fn execute_sub(&mut self, instruction: u32) {
+        self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_sub(y));
+    }
+
+    fn execute_mul(&mut self, instruction: u32) {
+        self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_mul(y));
+    }
+
+    fn execute_div(&mut self, instruction: u32) {
+        self.binary_register_operation_write0(instruction,
+            |this: &mut Self, x, y|
+                if y == 0 {
+                    this.write_error(Error::DivisorNotZero);
+                    0
+                } else {
+                    x / y
+                }
+        );
+    }
+
+    fn execute_addi(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_add(y));
+    }
+
+    fn execute_subi(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_sub(y));
+    }
+
+    fn execute_muli(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_mul(y));
+    }
+
+    fn execute_divi(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation_write0(instruction,
+            |this: &mut Self, x, y|
+                if y == 0 {
+                    this.write_error(Error::DivisorNotZero);
+                    0
+                } else {
+                    x / y
+                }
+        );
+    }
+
+    // Unconditional jumps
+    fn execute_j(&mut self, instruction: u32) {
+        let reg = Self::get_registers(instruction);
+        if let Some(reg_value) = Register::from_u8(reg) {
+            let address = self.read_user_register_value(reg_value);
+            self.branch_to(address);
+        }
+        else {
+            self.write_error(Error::Register);
+        }
+    }
+
+    fn execute_ji(&mut self, instruction: u32) {
+        let address = Self::get_immediate(instruction);
+        self.branch_to(address);
+    }
+
+    fn execute_jil(&mut self, instruction: u32) {
+        let address = Self::get_immediate(instruction);
+        self.write_register_value(Register::RA, self.get_link_address());
+        self.branch_to(address);
+    }
+
+    fn execute_jzi(&mut self, instruction: u32) {
+        self.unary_check_write_ip(instruction, |_: &mut Self, x| x == 0);
+    }
+
+    fn execute_jnzi(&mut self, instruction: u32) {
+        self.unary_check_write_ip(instruction, |_: &mut Self, x| x != 0);
+    }
+
+    fn execute_jlzi(&mut self, instruction: u32) {
+        self.unary_check_write_ip(instruction,
+            |_: &mut Self, x| i32::from_le_bytes(u32::to_le_bytes(x)) < 0
+        );
+    }
+
+    fn execute_jgzi(&mut self, instruction: u32) {
+        self.unary_check_write_ip(instruction,
+            |_: &mut Self, x| i32::from_le_bytes(u32::to_le_bytes(x)) > 0
+        );
+    }
+
+    fn execute_and(&mut self, instruction: u32) {
+        self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x & y);
+    }
+
+    fn execute_or(&mut self, instruction: u32) {
+        self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x | y);
+    }
+
+    fn execute_xor(&mut self, instruction: u32) {
+        self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x ^ y);
+    }
+
+    fn execute_not(&mut self, instruction: u32) {
+        let reg = Self::get_registers(instruction);
+        if let Some(reg_value) = Register::from_u8(reg) {
+            let val = self.read_user_register_value(reg_value);
+            self.write_user_register_value(reg_value, !val);
+        }
+        else {
+            self.write_error(Error::Register);
+        }
+    }
+
+    fn execute_srl(&mut self, instruction: u32) {
+        self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_shr(y));
+    }
+
+    fn execute_sll(&mut self, instruction: u32) {
+        self.binary_register_operation_write0(instruction, |_: &mut Self, x, y| x.wrapping_shl(y));
+    }
+
+    fn execute_srli(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation_write0(instruction,
+            |_: &mut Self, x, y| x.wrapping_shr(y));
+    }
+
+    fn execute_slli(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation_write0(instruction,
+            |_: &mut Self, x, y| x.wrapping_shl(y));
+    }
+
+    // Traps
+    fn execute_mfs(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
+            match this.read_system_register(imm) {
+                Some(value) => this.write_user_register_value(reg, value),
+                None => this.write_error(Error::Register),
+            }
+        );
+    }
+
+    fn execute_mts(&mut self, instruction: u32) {
+        self.binary_register_and_immediate_operation(instruction, |this: &mut Self, reg, imm|
+            if let Err(err) = this.write_system_register(imm, this.read_user_register_value(reg)) {
+                this.write_error(err);
+            }
+        );
+    }
+
+    fn execute_ei(&mut self, _instruction: u32) {
+        self.hart.status |= STATUS_INTERRUPTS_ENABLED;
+    }
+
+    fn execute_di(&mut self, _instruction: u32) {
+        self.hart.status &= !STATUS_INTERRUPTS_ENABLED;
+    }
+
+    fn execute_reti(&mut self, _instruction: u32) {
+        self.hart.status &= !STATUS_IN_TRAP;
+        self.hart.next_ip = self.hart.epc;
+    }
+
+    // Atomics
+    fn execute_amoswap(&mut self, instruction: u32) {
+        self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
+            this.atomic_operation(reg0, reg1, |_, x| x)
+        );
+    }
+
+    fn execute_amoadd(&mut self, instruction: u32) {
+        self.binary_register_operation(instruction, |this: &mut Self, reg0, reg1|
+            this.atomic_operation(reg0, reg1, u32::wrapping_add)
+        );
+    }
+    /// Replaces the word at the address in `reg1` with `op(word, reg0)` and
+    /// writes the old word into `reg0`. Other harts can't run in between.
+    fn atomic_operation(&mut self, reg0: Register, reg1: Register, op: fn (u32, u32) -> u32) {
+        let address = self.read_user_register_value(reg1);
+        match self.load_u32(address) {
+            Ok(old) => {
+                self.store_u32(address, op(old, self.read_user_register_value(reg0)));
+                if self.read_register_value(Register::ERR) == Error::NoError as u32 {
+                    self.write_user_register_value(reg0, old);
+                }
+            },
+            Err(err) => self.write_error(err),
+        }
+    }
+
+    /// Passes the executed instruction with all changed registers to the tracer
+    fn trace_instruction(&mut self, ip: u32, instruction: u32, registers_before: &[u32; LAST_REGISTER as usize + 1]) {
+        let register_writes = (0..=LAST_REGISTER as u8)
+            .filter_map(Register::from_u8)
+            .filter(|reg| *reg != Register::IP && registers_before[*reg as usize] != self.hart.registers[*reg as usize])
+            .map(|reg| (reg, self.hart.registers[reg as usize]))
+            .collect();
+
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace(TraceEntry { ip, instruction, register_writes });
+        }
+    }
+
+    /// Updates the performance counters for an instruction about to be executed.
+    /// Conditional jumps are counted when they're taken.
+    fn count_instruction(&mut self, opcode: OpCode) {
+        self.stats.instructions += 1;
+        if let Some(counts) = &mut self.opcode_counts {
+            counts[opcode as usize] += 1;
+        }
+
+        match opcode {
+            OpCode::LW | OpCode::LH | OpCode::LB
+                | OpCode::LWI | OpCode::LHI | OpCode::LBI => self.stats.memory_reads += 1,
+            OpCode::SW | OpCode::SH | OpCode::SB
+                | OpCode::SWI | OpCode::SHI | OpCode::SBI => self.stats.memory_writes += 1,
+            OpCode::AMOSWAP | OpCode::AMOADD => {
+                self.stats.memory_reads += 1;
+                self.stats.memory_writes += 1;
+            },
+            OpCode::SYSCALLI => self.stats.syscalls += 1,
+            _ => {}
+        }
+    }
+
+    /// Jumps to the immediate, if `unary_op` evaluates to true.
+    fn unary_check_write_ip(&mut self, instruction: u32, unary_op: fn (&mut Self, u32) -> bool) {
+      let (reg, imm) = Self::get_register_and_immediate(instruction);
+      if let Some(reg_value) = Register::from_u8(reg) {
+          let val = self.read_user_register_value(reg_value);
+          if unary_op(self, val) {
+            self.branch_to(imm);
+          }
+      }
+      else {
+          self.write_error(Error::Register);
+      }
+    }
+
+    fn binary_register_and_immediate_operation_write0(&mut self, instruction: u32, binary_op: fn (&mut Self, u32, u32) -> u32) {
+      let (reg, imm) = Self::get_register_and_immediate(instruction);
+      if let Some(reg_value) = Register::from_u8(reg) {
+          let val = self.read_user_register_value(reg_value);
+          let result = binary_op(self, val, imm);
+          self.write_user_register_value(reg_value, result);
+      }
+      else {
+          self.write_error(Error::Register);
+      }
+    }
+
+    fn binary_register_and_immediate_operation(&mut self, instruction: u32, binary_op: fn (&mut Self, Register, u32)) {
+      let (reg, imm) = Self::get_register_and_immediate(instruction);
+      if let Some(reg_value) = Register::from_u8(reg) {
+          binary_op(self, reg_value, imm);
+      }
+      else {
+          self.write_error(Error::Register);
+      }
+    }
+
+    /// Combines both values of the two registers parsed from the instruction with the function
+    /// `binary_op` and writes the result in the first registers
+    fn binary_register_operation_write0(&mut self, instruction: u32, binary_op: fn (&mut Self, u32, u32) -> u32) {
+      let (reg0, reg1) = Self::get_two_registers(instruction);
+      if let (Some(reg_value0), Some(reg_value1)) = (Register::from_u8(reg0), Register::from_u8(reg1)) {
+          let val0 = self.read_user_register_value(reg_value0);
+          let val1 = self.read_user_register_value(reg_value1);
+          let result = binary_op(self, val0, val1);
+          self.write_user_register_value(reg_value0, result);
+      }
+      else {
+          self.write_error(Error::Register);
+      }
+    }
+
+    fn binary_register_operation(&mut self, instruction: u32, binary_op: fn (&mut Self, Register, Register)) {
+      let (reg0, reg1) = Self::get_two_registers(instruction);
+      if let (Some(reg_value0), Some(reg_value1)) = (Register::from_u8(reg0), Register::from_u8(reg1)) {
+          binary_op(self, reg_value0, reg_value1);
+      }
+      else {
+          self.write_error(Error::Register);
+      }
+    }
+
+    /// Translates the virtual address of an access of `size` bytes into a
+    /// physical address. Accesses crossing into a page, which isn't physically
+    /// adjacent, are page faults, too.
+    fn translate(&mut self, address: u32, size: u32, write: bool) -> Result<u32, Error> {
+        let interpreter = &self.interpreter;
+        let result = interpreter.translate(address, write).and_then(|physical| {
+            let last = address.wrapping_add(size - 1);
+            if last / PAGE_SIZE == address / PAGE_SIZE || interpreter.translate(last, write)? == physical.wrapping_add(size - 1) {
+                Ok(physical)
+            }
+            else {
+                Err(PageFault { address: last - last % PAGE_SIZE, write })
+            }
+        });
+
+        result.map_err(|fault| {
+            self.hart.fault_address = Some(fault.address);
+            Error::PageFault
+        })
+    }
+
+    /// Fetches and decodes the instruction at `ip` (from the decode cache, if
+    /// it's enabled)
+    fn fetch_instruction(&mut self, ip: u32) -> Result<Instruction, Error> {
+        let physical = self.translate(ip, 4, false)?;
+        if self.write_xor_execute && self.is_writable(ip, physical) {
+            return Err(Error::ExecuteProtected);
+        }
+
+        let index = (physical / INSTRUCTION_SIZE) as usize;
+        let aligned = physical.is_multiple_of(INSTRUCTION_SIZE);
+        if let Some(cache) = &self.decode_cache {
+            if let Some(Some(instruction)) = cache.get(index).filter(|_| aligned) {
+                return Ok(*instruction);
+            }
+        }
+
+        let word = self.interpreter.read_u32(physical).ok_or_else(|| self.memory_fault(ip))?;
+        let instruction = self.decode_instruction(word);
+        if aligned && self.interpreter.is_cacheable(physical) {
+            if let Some(entry) = self.decode_cache.as_mut().and_then(|cache| cache.get_mut(index)) {
+                *entry = Some(instruction);
+            }
+        }
+
+        Ok(instruction)
+    }
+
+    /// Remembers the error `err` of the instruction at `ip` (see
+    /// `last_fault`)
+    fn record_fault(&mut self, err: u32, ip: u32, instruction: Option<u32>) {
+        let kind = Error::from_u32(err).unwrap_or(Error::Register);
+        let address = match kind {
+            Error::Memory | Error::PageFault => self.hart.fault_address,
+            _ => None,
+        };
+
+        self.last_fault = Some(Fault { kind, ip, address, instruction });
+    }
+
+    /// Remembers the virtual address of an access outside of the memory
+    fn memory_fault(&mut self, address: u32) -> Error {
+        self.hart.fault_address = Some(address);
+        Error::Memory
+    }
+
+    /// Check if the program could store into the instruction at the virtual
+    /// address `ip` (physical address `physical`)
+    fn is_writable(&self, ip: u32, physical: u32) -> bool {
+        self.interpreter.translate(ip, true).is_ok() && !self.is_write_protected(physical, INSTRUCTION_SIZE)
+    }
+
+    /// Check if a store of `size` bytes at the physical `address` overlaps
+    /// the write-protected range
+    fn is_write_protected(&self, address: u32, size: u32) -> bool {
+        if let Some(range) = &self.write_protected {
+            (address as u64) < range.end as u64 && address as u64 + size as u64 > range.start as u64
+        }
+        else {
+            false
+        }
+    }
+
+    fn load_u32(&mut self, address: u32) -> Result<u32, Error> {
+        let physical = self.translate(address, 4, false)?;
+        let result = self.interpreter.read_u32(physical);
+        let result = match (result, self.fault_injector.as_mut()) {
+            (Some(value), Some(fault_injector)) => Some(fault_injector.on_load(value)),
+            (result, _) => result,
+        };
+        result.ok_or_else(|| self.memory_fault(address))
+    }
+
+    fn store_u32(&mut self, address: u32, value: u32) {
+        let result = self.translate(address, 4, true).and_then(|physical|
+            if self.is_write_protected(physical, 4) {
+                Err(Error::WriteProtected)
+            }
+            else if self.fault_injector.as_mut().is_some_and(FaultInjector::on_write) || !self.interpreter.write_u32(physical, value) {
+                Err(self.memory_fault(address))
+            }
+            else {
+                self.invalidate_decoded(physical, 4);
+                Ok(())
+            }
+        );
+
+        if let Err(err) = result {
+            self.write_error(err);
+        }
+    }
+
+    fn load_u16(&mut self, address: u32) -> Result<u16, Error> {
+        let physical = self.translate(address, 2, false)?;
+        let result = self.interpreter.read_u16(physical);
+        result.ok_or_else(|| self.memory_fault(address))
+    }
+
+    fn store_u16(&mut self, address: u32, value: u16) {
+        let result = self.translate(address, 2, true).and_then(|physical|
+            if self.is_write_protected(physical, 2) {
+                Err(Error::WriteProtected)
+            }
+            else if self.fault_injector.as_mut().is_some_and(FaultInjector::on_write) || !self.interpreter.write_u16(physical, value) {
+                Err(self.memory_fault(address))
+            }
+            else {
+                self.invalidate_decoded(physical, 2);
+                Ok(())
+            }
+        );
+
+        if let Err(err) = result {
+            self.write_error(err);
+        }
+    }
+
+    fn load_u8(&mut self, address: u32) -> Result<u8, Error> {
+        let physical = self.translate(address, 1, false)?;
+        let result = self.interpreter.read_u8(physical);
+        result.ok_or_else(|| self.memory_fault(address))
+    }
+
+    fn store_u8(&mut self, address: u32, value: u8) {
+        let result = self.translate(address, 1, true).and_then(|physical|
+            if self.is_write_protected(physical, 1) {
+                Err(Error::WriteProtected)
+            }
+            else if self.fault_injector.as_mut().is_some_and(FaultInjector::on_write) || !self.interpreter.write_u8(physical, value) {
+                Err(self.memory_fault(address))
+            }
+            else {
+                self.invalidate_decoded(physical, 1);
+                Ok(())
+            }
+        );
+
+        if let Err(err) = result {
+            self.write_error(err);
+        }
+    }
+
+    /// Continues execution at `address` after the current instruction (or its
+    /// delay slot, see `BranchMode`)
+    fn branch_to(&mut self, address: u32) {
+        self.stats.jumps_taken += 1;
+        match self.branch_mode {
+            BranchMode::Immediate => self.hart.next_ip = address,
+            BranchMode::DelaySlot => self.hart.delayed_branch = Some(address),
+        }
+    }
+
+    /// Return address of a linking jump
+    fn get_link_address(&self) -> u32 {
+        let ip = self.read_register_value(Register::IP);
+        match self.branch_mode {
+            BranchMode::Immediate => ip.wrapping_add(INSTRUCTION_SIZE),
+            BranchMode::DelaySlot => ip.wrapping_add(2 * INSTRUCTION_SIZE), // Skip the delay slot
+        }
+    }
+
+    /// Saves the address of the next instruction in $ra
+    #[inline(always)]
+    fn write_next_instruction_address(&mut self) {
+        self.write_register_value(Register::RA, self.read_register_value(Register::IP) + INSTRUCTION_SIZE);
+    }
+
+    /// Check if register is read-only
+    fn is_readonly(reg: Register) -> bool {
+        return match reg {
+            Register::IP | Register::ERR => true,
+            _ => false
+        }
+    }
+
+    #[inline(always)]
+    fn write_error(&mut self, err: Error) {
+        self.write_register_value(Register::ERR, err as u32);
+    }
+
+    /// Write to unkonwn register value
+    #[inline(always)]
+    fn write_unknown_register_value(&mut self, reg: u8, value: u32) {
+        if let Some(reg_value) = Register::from_u8(reg) {
+            self.write_register_value(reg_value, value);
+        }
+        else {
+            self.write_register_value(Register::ERR, Error::Register as u32);
+        }
+    }
+
+    // If the user (program) writes to register reg
+    #[inline(always)]
+    pub fn write_user_register_value(&mut self, reg: Register, value: u32) {
+        if Self::is_readonly(reg) {
+           self.write_error(Error::ReadonlyRegister);
+        }
+        else {
+           self.write_register_value(reg, value);
+        }
+    }
+
+    /// Writes value value to register reg
+    #[inline(always)]
+    pub fn write_register_value(&mut self, reg: Register, value: u32) {
+        self.hart.registers[reg as usize] = value;
+    }
+
+    #[inline(always)]
+    fn read_user_register_value(&self, reg: Register) -> u32 {
+        self.read_register_value(reg)
+    }
+
+    /// Reads `len` bytes starting at the virtual address `addr`
+    fn read_user_chunk(&mut self, addr: u32, len: u32) -> Result<Vec<u8>, Error> {
+        let mut result = Vec::new();
+        let mut current = addr;
+        let mut remaining = len;
+        loop {
+            let in_page = (PAGE_SIZE - current % PAGE_SIZE).min(remaining);
+            let physical = self.translate(current, 1, false)?;
+            let chunk = self.interpreter.read_chunk(physical, in_page);
+            result.extend(chunk.ok_or_else(|| self.memory_fault(current))?);
+
+            remaining -= in_page;
+            if remaining == 0 {
+                break Ok(result);
+            }
+
+            current = current.checked_add(in_page).ok_or(Error::Memory)?;
+        }
+    }
+
+    /// Reads a NUL-terminated string starting at the virtual address `addr`
+    fn read_user_cstr(&mut self, addr: u32) -> Result<Vec<u8>, Error> {
+        let mut result = Vec::new();
+        let mut current = addr;
+        loop {
+            let c = self.load_u8(current)?;
+            if c == 0 {
+                break Ok(result);
+            }
+
+            result.push(c);
+            current = current.checked_add(1).ok_or(Error::Memory)?;
+        }
+    }
+
+    /// Reads value from register reg
+    #[inline(always)]
+    pub fn read_register_value(&self, reg: Register) -> u32 {
+        self.hart.registers[reg as usize]
+    }
+
+    fn syscall(&mut self, syscall: u32) {
+        match syscall {
+            syscall if self.allowed_syscalls.as_ref().is_some_and(|allowed| !allowed.contains(&syscall)) => {
+                self.write_register_value(Register::ERR, Error::Syscall as u32);
+            },
+            0 => {
+                log::debug!("Hart {} exited with {}", self.hart_id, self.read_register_value(Register::R1));
+                self.hart.running = false;
+            },
+            1 => {
+                let addr = self.read_user_register_value(Register::R1);
+                let len = self.read_user_register_value(Register::R2);
+                let chunk = self.read_user_chunk(addr, len);
+                self.print_chunk(chunk, false);
+            },
+            2 => {
+                let addr = self.read_user_register_value(Register::R1);
+                let chunk = self.read_user_cstr(addr);
+                self.print_chunk(chunk, false);
+            },
+            3 => {
+                let index = self.read_user_register_value(Register::R1);
+                if let Some(counter) = self.stats.get_counter(index) {
+                    self.write_user_register_value(Register::R0, counter as u32);
+                }
+                else {
+                    self.write_error(Error::Syscall);
+                }
+            },
+            4 => {
+                let entry = self.read_user_register_value(Register::R1);
+                let stack_pointer = self.read_user_register_value(Register::R2);
+                let argument = self.read_user_register_value(Register::R3);
+                let hart_id = self.start_hart(entry, stack_pointer, argument).unwrap_or(0);
+                self.write_user_register_value(Register::R0, hart_id as u32);
+            },
+            5 => {
+                self.write_user_register_value(Register::R0, self.hart_id as u32);
+            },
+            6 => {
+                let addr = self.read_user_register_value(Register::R1);
+                let len = self.read_user_register_value(Register::R2);
+                self.read_input(addr, len);
+            },
+            7 => {
+                let addr = self.read_user_register_value(Register::R1);
+                let len = self.read_user_register_value(Register::R2);
+                let chunk = self.read_user_chunk(addr, len);
+                self.print_chunk(chunk, true);
+            },
+            syscall if syscall >= HOST_SYSCALL_START => {
+                let mut args = [0; MAX_HOST_ARGUMENTS];
+                for (arg, register) in args.iter_mut().zip([Register::R1, Register::R2, Register::R3, Register::R4]) {
+                    *arg = self.read_user_register_value(register);
+                }
+
+                match self.host_functions.call(syscall - HOST_SYSCALL_START, args) {
+                    Some(Some(result)) => self.write_user_register_value(Register::R0, result),
+                    Some(None) => {},
+                    None => self.write_register_value(Register::ERR, Error::Syscall as u32),
+                }
+            },
+            _ => {
+                self.write_register_value(Register::ERR, Error::Syscall as u32);
+            }
+        }
+    }
+
+    /// Writes `chunk` to stdout (or stderr) and the success into $r0
+    fn print_chunk(&mut self, chunk: Result<Vec<u8>, Error>, to_stderr: bool) {
+        match chunk {
+            Ok(chunk) => {
+                let write_result = match (to_stderr, &mut self.stderr) {
+                    (false, _) => self.stdout.write_all(chunk.as_slice()).is_ok(),
+                    (true, Some(stderr)) => stderr.write_all(chunk.as_slice()).is_ok(),
+                    (true, None) => false,
+                };
+                self.write_user_register_value(Register::R0, write_result as u32);
+            },
+            Err(err) => self.write_error(err),
+        }
+    }
+
+    /// Reads at most `len` bytes (and at most a page) of input into memory
+    /// at `addr` and writes the number of bytes read into $r0 (0 at the end
+    /// of the input)
+    fn read_input(&mut self, addr: u32, len: u32) {
+        let mut buffer = vec![0; len.min(PAGE_SIZE) as usize];
+        let count = match &mut self.stdin {
+            Some(stdin) => match stdin.read(&mut buffer) {
+                Ok(count) => count,
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    self.input_pending = true;
+                    self.repeat_instruction();
+                    return;
+                },
+                Err(_) => 0,
+            },
+            None => 0,
+        };
+
+        for (i, byte) in buffer[..count].iter().enumerate() {
+            self.store_u8(addr.wrapping_add(i as u32), *byte);
+            if self.read_register_value(Register::ERR) != Error::NoError as u32 {
+                return;
+            }
+        }
+
+        self.write_user_register_value(Register::R0, count as u32);
+    }
+
+    /// Executes the current instruction again next (e.g. a syscall waiting
+    /// for input)
+    fn repeat_instruction(&mut self) {
+        let ip = self.read_register_value(Register::IP);
+        if self.hart.next_ip != ip.wrapping_add(INSTRUCTION_SIZE) {
+            // Delay slot: continue at the branch target afterwards
+            self.hart.delayed_branch = Some(self.hart.next_ip);
+        }
+
+        self.hart.next_ip = ip;
+    }
+
+    #[inline(always)]
+    fn get_opcode(instruction: u32) -> u8 {
+        u8::try_from((instruction & 0xFF000000) >> (3 * 8)).expect("Unexpected failure!")
+    }
+
+
+    #[inline(always)]
+    fn get_immediate(instruction: u32) -> u32 {
+        instruction & 0x00FFFFFF
+    }
+
+    #[inline(always)]
+    fn get_registers(instruction: u32) -> u8 {
+        u8::try_from(instruction & 0x0000000F).expect("Unexpected failure!")
+    }
+
+    #[inline(always)]
+    fn get_two_registers(instruction: u32) -> (u8, u8) {
+        (
+            u8::try_from((instruction & 0x00F00000) >> (2 * 8 + 4)).expect("Unexpected failure!"),
+            u8::try_from(instruction & 0x0000000F).expect("Unexpected failure!"),
+        )
+    }
+
+    #[inline(always)]
+    fn get_register_and_immediate(instruction: u32) -> (u8, u32) {
+        (
+            u8::try_from((instruction & 0x00F00000) >> (2 * 8 + 4)).expect("Unexpected failure!"),
+            instruction & 0x000FFFFF
+        )
+    }
+
+    #[inline(always)]
+    fn get_register_and_twos_complement_immediate(instruction: u32) -> (u8, u32) {
+        (
+            u8::try_from((instruction & 0x00F00000) >> (2 * 8 + 4)).expect("Unexpected failure!"),
+            Self::get_u32_from_immediate(instruction & 0x000FFFFF, 0x000FFFFF, 0x00080000)
+        )
+    }
+
+    #[inline(always)]
+    fn get_two_register_and_immediate(instruction: u32) -> (u8, u8, u32) {
+        (
+            u8::try_from((instruction & 0x00F00000) >> (2 * 8 + 4)).expect("Unexpected failure!"),
+            u8::try_from((instruction & 0x000F0000) >> (2 * 8 + 0)).expect("Unexpected failure!"),
+            instruction & 0x0000FFFF
+        )
+    }
+
+    /// Returns u32 from immediate. Immediate is a twos complement!
+    #[inline(always)]
+    fn get_u32_from_immediate(imm: u32, bitmask: u32, check_negative_bitmask: u32) -> u32 {
+        if imm & check_negative_bitmask == 0 { // Positive
+            imm
+        }
+        else {
+            imm | !bitmask // Two's complement -> Add 1 to the start
+        }
+    }
+
+    /// Bytes of the (physical) memory in `range`, borrowed if the
+    /// interpreter allows it (see `Interpreter::memory`). Returns None, if
+    /// the range isn't inside of the memory.
+    pub fn read_memory(&self, range: Range<u32>) -> Option<Cow<'_, [u8]>> {
+        match self.interpreter.memory(range.clone()) {
+            Some(data) => Some(Cow::Borrowed(data)),
+            None => self.interpreter.read_chunk(range.start, range.end.checked_sub(range.start)?).map(Cow::Owned),
+        }
+    }
+
+    /// Reads `len` bytes starting at the physical address `addr` (devices
+    /// included)
+    pub fn read_bytes(&self, addr: u32, len: u32) -> Result<Vec<u8>, VmError> {
+        (0..len).map(|i| {
+            let pos = addr.checked_add(i).ok_or_else(|| self.host_memory_error(addr))?;
+            self.interpreter.read_u8(pos).ok_or_else(|| self.host_memory_error(pos))
+        }).collect()
+    }
+
+    /// Writes `bytes` starting at the physical address `addr` (devices
+    /// included). The bytes before a failing address are written.
+    pub fn write_bytes(&mut self, addr: u32, bytes: &[u8]) -> Result<(), VmError> {
+        for (i, byte) in bytes.iter().enumerate() {
+            let pos = addr.checked_add(i as u32).ok_or_else(|| self.host_memory_error(addr))?;
+            if !self.interpreter.write_u8(pos, *byte) {
+                return Err(self.host_memory_error(pos));
+            }
+
+            self.invalidate_decoded(pos, 1);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the NUL-terminated string at the physical address `addr`
+    /// (without the terminating NUL)
+    pub fn read_cstr(&self, addr: u32) -> Result<Vec<u8>, VmError> {
+        let mut result = Vec::new();
+        let mut current = addr;
+        loop {
+            let c = self.interpreter.read_u8(current).ok_or_else(|| self.host_memory_error(current))?;
+            if c == 0 {
+                break Ok(result);
+            }
+
+            result.push(c);
+            current = current.checked_add(1).ok_or_else(|| self.host_memory_error(addr))?;
+        }
+    }
+
+    /// Reads the word at the physical address `addr`
+    pub fn read_u32(&self, addr: u32) -> Result<u32, VmError> {
+        self.interpreter.read_u32(addr).ok_or_else(|| self.host_memory_error(addr))
+    }
+
+    /// Writes the word `value` at the physical address `addr`
+    pub fn write_u32(&mut self, addr: u32, value: u32) -> Result<(), VmError> {
+        if !self.interpreter.write_u32(addr, value) {
+            return Err(self.host_memory_error(addr));
+        }
+
+        self.invalidate_decoded(addr, 4);
+        Ok(())
+    }
+
+    /// Error of a failed memory access of the embedder at `address`
+    fn host_memory_error(&self, address: u32) -> VmError {
+        VmError { kind: Error::Memory, ip: self.read_register_value(Register::IP), address: Some(address) }
+    }
+
+    /// Hexdump of the memory in `range` (see `hexdump::hexdump`). Returns
+    /// None, if the range isn't inside of the memory.
+    pub fn hexdump(&self, range: Range<u32>) -> Option<String> {
+        let start = range.start;
+        self.read_memory(range).map(|data| hexdump(&data, start))
+    }
+
+    /// Reads a typed region from the memory (e.g. the results of a program)
+    pub fn read_region(&self, region: &TypedRegion) -> Result<Vec<Vec<Value>>, ViewError> {
+        region.read(&self.interpreter)
+    }
+
+    pub fn get_interpreter(&mut self) -> &InterpreterImpl {
+        &self.interpreter
+    }
+
+    /// Mutable access to the interpreter. Clears the decode cache, because
+    /// the memory might change.
+    pub fn get_interpreter_mut(&mut self) -> &mut InterpreterImpl {
+        self.clear_decode_cache();
+        &mut self.interpreter
+    }
+}
+
+pub mod utils {
+    use super::{OpCode, Register};
+
+    pub const fn create_instruction(opcode: OpCode) -> u32
+    {
+        (opcode as u32) << (3 * 8)
+    }
+
+    pub const fn create_instruction_register(opcode: OpCode, reg: Register) -> u32
+    {
+        ((opcode as u32) << 3 * 8) | (reg as u32)
+    }
+
+    pub const fn create_instruction_immediate(opcode: OpCode, imm: u32) -> u32
+    {
+        ((opcode as u32) << 3 * 8) | (imm & 0x00FFFFFF)
+    }
+
+    pub const fn create_instruction_register_and_immediate(opcode: OpCode, reg: Register, imm: u32) -> u32 {
+        ((opcode as u32)  << 3 * 8) | ((reg as u32) << 2 * 8 + 4) | (imm & 0x000FFFFF)
+    }
+    
+    pub const fn create_instruction_two_registers(opcode: OpCode, reg0: Register, reg1: Register) -> u32 {
+        ((opcode as u32)  << 3 * 8) | ((reg0 as u32) << 2 * 8 + 4) | (reg1 as u32)
+    }
+    
+    pub const fn create_instruction_two_registers_and_immediate(opcode: OpCode, reg0: Register, reg1: Register, imm: u32) -> u32 {
+        ((opcode as u32)  << 3 * 8) | ((reg0 as u32) << 2 * 8 + 4) | ((reg1 as u32) << 2 * 8) | (imm & 0x0000FFFF)
+    }
+}
+
+pub type BinaryVirtualMachine<'source> = VirtualMachine<'source, BinaryInterpreter>;
+
+#[cfg(test)]
+mod tests {
+    use super::{OpCode, BinaryInterpreter, BinaryVirtualMachine, Interpreter, Register, SystemRegister, STATUS_IN_TRAP, STATUS_INTERRUPTS_ENABLED, utils, Error, ExitStatus, SliceStatus, VmError, Fault, InvalidOpcodePolicy, IsaVersion, VmStats, BranchMode, TraceEntry, WriteTraceSink, ERROR_START_NUM, BINARY_INTERPRETER_MEM_SIZE, parse_memory_size};
+    use crate::view::{TypedRegion, FieldType, Value};
+    use crate::mmu::{PageTable, PAGE_SIZE};
+    use crate::symbols::MemoryMap;
+    use crate::fault::{FaultInjector, InjectedFault};
+    use crate::device::Device;
+    use crate::host::HOST_SYSCALL_START;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const SYSCALLI_EXIT_INSTRUCTION: u32 = u32::to_le((OpCode::SYSCALLI as u32) << 3 * 8);
+    const LOAD_0_IN_R1_INSTRUCTION: u32 = utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 0);
+
+    #[test]
+    fn get_opcode() {
+        assert_eq!(OpCode::SYSCALLI as u8, BinaryVirtualMachine::get_opcode(SYSCALLI_EXIT_INSTRUCTION));
+    }
+
+    #[test]
+    fn syscall_exit() {
+        let syscode_inst = SYSCALLI_EXIT_INSTRUCTION;
+        let interpreter = BinaryInterpreter::new_with_program(&[syscode_inst]).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.read_register_value(Register::IP));
+        assert_eq!(Some(syscode_inst), vm.get_interpreter().read_u32(0));
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(0, vm.read_register_value(Register::IP));
+
+        let syscode_inst = SYSCALLI_EXIT_INSTRUCTION;
+        let interpreter = BinaryInterpreter::new_with_program(&[
+            syscode_inst,
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32)
+        ]).expect("Unexpected error!");
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.read_register_value(Register::IP));
+        assert_eq!(Some(syscode_inst), vm.get_interpreter().read_u32(0));
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(0, vm.read_register_value(Register::R0));
+        assert_eq!(0, vm.read_register_value(Register::IP));
+
+    }
+
+    #[test]
+    fn li_r0() {
+        let inst = utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 564);
+        let interpreter = BinaryInterpreter::new_with_program(&[inst, LOAD_0_IN_R1_INSTRUCTION, SYSCALLI_EXIT_INSTRUCTION]).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(564, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn li_r1() {
+        let inst = utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 563);
+        let interpreter = BinaryInterpreter::new_with_program(&[inst, SYSCALLI_EXIT_INSTRUCTION]).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(563, vm.execute_first());
+        assert_eq!(563, vm.read_register_value(Register::R1));
+    }
+
+    #[test]
+    fn li_r7() {
+        let inst = utils::create_instruction_register_and_immediate(OpCode::LI, Register::R7, 513);
+        let interpreter = BinaryInterpreter::new_with_program(&[inst, LOAD_0_IN_R1_INSTRUCTION, SYSCALLI_EXIT_INSTRUCTION]).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(513, vm.read_register_value(Register::R7));
+    }
+
+    #[test]
+    fn li_ip() {
+        let inst = utils::create_instruction_register_and_immediate(OpCode::LI, Register::IP, 12);
+        let interpreter = BinaryInterpreter::new_with_program(&[inst, LOAD_0_IN_R1_INSTRUCTION, SYSCALLI_EXIT_INSTRUCTION]).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(ERROR_START_NUM + (Error::ReadonlyRegister as u32), vm.execute_first());
+    }
+
+    #[test]
+    fn li_err() {
+        let inst = utils::create_instruction_register_and_immediate(OpCode::LI, Register::ERR, 12);
+        let interpreter = BinaryInterpreter::new_with_program(&[inst, LOAD_0_IN_R1_INSTRUCTION, SYSCALLI_EXIT_INSTRUCTION]).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(ERROR_START_NUM + (Error::ReadonlyRegister as u32), vm.execute_first());
+    }
+
+    #[test]
+    fn add() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 16),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 5),
+            utils::create_instruction_two_registers(OpCode::ADD, Register::R0, Register::R1),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(21, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn cpy() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 16),
+            utils::create_instruction_two_registers(OpCode::CPY, Register::R2, Register::R0),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(16, vm.read_register_value(Register::R2));
+    }
+
+    #[test]
+    fn sub() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 16),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 5),
+            utils::create_instruction_two_registers(OpCode::SUB, Register::R0, Register::R1),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(11, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn mul() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 5),
+            utils::create_instruction_two_registers(OpCode::MUL, Register::R0, Register::R1),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(20, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn div() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 20),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 5),
+            utils::create_instruction_two_registers(OpCode::DIV, Register::R0, Register::R1),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(4, vm.read_register_value(Register::R0));
+
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 24),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 5),
+            utils::create_instruction_two_registers(OpCode::DIV, Register::R0, Register::R1),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(4, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn div_divisor_zero() {
+      let program: [u32; 5] = [
+          utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 20),
+          utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 0),
+          utils::create_instruction_two_registers(OpCode::DIV, Register::R0, Register::R1),
+          LOAD_0_IN_R1_INSTRUCTION,
+          SYSCALLI_EXIT_INSTRUCTION
+      ];
+
+      let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+      let mut stdout = std::io::stdout();
+      let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+      assert_eq!(ERROR_START_NUM + Error::DivisorNotZero as u32, vm.execute_first());
+      assert_eq!(0, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn addi() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 16),
+            utils::create_instruction_register_and_immediate(OpCode::ADDI, Register::R0, 5),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(21, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn subi() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 16),
+            utils::create_instruction_register_and_immediate(OpCode::SUBI, Register::R0, 5),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(11, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn muli() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 4),
+            utils::create_instruction_register_and_immediate(OpCode::MULI, Register::R0, 5),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(20, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn divi() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 20),
+            utils::create_instruction_register_and_immediate(OpCode::DIVI, Register::R0, 5),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(4, vm.read_register_value(Register::R0));
+
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 24),
+            utils::create_instruction_register_and_immediate(OpCode::DIVI, Register::R0, 5),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(4, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn divi_divisor_zero() {
+      let program: [u32; 4] = [
+          utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 20),
+          utils::create_instruction_register_and_immediate(OpCode::DIVI, Register::R0, 0),
+          LOAD_0_IN_R1_INSTRUCTION,
+          SYSCALLI_EXIT_INSTRUCTION
+      ];
+
+      let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+      let mut stdout = std::io::stdout();
+      let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+      assert_eq!(ERROR_START_NUM + Error::DivisorNotZero as u32, vm.execute_first());
+      assert_eq!(0, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn lw() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 4 * 4),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            0xFF00FF00
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(0xFF00FF00, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn sw() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 5 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1033),
+            utils::create_instruction_two_registers(OpCode::SW, Register::R0, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(1033, vm.get_interpreter().read_u32(5 * 4).expect("Cannot read memory address"));
+    }
+
+    #[test]
+    fn lh() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 4 * 4),
+            utils::create_instruction_two_registers(OpCode::LH, Register::R0, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            1032 // Will be stored in [0] and [1] of integer 0124, because little endian
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(1032, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
     fn sh() {
         let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 5 * 4),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1033),
-            utils::create_instruction_two_registers(OpCode::SH, Register::R0, Register::R2),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 5 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1033),
+            utils::create_instruction_two_registers(OpCode::SH, Register::R0, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(1033, vm.get_interpreter().read_u16(5 * 4).expect("Cannot read memory address"));
+    }
+
+    #[test]
+    fn lb() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 4 * 4),
+            utils::create_instruction_two_registers(OpCode::LB, Register::R0, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            234 // Will be stored in [0] and [1] of integer 0124, because little endian
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(234, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn sb() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 5 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 234),
+            utils::create_instruction_two_registers(OpCode::SB, Register::R0, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(234, vm.get_interpreter().read_u8(5 * 4).expect("Cannot read memory address"));
+    }
+
+    #[test]
+    fn lb_partial() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 4 * 4),
+            utils::create_instruction_two_registers(OpCode::LB, Register::R0, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            1024 + 234 // Will be stored in [0] and [1] of integer 0124, because little endian
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(234, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn sb_partial() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 5 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1024 + 234),
+            utils::create_instruction_two_registers(OpCode::SB, Register::R0, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(234, vm.get_interpreter().read_u8(5 * 4).expect("Cannot read memory address"));
+        assert_eq!(234, vm.get_interpreter().read_u32(5 * 4).expect("Cannot read memory address"));
+    }
+
+    #[test]
+    fn lwi() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 3 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            0xFF00FF00
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(0xFF00FF00, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn swi() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1033),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R0, 4 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(1033, vm.get_interpreter().read_u32(4 * 4).expect("Cannot read memory address"));
+    }
+
+    #[test]
+    fn lhi() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LHI, Register::R0, 3 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            1032 // Will be stored in [0] and [1] of integer 0124, because little endian
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(1032, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn shi() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1033),
+            utils::create_instruction_register_and_immediate(OpCode::SHI, Register::R0, 4 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(1033, vm.get_interpreter().read_u16(4 * 4).expect("Cannot read memory address"));
+    }
+
+    #[test]
+    fn lbi() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LBI, Register::R0, 3 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            234 // Will be stored in [0] and [1] of integer 0124, because little endian
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(234, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn sbi() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 234),
+            utils::create_instruction_register_and_immediate(OpCode::SBI, Register::R0, 4 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(234, vm.get_interpreter().read_u8(4 * 4).expect("Cannot read memory address"));
+    }
+
+    #[test]
+    fn lbi_partial() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LBI, Register::R0, 3 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            1024 + 234 // Will be stored in [0] and [1] of integer 0124, because little endian
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(234, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn sbi_partial() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1024 + 234),
+            utils::create_instruction_register_and_immediate(OpCode::SBI, Register::R0, 4 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(234, vm.get_interpreter().read_u8(4 * 4).expect("Cannot read memory address"));
+        assert_eq!(234, vm.get_interpreter().read_u32(4 * 4).expect("Cannot read memory address"));
+    }
+
+    #[test]
+    fn j() {
+        let program: [u32; 7] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 4 * 4),
+            utils::create_instruction_register(OpCode::J, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(32, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn ji() {
+        let program: [u32; 6] = [
+            utils::create_instruction_immediate(OpCode::JI, 3 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(32, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn jil() {
+        let program: [u32; 8] = [
+            utils::create_instruction_immediate(OpCode::JI, 1 * 4), // nop
+            utils::create_instruction_immediate(OpCode::JIL, 4 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
+            utils::create_instruction_two_registers(OpCode::CPY, Register::R3, Register::RA),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(32, vm.read_register_value(Register::R0));
+        assert_eq!(8, vm.read_register_value(Register::R3));
+    }
+
+    #[test]
+    fn write_protected() {
+        let program: [u32; 3] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 12),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R0, 0),
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.set_write_protected(Some(0..12));
+        assert_eq!(Some(0..12), vm.get_write_protected());
+
+        assert_eq!(Error::WriteProtected as u32 + ERROR_START_NUM, vm.execute_first());
+        assert_eq!(Some(program[0]), vm.get_interpreter().read_u32(0));
+
+        // Stores, which only partially overlap, are rejected, too
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 20),
+            utils::create_instruction_two_registers(OpCode::SB, Register::R0, Register::R0),
+            utils::create_instruction_register_and_immediate(OpCode::SHI, Register::R0, 23),
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.set_write_protected(Some(0..24));
+
+        assert_eq!(Error::WriteProtected as u32 + ERROR_START_NUM, vm.execute_first());
+        assert_eq!(4, vm.read_register_value(Register::IP));
+
+        vm.set_write_protected(Some(21..24));
+        assert_eq!(Error::WriteProtected as u32 + ERROR_START_NUM, vm.execute_first());
+        assert_eq!(8, vm.read_register_value(Register::IP));
+
+        vm.set_write_protected(None);
+        assert_eq!(0, vm.execute_first());
+    }
+
+    #[test]
+    fn page_fault() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 42),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R0, 0x10004),
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R2, 0x10004),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R0, 0x20000),
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let mut interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut page_table = PageTable::new();
+        page_table.map(0, 0, false);
+        page_table.map(0x10000 / PAGE_SIZE, 2, true);
+        interpreter.set_page_table(Some(page_table));
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(Error::PageFault as u32 + ERROR_START_NUM, vm.execute_first());
+        assert_eq!(Some(0x20000), vm.get_fault_address());
+        assert_eq!(12, vm.read_register_value(Register::IP));
+        assert_eq!(42, vm.read_register_value(Register::R2));
+        assert_eq!(Some(42), vm.get_interpreter().read_u32(2 * PAGE_SIZE + 4));
+
+        // Instruction fetch from an unmapped page
+        assert_eq!(Error::PageFault as u32 + ERROR_START_NUM, vm.execute(0x30000));
+        assert_eq!(Some(0x30000), vm.get_fault_address());
+
+        // Write to a page, which isn't writable
+        vm.get_interpreter_mut().get_page_table_mut().unwrap().map(0x20000 / PAGE_SIZE, 0, false);
+        assert_eq!(Error::PageFault as u32 + ERROR_START_NUM, vm.execute_first());
+        assert_eq!(Some(0x20000), vm.get_fault_address());
+
+        // Access crossing into a page, which isn't physically adjacent
+        vm.get_interpreter_mut().get_page_table_mut().unwrap().map(0x20000 / PAGE_SIZE, 3, true);
+        vm.get_interpreter_mut().get_page_table_mut().unwrap().map(0x10000 / PAGE_SIZE - 1, 1, true);
+        vm.get_interpreter_mut().get_page_table_mut().unwrap().map(0x10000 / PAGE_SIZE, 3, true);
+        assert_eq!(Error::PageFault as u32 + ERROR_START_NUM, vm.execute(0x10000 - 2));
+    }
+
+    #[test]
+    fn memory_fault_address() {
+        let program: [u32; 2] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 0xFFFFF),
+            utils::create_instruction_two_registers(OpCode::SW, Register::R0, Register::R0),
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(None, vm.get_fault_address());
+        assert_eq!(Error::Memory as u32 + ERROR_START_NUM, vm.execute_first());
+        assert_eq!(Some(0xFFFFFFFF), vm.get_fault_address());
+    }
+
+    #[test]
+    fn last_fault() {
+        let program: [u32; 2] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 0xFFFFF),
+            utils::create_instruction_two_registers(OpCode::SW, Register::R0, Register::R0),
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(None, vm.last_fault());
+        assert_eq!(Error::Memory as u32 + ERROR_START_NUM, vm.execute_first());
+        assert_eq!(Some(Fault { kind: Error::Memory, ip: 4, address: Some(0xFFFFFFFF), instruction: Some(program[1]) }), vm.last_fault());
+
+        // Jumping outside of the memory fails fetching the instruction
+        let program = [utils::create_instruction_immediate(OpCode::JI, 0xFFFFF0)];
+        assert!(vm.load_program(&program.iter().flat_map(|word| word.to_le_bytes()).collect::<Vec<u8>>()));
+        assert_eq!(None, vm.last_fault());
+        assert_eq!(Error::Memory as u32 + ERROR_START_NUM, vm.execute_first());
+        assert_eq!(Some(Fault { kind: Error::Memory, ip: 0xFFFFF0, address: Some(0xFFFFF0), instruction: None }), vm.last_fault());
+    }
+
+    #[test]
+    fn fault_injection() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 64),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R0, 64),
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R2, 64),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R0, 68),
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.set_fault_injector(Some(FaultInjector::new(1).fail_memory_write(2).corrupt_loaded_word(1)));
+
+        assert_eq!(Error::Memory as u32 + ERROR_START_NUM, vm.execute_first());
+        assert_eq!(12, vm.read_register_value(Register::IP));
+        assert_eq!(Some(68), vm.get_fault_address());
+        assert_eq!(1, (vm.read_register_value(Register::R2) ^ 64).count_ones());
+
+        let injected = vm.get_fault_injector().expect("Expected").injected();
+        assert_eq!(2, injected.len());
+        assert!(matches!(injected[0], InjectedFault::CorruptedLoad { load: 1, .. }));
+        assert_eq!(InjectedFault::FailedWrite { write: 2 }, injected[1]);
+    }
+
+    #[test]
+    fn fault_injection_register_bit_flip() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 0),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 0),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 0),
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let run = |seed| {
+            let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+            let mut stdout = std::io::stdout();
+            let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+            vm.set_fault_injector(Some(FaultInjector::new(seed).flip_register_bit_every(3)));
+            vm.execute_first();
+
+            let injected = vm.get_fault_injector().expect("Expected").injected().to_vec();
+            assert_eq!(1, injected.len());
+            if let InjectedFault::RegisterBitFlip { instruction, register, bit } = injected[0] {
+                assert_eq!(3, instruction);
+                let expected = if register == Register::SP { BINARY_INTERPRETER_MEM_SIZE } else { 0 } ^ (1 << bit);
+                assert_eq!(expected, vm.read_register_value(register));
+            }
+            else {
+                panic!("Expected register bit flip");
+            }
+
+            injected
+        };
+
+        assert_eq!(run(5), run(5));
+    }
+
+    /// Device for testing: reading returns the offset, writes are recorded
+    #[derive(Debug)]
+    struct RecordingDevice {
+        writes: Rc<RefCell<Vec<(u32, u8)>>>,
+    }
+
+    impl Device for RecordingDevice {
+        fn read_u8(&self, offset: u32) -> Option<u8> {
+            Some(offset as u8)
+        }
+
+        fn write_u8(&mut self, offset: u32, value: u8) -> bool {
+            self.writes.borrow_mut().push((offset, value));
+            true
+        }
+    }
+
+    #[test]
+    fn device() {
+        let program: [u32; 7] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 0x1000),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 0x41),
+            utils::create_instruction_two_registers(OpCode::SB, Register::R1, Register::R0),
+            utils::create_instruction_register_and_immediate(OpCode::SHI, Register::R1, 0x1002),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R2, Register::R0),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let writes = Rc::new(RefCell::new(Vec::new()));
+        let mut interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        assert!(interpreter.map_device(0x1000, 4, Box::new(RecordingDevice { writes: writes.clone() })));
+        assert!(!interpreter.map_device(0x1003, 4, Box::new(RecordingDevice { writes: writes.clone() })));
+        assert!(!interpreter.map_device(0x2000, 0, Box::new(RecordingDevice { writes: writes.clone() })));
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(u32::from_le_bytes([0, 1, 2, 3]), vm.read_register_value(Register::R2));
+        assert_eq!(vec![(0, 0x41), (2, 0x41), (3, 0)], *writes.borrow());
+
+        // Accesses crossing the end of the window are partially routed to the memory
+        assert_eq!(Some(u16::from_le_bytes([3, 0])), vm.get_interpreter().read_u16(0x1003));
+        assert!(vm.get_interpreter_mut().unmap_device(0x1000).is_some());
+        assert_eq!(Some(0), vm.get_interpreter().read_u32(0x1000));
+    }
+
+    #[test]
+    fn step() {
+        let program: [u32; 3] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 7),
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert!(!vm.is_running());
+        vm.start(0);
+        assert!(vm.is_running());
+        assert!(vm.step());
+        assert_eq!(1, vm.read_register_value(Register::R0));
+        assert_eq!(4, vm.read_register_value(Register::IP));
+
+        assert_eq!(None, vm.run_for(1));
+        assert_eq!(7, vm.read_register_value(Register::R1));
+        assert_eq!(Some(7), vm.run_for(10));
+        assert!(!vm.is_running());
+        assert!(!vm.step());
+        assert_eq!(7, vm.get_result());
+    }
+
+    #[test]
+    fn syscall_print_paged() {
+        const STRING: &[u8] = b"Hello!";
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 2 * PAGE_SIZE - 3),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, STRING.len() as u32),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 1),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        // The string spans two virtual pages, which aren't physically adjacent
+        let mut interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        for (i, c) in STRING.iter().enumerate() {
+            let physical = if i < 3 { 4 * PAGE_SIZE - 3 + i as u32 } else { 5 * PAGE_SIZE + i as u32 - 3 };
+            assert!(interpreter.write_u8(physical, *c));
+        }
+        let mut page_table = PageTable::new();
+        page_table.map(0, 0, false);
+        page_table.map(1, 3, false);
+        page_table.map(2, 5, false);
+        interpreter.set_page_table(Some(page_table));
+        let mut buffer = Vec::new();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut buffer);
+
+        assert_eq!(0, vm.execute_first());
+
+        vm.get_interpreter_mut().get_page_table_mut().unwrap().unmap(2);
+        assert_eq!(Error::PageFault as u32 + ERROR_START_NUM, vm.execute_first());
+        assert_eq!(Some(2 * PAGE_SIZE), vm.get_fault_address());
+
+        drop(vm);
+        assert_eq!(STRING, buffer.as_slice());
+    }
+
+    #[test]
+    fn ji_delay_slot() {
+        let program: [u32; 6] = [
+            utils::create_instruction_immediate(OpCode::JI, 4 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32), // Delay slot
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 1),
+            SYSCALLI_EXIT_INSTRUCTION,
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(BranchMode::Immediate, vm.get_branch_mode());
+        vm.set_branch_mode(BranchMode::DelaySlot);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(32, vm.read_register_value(Register::R0));
+        assert_eq!(0, vm.read_register_value(Register::R2));
+        assert_eq!(1, vm.stats().jumps_taken);
+
+        // Without delay slot, the instruction after the jump is skipped
+        vm.reset();
+        vm.set_branch_mode(BranchMode::Immediate);
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(0, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn jil_delay_slot() {
+        let program: [u32; 7] = [
+            utils::create_instruction_immediate(OpCode::JIL, 4 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32), // Delay slot
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            utils::create_instruction_two_registers(OpCode::CPY, Register::R3, Register::RA),
+            utils::create_instruction_register(OpCode::J, Register::RA),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R4, 7), // Delay slot
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.set_branch_mode(BranchMode::DelaySlot);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(32, vm.read_register_value(Register::R0));
+        assert_eq!(8, vm.read_register_value(Register::R3));
+        assert_eq!(7, vm.read_register_value(Register::R4));
+    }
+
+    #[test]
+    fn conditional_jump_delay_slot() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::JZI, Register::R0, 4 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1), // Delay slot
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 1),
+            SYSCALLI_EXIT_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.set_branch_mode(BranchMode::DelaySlot);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(1, vm.read_register_value(Register::R0));
+        assert_eq!(0, vm.read_register_value(Register::R2));
+    }
+
+    #[test]
+    fn jzi() {
+        let program: [u32; 6] = [
+            utils::create_instruction_register_and_immediate(OpCode::JZI, Register::R0, 3 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(32, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn jnzi() {
+        let program: [u32; 7] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, u32::from_le_bytes(i32::to_le_bytes(-1))),
+            utils::create_instruction_register_and_immediate(OpCode::JNZI, Register::R0, 4 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(32, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn li_minus_1()
+    {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 4 * 4),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
+            u32::from_le_bytes(i32::to_le_bytes(-1))
         ];
 
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
@@ -1103,17 +3644,16 @@ mod tests {
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
 
         assert_eq!(0, vm.execute_first());
-        assert_eq!(1033, vm.get_interpreter().read_u16(5 * 4).expect("Cannot read memory address"));
+        assert_eq!(-1, i32::from_le_bytes(u32::to_le_bytes(vm.read_register_value(Register::R0))));
     }
 
     #[test]
-    fn lb() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 4 * 4),
-            utils::create_instruction_two_registers(OpCode::LB, Register::R0, Register::R2),
+    fn li_minus_1_new_way()
+    {
+        let program: [u32; 3] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, u32::from_le_bytes(i32::to_le_bytes(-1))),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
-            234 // Will be stored in [0] and [1] of integer 0124, because little endian
         ];
 
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
@@ -1121,17 +3661,21 @@ mod tests {
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
 
         assert_eq!(0, vm.execute_first());
-        assert_eq!(234, vm.read_register_value(Register::R0));
+        assert_eq!(-1, i32::from_le_bytes(u32::to_le_bytes(vm.read_register_value(Register::R0))));
     }
 
     #[test]
-    fn sb() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 5 * 4),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 234),
-            utils::create_instruction_two_registers(OpCode::SB, Register::R0, Register::R2),
+    fn jlzi() {
+        let program: [u32; 9] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 8 * 4),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
+            utils::create_instruction_register_and_immediate(OpCode::JLZI, Register::R0, 5 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
+            u32::from_le_bytes(i32::to_le_bytes(-1))
         ];
 
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
@@ -1139,17 +3683,19 @@ mod tests {
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
 
         assert_eq!(0, vm.execute_first());
-        assert_eq!(234, vm.get_interpreter().read_u8(5 * 4).expect("Cannot read memory address"));
+        assert_eq!(32, vm.read_register_value(Register::R0) as i32);
     }
 
     #[test]
-    fn lb_partial() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 4 * 4),
-            utils::create_instruction_two_registers(OpCode::LB, Register::R0, Register::R2),
+    fn jlzi_new_way() {
+        let program: [u32; 7] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, u32::from_le_bytes(i32::to_le_bytes(-1))),
+            utils::create_instruction_register_and_immediate(OpCode::JLZI, Register::R0, 4 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
-            1024 + 234 // Will be stored in [0] and [1] of integer 0124, because little endian
         ];
 
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
@@ -1157,15 +3703,17 @@ mod tests {
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
 
         assert_eq!(0, vm.execute_first());
-        assert_eq!(234, vm.read_register_value(Register::R0));
+        assert_eq!(32, vm.read_register_value(Register::R0) as i32);
     }
 
     #[test]
-    fn sb_partial() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 5 * 4),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1024 + 234),
-            utils::create_instruction_two_registers(OpCode::SB, Register::R0, Register::R2),
+    fn jgzi() {
+        let program: [u32; 7] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1),
+            utils::create_instruction_register_and_immediate(OpCode::JGZI, Register::R0, 4 * 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
         ];
@@ -1175,17 +3723,21 @@ mod tests {
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
 
         assert_eq!(0, vm.execute_first());
-        assert_eq!(234, vm.get_interpreter().read_u8(5 * 4).expect("Cannot read memory address"));
-        assert_eq!(234, vm.get_interpreter().read_u32(5 * 4).expect("Cannot read memory address"));
+        assert_eq!(32, vm.read_register_value(Register::R0));
     }
 
     #[test]
-    fn lwi() {
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 3 * 4),
+    fn and() {
+        let program: [u32; 9] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 7 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 8 * 4),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R2, Register::R2),
+            utils::create_instruction_two_registers(OpCode::AND, Register::R0, Register::R2),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
-            0xFF00FF00
+            0x0000FFFF,
+            0xFFFFA000,
         ];
 
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
@@ -1193,921 +3745,1447 @@ mod tests {
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
 
         assert_eq!(0, vm.execute_first());
-        assert_eq!(0xFF00FF00, vm.read_register_value(Register::R0));
+        assert_eq!(0x0000A000, vm.read_register_value(Register::R0));
     }
 
     #[test]
-    fn swi() {
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1033),
-            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R0, 4 * 4),
+    fn or() {
+        let program: [u32; 9] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 7 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 8 * 4),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R2, Register::R2),
+            utils::create_instruction_two_registers(OpCode::OR, Register::R0, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            0x00000FFF,
+            0xFFF00000,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(0xFFF00FFF, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn xor() {
+        let program: [u32; 9] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 7 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 8 * 4),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R2, Register::R2),
+            utils::create_instruction_two_registers(OpCode::XOR, Register::R0, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            0x0000FFFF,
+            0xFFFFF000,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(0xFFFF0FFF, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn not() {
+        let program: [u32; 10] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 8 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 9 * 4),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R2, Register::R2),
+            utils::create_instruction_register(OpCode::NOT, Register::R0),
+            utils::create_instruction_register(OpCode::NOT, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            0x00000000,
+            0xFFFFFFF0,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(0xFFFFFFFF, vm.read_register_value(Register::R0));
+        assert_eq!(0x0000000F, vm.read_register_value(Register::R2));
+    }
+
+    #[test]
+    fn srl() {
+        let program: [u32; 9] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 7 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 8 * 4),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R2, Register::R2),
+            utils::create_instruction_two_registers(OpCode::SRL, Register::R0, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            0x00FFFF00,
+            4,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(0x000FFFF0, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn sll() {
+        let program: [u32; 9] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 7 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 8 * 4),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R2, Register::R2),
+            utils::create_instruction_two_registers(OpCode::SLL, Register::R0, Register::R2),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            0x00FFFF00,
+            4,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(0x0FFFF000, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn srli() {
+        let program: [u32; 6] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 5 * 4),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
+            utils::create_instruction_register_and_immediate(OpCode::SRLI, Register::R0, 4),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+            0x00FFFF00,
+        ];
+
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(0x000FFFF0, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn slli() {
+        let program: [u32; 6] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 5 * 4),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
+            utils::create_instruction_register_and_immediate(OpCode::SLLI, Register::R0, 4),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
+            0x00FFFF00,
         ];
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(0x0FFFF000, vm.read_register_value(Register::R0));
+    }
+
+    #[test]
+    fn new_with_program_overflow() {
+        let program = vec!(0; BINARY_INTERPRETER_MEM_SIZE as usize + 100);
+        let interpreter = BinaryInterpreter::new_with_program(&program);
+        assert_eq!(None, interpreter, "Should be None");
+    }
+
+    #[test]
+    fn new_with_initial() {
+        let mem: Vec<u8> = vec!(1, 2, 3, 4, 10, 100);
+        let interpreter = BinaryInterpreter::new_with_initial(&mem);
+        assert_ne!(None, interpreter);
+        let interpreter = interpreter.expect("Already checked");
+        for i in 0..(mem.len() as u32) {
+            let read_byte = interpreter.read_u8(i);
+            assert_eq!(Some(mem[i as usize]), read_byte);
+        }
+    }
+
+    #[test]
+    fn new_with_initial_with_overflow() {
+        let mem: Vec<u8> = vec!(0; BINARY_INTERPRETER_MEM_SIZE as usize + 100);
+        let interpreter = BinaryInterpreter::new_with_initial(&mem);
+        assert_eq!(None, interpreter);
+    }
+
+    #[test]
+    fn memory_size() {
+        let mut interpreter = BinaryInterpreter::with_memory_size(64).expect("Expected valid size");
+        assert_eq!(64, interpreter.len());
+        assert!(interpreter.load_program(&[1, 2, 3, 4]));
+        assert_eq!(Some(0x04030201), interpreter.read_u32(0));
+        assert_eq!(None, interpreter.read_u32(64));
+        assert!(!interpreter.load_program(&[0; 68]));
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(1033, vm.get_interpreter().read_u32(4 * 4).expect("Cannot read memory address"));
+        let mut stdout = Vec::new();
+        let vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(64, vm.read_register_value(Register::SP));
+
+        assert_eq!(None, BinaryInterpreter::with_memory_size(0));
+        assert_eq!(None, BinaryInterpreter::with_memory_size(6));
     }
 
     #[test]
-    fn lhi() {
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LHI, Register::R0, 3 * 4),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            1032 // Will be stored in [0] and [1] of integer 0124, because little endian
-        ];
+    fn parse_memory_sizes() {
+        assert_eq!(Some(4096), parse_memory_size("4096"));
+        assert_eq!(Some(64 * 1024), parse_memory_size("64K"));
+        assert_eq!(Some(64 * 1024), parse_memory_size("64kib"));
+        assert_eq!(Some(16 * 1024 * 1024), parse_memory_size("16M"));
+        assert_eq!(Some(1024 * 1024 * 1024), parse_memory_size("1G"));
+        assert_eq!(None, parse_memory_size("4G"));
+        assert_eq!(None, parse_memory_size("16X"));
+        assert_eq!(None, parse_memory_size("M"));
+        assert_eq!(None, parse_memory_size(""));
+    }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    #[test]
+    fn read_u32_out_of_bounds() {
+        let interpreter = BinaryInterpreter::new();
+        assert_eq!(None, interpreter.read_u32(BINARY_INTERPRETER_MEM_SIZE as u32));
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(1032, vm.read_register_value(Register::R0));
+    #[test]
+    fn read_u16_out_of_bounds() {
+        let interpreter = BinaryInterpreter::new();
+        assert_eq!(None, interpreter.read_u16(BINARY_INTERPRETER_MEM_SIZE as u32));
     }
 
     #[test]
-    fn shi() {
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1033),
-            utils::create_instruction_register_and_immediate(OpCode::SHI, Register::R0, 4 * 4),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-        ];
+    fn read_u8_out_of_bounds() {
+        let interpreter = BinaryInterpreter::new();
+        assert_eq!(None, interpreter.read_u8(BINARY_INTERPRETER_MEM_SIZE as u32));
+    }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+    #[test]
+    fn write_u32_out_of_bounds() {
+        let mut interpreter = BinaryInterpreter::new();
+        assert_eq!(false, interpreter.write_u32(BINARY_INTERPRETER_MEM_SIZE as u32, 0));
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(1033, vm.get_interpreter().read_u16(4 * 4).expect("Cannot read memory address"));
+    #[test]
+    fn write_u16_out_of_bounds() {
+        let mut interpreter = BinaryInterpreter::new();
+        assert_eq!(false, interpreter.write_u16(BINARY_INTERPRETER_MEM_SIZE as u32, 0));
     }
 
     #[test]
-    fn lbi() {
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LBI, Register::R0, 3 * 4),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            234 // Will be stored in [0] and [1] of integer 0124, because little endian
-        ];
+    fn write_u8_out_of_bounds() {
+        let mut interpreter = BinaryInterpreter::new();
+        assert_eq!(false, interpreter.write_u8(BINARY_INTERPRETER_MEM_SIZE as u32, 0));
+    }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+    #[test]
+    fn execute_out_of_bounds() {
+        let interpreter = BinaryInterpreter::new();
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(234, vm.read_register_value(Register::R0));
+        vm.execute(BINARY_INTERPRETER_MEM_SIZE as u32);
+        assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
     }
 
     #[test]
-    fn sbi() {
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 234),
-            utils::create_instruction_register_and_immediate(OpCode::SBI, Register::R0, 4 * 4),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-        ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+    fn test_no_such_register() {
+        let program: [u32; 1] = [utils::create_instruction_register(OpCode::J, Register::R0) + 0xF]; // Make sure to annihilate the register
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(234, vm.get_interpreter().read_u8(4 * 4).expect("Cannot read memory address"));
+        vm.execute_first();
+        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
     }
 
     #[test]
-    fn lbi_partial() {
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LBI, Register::R0, 3 * 4),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            1024 + 234 // Will be stored in [0] and [1] of integer 0124, because little endian
-        ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+    fn test_no_such_register_write_register() {
+        let program: [u32; 1] = [utils::create_instruction_two_registers(OpCode::CPY, Register::R0, Register::R1) + 0xE]; // Make sure to annihilate the register
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.execute_first();
+        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(234, vm.read_register_value(Register::R0));
+        // test binary_register_operation_write0
+        let program: [u32; 1] = [
+            utils::create_instruction_two_registers(OpCode::CPY, Register::R0, Register::R1)
+                + utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R0) * 0xF // sophisticated bs
+        ]; // Make sure to annihilate the register
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.execute_first();
+        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
     }
 
     #[test]
-    fn sbi_partial() {
-        let program: [u32; 4] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1024 + 234),
-            utils::create_instruction_register_and_immediate(OpCode::SBI, Register::R0, 4 * 4),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-        ];
+    fn test_no_such_register_biop_write() {
+        let program: [u32; 1] = [utils::create_instruction_two_registers(OpCode::ADD, Register::R0, Register::R1) + 0xE]; // Make sure to annihilate the register
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.execute_first();
+        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        // test binary_register_operation_write0
+        let program: [u32; 1] = [
+            utils::create_instruction_two_registers(OpCode::ADD, Register::R0, Register::R1)
+                + utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R0) * 0xF // sophisticated bs
+        ]; // Make sure to annihilate the register
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.execute_first();
+        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(234, vm.get_interpreter().read_u8(4 * 4).expect("Cannot read memory address"));
-        assert_eq!(234, vm.get_interpreter().read_u32(4 * 4).expect("Cannot read memory address"));
+        let program: [u32; 1] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 123)
+                + utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R0) * 0xF // sophisticated bs
+        ]; // Make sure to annihilate the register
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.execute_first();
+        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
+
+        // test binary_register_and_immediate_operation_write0
+        let program: [u32; 1] = [
+            utils::create_instruction_register_and_immediate(OpCode::SRLI, Register::R0, 123)
+                + utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R0) * 0xF // sophisticated bs
+        ]; // Make sure to annihilate the register
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.execute_first();
+        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
     }
 
     #[test]
-    fn j() {
-        let program: [u32; 7] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 4 * 4),
-            utils::create_instruction_register(OpCode::J, Register::R2),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-        ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+    fn test_no_such_register_check_write_ip() {
+        let program: [u32; 1] = [
+            utils::create_instruction_register_and_immediate(OpCode::JGZI, Register::R0, 4)
+                + utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R0) * 0xF // sophisticated bs
+        ]; // Make sure to annihilate the register
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(32, vm.read_register_value(Register::R0));
+        vm.execute_first();
+        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
     }
 
     #[test]
-    fn ji() {
-        let program: [u32; 6] = [
-            utils::create_instruction_immediate(OpCode::JI, 3 * 4),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-        ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+    fn test_no_such_register_not() {
+        let program: [u32; 1] = [
+            utils::create_instruction_register(OpCode::NOT, Register::R0) + 0xF
+        ]; // Make sure to annihilate the register
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(32, vm.read_register_value(Register::R0));
+        vm.execute_first();
+        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
     }
 
     #[test]
-    fn jil() {
-        let program: [u32; 8] = [
-            utils::create_instruction_immediate(OpCode::JI, 1 * 4), // nop
-            utils::create_instruction_immediate(OpCode::JIL, 4 * 4),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
-            utils::create_instruction_two_registers(OpCode::CPY, Register::R3, Register::RA),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-        ];
+    fn test_cannot_write_register() {
+        let program: [u32; 1] = [utils::create_instruction_two_registers(OpCode::CPY, Register::IP, Register::R0)];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.execute_first();
+        assert_eq!(Error::ReadonlyRegister as u32, vm.read_register_value(Register::ERR));
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let program: [u32; 1] = [utils::create_instruction_two_registers(OpCode::CPY, Register::ERR, Register::R0)];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.execute_first();
+        assert_eq!(Error::ReadonlyRegister as u32, vm.read_register_value(Register::ERR));
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(32, vm.read_register_value(Register::R0));
-        assert_eq!(8, vm.read_register_value(Register::R3));
+    #[test]
+    fn test_can_write_registers() {
+        for register in [Register::R0, Register::R1, Register::R2, Register::R3, Register::R4, Register::R5, Register::R6, Register::R7, Register::RA, Register::SP] {
+            let program: [u32; 3] = [
+                utils::create_instruction_two_registers(OpCode::CPY, register, Register::IP),
+                LOAD_0_IN_R1_INSTRUCTION,
+                SYSCALLI_EXIT_INSTRUCTION
+            ];
+            let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+            let mut stdout = std::io::stdout();
+            let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+            vm.execute_first();
+            assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
+        }
+    }
+
+    #[test]
+    fn test_invalid_syscall() {
+        let program: [u32; 1] = [utils::create_instruction_immediate(OpCode::SYSCALLI, 0xFF)];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.execute_first();
+        assert_eq!(Error::Syscall as u32, vm.read_register_value(Register::ERR));
     }
 
     #[test]
-    fn jzi() {
-        let program: [u32; 6] = [
-            utils::create_instruction_register_and_immediate(OpCode::JZI, Register::R0, 3 * 4),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
+    fn lw_edge() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 4 * 4),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
+            BINARY_INTERPRETER_MEM_SIZE - 4
         ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(32, vm.read_register_value(Register::R0));
+        vm.execute_first();
+        assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
     }
 
     #[test]
-    fn jnzi() {
-        let program: [u32; 7] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, u32::from_le_bytes(i32::to_le_bytes(-1))),
-            utils::create_instruction_register_and_immediate(OpCode::JNZI, Register::R0, 4 * 4),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
+    fn lh_edge() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 4 * 4),
+            utils::create_instruction_two_registers(OpCode::LH, Register::R0, Register::R0),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
+            BINARY_INTERPRETER_MEM_SIZE - 2
         ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(32, vm.read_register_value(Register::R0));
+        vm.execute_first();
+        assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
     }
 
     #[test]
-    fn li_minus_1()
-    {
+    fn lb_edge() {
         let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 4 * 4),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 4 * 4),
+            utils::create_instruction_two_registers(OpCode::LB, Register::R0, Register::R0),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
-            u32::from_le_bytes(i32::to_le_bytes(-1))
+            BINARY_INTERPRETER_MEM_SIZE - 1
         ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.execute_first();
+        assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(-1, i32::from_le_bytes(u32::to_le_bytes(vm.read_register_value(Register::R0))));
+    #[test]
+    fn lw_out_of_bounds() {
+        for i in 0..3 { // 32-bit = 4-byte
+            let program: [u32; 2] = [
+                utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, BINARY_INTERPRETER_MEM_SIZE as u32 - i),
+                utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0)
+            ];
+            let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+            let mut stdout = std::io::stdout();
+            let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+            vm.execute_first();
+            assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
+        }
     }
 
     #[test]
-    fn li_minus_1_new_way()
-    {
-        let program: [u32; 3] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, u32::from_le_bytes(i32::to_le_bytes(-1))),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-        ];
+    fn lh_out_of_bounds() {
+        for i in 0..1 { // 16-bit = 2-byte
+            let program: [u32; 2] = [
+                utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, BINARY_INTERPRETER_MEM_SIZE as u32 - i),
+                utils::create_instruction_two_registers(OpCode::LH, Register::R0, Register::R0)
+            ];
+            let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+            let mut stdout = std::io::stdout();
+            let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+            vm.execute_first();
+            assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
+        }
+    }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+    #[test]
+    fn lb_out_of_bounds() {
+        let program: [u32; 2] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, BINARY_INTERPRETER_MEM_SIZE as u32),
+            utils::create_instruction_two_registers(OpCode::LB, Register::R0, Register::R0)
+        ];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.execute_first();
+        assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(-1, i32::from_le_bytes(u32::to_le_bytes(vm.read_register_value(Register::R0))));
+    #[test]
+    fn edge_binary_interpreter() {
+        let mut interpreter = BinaryInterpreter::new();
+        assert_eq!(Some(0), interpreter.read_u8(BINARY_INTERPRETER_MEM_SIZE - 1));
+        assert_eq!(true, interpreter.write_u8(BINARY_INTERPRETER_MEM_SIZE - 1, 128 as u8));
+        assert_eq!(Some(128), interpreter.read_u8(BINARY_INTERPRETER_MEM_SIZE - 1));
+        
+        let mut interpreter = BinaryInterpreter::new();
+        assert_eq!(Some(0), interpreter.read_u16(BINARY_INTERPRETER_MEM_SIZE - 2));
+        assert_eq!(true, interpreter.write_u16(BINARY_INTERPRETER_MEM_SIZE - 2, 30230));
+        assert_eq!(Some(30230), interpreter.read_u16(BINARY_INTERPRETER_MEM_SIZE - 2));
+
+        let mut interpreter = BinaryInterpreter::new();
+        assert_eq!(Some(0), interpreter.read_u32(BINARY_INTERPRETER_MEM_SIZE - 4));
+        assert_eq!(true, interpreter.write_u32(BINARY_INTERPRETER_MEM_SIZE - 4, 30230));
+        assert_eq!(Some(30230), interpreter.read_u32(BINARY_INTERPRETER_MEM_SIZE - 4));
     }
 
     #[test]
-    fn jlzi() {
-        let program: [u32; 9] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 8 * 4),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
-            utils::create_instruction_register_and_immediate(OpCode::JLZI, Register::R0, 5 * 4),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
+    fn sw_edge() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 4 * 4),
+            utils::create_instruction_two_registers(OpCode::SW, Register::R0, Register::R0),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
-            u32::from_le_bytes(i32::to_le_bytes(-1))
+            BINARY_INTERPRETER_MEM_SIZE - 4
         ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(32, vm.read_register_value(Register::R0) as i32);
+        vm.execute_first();
+        assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
     }
 
     #[test]
-    fn jlzi_new_way() {
-        let program: [u32; 7] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, u32::from_le_bytes(i32::to_le_bytes(-1))),
-            utils::create_instruction_register_and_immediate(OpCode::JLZI, Register::R0, 4 * 4),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
+    fn sh_edge() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 4 * 4),
+            utils::create_instruction_two_registers(OpCode::SH, Register::R0, Register::R0),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
+            BINARY_INTERPRETER_MEM_SIZE - 2
         ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(32, vm.read_register_value(Register::R0) as i32);
+        vm.execute_first();
+        assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
     }
 
     #[test]
-    fn jgzi() {
-        let program: [u32; 7] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1),
-            utils::create_instruction_register_and_immediate(OpCode::JGZI, Register::R0, 4 * 4),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 32),
+    fn sb_edge() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 4 * 4),
+            utils::create_instruction_two_registers(OpCode::SB, Register::R0, Register::R0),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
+            BINARY_INTERPRETER_MEM_SIZE - 1
         ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.execute_first();
+        assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
+    }
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(32, vm.read_register_value(Register::R0));
+    #[test]
+    fn sw_out_of_bounds() {
+        for i in 0..3 { // 32-bit = 4-byte
+            let program: [u32; 2] = [
+                utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, BINARY_INTERPRETER_MEM_SIZE as u32 - i),
+                utils::create_instruction_two_registers(OpCode::SW, Register::R0, Register::R0)
+            ];
+            let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+            let mut stdout = std::io::stdout();
+            let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+            vm.execute_first();
+            assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
+        }
     }
 
     #[test]
-    fn and() {
-        let program: [u32; 9] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 7 * 4),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 8 * 4),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R2, Register::R2),
-            utils::create_instruction_two_registers(OpCode::AND, Register::R0, Register::R2),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            0x0000FFFF,
-            0xFFFFA000,
-        ];
+    fn sh_out_of_bounds() {
+        for i in 0..1 { // 16-bit = 2-byte
+            let program: [u32; 2] = [
+                utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, BINARY_INTERPRETER_MEM_SIZE as u32 - i),
+                utils::create_instruction_two_registers(OpCode::SH, Register::R0, Register::R0)
+            ];
+            let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+            let mut stdout = std::io::stdout();
+            let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+            vm.execute_first();
+            assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
+        }
+    }
 
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+    #[test]
+    fn sb_out_of_bounds() {
+        let program: [u32; 2] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, BINARY_INTERPRETER_MEM_SIZE as u32),
+            utils::create_instruction_two_registers(OpCode::SB, Register::R0, Register::R0)
+        ];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(0x0000A000, vm.read_register_value(Register::R0));
+        vm.execute_first();
+        assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
     }
 
     #[test]
-    fn or() {
-        let program: [u32; 9] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 7 * 4),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 8 * 4),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R2, Register::R2),
-            utils::create_instruction_two_registers(OpCode::OR, Register::R0, Register::R2),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            0x00000FFF,
-            0xFFF00000,
+    fn test_no_such_instruction() {
+        let program: [u32; 1] = [
+            0xFF000000
         ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.execute_first();
+        assert_eq!(Error::OpCode as u32, vm.read_register_value(Register::ERR));
+    }
+
+    #[test]
+    fn read_cstr() {
+        let mut interpreter = BinaryInterpreter::new_with_initial(&b"Hello\0world".to_vec()).expect("Expected");
+        assert_eq!(Some(b"Hello".to_vec()), interpreter.read_cstr(0));
+        assert_eq!(Some(Vec::new()), interpreter.read_cstr(5));
+        assert_eq!(Some(Vec::new()), interpreter.read_cstr(11));
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(0xFFF00FFF, vm.read_register_value(Register::R0));
+        // String isn't terminated inside of the memory
+        assert!(interpreter.write_u8(BINARY_INTERPRETER_MEM_SIZE - 1, b'a'));
+        assert_eq!(None, interpreter.read_cstr(BINARY_INTERPRETER_MEM_SIZE - 1));
+        assert_eq!(None, interpreter.read_cstr(BINARY_INTERPRETER_MEM_SIZE));
     }
 
     #[test]
-    fn xor() {
-        let program: [u32; 9] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 7 * 4),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 8 * 4),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R2, Register::R2),
-            utils::create_instruction_two_registers(OpCode::XOR, Register::R0, Register::R2),
+    fn syscall_print_cstr() {
+        let program: [u32; 5] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 4 * 4),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 2),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
-            0x0000FFFF,
-            0xFFFFF000,
+            u32::from_le_bytes(*b"Hi!\0"),
         ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
-        let mut stdout = std::io::stdout();
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut stdout = Vec::new();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-
         assert_eq!(0, vm.execute_first());
-        assert_eq!(0xFFFF0FFF, vm.read_register_value(Register::R0));
+        assert_eq!(1, vm.read_register_value(Register::R0));
+        assert_eq!(b"Hi!", stdout.as_slice());
     }
 
     #[test]
-    fn not() {
-        let program: [u32; 10] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 8 * 4),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 9 * 4),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R2, Register::R2),
-            utils::create_instruction_register(OpCode::NOT, Register::R0),
-            utils::create_instruction_register(OpCode::NOT, Register::R2),
-            LOAD_0_IN_R1_INSTRUCTION,
+    fn stats() {
+        let program: [u32; 8] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 7 * 4),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R2),
+            utils::create_instruction_two_registers(OpCode::SW, Register::R0, Register::R2),
+            utils::create_instruction_register_and_immediate(OpCode::JZI, Register::R0, 0), // Not taken
+            utils::create_instruction_immediate(OpCode::JI, 5 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 0),
             SYSCALLI_EXIT_INSTRUCTION,
-            0x00000000,
-            0xFFFFFFF0,
+            1
         ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-
         assert_eq!(0, vm.execute_first());
-        assert_eq!(0xFFFFFFFF, vm.read_register_value(Register::R0));
-        assert_eq!(0x0000000F, vm.read_register_value(Register::R2));
+        assert_eq!(VmStats { instructions: 7, memory_reads: 1, memory_writes: 1, jumps_taken: 1, syscalls: 1, traps: 0 }, vm.stats());
+
+        vm.reset_stats();
+        assert_eq!(VmStats::default(), vm.stats());
     }
 
     #[test]
-    fn srl() {
-        let program: [u32; 9] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 7 * 4),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 8 * 4),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R2, Register::R2),
-            utils::create_instruction_two_registers(OpCode::SRL, Register::R0, Register::R2),
+    fn syscall_read_counter() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 0),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 3),
             LOAD_0_IN_R1_INSTRUCTION,
             SYSCALLI_EXIT_INSTRUCTION,
-            0x00FFFF00,
-            4,
         ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-
         assert_eq!(0, vm.execute_first());
-        assert_eq!(0x000FFFF0, vm.read_register_value(Register::R0));
+        assert_eq!(2, vm.read_register_value(Register::R0));
+
+        let program: [u32; 2] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 5),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 3),
+        ];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(ERROR_START_NUM + Error::Syscall as u32, vm.execute_first());
     }
 
     #[test]
-    fn sll() {
-        let program: [u32; 9] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 7 * 4),
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 8 * 4),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R2, Register::R2),
-            utils::create_instruction_two_registers(OpCode::SLL, Register::R0, Register::R2),
-            LOAD_0_IN_R1_INSTRUCTION,
+    fn trace() {
+        let program: [u32; 3] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 3),
+            utils::create_instruction_two_registers(OpCode::ADD, Register::R1, Register::R0),
             SYSCALLI_EXIT_INSTRUCTION,
-            0x00FFFF00,
-            4,
         ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
+        let mut trace: Vec<TraceEntry> = Vec::new();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.set_tracer(Some(&mut trace));
+        assert_eq!(3, vm.execute_first());
+        drop(vm);
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(0x0FFFF000, vm.read_register_value(Register::R0));
+        assert_eq!(vec![
+            TraceEntry { ip: 0, instruction: program[0], register_writes: vec![(Register::R0, 3)] },
+            TraceEntry { ip: 4, instruction: program[1], register_writes: vec![(Register::R1, 3)] },
+            TraceEntry { ip: 8, instruction: program[2], register_writes: vec![(Register::RA, 12)] },
+        ], trace);
+        assert_eq!(Some("add $r1, $r0".to_string()), trace[1].mnemonic());
     }
 
     #[test]
-    fn srli() {
-        let program: [u32; 6] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 5 * 4),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
-            utils::create_instruction_register_and_immediate(OpCode::SRLI, Register::R0, 4),
-            LOAD_0_IN_R1_INSTRUCTION,
+    fn write_trace_sink() {
+        let program: [u32; 2] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 3),
             SYSCALLI_EXIT_INSTRUCTION,
-            0x00FFFF00,
         ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
+        let mut sink = WriteTraceSink::new(Vec::new());
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.set_tracer(Some(&mut sink));
+        vm.execute_first();
+        drop(vm);
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(0x000FFFF0, vm.read_register_value(Register::R0));
+        let output = String::from_utf8(sink.writer).expect("Expected");
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with("0x00000000: li $r1, 3"));
+        assert!(lines[0].ends_with(" $r1=3"));
     }
 
     #[test]
-    fn slli() {
-        let program: [u32; 6] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 5 * 4),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
-            utils::create_instruction_register_and_immediate(OpCode::SLLI, Register::R0, 4),
-            LOAD_0_IN_R1_INSTRUCTION,
+    fn write_trace_sink_memory_map() {
+        let program: [u32; 2] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 3),
             SYSCALLI_EXIT_INSTRUCTION,
-            0x00FFFF00,
         ];
-
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Unexpected error!");
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
+        let mut memory_map = MemoryMap::new();
+        memory_map.add_region("main", 0, 8);
+        let mut sink = WriteTraceSink::with_memory_map(Vec::new(), memory_map);
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.set_tracer(Some(&mut sink));
+        vm.execute_first();
+        drop(vm);
 
-        assert_eq!(0, vm.execute_first());
-        assert_eq!(0x0FFFF000, vm.read_register_value(Register::R0));
+        let output = String::from_utf8(sink.writer).expect("Expected");
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with("0x0 (main): li $r1, 3"));
+        assert!(lines[1].starts_with("0x4 (main+0x4): syscalli 0"));
     }
 
     #[test]
-    fn new_with_program_overflow() {
-        let program = vec!(0; BINARY_INTERPRETER_MEM_SIZE as usize + 100);
-        let interpreter = BinaryInterpreter::new_with_program(&program);
-        assert_eq!(None, interpreter, "Should be None");
-    }
+    #[cfg(feature = "assembler")]
+    fn write_trace_sink_line_table() {
+        let source = "main:\n  li $r1, 3\n  syscalli 0";
+        let mut parser = crate::parser::parse_str(source);
+        let output = crate::compiler::compile_output(&mut parser, &crate::compiler::CompileOptions::default()).expect("Expected");
+        let interpreter = BinaryInterpreter::new_with_initial(&output.binary).expect("Expected");
+        let mut stdout = std::io::stdout();
+        let mut sink = WriteTraceSink::new(Vec::new()).line_table(crate::debuginfo::LineTable::new("main.asm", source, &output));
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.set_tracer(Some(&mut sink));
+        vm.execute_first();
+        drop(vm);
 
-    #[test]
-    fn new_with_initial() {
-        let mem: Vec<u8> = vec!(1, 2, 3, 4, 10, 100);
-        let interpreter = BinaryInterpreter::new_with_initial(&mem);
-        assert_ne!(None, interpreter);
-        let interpreter = interpreter.expect("Already checked");
-        for i in 0..(mem.len() as u32) {
-            let read_byte = interpreter.read_u8(i);
-            assert_eq!(Some(mem[i as usize]), read_byte);
-        }
+        let output = String::from_utf8(sink.writer).expect("Expected");
+        let lines: Vec<&str> = output.lines().collect();
+        assert!(lines[0].ends_with(" $r1=3 at main.asm:2:3"));
+        assert!(lines[1].ends_with(" at main.asm:3:3"));
     }
 
     #[test]
-    fn new_with_initial_with_overflow() {
-        let mem: Vec<u8> = vec!(0; BINARY_INTERPRETER_MEM_SIZE as usize + 100);
-        let interpreter = BinaryInterpreter::new_with_initial(&mem);
-        assert_eq!(None, interpreter);
+    fn read_region() {
+        let program: [u32; 4] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 42),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R0, 16),
+            LOAD_0_IN_R1_INSTRUCTION,
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(Ok(vec![vec![Value::U32(42)]]), vm.read_region(&TypedRegion::array(16, FieldType::U32, 1)));
     }
 
     #[test]
-    fn read_u32_out_of_bounds() {
-        let interpreter = BinaryInterpreter::new();
-        assert_eq!(None, interpreter.read_u32(BINARY_INTERPRETER_MEM_SIZE as u32));
-    }
+    fn isa_version() {
+        let program: [u32; 3] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 1),
+            utils::create_instruction_register_and_immediate(OpCode::ADDI, Register::R0, 1),
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.set_isa_version(IsaVersion::V1);
+        assert_eq!(ERROR_START_NUM + Error::OpCode as u32, vm.execute_first());
+        assert_eq!(1, vm.read_register_value(Register::R0));
 
-    #[test]
-    fn read_u16_out_of_bounds() {
-        let interpreter = BinaryInterpreter::new();
-        assert_eq!(None, interpreter.read_u16(BINARY_INTERPRETER_MEM_SIZE as u32));
+        vm.set_isa_version(IsaVersion::V2);
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(2, vm.read_register_value(Register::R0));
     }
 
-    #[test]
-    fn read_u8_out_of_bounds() {
-        let interpreter = BinaryInterpreter::new();
-        assert_eq!(None, interpreter.read_u8(BINARY_INTERPRETER_MEM_SIZE as u32));
+    /// Divides by zero with a vector table at 64. The handler at 20 returns
+    /// the cause in $r1 and continues after the faulting instruction.
+    fn trap_program(handler: u32) -> [u32; 25] {
+        let mut program = [0; 25];
+        program[..10].copy_from_slice(&[
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 64),
+            utils::create_instruction_register_and_immediate(OpCode::MTS, Register::R0, SystemRegister::VectorTable as u32),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 5),
+            utils::create_instruction_register_and_immediate(OpCode::DIVI, Register::R1, 0),
+            SYSCALLI_EXIT_INSTRUCTION,
+            utils::create_instruction_register_and_immediate(OpCode::MFS, Register::R1, SystemRegister::Cause as u32),
+            utils::create_instruction_register_and_immediate(OpCode::MFS, Register::R2, SystemRegister::Epc as u32),
+            utils::create_instruction_register_and_immediate(OpCode::ADDI, Register::R2, 4),
+            utils::create_instruction_register_and_immediate(OpCode::MTS, Register::R2, SystemRegister::Epc as u32),
+            utils::create_instruction(OpCode::RETI),
+        ]);
+        program[16 + Error::DivisorNotZero as usize] = handler;
+        program
     }
 
     #[test]
-    fn write_u32_out_of_bounds() {
-        let mut interpreter = BinaryInterpreter::new();
-        assert_eq!(false, interpreter.write_u32(BINARY_INTERPRETER_MEM_SIZE as u32, 0));
+    fn trap_error() {
+        let interpreter = BinaryInterpreter::new_with_program(&trap_program(5 * 4)).expect("Expected");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(Error::DivisorNotZero as u32, vm.execute_first());
+        assert_eq!(3 * 4 + 4, vm.read_register_value(Register::R2));
+        assert_eq!(Some(0), vm.read_system_register(SystemRegister::Status as u32));
+        assert_eq!(1, vm.stats().traps);
     }
 
     #[test]
-    fn write_u16_out_of_bounds() {
-        let mut interpreter = BinaryInterpreter::new();
-        assert_eq!(false, interpreter.write_u16(BINARY_INTERPRETER_MEM_SIZE as u32, 0));
+    fn trap_without_handler() {
+        let interpreter = BinaryInterpreter::new_with_program(&trap_program(0)).expect("Expected");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(ERROR_START_NUM + Error::DivisorNotZero as u32, vm.execute_first());
+        assert_eq!(3 * 4, vm.read_register_value(Register::IP));
+        assert_eq!(0, vm.stats().traps);
     }
 
     #[test]
-    fn write_u8_out_of_bounds() {
-        let mut interpreter = BinaryInterpreter::new();
-        assert_eq!(false, interpreter.write_u8(BINARY_INTERPRETER_MEM_SIZE as u32, 0));
+    fn trap_in_handler() {
+        // The handler divides by zero again
+        let interpreter = BinaryInterpreter::new_with_program(&trap_program(3 * 4)).expect("Expected");
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(ERROR_START_NUM + Error::DivisorNotZero as u32, vm.execute_first());
+        assert_eq!(Some(STATUS_IN_TRAP), vm.read_system_register(SystemRegister::Status as u32));
+        assert_eq!(1, vm.stats().traps);
     }
 
     #[test]
-    fn execute_out_of_bounds() {
-        let interpreter = BinaryInterpreter::new();
+    fn system_registers() {
+        let program: [u32; 3] = [
+            utils::create_instruction_register_and_immediate(OpCode::MFS, Register::R0, SystemRegister::FaultAddress as u32),
+            utils::create_instruction_register_and_immediate(OpCode::MTS, Register::R0, SystemRegister::FaultAddress as u32),
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute(BINARY_INTERPRETER_MEM_SIZE as u32);
-        assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
+        assert_eq!(ERROR_START_NUM + Error::ReadonlyRegister as u32, vm.execute_first());
+
+        let program: [u32; 2] = [
+            utils::create_instruction_register_and_immediate(OpCode::MFS, Register::R0, 100),
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(ERROR_START_NUM + Error::Register as u32, vm.execute_first());
+
+        let program: [u32; 3] = [
+            utils::create_instruction(OpCode::EI),
+            utils::create_instruction_register_and_immediate(OpCode::MFS, Register::R1, SystemRegister::Status as u32),
+            SYSCALLI_EXIT_INSTRUCTION
+        ];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(STATUS_INTERRUPTS_ENABLED, vm.execute_first());
+        vm.set_isa_version(IsaVersion::V2);
+        assert_eq!(ERROR_START_NUM + Error::OpCode as u32, vm.execute_first());
     }
 
     #[test]
-    fn test_no_such_register() {
-        let program: [u32; 1] = [utils::create_instruction_register(OpCode::J, Register::R0) + 0xF]; // Make sure to annihilate the register
+    fn atomic_operations() {
+        let program: [u32; 9] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 3),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 8 * 4),
+            utils::create_instruction_two_registers(OpCode::AMOADD, Register::R0, Register::R1),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 1),
+            utils::create_instruction_two_registers(OpCode::AMOSWAP, Register::R2, Register::R1),
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R1, 8 * 4),
+            SYSCALLI_EXIT_INSTRUCTION,
+            0,
+            5,
+        ];
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
+        assert_eq!(1, vm.execute_first());
+        assert_eq!(5, vm.read_register_value(Register::R0));
+        assert_eq!(8, vm.read_register_value(Register::R2));
+        assert_eq!(3, vm.stats().memory_reads);
+        assert_eq!(2, vm.stats().memory_writes);
     }
 
     #[test]
-    fn test_no_such_register_write_register() {
-        let program: [u32; 1] = [utils::create_instruction_two_registers(OpCode::CPY, Register::R0, Register::R1) + 0xE]; // Make sure to annihilate the register
+    fn start_hart() {
+        let program: [u32; 8] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 6 * 4),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 4),
+            utils::create_instruction_two_registers(OpCode::CPY, Register::R5, Register::R0),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 4), // No stopped hart left
+            utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R5),
+            SYSCALLI_EXIT_INSTRUCTION,
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 5), // Hart 1
+            utils::create_instruction_immediate(OpCode::JI, 7 * 4),
+        ];
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
+        assert_eq!(0, vm.execute_first());
+
+        vm.set_hart_count(2);
+        assert_eq!(2, vm.get_hart_count());
+        assert_eq!(1, vm.execute_first());
+        assert_eq!(0, vm.read_register_value(Register::R0));
+        assert_eq!(0, vm.get_hart_id());
+
+        vm.start(0);
+        vm.step();
+        vm.step();
+        assert_eq!(1, vm.get_hart_id());
+        assert_eq!(6 * 4, vm.read_register_value(Register::IP));
+        vm.step();
+        assert_eq!(0, vm.get_hart_id());
+        assert!(vm.is_running());
+    }
+
+    #[test]
+    fn decode_cache_self_modifying() {
+        let program = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 2),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 1),
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R3, 10 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R3, 4), // Replaces the 2nd instruction
+            utils::create_instruction_register_and_immediate(OpCode::SUBI, Register::R2, 1),
+            utils::create_instruction_register_and_immediate(OpCode::JNZI, Register::R2, 4),
+            SYSCALLI_EXIT_INSTRUCTION,
+            0, 0, 0,
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 7),
+        ];
 
-        // test binary_register_operation_write0
-        let program: [u32; 1] = [
-            utils::create_instruction_two_registers(OpCode::CPY, Register::R0, Register::R1)
-                + utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R0) * 0xF // sophisticated bs
-        ]; // Make sure to annihilate the register
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
+        assert!(!vm.get_decode_cache());
+        vm.set_decode_cache(true);
+        assert!(vm.get_decode_cache());
+        assert_eq!(7, vm.execute_first());
+        assert_eq!(12, vm.stats().instructions);
+
+        // The reset restores the program
+        vm.reset_full();
+        assert_eq!(7, vm.execute_first());
     }
 
     #[test]
-    fn test_no_such_register_biop_write() {
-        let program: [u32; 1] = [utils::create_instruction_two_registers(OpCode::ADD, Register::R0, Register::R1) + 0xE]; // Make sure to annihilate the register
+    fn decode_cache_isa_version() {
+        let program = [
+            utils::create_instruction(OpCode::EI),
+            SYSCALLI_EXIT_INSTRUCTION,
+        ];
+
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
+        vm.set_decode_cache(true);
+        assert_eq!(0, vm.execute_first());
 
-        // test binary_register_operation_write0
-        let program: [u32; 1] = [
-            utils::create_instruction_two_registers(OpCode::ADD, Register::R0, Register::R1)
-                + utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R0) * 0xF // sophisticated bs
-        ]; // Make sure to annihilate the register
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        vm.set_isa_version(IsaVersion::V2);
+        assert_eq!(ERROR_START_NUM + Error::OpCode as u32, vm.execute_first());
+    }
+
+    fn snapshot_program() -> [u32; 11] {
+        [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 5),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R0, 10 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::ADDI, Register::R0, 1),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R0, 10 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R1, 10 * 4),
+            SYSCALLI_EXIT_INSTRUCTION,
+            0, 0, 0, 0,
+            0,
+        ]
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let interpreter = BinaryInterpreter::new_with_program(&snapshot_program()).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
+        vm.start(0);
+        assert!(vm.step());
+        assert!(vm.step());
 
-        let program: [u32; 1] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 123)
-                + utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R0) * 0xF // sophisticated bs
-        ]; // Make sure to annihilate the register
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let snapshot = vm.snapshot();
+        assert_eq!(2 * 4, snapshot.read_register_value(Register::IP));
+        assert_eq!(2, snapshot.stats().instructions);
+        assert_eq!(5, snapshot.memory()[10 * 4]);
+
+        while vm.step() {}
+        assert_eq!(6, vm.get_result());
+        assert_eq!(Some(6), vm.get_interpreter().read_u32(10 * 4));
+
+        assert!(vm.restore(&snapshot));
+        assert!(vm.is_running());
+        assert_eq!(5, vm.read_register_value(Register::R0));
+        assert_eq!(Some(5), vm.get_interpreter().read_u32(10 * 4));
+        assert_eq!(2, vm.stats().instructions);
+        assert_eq!(snapshot, vm.snapshot());
+
+        while vm.step() {}
+        assert_eq!(6, vm.get_result());
+    }
+
+    #[test]
+    fn restore_invalid_snapshot() {
+        let interpreter = BinaryInterpreter::new_with_program(&snapshot_program()).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
+        let mut snapshot = vm.snapshot();
+        snapshot.memory.pop();
+        assert!(!vm.restore(&snapshot));
+    }
 
-        // test binary_register_and_immediate_operation_write0
-        let program: [u32; 1] = [
-            utils::create_instruction_register_and_immediate(OpCode::SRLI, Register::R0, 123)
-                + utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R0) * 0xF // sophisticated bs
-        ]; // Make sure to annihilate the register
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshot_to_bytes() {
+        let interpreter = BinaryInterpreter::new_with_program(&snapshot_program()).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
+        vm.start(0);
+        assert!(vm.step());
+
+        let snapshot = vm.snapshot();
+        assert_eq!(Some(snapshot.clone()), super::VmSnapshot::from_bytes(&snapshot.to_bytes()));
+        assert_eq!(None, super::VmSnapshot::from_bytes(&[1, 2, 3]));
     }
 
     #[test]
-    fn test_no_such_register_check_write_ip() {
-        let program: [u32; 1] = [
-            utils::create_instruction_register_and_immediate(OpCode::JGZI, Register::R0, 4)
-                + utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R0) * 0xF // sophisticated bs
-        ]; // Make sure to annihilate the register
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+    fn reset_full() {
+        let interpreter = BinaryInterpreter::new_with_program(&snapshot_program()).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
+        vm.start(0);
+        assert!(vm.step());
+        assert!(vm.step());
+        vm.write_register_value(Register::SP, 4);
+
+        vm.reset_full();
+        assert!(!vm.is_running());
+        assert_eq!(BINARY_INTERPRETER_MEM_SIZE, vm.read_register_value(Register::SP));
+        assert_eq!(0, vm.read_register_value(Register::R0));
+        assert_eq!(Some(0), vm.get_interpreter().read_u32(10 * 4));
+        assert_eq!(Some(snapshot_program()[0]), vm.get_interpreter().read_u32(0));
+        assert_eq!(VmStats::default(), vm.stats());
+        assert_eq!(6, vm.execute_first());
     }
 
     #[test]
-    fn test_no_such_register_not() {
-        let program: [u32; 1] = [
-            utils::create_instruction_register(OpCode::NOT, Register::R0) + 0xF
-        ]; // Make sure to annihilate the register
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+    fn load_program() {
+        let interpreter = BinaryInterpreter::new_with_program(&snapshot_program()).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::Register as u32, vm.read_register_value(Register::ERR));
+        assert_eq!(6, vm.execute_first());
+
+        let program: Vec<u8> = [
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R1, 10 * 4),
+            SYSCALLI_EXIT_INSTRUCTION,
+        ].iter().flat_map(|instruction| instruction.to_le_bytes()).collect();
+        assert!(vm.load_program(&program));
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(2, vm.stats().instructions);
+
+        assert!(!vm.load_program(&vec![0; BINARY_INTERPRETER_MEM_SIZE as usize + 1]));
     }
 
     #[test]
-    fn test_cannot_write_register() {
-        let program: [u32; 1] = [utils::create_instruction_two_registers(OpCode::CPY, Register::IP, Register::R0)];
-        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+    fn run_result() {
         let mut stdout = std::io::stdout();
+        let interpreter = BinaryInterpreter::new_with_program(&[LOAD_0_IN_R1_INSTRUCTION, SYSCALLI_EXIT_INSTRUCTION]).expect("Expected");
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::ReadonlyRegister as u32, vm.read_register_value(Register::ERR));
+        assert_eq!(Ok(ExitStatus { code: 0 }), vm.run(0));
 
-        let program: [u32; 1] = [utils::create_instruction_two_registers(OpCode::CPY, Register::ERR, Register::R0)];
+        let program: [u32; 3] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 0x7FFFF),
+            utils::create_instruction_register_and_immediate(OpCode::MULI, Register::R0, 16),
+            utils::create_instruction_two_registers(OpCode::LW, Register::R1, Register::R0),
+        ];
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
-        let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::ReadonlyRegister as u32, vm.read_register_value(Register::ERR));
+        let err = vm.run(0).expect_err("Out-of-bounds load");
+        assert_eq!(VmError { kind: Error::Memory, ip: 2 * 4, address: Some(0x7FFFF0) }, err);
+        assert_eq!("Memory at 0x8 (address 0x7FFFF0)", err.to_string());
+        assert_eq!(ERROR_START_NUM + Error::Memory as u32, vm.get_result());
+
+        let interpreter = BinaryInterpreter::new_with_program(&[utils::create_instruction_immediate(OpCode::SYSCALLI, 0xFF)]).expect("Expected");
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(Err(VmError { kind: Error::Syscall, ip: 0, address: None }), vm.run(0));
     }
 
     #[test]
-    fn test_can_write_registers() {
-        for register in [Register::R0, Register::R1, Register::R2, Register::R3, Register::R4, Register::R5, Register::R6, Register::R7, Register::RA, Register::SP] {
-            let program: [u32; 3] = [
-                utils::create_instruction_two_registers(OpCode::CPY, register, Register::IP),
-                LOAD_0_IN_R1_INSTRUCTION,
-                SYSCALLI_EXIT_INSTRUCTION
-            ];
-            let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
-            let mut stdout = std::io::stdout();
-            let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-            vm.execute_first();
-            assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
+    fn syscall_streams() {
+        const STRING: &[u8] = b"Hi";
+        let program: [u32; 10] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 12 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 10),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 6), // Read "Hi"
+            utils::create_instruction_two_registers(OpCode::CPY, Register::R2, Register::R0),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 7), // Print "Hi" to stderr
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 1),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 6), // End of input
+            utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R0),
+            SYSCALLI_EXIT_INSTRUCTION,
+            0,
+        ];
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut stdin = STRING;
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let mut vm = BinaryVirtualMachine::with_streams(interpreter, &mut stdin, &mut stdout, &mut stderr);
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(STRING, stdout.as_slice());
+        assert_eq!(STRING, stderr.as_slice());
+
+        // Without streams
+        let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut stdout = Vec::new();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(0, vm.execute_first());
+        assert!(stdout.is_empty());
+    }
+
+    /// Input stream without data for the first reads
+    struct SlowInput {
+        would_block: u32,
+        data: &'static [u8],
+    }
+
+    impl std::io::Read for SlowInput {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.would_block > 0 {
+                self.would_block -= 1;
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+
+            self.data.read(buf)
         }
     }
 
     #[test]
-    fn test_invalid_syscall() {
-        let program: [u32; 1] = [utils::create_instruction_immediate(OpCode::SYSCALLI, 0xFF)];
+    fn run_slice() {
+        let program: [u32; 8] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 8 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 4),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 6),
+            utils::create_instruction_register_and_immediate(OpCode::LBI, Register::R1, 8 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::ADDI, Register::R1, 1),
+            SYSCALLI_EXIT_INSTRUCTION,
+            0, 0,
+        ];
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
-        let mut stdout = std::io::stdout();
-        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::Syscall as u32, vm.read_register_value(Register::ERR));
+        let mut stdin = SlowInput { would_block: 2, data: b"A" };
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        let mut vm = BinaryVirtualMachine::with_streams(interpreter, &mut stdin, &mut stdout, &mut stderr);
+        vm.start(0);
+        assert_eq!(SliceStatus::Yielded, vm.run_slice(1));
+        assert_eq!(SliceStatus::Pending, vm.run_slice(100));
+        assert_eq!(2 * 4, vm.read_register_value(Register::IP));
+        assert_eq!(SliceStatus::Pending, vm.run_slice(100));
+        assert_eq!(SliceStatus::Finished(Ok(ExitStatus { code: b'A' as u32 + 1 })), vm.run_slice(100));
     }
 
     #[test]
-    fn lw_edge() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 4 * 4),
-            utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0),
-            LOAD_0_IN_R1_INSTRUCTION,
+    fn execute_async() {
+        let program = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 30000),
+            utils::create_instruction_register_and_immediate(OpCode::SUBI, Register::R0, 1),
+            utils::create_instruction_register_and_immediate(OpCode::JNZI, Register::R0, 4),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 5),
             SYSCALLI_EXIT_INSTRUCTION,
-            BINARY_INTERPRETER_MEM_SIZE - 4
         ];
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
+
+        let mut future = std::pin::pin!(vm.execute_async(0));
+        let mut context = std::task::Context::from_waker(std::task::Waker::noop());
+        let mut polls = 1;
+        let result = loop {
+            match std::future::Future::poll(future.as_mut(), &mut context) {
+                std::task::Poll::Ready(result) => break result,
+                std::task::Poll::Pending => polls += 1,
+            }
+        };
+
+        assert_eq!(Ok(ExitStatus { code: 5 }), result);
+        assert!(polls > 1);
     }
 
     #[test]
-    fn lh_edge() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 4 * 4),
-            utils::create_instruction_two_registers(OpCode::LH, Register::R0, Register::R0),
-            LOAD_0_IN_R1_INSTRUCTION,
-            SYSCALLI_EXIT_INSTRUCTION,
-            BINARY_INTERPRETER_MEM_SIZE - 2
-        ];
+    fn read_memory() {
+        let program = [utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 10), 0x64636261];
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
+        let vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(Some(&b"abcd"[..]), vm.read_memory(4..8).as_deref());
+        assert_eq!(Some(&[][..]), vm.read_memory(8..8).as_deref());
+        assert_eq!(None, vm.read_memory(BINARY_INTERPRETER_MEM_SIZE - 2..BINARY_INTERPRETER_MEM_SIZE + 2));
+        assert_eq!(Some("00000004  61 62 63 64                                       |abcd|\n".to_string()), vm.hexdump(4..8));
+    }
+
+    #[test]
+    fn host_memory_access() {
+        let interpreter = BinaryInterpreter::new_with_program(&[SYSCALLI_EXIT_INSTRUCTION]).expect("Expected");
+        let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
+
+        assert_eq!(Ok(()), vm.write_bytes(0x100, b"abc\0"));
+        assert_eq!(Ok(b"abc\0".to_vec()), vm.read_bytes(0x100, 4));
+        assert_eq!(Ok(b"abc".to_vec()), vm.read_cstr(0x100));
+        assert_eq!(Ok(()), vm.write_u32(0x104, 0x12345678));
+        assert_eq!(Ok(0x12345678), vm.read_u32(0x104));
+        assert_eq!(Ok(vec![0x78, 0x56]), vm.read_bytes(0x104, 2));
+
+        let end = BINARY_INTERPRETER_MEM_SIZE;
+        let err = VmError { kind: Error::Memory, ip: 0, address: Some(end) };
+        assert_eq!(Err(err), vm.read_bytes(end - 2, 4));
+        assert_eq!(Err(err), vm.write_bytes(end - 1, b"ab"));
+        assert_eq!(Ok(vec![b'a']), vm.read_bytes(end - 1, 1));
+        assert_eq!(Err(err), vm.read_cstr(end - 1));
+        assert_eq!(Err(VmError { kind: Error::Memory, ip: 0, address: Some(end - 2) }), vm.read_u32(end - 2));
+        assert_eq!(Err(VmError { kind: Error::Memory, ip: 0, address: Some(end - 2) }), vm.write_u32(end - 2, 0));
     }
 
     #[test]
-    fn lb_edge() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 4 * 4),
-            utils::create_instruction_two_registers(OpCode::LB, Register::R0, Register::R0),
-            LOAD_0_IN_R1_INSTRUCTION,
+    fn write_xor_execute() {
+        // Copies the first instruction behind the program and jumps there
+        let program = [
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 0),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R0, 4 * 4),
+            utils::create_instruction_immediate(OpCode::JI, 4 * 4),
+            SYSCALLI_EXIT_INSTRUCTION,
+            0,
             SYSCALLI_EXIT_INSTRUCTION,
-            BINARY_INTERPRETER_MEM_SIZE - 1
         ];
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
-    }
+        vm.set_write_protected(Some(0..4 * 4));
+        assert_eq!(0, vm.execute_first());
+        assert!(!vm.get_write_xor_execute());
+        vm.set_write_xor_execute(true);
+        assert!(vm.get_write_xor_execute());
+        assert_eq!(Err(VmError { kind: Error::ExecuteProtected, ip: 4 * 4, address: None }), vm.run(0));
 
-    #[test]
-    fn lw_out_of_bounds() {
-        for i in 0..3 { // 32-bit = 4-byte
-            let program: [u32; 2] = [
-                utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, BINARY_INTERPRETER_MEM_SIZE as u32 - i),
-                utils::create_instruction_two_registers(OpCode::LW, Register::R0, Register::R0)
-            ];
-            let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
-            let mut stdout = std::io::stdout();
-            let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-            vm.execute_first();
-            assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
-        }
+        // Without the write-protected range nothing is executable
+        vm.set_write_protected(None);
+        assert_eq!(Err(VmError { kind: Error::ExecuteProtected, ip: 0, address: None }), vm.run(0));
     }
 
     #[test]
-    fn lh_out_of_bounds() {
-        for i in 0..1 { // 16-bit = 2-byte
-            let program: [u32; 2] = [
-                utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, BINARY_INTERPRETER_MEM_SIZE as u32 - i),
-                utils::create_instruction_two_registers(OpCode::LH, Register::R0, Register::R0)
-            ];
-            let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
-            let mut stdout = std::io::stdout();
-            let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-            vm.execute_first();
-            assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
-        }
+    fn write_xor_execute_mmu() {
+        let program = [
+            utils::create_instruction_immediate(OpCode::JI, 0x1000),
+        ];
+        let mut interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        let mut page_table = PageTable::new();
+        page_table.map(0, 0, false);
+        page_table.map(1, 1, true);
+        interpreter.set_page_table(Some(page_table));
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.set_write_xor_execute(true);
+        assert_eq!(Err(VmError { kind: Error::ExecuteProtected, ip: 0x1000, address: None }), vm.run(0));
     }
 
     #[test]
-    fn lb_out_of_bounds() {
-        let program: [u32; 2] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, BINARY_INTERPRETER_MEM_SIZE as u32),
-            utils::create_instruction_two_registers(OpCode::LB, Register::R0, Register::R0)
+    fn invalid_opcode_policy() {
+        let program = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 5 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::MTS, Register::R0, SystemRegister::VectorTable as u32),
+            0xFF000000,
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 2),
+            SYSCALLI_EXIT_INSTRUCTION,
+            0, // Vector table
+            7 * 4,
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 3), // handler:
+            SYSCALLI_EXIT_INSTRUCTION,
         ];
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
-    }
+        assert_eq!(InvalidOpcodePolicy::Trap, vm.get_invalid_opcode_policy());
+        assert_eq!(3, vm.execute_first());
 
-    #[test]
-    fn edge_binary_interpreter() {
-        let mut interpreter = BinaryInterpreter::new();
-        assert_eq!(Some(0), interpreter.read_u8(BINARY_INTERPRETER_MEM_SIZE - 1));
-        assert_eq!(true, interpreter.write_u8(BINARY_INTERPRETER_MEM_SIZE - 1, 128 as u8));
-        assert_eq!(Some(128), interpreter.read_u8(BINARY_INTERPRETER_MEM_SIZE - 1));
-        
-        let mut interpreter = BinaryInterpreter::new();
-        assert_eq!(Some(0), interpreter.read_u16(BINARY_INTERPRETER_MEM_SIZE - 2));
-        assert_eq!(true, interpreter.write_u16(BINARY_INTERPRETER_MEM_SIZE - 2, 30230));
-        assert_eq!(Some(30230), interpreter.read_u16(BINARY_INTERPRETER_MEM_SIZE - 2));
+        vm.set_invalid_opcode_policy(InvalidOpcodePolicy::Stop);
+        assert_eq!(Err(VmError { kind: Error::OpCode, ip: 2 * 4, address: None }), vm.run(0));
+        assert_eq!(1, vm.stats().traps); // Only the first execution trapped
 
-        let mut interpreter = BinaryInterpreter::new();
-        assert_eq!(Some(0), interpreter.read_u32(BINARY_INTERPRETER_MEM_SIZE - 4));
-        assert_eq!(true, interpreter.write_u32(BINARY_INTERPRETER_MEM_SIZE - 4, 30230));
-        assert_eq!(Some(30230), interpreter.read_u32(BINARY_INTERPRETER_MEM_SIZE - 4));
+        vm.set_invalid_opcode_policy(InvalidOpcodePolicy::Skip);
+        assert_eq!(2, vm.execute_first());
     }
 
     #[test]
-    fn sw_edge() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 4 * 4),
-            utils::create_instruction_two_registers(OpCode::SW, Register::R0, Register::R0),
-            LOAD_0_IN_R1_INSTRUCTION,
+    fn allowed_syscalls() {
+        let program = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 0),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 5),
             SYSCALLI_EXIT_INSTRUCTION,
-            BINARY_INTERPRETER_MEM_SIZE - 4
         ];
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
+        assert_eq!(None, vm.get_allowed_syscalls());
+        assert_eq!(0, vm.execute_first());
+
+        vm.set_allowed_syscalls(Some(vec![0]));
+        assert_eq!(Some(&[0][..]), vm.get_allowed_syscalls());
+        assert_eq!(Err(VmError { kind: Error::Syscall, ip: 4, address: None }), vm.run(0));
+
+        vm.set_allowed_syscalls(Some(vec![0, 5]));
+        assert_eq!(0, vm.execute_first());
     }
 
     #[test]
-    fn sh_edge() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 4 * 4),
-            utils::create_instruction_two_registers(OpCode::SH, Register::R0, Register::R0),
-            LOAD_0_IN_R1_INSTRUCTION,
+    fn host_functions() {
+        let program = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 3),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, 4),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, HOST_SYSCALL_START + 1),
+            utils::create_instruction_two_registers(OpCode::CPY, Register::R1, Register::R0),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, HOST_SYSCALL_START),
             SYSCALLI_EXIT_INSTRUCTION,
-            BINARY_INTERPRETER_MEM_SIZE - 2
         ];
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
-        let mut stdout = std::io::stdout();
+        let mut stdout = std::io::sink();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
+
+        // Not linked
+        assert_eq!(Err(VmError { kind: Error::Syscall, ip: 2 * 4, address: None }), vm.run(0));
+
+        let pixels = Rc::new(RefCell::new(Vec::new()));
+        let drawn = pixels.clone();
+        vm.bind("draw_pixel", move |x: u32, y: u32| drawn.borrow_mut().push((x, y)));
+        assert_eq!(Err(vec!["mul".to_string()]), vm.link_imports(&["draw_pixel".to_string(), "mul".to_string()]));
+
+        vm.bind("mul", |a: u32, b: u32| a * b);
+        assert_eq!(Ok(()), vm.link_imports(&["draw_pixel".to_string(), "mul".to_string()]));
+        assert_eq!(12, vm.execute_first());
+        assert_eq!(vec![(12, 4)], *pixels.borrow());
+
+        // Restricted like the builtin syscalls
+        vm.set_allowed_syscalls(Some(vec![0, HOST_SYSCALL_START]));
+        assert_eq!(Err(VmError { kind: Error::Syscall, ip: 2 * 4, address: None }), vm.run(0));
     }
 
     #[test]
-    fn sb_edge() {
-        let program: [u32; 5] = [
-            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R0, 4 * 4),
-            utils::create_instruction_two_registers(OpCode::SB, Register::R0, Register::R0),
-            LOAD_0_IN_R1_INSTRUCTION,
+    fn opcode_profile() {
+        let program = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 3),
+            utils::create_instruction_register_and_immediate(OpCode::SUBI, Register::R0, 1),
+            utils::create_instruction_register_and_immediate(OpCode::JNZI, Register::R0, 4),
             SYSCALLI_EXIT_INSTRUCTION,
-            BINARY_INTERPRETER_MEM_SIZE - 1
         ];
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::NoError as u32, vm.read_register_value(Register::ERR));
-    }
+        assert_eq!(None, vm.opcode_profile());
 
-    #[test]
-    fn sw_out_of_bounds() {
-        for i in 0..3 { // 32-bit = 4-byte
-            let program: [u32; 2] = [
-                utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, BINARY_INTERPRETER_MEM_SIZE as u32 - i),
-                utils::create_instruction_two_registers(OpCode::SW, Register::R0, Register::R0)
-            ];
-            let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
-            let mut stdout = std::io::stdout();
-            let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-            vm.execute_first();
-            assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
-        }
-    }
+        vm.set_opcode_profiling(true);
+        assert_eq!(0, vm.execute_first());
+        let profile = vm.opcode_profile().expect("Expected");
+        assert_eq!(4, profile.len());
+        assert_eq!(Some(&1), profile.get(&OpCode::LI));
+        assert_eq!(Some(&3), profile.get(&OpCode::SUBI));
+        assert_eq!(Some(&3), profile.get(&OpCode::JNZI));
+        assert_eq!(Some(&1), profile.get(&OpCode::SYSCALLI));
 
-    #[test]
-    fn sh_out_of_bounds() {
-        for i in 0..1 { // 16-bit = 2-byte
-            let program: [u32; 2] = [
-                utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, BINARY_INTERPRETER_MEM_SIZE as u32 - i),
-                utils::create_instruction_two_registers(OpCode::SH, Register::R0, Register::R0)
-            ];
-            let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
-            let mut stdout = std::io::stdout();
-            let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-            vm.execute_first();
-            assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
-        }
+        // Enabling again resets the counts
+        vm.set_opcode_profiling(true);
+        assert_eq!(Some(0), vm.opcode_profile().map(|profile| profile.len()));
     }
 
     #[test]
-    fn sb_out_of_bounds() {
-        let program: [u32; 2] = [
-            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, BINARY_INTERPRETER_MEM_SIZE as u32),
-            utils::create_instruction_two_registers(OpCode::SB, Register::R0, Register::R0)
+    fn hot_addresses() {
+        let program = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 3),
+            utils::create_instruction_register_and_immediate(OpCode::SUBI, Register::R0, 1),
+            utils::create_instruction_register_and_immediate(OpCode::JNZI, Register::R0, 4),
+            SYSCALLI_EXIT_INSTRUCTION,
         ];
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::Memory as u32, vm.read_register_value(Register::ERR));
+        assert_eq!(None, vm.hot_addresses(1));
+
+        vm.set_address_profiling(true);
+        assert_eq!(0, vm.execute_first());
+        assert_eq!(Some(vec![(4, 3), (8, 3), (0, 1), (12, 1)]), vm.hot_addresses(10));
+        assert_eq!(Some(vec![(4, 3)]), vm.hot_addresses(1));
+        assert_eq!(Some(Vec::new()), vm.hot_addresses(0));
     }
 
     #[test]
-    fn test_no_such_instruction() {
-        let program: [u32; 1] = [
-            0xFF000000
+    fn coverage() {
+        let program = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 0),
+            utils::create_instruction_register_and_immediate(OpCode::JZI, Register::R0, 12),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R1, 1), // Never executed
+            SYSCALLI_EXIT_INSTRUCTION,
         ];
         let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
         let mut stdout = std::io::stdout();
         let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
-        vm.execute_first();
-        assert_eq!(Error::OpCode as u32, vm.read_register_value(Register::ERR));
+        assert_eq!(None, vm.coverage());
+
+        vm.set_coverage(true);
+        assert_eq!(0, vm.execute_first());
+        let coverage = vm.coverage().expect("Expected");
+        assert_eq!(3, coverage.len());
+        assert!(coverage.contains(&0));
+        assert!(coverage.contains(&4));
+        assert!(!coverage.contains(&8));
+        assert!(coverage.contains(&12));
     }
 
     #[test]
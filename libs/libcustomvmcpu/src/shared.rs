@@ -0,0 +1,439 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Memory shared with the host: a buffer of the embedder is mapped into an
+//! address window of the program. The host reads and writes the buffer
+//! directly instead of copying the data with `read_bytes`/`write_bytes`.
+
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use super::mmu::PageFault;
+use super::runtime::{BinaryInterpreter, Interpreter, INSTRUCTION_SIZE};
+
+/// Buffer, which can be shared with a program. Offsets are relative to the
+/// start of the buffer.
+pub trait SharedMemory {
+    /// Size of the buffer in bytes
+    fn size(&self) -> usize;
+
+    /// Fill `buffer` with the bytes at `offset`. Returns false, if the range
+    /// is outside of the buffer.
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> bool;
+
+    /// Write `data` at `offset`. Returns false, if the range is outside of
+    /// the buffer.
+    fn write(&mut self, offset: usize, data: &[u8]) -> bool;
+}
+
+fn read_slice(memory: &[u8], offset: usize, buffer: &mut [u8]) -> bool {
+    match memory.get(offset..offset.saturating_add(buffer.len())) {
+        Some(bytes) => {
+            buffer.copy_from_slice(bytes);
+            true
+        },
+        None => false,
+    }
+}
+
+fn write_slice(memory: &mut [u8], offset: usize, data: &[u8]) -> bool {
+    match memory.get_mut(offset..offset.saturating_add(data.len())) {
+        Some(bytes) => {
+            bytes.copy_from_slice(data);
+            true
+        },
+        None => false,
+    }
+}
+
+impl SharedMemory for &mut [u8] {
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> bool {
+        read_slice(self, offset, buffer)
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> bool {
+        write_slice(self, offset, data)
+    }
+}
+
+impl SharedMemory for Vec<u8> {
+    fn size(&self) -> usize {
+        self.len()
+    }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> bool {
+        read_slice(self, offset, buffer)
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> bool {
+        write_slice(self, offset, data)
+    }
+}
+
+/// Shared with other threads. The lock is held for a single access, a
+/// poisoned lock is ignored.
+impl SharedMemory for Arc<Mutex<Vec<u8>>> {
+    fn size(&self) -> usize {
+        self.lock().unwrap_or_else(|err| err.into_inner()).len()
+    }
+
+    fn read(&self, offset: usize, buffer: &mut [u8]) -> bool {
+        read_slice(&self.lock().unwrap_or_else(|err| err.into_inner()), offset, buffer)
+    }
+
+    fn write(&mut self, offset: usize, data: &[u8]) -> bool {
+        write_slice(&mut self.lock().unwrap_or_else(|err| err.into_inner()), offset, data)
+    }
+}
+
+/// Interpreter, which maps `shared` into the address window starting at
+/// `start`. Accesses to the window go to the shared memory, all other
+/// accesses to the wrapped interpreter. The window may lie behind the memory
+/// of the wrapped interpreter or hide a part of it. The size of the window is
+/// the size of the shared memory, when it was mapped (see `set_window_size`).
+#[derive(Debug)]
+pub struct SharedMemoryInterpreter<M: SharedMemory, InterpreterImpl: Interpreter = BinaryInterpreter> {
+    inner: InterpreterImpl,
+    start: u32,
+    shared: M,
+    /// Size of the window in bytes
+    size: usize,
+}
+
+impl<M: SharedMemory, InterpreterImpl: Interpreter> SharedMemoryInterpreter<M, InterpreterImpl> {
+    /// Maps `shared` at `start` into `inner`. Returns None, if the window
+    /// doesn't fit into the 32-bit address space.
+    pub fn new(inner: InterpreterImpl, start: u32, shared: M) -> Option<SharedMemoryInterpreter<M, InterpreterImpl>> {
+        let size = shared.size();
+        if start as u64 + size as u64 > u32::MAX as u64 + 1 {
+            return None;
+        }
+
+        Some(SharedMemoryInterpreter { inner, start, shared, size })
+    }
+
+    /// Addresses of the shared memory
+    pub fn window(&self) -> Range<u32> {
+        self.start..(self.start as u64 + self.size as u64).min(u32::MAX as u64) as u32
+    }
+
+    /// Changes the size of the window (like after the host resized the
+    /// shared memory). Accesses to the window behind the end of the shared
+    /// memory fail. Returns false, if the window doesn't fit into the 32-bit
+    /// address space.
+    pub fn set_window_size(&mut self, size: usize) -> bool {
+        if self.start as u64 + size as u64 > u32::MAX as u64 + 1 {
+            return false;
+        }
+
+        self.size = size;
+        true
+    }
+
+    pub fn shared(&self) -> &M {
+        &self.shared
+    }
+
+    pub fn shared_mut(&mut self) -> &mut M {
+        &mut self.shared
+    }
+
+    pub fn inner(&self) -> &InterpreterImpl {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut InterpreterImpl {
+        &mut self.inner
+    }
+
+    /// Returns the wrapped interpreter and the shared memory
+    pub fn into_parts(self) -> (InterpreterImpl, M) {
+        (self.inner, self.shared)
+    }
+
+    /// Check if the range of `len` bytes at `pos` overlaps the window
+    fn overlaps(&self, pos: u32, len: u32) -> bool {
+        (pos as u64) < self.start as u64 + self.size as u64
+            && pos as u64 + len as u64 > self.start as u64
+    }
+
+    /// Reads the bytes inside of the window from the shared memory and all
+    /// other bytes from the wrapped interpreter
+    fn read_into(&self, pos: u32, buffer: &mut [u8]) -> bool {
+        if pos >= self.start && (pos - self.start) as u64 + buffer.len() as u64 <= self.size as u64 {
+            return self.shared.read((pos - self.start) as usize, buffer);
+        }
+
+        buffer.iter_mut().enumerate().all(|(i, byte)| {
+            let pos = match pos.checked_add(i as u32) {
+                Some(pos) => pos,
+                None => return false,
+            };
+
+            let value = if self.overlaps(pos, 1) {
+                let mut value = [0];
+                self.shared.read((pos - self.start) as usize, &mut value).then_some(value[0])
+            }
+            else {
+                self.inner.read_u8(pos)
+            };
+
+            value.map(|value| *byte = value).is_some()
+        })
+    }
+
+    /// Writes the bytes inside of the window to the shared memory and all
+    /// other bytes to the wrapped interpreter
+    fn write_from(&mut self, pos: u32, data: &[u8]) -> bool {
+        if pos >= self.start && (pos - self.start) as u64 + data.len() as u64 <= self.size as u64 {
+            return self.shared.write((pos - self.start) as usize, data);
+        }
+
+        data.iter().enumerate().all(|(i, byte)| {
+            let pos = match pos.checked_add(i as u32) {
+                Some(pos) => pos,
+                None => return false,
+            };
+
+            if self.overlaps(pos, 1) {
+                self.shared.write((pos - self.start) as usize, &[*byte])
+            }
+            else {
+                self.inner.write_u8(pos, *byte)
+            }
+        })
+    }
+
+    fn read_array<const N: usize>(&self, pos: u32) -> Option<[u8; N]> {
+        let mut result = [0; N];
+        self.read_into(pos, &mut result).then_some(result)
+    }
+}
+
+impl<M: SharedMemory, InterpreterImpl: Interpreter> Interpreter for SharedMemoryInterpreter<M, InterpreterImpl> {
+    fn read_u32(&self, pos: u32) -> Option<u32> {
+        if !self.overlaps(pos, 4) {
+            return self.inner.read_u32(pos);
+        }
+
+        self.read_array(pos).map(u32::from_le_bytes)
+    }
+
+    fn write_u32(&mut self, pos: u32, value: u32) -> bool {
+        if !self.overlaps(pos, 4) {
+            return self.inner.write_u32(pos, value);
+        }
+
+        self.write_from(pos, &value.to_le_bytes())
+    }
+
+    fn read_u16(&self, pos: u32) -> Option<u16> {
+        if !self.overlaps(pos, 2) {
+            return self.inner.read_u16(pos);
+        }
+
+        self.read_array(pos).map(u16::from_le_bytes)
+    }
+
+    fn write_u16(&mut self, pos: u32, value: u16) -> bool {
+        if !self.overlaps(pos, 2) {
+            return self.inner.write_u16(pos, value);
+        }
+
+        self.write_from(pos, &value.to_le_bytes())
+    }
+
+    fn read_u8(&self, pos: u32) -> Option<u8> {
+        if !self.overlaps(pos, 1) {
+            return self.inner.read_u8(pos);
+        }
+
+        self.read_array(pos).map(u8::from_le_bytes)
+    }
+
+    fn write_u8(&mut self, pos: u32, value: u8) -> bool {
+        if !self.overlaps(pos, 1) {
+            return self.inner.write_u8(pos, value);
+        }
+
+        self.write_from(pos, &[value])
+    }
+
+    fn read_chunk(&self, pos: u32, len: u32) -> Option<Vec<u8>> {
+        if !self.overlaps(pos, len) {
+            return self.inner.read_chunk(pos, len);
+        }
+
+        let mut result = vec![0; len as usize];
+        self.read_into(pos, &mut result).then_some(result)
+    }
+
+    fn translate(&self, pos: u32, write: bool) -> Result<u32, PageFault> {
+        self.inner.translate(pos, write)
+    }
+
+    fn tick(&mut self) {
+        self.inner.tick();
+    }
+
+    fn interrupt_pending(&self) -> bool {
+        self.inner.interrupt_pending()
+    }
+
+    /// The host may change the shared memory at any time
+    fn is_cacheable(&self, pos: u32) -> bool {
+        !self.overlaps(pos, INSTRUCTION_SIZE) && self.inner.is_cacheable(pos)
+    }
+
+    /// The shared memory isn't part of snapshots (like devices)
+    fn memory_image(&self) -> Vec<u8> {
+        self.inner.memory_image()
+    }
+
+    fn restore_memory_image(&mut self, image: &[u8]) -> bool {
+        self.inner.restore_memory_image(image)
+    }
+
+    fn reset_memory(&mut self) {
+        self.inner.reset_memory();
+    }
+
+    fn load_program(&mut self, program: &[u8]) -> bool {
+        self.inner.load_program(program)
+    }
+
+    /// The window can't be borrowed (`read_chunk` reads it)
+    fn memory(&self, range: Range<u32>) -> Option<&[u8]> {
+        if self.overlaps(range.start, range.end.saturating_sub(range.start)) {
+            return None;
+        }
+
+        self.inner.memory(range)
+    }
+
+    fn len(&self) -> u32 {
+        self.inner.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use super::{SharedMemoryInterpreter, SharedMemory};
+    use crate::common::{OpCode, Register};
+    use crate::runtime::{utils, BinaryInterpreter, Interpreter, VirtualMachine};
+
+    const WINDOW: u32 = 0x10000;
+
+    #[test]
+    fn program_accesses() {
+        let program: Vec<u8> = [
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R1, WINDOW),
+            utils::create_instruction_register_and_immediate(OpCode::ADDI, Register::R1, 1),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R1, WINDOW + 4),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 0),
+        ].iter().flat_map(|instruction| instruction.to_le_bytes()).collect();
+        let mut inner = BinaryInterpreter::with_memory_size(0x1000).expect("Expected");
+        assert!(inner.load_program(&program));
+
+        let shared = Arc::new(Mutex::new([41u32.to_le_bytes(), [0; 4]].concat()));
+        let interpreter = SharedMemoryInterpreter::new(inner, WINDOW, shared.clone()).expect("Expected");
+        assert_eq!(WINDOW..WINDOW + 8, interpreter.window());
+
+        let mut stdout = std::io::sink();
+        let mut vm = VirtualMachine::new(interpreter, &mut stdout);
+        assert_eq!(42, vm.execute_first());
+        assert_eq!([41u32.to_le_bytes(), 42u32.to_le_bytes()].concat(), *shared.lock().unwrap());
+
+        // The host changes the data between runs
+        shared.lock().unwrap()[0] = 99;
+        assert_eq!(100, vm.execute_first());
+
+        // Not part of snapshots
+        assert_eq!(0x1000, vm.get_interpreter().memory_image().len());
+    }
+
+    #[test]
+    fn window() {
+        let inner = BinaryInterpreter::with_memory_size(16).expect("Expected");
+        let mut buffer = [0u8; 6];
+        {
+            let mut interpreter = SharedMemoryInterpreter::new(inner, 14, &mut buffer[..]).expect("Expected");
+            assert_eq!(14..20, interpreter.window());
+
+            // Partially inside of the window
+            assert!(interpreter.write_u32(12, 0xAABBCCDD));
+            assert_eq!(Some(0xAABBCCDD), interpreter.read_u32(12));
+            assert_eq!(Some(0xCCDD), interpreter.inner().read_u16(12));
+            assert_eq!(Some(0), interpreter.inner().read_u16(14));
+
+            // Behind the memory
+            assert!(!interpreter.write_u16(19, 0x1234));
+            assert!(interpreter.write_u16(18, 0x1234));
+            assert_eq!(None, interpreter.read_u8(20));
+            assert_eq!(Some(vec![0xDD, 0xCC, 0xBB, 0xAA, 0, 0, 0x34, 0x12]), interpreter.read_chunk(12, 8));
+
+            assert!(!interpreter.is_cacheable(12));
+            assert!(interpreter.is_cacheable(8));
+        }
+
+        assert_eq!([0xBB, 0xAA, 0, 0, 0x34, 0x12], buffer);
+        assert_eq!(6, buffer.to_vec().size());
+        assert!(SharedMemoryInterpreter::new(BinaryInterpreter::new(), u32::MAX, vec![0; 2]).is_none());
+    }
+
+    #[test]
+    fn read_memory() {
+        let mut inner = BinaryInterpreter::with_memory_size(16).expect("Expected");
+        assert!(inner.load_program(&[1, 2, 3, 4, 5, 6, 7, 8]));
+        let interpreter = SharedMemoryInterpreter::new(inner, 4, vec![0xAA, 0xBB]).expect("Expected");
+        let mut stdout = std::io::sink();
+        let vm = VirtualMachine::new(interpreter, &mut stdout);
+
+        // The window hides the memory of the wrapped interpreter
+        assert_eq!(Some(&[1, 2, 3, 4, 0xAA, 0xBB, 7, 8][..]), vm.read_memory(0..8).as_deref());
+        assert_eq!(Some(&[0xBB, 7][..]), vm.read_memory(5..7).as_deref());
+        assert_eq!(Some(&[1, 2, 3, 4][..]), vm.read_memory(0..4).as_deref());
+        assert_eq!(Some(&[7, 8][..]), vm.read_memory(6..8).as_deref());
+    }
+
+    #[test]
+    fn window_size() {
+        let shared = Arc::new(Mutex::new(vec![1, 2]));
+        let mut interpreter = SharedMemoryInterpreter::new(BinaryInterpreter::with_memory_size(16).expect("Expected"), 4, shared.clone()).expect("Expected");
+
+        // The window keeps its size, until the host changes it
+        shared.lock().unwrap().extend_from_slice(&[3, 4]);
+        assert_eq!(4..6, interpreter.window());
+        assert_eq!(Some(0), interpreter.read_u8(6));
+        assert!(interpreter.set_window_size(4));
+        assert_eq!(4..8, interpreter.window());
+        assert_eq!(Some(0x04030201), interpreter.read_u32(4));
+
+        // Behind the end of the shared memory
+        shared.lock().unwrap().truncate(2);
+        assert_eq!(None, interpreter.read_u8(6));
+
+        assert!(!interpreter.set_window_size(u32::MAX as usize));
+        assert_eq!(4..8, interpreter.window());
+    }
+}
@@ -0,0 +1,166 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use std::collections::HashMap;
+
+/// Named memory range (like stack, heap or a label in the program)
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryRegion {
+    pub name: String,
+    pub start: u32,
+    pub size: u32,
+}
+
+impl MemoryRegion {
+    pub fn contains(&self, address: u32) -> bool {
+        address >= self.start && ((address - self.start) as u64) < self.size as u64
+    }
+}
+
+/// Named memory regions for reporting addresses like `0x3FFFF8 (stack+0x8)`
+#[derive(PartialEq, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryMap {
+    regions: Vec<MemoryRegion>,
+}
+
+impl MemoryMap {
+    pub fn new() -> MemoryMap {
+        MemoryMap::default()
+    }
+
+    /// Regions for the labels of a compiled program (see
+    /// `compiler::get_symbols`). A label's region ends at the next label or
+    /// at the end of the program.
+    pub fn from_symbols(symbols: &HashMap<String, u32>, program_len: u32) -> MemoryMap {
+        let mut labels: Vec<(&String, u32)> = symbols.iter().map(|(name, address)| (name, *address)).collect();
+        labels.sort_by(|(name0, address0), (name1, address1)| address0.cmp(address1).then(name0.cmp(name1)));
+
+        let mut result = MemoryMap::new();
+        for (name, start) in &labels {
+            let end = labels.iter()
+                .map(|(_, address)| *address)
+                .find(|address| address > start)
+                .unwrap_or_else(|| program_len.max(*start));
+            result.add_region(name, *start, end - start);
+        }
+
+        result
+    }
+
+    pub fn add_region(&mut self, name: &str, start: u32, size: u32) {
+        self.regions.push(MemoryRegion { name: name.to_string(), start, size });
+    }
+
+    pub fn regions(&self) -> &[MemoryRegion] {
+        &self.regions
+    }
+
+    /// Smallest region containing the address (the first one, if there are
+    /// multiple)
+    pub fn find(&self, address: u32) -> Option<&MemoryRegion> {
+        self.regions.iter()
+            .filter(|region| region.contains(address))
+            .min_by_key(|region| region.size)
+    }
+
+    /// Formats the address with the region containing it, like
+    /// `0x3FFFF8 (stack+0x8)`
+    pub fn format_address(&self, address: u32) -> String {
+        match self.find(address) {
+            Some(region) if region.start == address => format!("0x{:X} ({})", address, region.name),
+            Some(region) => format!("0x{:X} ({}+0x{:X})", address, region.name, address - region.start),
+            None => format!("0x{:X}", address),
+        }
+    }
+}
+
+/// Map file of the symbols: a line `0x00000010 loop` per symbol, sorted by
+/// address (and name)
+pub fn format_map_file(symbols: &HashMap<String, u32>) -> String {
+    let mut symbols: Vec<(&String, &u32)> = symbols.iter().collect();
+    symbols.sort_by(|(name0, address0), (name1, address1)| address0.cmp(address1).then(name0.cmp(name1)));
+    symbols.iter().map(|(name, address)| format!("0x{:08X} {}\n", address, name)).collect()
+}
+
+/// Reads a map file (see `format_map_file`). Returns None, if a line isn't
+/// an address followed by a name.
+pub fn parse_map_file(content: &str) -> Option<HashMap<String, u32>> {
+    content.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let mut parts = line.split_whitespace();
+            let address = u32::from_str_radix(parts.next()?.strip_prefix("0x")?, 16).ok()?;
+            match (parts.next(), parts.next()) {
+                (Some(name), None) => Some((name.to_string(), address)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::{MemoryMap, format_map_file, parse_map_file};
+
+    #[test]
+    fn format_address() {
+        let mut map = MemoryMap::new();
+        map.add_region("stack", 0x3FFFF0, 0x10);
+        map.add_region("memory", 0, 0x400000);
+
+        assert_eq!("0x3FFFF8 (stack+0x8)", map.format_address(0x3FFFF8));
+        assert_eq!("0x3FFFF0 (stack)", map.format_address(0x3FFFF0));
+        assert_eq!("0x10 (memory+0x10)", map.format_address(0x10));
+        assert_eq!("0x400000", map.format_address(0x400000));
+    }
+
+    #[test]
+    fn from_symbols() {
+        let mut symbols = HashMap::new();
+        symbols.insert("main".to_string(), 0);
+        symbols.insert("loop".to_string(), 8);
+        symbols.insert("string".to_string(), 16);
+        let map = MemoryMap::from_symbols(&symbols, 24);
+
+        assert_eq!(3, map.regions().len());
+        assert_eq!("0x4 (main+0x4)", map.format_address(4));
+        assert_eq!("0x8 (loop)", map.format_address(8));
+        assert_eq!("0x17 (string+0x7)", map.format_address(23));
+        assert_eq!("0x18", map.format_address(24));
+    }
+
+    #[test]
+    fn map_file() {
+        let mut symbols = HashMap::new();
+        symbols.insert("string".to_string(), 0x1C);
+        symbols.insert("main".to_string(), 0);
+        symbols.insert("start".to_string(), 0);
+        assert_eq!("0x00000000 main\n0x00000000 start\n0x0000001C string\n", format_map_file(&symbols));
+        assert_eq!("", format_map_file(&HashMap::new()));
+
+        assert_eq!(Some(symbols.clone()), parse_map_file(&format_map_file(&symbols)));
+        assert_eq!(Some(HashMap::new()), parse_map_file("\n"));
+        assert_eq!(None, parse_map_file("0x00000000 main\n0x10\n"));
+        assert_eq!(None, parse_map_file("16 main\n"));
+        assert_eq!(None, parse_map_file("0x00000000 two names\n"));
+    }
+}
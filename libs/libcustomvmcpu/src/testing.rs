@@ -0,0 +1,154 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Random instructions for fuzzing the decoder and the virtual machine.
+//! `InstructionGenerator` creates reproducible sequences from a seed, with
+//! the `arbitrary` feature `Instruction` can be created by fuzzers.
+
+use num_traits::FromPrimitive;
+use super::common::{OpCode, Register, LAST_REGISTER, InstructionParseType, get_instruction_parse_type};
+use super::runtime::utils;
+
+/// Well-formed instruction: a valid opcode with valid registers. Operands,
+/// which the opcode doesn't have, are ignored by `encode`.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Instruction {
+    pub op_code: OpCode,
+    pub reg0: Register,
+    pub reg1: Register,
+    /// Truncated to the immediate size of the instruction format
+    pub immediate: u32,
+}
+
+impl Instruction {
+    /// Machine code of the instruction
+    pub fn encode(&self) -> u32 {
+        match get_instruction_parse_type(self.op_code) {
+            InstructionParseType::TwoRegisters => utils::create_instruction_two_registers(self.op_code, self.reg0, self.reg1),
+            InstructionParseType::RegisterAndImmediate => utils::create_instruction_register_and_immediate(self.op_code, self.reg0, self.immediate),
+            InstructionParseType::Register => utils::create_instruction_register(self.op_code, self.reg1),
+            InstructionParseType::Immediate => utils::create_instruction_immediate(self.op_code, self.immediate & 0x00FFFFFF),
+            InstructionParseType::TwoRegistersAndImmediate => utils::create_instruction_two_registers_and_immediate(self.op_code, self.reg0, self.reg1, self.immediate),
+            InstructionParseType::NoOperands => utils::create_instruction(self.op_code),
+        }
+    }
+}
+
+/// All valid opcodes
+pub fn op_codes() -> Vec<OpCode> {
+    (0..=u8::MAX).filter_map(OpCode::from_u8).collect()
+}
+
+/// All registers
+pub fn registers() -> Vec<Register> {
+    (0..=LAST_REGISTER as u8).filter_map(Register::from_u8).collect()
+}
+
+/// Pseudo-random generator of instructions (xorshift64*). The same seed
+/// always creates the same sequence.
+#[derive(Debug, Clone)]
+pub struct InstructionGenerator {
+    state: u64,
+    op_codes: Vec<OpCode>,
+    registers: Vec<Register>,
+}
+
+impl InstructionGenerator {
+    pub fn new(seed: u64) -> InstructionGenerator {
+        // The state must not be 0
+        InstructionGenerator { state: seed ^ 0x9E3779B97F4A7C15, op_codes: op_codes(), registers: registers() }
+    }
+
+    /// Random raw instruction word (usually invalid)
+    pub fn next_raw(&mut self) -> u32 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545F4914F6CDD1D) >> 32) as u32
+    }
+
+    /// Random well-formed instruction
+    pub fn next_instruction(&mut self) -> Instruction {
+        let op_code = self.next_raw() as usize % self.op_codes.len();
+        let reg0 = self.next_raw() as usize % self.registers.len();
+        let reg1 = self.next_raw() as usize % self.registers.len();
+        Instruction {
+            op_code: self.op_codes[op_code],
+            reg0: self.registers[reg0],
+            reg1: self.registers[reg1],
+            immediate: self.next_raw(),
+        }
+    }
+
+    /// Machine code of `len` random well-formed instructions
+    pub fn program(&mut self, len: usize) -> Vec<u32> {
+        (0..len).map(|_| self.next_instruction().encode()).collect()
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Instruction {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Instruction {
+            op_code: *u.choose(&op_codes())?,
+            reg0: *u.choose(&registers())?,
+            reg1: *u.choose(&registers())?,
+            immediate: u.arbitrary()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InstructionGenerator;
+    use crate::disassembler::disassemble_instruction;
+    use crate::runtime::{BinaryInterpreter, BinaryVirtualMachine};
+
+    #[test]
+    fn reproducible() {
+        assert_eq!(InstructionGenerator::new(1).program(16), InstructionGenerator::new(1).program(16));
+        assert_ne!(InstructionGenerator::new(1).program(16), InstructionGenerator::new(2).program(16));
+    }
+
+    #[test]
+    fn well_formed() {
+        let mut generator = InstructionGenerator::new(3);
+        for _ in 0..1000 {
+            let instruction = generator.next_instruction();
+            let disassembled = disassemble_instruction(instruction.encode()).expect("Expected valid instruction");
+            assert!(disassembled.starts_with(&instruction.op_code.to_string()));
+        }
+    }
+
+    #[test]
+    fn execute_random_programs() {
+        let mut generator = InstructionGenerator::new(4);
+        for _ in 0..200 {
+            for program in [generator.program(64), (0..64).map(|_| generator.next_raw()).collect()] {
+                let interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+                let mut stdout = std::io::sink();
+                let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+                vm.set_hart_count(2);
+                vm.start(0);
+                vm.run_for(1000);
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+
+//! Property-based testing of programs, the assembler and the virtual
+//! machine, also for downstream extensions: helpers asserting invariants
+//! (they panic like `assert!`) and, with the `proptest` feature, strategies
+//! for registers, opcodes, instructions and small programs.
+
+use super::compiler::{compile_image, CompileOptions};
+use super::disassembler::disassemble_program;
+use super::format::format_source;
+use super::parser::parse_string;
+use super::runtime::{BinaryInterpreter, BinaryVirtualMachine, Interpreter};
+
+/// Asserts, that disassembling `program` and assembling the result again
+/// yields the same binary and entry point
+pub fn assert_disassembly_roundtrip(program: &[u8], entry: u32) {
+    let source = disassemble_program(program, entry);
+    let image = compile_image(&mut parse_string(&source), &CompileOptions::default())
+        .unwrap_or_else(|| panic!("Expected the disassembly to compile:\n{}", source));
+    assert_eq!(program, &image.program[..], "Disassembly:\n{}", source);
+    assert_eq!(entry, image.entry, "Disassembly:\n{}", source);
+}
+
+/// Asserts, that formatting `source` keeps the compiled program and that
+/// formatting is idempotent. The program must compile.
+pub fn assert_format_roundtrip(source: &str) {
+    let compile = |source: &str| compile_image(&mut parse_string(&source.to_string()), &CompileOptions::default());
+    let expected = compile(source).unwrap_or_else(|| panic!("Expected the program to compile:\n{}", source));
+    let formatted = format_source(source).unwrap_or_else(|errors| panic!("Expected the program to be formatted: {:?}", errors));
+    let image = compile(&formatted).unwrap_or_else(|| panic!("Expected the formatted program to compile:\n{}", formatted));
+    assert_eq!(expected, image, "Formatted:\n{}", formatted);
+    assert_eq!(Ok(formatted.clone()), format_source(&formatted), "Expected formatting to be idempotent");
+}
+
+/// Asserts, that running `program` twice for at most `max_steps`
+/// instructions ends with the same result and state (see `VmSnapshot`)
+pub fn assert_deterministic(program: &[u8], max_steps: u64) {
+    let run = || {
+        let mut interpreter = BinaryInterpreter::new();
+        assert!(interpreter.load_program(program), "Expected the program to fit into the memory");
+        let mut stdout = Vec::new();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+        vm.start(0);
+        let result = vm.run_for(max_steps);
+        let snapshot = vm.snapshot();
+        drop(vm);
+        (result, snapshot, stdout)
+    };
+
+    // Not assert_eq, which would print the whole memory
+    assert!(run() == run(), "Expected the same result and state for {:02X?}", program);
+}
+
+#[cfg(feature = "proptest")]
+mod strategies {
+    use proptest::prelude::*;
+    use proptest::sample::select;
+    use crate::common::{OpCode, Register};
+    use crate::disassembler::disassemble_program;
+    use crate::testing::{self, Instruction};
+
+    pub fn register() -> impl Strategy<Value = Register> {
+        select(testing::registers())
+    }
+
+    pub fn op_code() -> impl Strategy<Value = OpCode> {
+        select(testing::op_codes())
+    }
+
+    /// Well-formed instructions (see `testing::Instruction`)
+    pub fn instruction() -> impl Strategy<Value = Instruction> {
+        (op_code(), register(), register(), any::<u32>())
+            .prop_map(|(op_code, reg0, reg1, immediate)| Instruction { op_code, reg0, reg1, immediate })
+    }
+
+    /// Machine code of up to `max_len` well-formed instructions
+    pub fn program(max_len: usize) -> impl Strategy<Value = Vec<u8>> {
+        proptest::collection::vec(instruction(), 0..=max_len)
+            .prop_map(|instructions| instructions.iter().flat_map(|instruction| instruction.encode().to_le_bytes()).collect())
+    }
+
+    /// Assembler source of up to `max_len` well-formed instructions
+    pub fn source(max_len: usize) -> impl Strategy<Value = String> {
+        program(max_len).prop_map(|program| disassemble_program(&program, 0))
+    }
+}
+
+#[cfg(feature = "proptest")]
+pub use strategies::{register, op_code, instruction, program, source};
+
+#[cfg(test)]
+mod tests {
+    use super::{assert_disassembly_roundtrip, assert_format_roundtrip, assert_deterministic};
+    use crate::testing::InstructionGenerator;
+
+    #[test]
+    fn invariants() {
+        let mut generator = InstructionGenerator::new(5);
+        for len in 0..64 {
+            let program: Vec<u8> = generator.program(len).iter().flat_map(|word| word.to_le_bytes()).collect();
+            assert_disassembly_roundtrip(&program, 0);
+            assert_deterministic(&program, 1000);
+        }
+
+        assert_format_roundtrip(include_str!("../../interpreter/program/threads.asm"));
+        assert_format_roundtrip("main:   li $r0,3\n\n\n  ji %main // Loop\n");
+    }
+
+    #[test]
+    fn roundtrip_bytes() {
+        // Trailing bytes, which aren't UTF-8
+        assert_disassembly_roundtrip(&[0xFF], 0);
+        assert_disassembly_roundtrip(&[0, 0, 0, 0, 0xC3], 0);
+    }
+
+    #[cfg(feature = "proptest")]
+    mod properties {
+        use proptest::prelude::*;
+        use super::super::{assert_disassembly_roundtrip, assert_format_roundtrip, assert_deterministic, program, source};
+
+        proptest! {
+            #[test]
+            fn disassembly_roundtrip(program in program(32)) {
+                assert_disassembly_roundtrip(&program, 0);
+            }
+
+            #[test]
+            fn deterministic(program in program(32)) {
+                assert_deterministic(&program, 500);
+            }
+
+            #[test]
+            fn format_roundtrip(source in source(16)) {
+                assert_format_roundtrip(&source);
+            }
+        }
+    }
+}
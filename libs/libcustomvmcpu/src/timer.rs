@@ -0,0 +1,205 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::device::{Device, DEVICE_AREA_START};
+
+/// Default address of the timer
+pub const TIMER_DEFAULT_ADDRESS: u32 = DEVICE_AREA_START;
+
+/// Size of the timer's address window
+pub const TIMER_SIZE: u32 = 12;
+
+/// Remaining instructions until the timer expires (0 stops the timer)
+pub const TIMER_COUNTER: u32 = 0;
+/// Bit 0 is set, when the timer expired. Writing 1 clears it.
+pub const TIMER_STATUS: u32 = 4;
+/// Counter value loaded after expiring (0 for a one-shot timer)
+pub const TIMER_RELOAD: u32 = 8;
+
+/// Bit of TIMER_STATUS set by expiring
+pub const TIMER_STATUS_EXPIRED: u32 = 1;
+
+/// Programmable countdown timer counting executed instructions. It requests
+/// an interrupt while the expired flag is set.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct Timer {
+    counter: u32,
+    status: u32,
+    reload: u32,
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        Timer::default()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.status & TIMER_STATUS_EXPIRED != 0
+    }
+
+    fn get_register(&self, offset: u32) -> Option<u32> {
+        match offset {
+            TIMER_COUNTER => Some(self.counter),
+            TIMER_STATUS => Some(self.status),
+            TIMER_RELOAD => Some(self.reload),
+            _ => None,
+        }
+    }
+}
+
+/// Replaces the byte `index` of `value`
+fn replace_byte(value: u32, index: u32, byte: u8) -> u32 {
+    let shift = index * 8;
+    (value & !(0xFF << shift)) | ((byte as u32) << shift)
+}
+
+impl Device for Timer {
+    fn read_u8(&self, offset: u32) -> Option<u8> {
+        let value = self.get_register(offset - offset % 4)?;
+        Some(value.to_le_bytes()[(offset % 4) as usize])
+    }
+
+    fn write_u8(&mut self, offset: u32, value: u8) -> bool {
+        let index = offset % 4;
+        match offset - index {
+            TIMER_COUNTER => self.counter = replace_byte(self.counter, index, value),
+            TIMER_STATUS => self.status &= !((value as u32) << (index * 8)),
+            TIMER_RELOAD => self.reload = replace_byte(self.reload, index, value),
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn tick(&mut self) {
+        if self.counter == 0 {
+            return;
+        }
+
+        self.counter -= 1;
+        if self.counter == 0 {
+            self.status |= TIMER_STATUS_EXPIRED;
+            self.counter = self.reload;
+        }
+    }
+
+    fn interrupt_pending(&self) -> bool {
+        self.is_expired()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Timer, TIMER_COUNTER, TIMER_STATUS, TIMER_RELOAD, TIMER_STATUS_EXPIRED, TIMER_DEFAULT_ADDRESS, TIMER_SIZE};
+    use crate::common::{OpCode, Register, SystemRegister};
+    use crate::device::Device;
+    use crate::runtime::{BinaryInterpreter, BinaryVirtualMachine, Interpreter, utils};
+
+    fn write_u32(timer: &mut Timer, offset: u32, value: u32) {
+        for (i, byte) in value.to_le_bytes().iter().enumerate() {
+            assert!(timer.write_u8(offset + i as u32, *byte));
+        }
+    }
+
+    #[test]
+    fn countdown() {
+        let mut timer = Timer::new();
+        timer.tick();
+        assert!(!timer.is_expired());
+
+        write_u32(&mut timer, TIMER_COUNTER, 2);
+        timer.tick();
+        assert!(!timer.is_expired());
+        assert_eq!(Some(1), timer.read_u8(TIMER_COUNTER));
+        timer.tick();
+        assert!(timer.is_expired());
+        assert!(timer.interrupt_pending());
+        assert_eq!(Some(TIMER_STATUS_EXPIRED as u8), timer.read_u8(TIMER_STATUS));
+
+        // One-shot
+        assert_eq!(Some(0), timer.read_u8(TIMER_COUNTER));
+        write_u32(&mut timer, TIMER_STATUS, TIMER_STATUS_EXPIRED);
+        assert!(!timer.is_expired());
+
+        assert!(!timer.write_u8(TIMER_RELOAD + 4, 0));
+        assert_eq!(None, timer.read_u8(TIMER_RELOAD + 4));
+    }
+
+    #[test]
+    fn reload() {
+        let mut timer = Timer::new();
+        write_u32(&mut timer, TIMER_RELOAD, 300);
+        write_u32(&mut timer, TIMER_COUNTER, 1);
+        timer.tick();
+        assert!(timer.is_expired());
+        assert_eq!(Some(300u32.to_le_bytes()[1]), timer.read_u8(TIMER_COUNTER + 1));
+    }
+
+    #[test]
+    fn polling_program() {
+        // Starts the timer and waits until it expired
+        let program: [u32; 7] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 5),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R0, TIMER_DEFAULT_ADDRESS + TIMER_COUNTER),
+            utils::create_instruction_register_and_immediate(OpCode::ADDI, Register::R1, 1), // wait:
+            utils::create_instruction_register_and_immediate(OpCode::LWI, Register::R2, TIMER_DEFAULT_ADDRESS + TIMER_STATUS),
+            utils::create_instruction_register_and_immediate(OpCode::JZI, Register::R2, 2 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R2, TIMER_DEFAULT_ADDRESS + TIMER_STATUS),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 0),
+        ];
+
+        let mut interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        assert!(interpreter.map_device(TIMER_DEFAULT_ADDRESS, TIMER_SIZE, Box::new(Timer::new())));
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        // The timer expires after the fifth instruction following the store
+        assert_eq!(2, vm.execute_first());
+        assert!(!vm.get_interpreter().interrupt_pending());
+    }
+
+    #[test]
+    fn interrupt_program() {
+        // Waits in a loop until the interrupt handler sets $r3
+        let program: [u32; 13] = [
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 12 * 4),
+            utils::create_instruction_register_and_immediate(OpCode::MTS, Register::R0, SystemRegister::VectorTable as u32),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R0, 5),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R0, TIMER_DEFAULT_ADDRESS + TIMER_COUNTER),
+            utils::create_instruction(OpCode::EI),
+            utils::create_instruction_register_and_immediate(OpCode::ADDI, Register::R1, 1), // wait:
+            utils::create_instruction_register_and_immediate(OpCode::JZI, Register::R3, 5 * 4),
+            utils::create_instruction_immediate(OpCode::SYSCALLI, 0),
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R3, 1), // handler:
+            utils::create_instruction_register_and_immediate(OpCode::LI, Register::R2, TIMER_STATUS_EXPIRED),
+            utils::create_instruction_register_and_immediate(OpCode::SWI, Register::R2, TIMER_DEFAULT_ADDRESS + TIMER_STATUS),
+            utils::create_instruction(OpCode::RETI),
+            8 * 4, // Vector table: interrupt handler
+        ];
+
+        let mut interpreter = BinaryInterpreter::new_with_program(&program).expect("Expected");
+        assert!(interpreter.map_device(TIMER_DEFAULT_ADDRESS, TIMER_SIZE, Box::new(Timer::new())));
+        let mut stdout = std::io::stdout();
+        let mut vm = BinaryVirtualMachine::new(interpreter, &mut stdout);
+
+        assert_eq!(2, vm.execute_first());
+        assert_eq!(1, vm.read_register_value(Register::R3));
+        assert_eq!(1, vm.stats().traps);
+    }
+}
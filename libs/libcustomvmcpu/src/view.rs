@@ -0,0 +1,214 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+use super::runtime::Interpreter;
+
+/// Type of a field inside of a typed memory region (little-endian like the
+/// rest of the machine)
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum FieldType {
+    U8,
+    U16,
+    U32,
+    I32,
+}
+
+impl FieldType {
+    /// Size of the field in bytes
+    pub fn size(&self) -> u32 {
+        match self {
+            Self::U8 => 1,
+            Self::U16 => 2,
+            Self::U32 | Self::I32 => 4,
+        }
+    }
+}
+
+/// Value of a field read from memory
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Value {
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    I32(i32),
+}
+
+impl Value {
+    /// Value as (not sign extended) u32
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            Self::U8(value) => *value as u32,
+            Self::U16(value) => *value as u32,
+            Self::U32(value) => *value,
+            Self::I32(value) => *value as u32,
+        }
+    }
+}
+
+/// Errors when validating or reading a typed region
+#[derive(PartialEq, Debug, Clone)]
+pub enum ViewError {
+    /// Region has no fields
+    NoFields,
+
+    /// Region or a field in it isn't aligned to the size of the field
+    Misaligned { address: u32, alignment: u32 },
+
+    /// Region doesn't lie completely inside of the memory
+    OutOfBounds { start: u32, len: u64 },
+}
+
+/// A region in the memory of the guest, which the host can read as
+/// structured data: `count` consecutive records, each made of `fields`
+/// (packed, no padding is inserted).
+///
+/// # Example
+///
+/// An array of 10 u32 results at address 64:
+///
+/// ```
+/// use libcustomvmcpu::view::{TypedRegion, FieldType};
+/// let region = TypedRegion::array(64, FieldType::U32, 10);
+/// assert_eq!(40, region.size());
+/// ```
+#[derive(PartialEq, Debug, Clone)]
+pub struct TypedRegion {
+    pub offset: u32,
+    pub fields: Vec<FieldType>,
+    pub count: u32,
+}
+
+impl TypedRegion {
+    pub fn new(offset: u32, fields: Vec<FieldType>, count: u32) -> TypedRegion {
+        TypedRegion { offset, fields, count }
+    }
+
+    /// Region of `count` values of the same type
+    pub fn array(offset: u32, field: FieldType, count: u32) -> TypedRegion {
+        Self::new(offset, vec![field], count)
+    }
+
+    /// Size of a single record in bytes
+    pub fn record_size(&self) -> u32 {
+        self.fields.iter().map(FieldType::size).sum()
+    }
+
+    /// Size of the whole region in bytes
+    pub fn size(&self) -> u64 {
+        self.record_size() as u64 * self.count as u64
+    }
+
+    /// Checks, that every field is aligned to its size and the region lies
+    /// completely inside of the memory of `interpreter`
+    pub fn validate<InterpreterImpl: Interpreter>(&self, interpreter: &InterpreterImpl) -> Result<(), ViewError> {
+        if self.fields.is_empty() {
+            return Err(ViewError::NoFields);
+        }
+
+        // Fields are aligned in all records, if they're aligned in the first
+        // one and the record size is a multiple of the largest field
+        let alignment = self.fields.iter().map(FieldType::size).max().unwrap_or(1);
+        let mut address = self.offset as u64;
+        for field in &self.fields {
+            if !address.is_multiple_of(field.size() as u64) {
+                return Err(ViewError::Misaligned { address: address as u32, alignment: field.size() });
+            }
+
+            address += field.size() as u64;
+        }
+
+        if self.count > 1 && !self.record_size().is_multiple_of(alignment) {
+            return Err(ViewError::Misaligned { address: self.offset + self.record_size(), alignment });
+        }
+
+        if self.offset as u64 + self.size() > interpreter.len() as u64 {
+            return Err(ViewError::OutOfBounds { start: self.offset, len: self.size() });
+        }
+
+        Ok(())
+    }
+
+    /// Reads all records of the region
+    pub fn read<InterpreterImpl: Interpreter>(&self, interpreter: &InterpreterImpl) -> Result<Vec<Vec<Value>>, ViewError> {
+        self.validate(interpreter)?;
+
+        let out_of_bounds = ViewError::OutOfBounds { start: self.offset, len: self.size() };
+        let mut address = self.offset;
+        let mut result = Vec::with_capacity(self.count as usize);
+        for _ in 0..self.count {
+            let mut record = Vec::with_capacity(self.fields.len());
+            for field in &self.fields {
+                let value = match field {
+                    FieldType::U8 => interpreter.read_u8(address).map(Value::U8),
+                    FieldType::U16 => interpreter.read_u16(address).map(Value::U16),
+                    FieldType::U32 => interpreter.read_u32(address).map(Value::U32),
+                    FieldType::I32 => interpreter.read_u32(address).map(|value| Value::I32(value as i32)),
+                };
+
+                record.push(value.ok_or_else(|| out_of_bounds.clone())?);
+                address += field.size();
+            }
+
+            result.push(record);
+        }
+
+        Ok(result)
+    }
+
+    /// Reads a region with a single field per record as flat list of u32
+    pub fn read_u32s<InterpreterImpl: Interpreter>(&self, interpreter: &InterpreterImpl) -> Result<Vec<u32>, ViewError> {
+        Ok(self.read(interpreter)?.iter().flatten().map(Value::as_u32).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TypedRegion, FieldType, Value, ViewError};
+    use crate::runtime::{BinaryInterpreter, BINARY_INTERPRETER_MEM_SIZE};
+
+    #[test]
+    fn read_array() {
+        let interpreter = BinaryInterpreter::new_with_program(&[0, 1, 2, 0xFFFFFFFF]).expect("Expected");
+        let region = TypedRegion::array(4, FieldType::U32, 3);
+        assert_eq!(Ok(vec![1, 2, 0xFFFFFFFF]), region.read_u32s(&interpreter));
+
+        let region = TypedRegion::array(12, FieldType::I32, 1);
+        assert_eq!(Ok(vec![vec![Value::I32(-1)]]), region.read(&interpreter));
+    }
+
+    #[test]
+    fn read_records() {
+        let interpreter = BinaryInterpreter::new_with_initial(&vec![1, 0, 2, 3, 4, 0, 5, 6]).expect("Expected");
+        let region = TypedRegion::new(0, vec![FieldType::U16, FieldType::U8, FieldType::U8], 2);
+        assert_eq!(Ok(vec![
+            vec![Value::U16(1), Value::U8(2), Value::U8(3)],
+            vec![Value::U16(4), Value::U8(5), Value::U8(6)],
+        ]), region.read(&interpreter));
+    }
+
+    #[test]
+    fn validate() {
+        let interpreter = BinaryInterpreter::new();
+        assert_eq!(Err(ViewError::NoFields), TypedRegion::new(0, Vec::new(), 1).validate(&interpreter));
+        assert_eq!(Err(ViewError::Misaligned { address: 2, alignment: 4 }), TypedRegion::array(2, FieldType::U32, 1).validate(&interpreter));
+        assert_eq!(Err(ViewError::Misaligned { address: 5, alignment: 4 }), TypedRegion::new(0, vec![FieldType::U32, FieldType::U8], 2).validate(&interpreter));
+        assert_eq!(Err(ViewError::OutOfBounds { start: BINARY_INTERPRETER_MEM_SIZE - 4, len: 8 }), TypedRegion::array(BINARY_INTERPRETER_MEM_SIZE - 4, FieldType::U32, 2).validate(&interpreter));
+        assert_eq!(Ok(()), TypedRegion::array(BINARY_INTERPRETER_MEM_SIZE - 4, FieldType::U32, 1).validate(&interpreter));
+    }
+}
@@ -0,0 +1,40 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! All tools in one binary: `vmcpu asm`, `fmt`, `disasm`, `run`, `exec`,
+//! `debug` and `repl` (the other binaries are kept for compatibility)
+
+use clap::Parser;
+use libcustomvmcpu::diagnostic::ColorChoice;
+
+/// Virtual CPU written in rust: assembles, runs and debugs programs
+#[derive(Parser)]
+#[command(name = "vmcpu", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: rust_customvmcpu::Command,
+    /// Colors diagnostics (auto, always or never)
+    #[arg(long, global = true, value_name = "WHEN", default_value = "auto")]
+    color: ColorChoice,
+}
+
+fn main() {
+    let cli = Cli::parse();
+    rust_customvmcpu::run_command(cli.command, cli.color);
+}
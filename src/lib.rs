@@ -0,0 +1,369 @@
+/*
+ * 
+ * Custom, virtual CPU environment written in Rust
+ * Copyright (C) 2021  Fionn Langhans
+
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Command line of the main binary (`rust-customvmcpu`) and of `vmcpu`, which
+//! share the subcommands (`Command`)
+
+use std::{fs, str, thread, process::exit};
+use std::time::{Duration, Instant};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+use clap::{Args, Parser, Subcommand};
+use clap::error::ErrorKind;
+
+use libcustomvmcpu::runtime::BinaryInterpreter;
+use libcustomvmcpu::common::{OpCode, Register, Error, ERROR_START_NUM};
+use libcustomvmcpu::parser::get_register_by_name;
+use libcustomvmcpu::image::Image;
+use libcustomvmcpu::builder::VmBuilder;
+use libcustomvmcpu::{parser, compiler, diagnostic, repl, profile, symbols};
+
+/// How often `run --watch` checks the file for modifications
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Instructions executed between checking, whether the run is cancelled
+const SLICE_INSTRUCTIONS: u64 = 100_000;
+
+/// Virtual CPU written in rust: runs a compiled program
+#[derive(Parser)]
+#[command(name = "rust-customvmcpu", version, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Compiled program (`-` is the standard input)
+    #[arg(required = true)]
+    file: Option<String>,
+    #[command(flatten)]
+    options: Options,
+    /// Colors diagnostics (auto, always or never)
+    #[arg(long, global = true, value_name = "WHEN", default_value = "auto")]
+    color: diagnostic::ColorChoice,
+}
+
+/// Options for running programs (`run` and `exec`)
+#[derive(Args)]
+pub struct Options {
+    /// Prints the value of the register after the run (like `r0` or `$sp`)
+    #[arg(long, value_name = "REGISTER", value_parser = parse_register)]
+    print_register: Vec<Register>,
+    /// Prints all registers after the run
+    #[arg(long)]
+    register_table: bool,
+    /// Input of the program (instead of the standard input)
+    #[arg(long, value_name = "FILE")]
+    stdin_file: Option<String>,
+    /// Prints how often every opcode and the most executed addresses were
+    /// executed
+    #[arg(long)]
+    profile: bool,
+    /// Labels from a map file (written by `compiler --map`) for the profile
+    #[arg(long, value_name = "FILE")]
+    symbols: Option<String>,
+}
+
+/// Subcommands of `rust-customvmcpu` and `vmcpu`
+#[derive(Subcommand)]
+pub enum Command {
+    /// Compiles an assembler program (like `compiler`)
+    Asm(::compiler::AsmArgs),
+    /// Prints the program in the canonical format
+    Fmt(::compiler::FmtArgs),
+    /// Prints the addresses, words and instructions of a compiled program
+    Disasm(::compiler::DisasmArgs),
+    /// Compiles and runs an assembler program (without writing the binary)
+    Run {
+        /// Compiles and runs the program again, whenever it is modified
+        #[arg(long)]
+        watch: bool,
+        /// Program to run (`-` is the standard input)
+        file: String,
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Runs a compiled program
+    Exec {
+        /// Compiled program (`-` is the standard input)
+        file: String,
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Serves the Debug Adapter Protocol on the standard input and output
+    Debug,
+    /// Compiles and executes assembler lines interactively
+    Repl,
+}
+
+/// Parses a register name (with or without `$`, in any case)
+fn parse_register(name: &str) -> Result<Register, String> {
+    get_register_by_name(&name.trim_start_matches('$').to_lowercase())
+        .ok_or_else(|| format!("unknown register `{}`", name))
+}
+
+/// Reads the file or the standard input (`-`)
+fn read_input(file: &str) -> Vec<u8> {
+    if file != "-" {
+        if let Ok(data) = fs::read(file) {
+            data
+        }
+        else {
+            eprintln!("Error: Could not read file \"{}\"", file);
+            exit(1);
+        }
+    } else {
+        let mut result: Vec<u8> = Vec::new();
+        if io::stdin().lock().read_to_end(&mut result).is_err() {
+            eprintln!("Error: Could not read from standard input");
+            exit(1);
+        }
+        result
+    }
+}
+
+/// Labels of `--symbols` (None without the option)
+fn read_symbols(options: &Options) -> Option<HashMap<String, u32>> {
+    let symbols_file = options.symbols.as_ref()?;
+    match fs::read_to_string(symbols_file).ok().and_then(|content| symbols::parse_map_file(&content)) {
+        Some(symbols) => Some(symbols),
+        None => {
+            eprintln!("Error: Could not read the symbols from \"{}\"", symbols_file);
+            exit(1);
+        }
+    }
+}
+
+/// Compiles an assembler program and prints its diagnostics. Returns None,
+/// if it has errors.
+fn compile_source(input: &[u8], color: bool) -> Option<(Image, HashMap<String, u32>)> {
+    let source = match str::from_utf8(input) {
+        Ok(source) => source.to_string(),
+        Err(_) => {
+            eprintln!("Error: The program isn't valid UTF-8");
+            return None;
+        }
+    };
+
+    let mut parser = parser::parse_string(&source);
+    let output = compiler::compile_output(&mut parser, &compiler::CompileOptions::default());
+    for diagnostic in parser.diagnostics() {
+        eprintln!("{}", diagnostic.render_pretty(&source, color));
+    }
+
+    match output {
+        Some(output) => Some((Image::new(output.binary, output.entry).with_imports(output.imports), output.symbols)),
+        None => {
+            eprintln!("Cannot compile program");
+            None
+        }
+    }
+}
+
+/// Input of the program: the file or the standard input
+fn open_input(stdin_file: Option<&str>) -> Box<dyn Read> {
+    match stdin_file {
+        Some(path) => match fs::File::open(path) {
+            Ok(input) => Box::new(input),
+            Err(_) => {
+                eprintln!("Error: Could not read file \"{}\"", path);
+                exit(1);
+            }
+        },
+        None => Box::new(io::stdin()),
+    }
+}
+
+/// Runs the program and prints the selected registers. Returns the exit
+/// code or None, if `cancel` returned true (it is called after every
+/// `SLICE_INSTRUCTIONS` instructions).
+fn execute(image: &Image, symbols: &HashMap<String, u32>, options: &Options, mut cancel: impl FnMut() -> bool) -> Option<i32> {
+    let interpreter = BinaryInterpreter::new_with_initial(&image.program);
+    if let Some(interpreter) = interpreter {
+        let mut stdin = open_input(options.stdin_file.as_deref());
+        let mut stdout = std::io::stdout();
+        let mut stderr = std::io::stderr();
+        let mut vm = VmBuilder::new(interpreter, &mut stdout)
+            .stdin(&mut stdin)
+            .stderr(&mut stderr)
+            .entry(image.entry)
+            .opcode_profiling(options.profile)
+            .address_profiling(options.profile)
+            .build();
+        vm.start(image.entry);
+        let exit_code = loop {
+            if let Some(result) = vm.run_for(SLICE_INSTRUCTIONS) {
+                break result as i32;
+            }
+            if cancel() {
+                return None;
+            }
+        };
+
+        for reg in &options.print_register {
+            println!("{}: {}", reg, vm.read_register_value(*reg));
+        }
+
+        if options.register_table {
+            println!("R0: {}\nR1: {}\nR2: {}\nR3: {}\nR4: {}\nR5: {}\nR6: {}\nR7: {}\nIP: {}\nSP: {}\nRA: {}\nERR: {}\n",
+                vm.read_register_value(Register::R0),
+                vm.read_register_value(Register::R1),
+                vm.read_register_value(Register::R2),
+                vm.read_register_value(Register::R3),
+                vm.read_register_value(Register::R4),
+                vm.read_register_value(Register::R5),
+                vm.read_register_value(Register::R6),
+                vm.read_register_value(Register::R7),
+                vm.read_register_value(Register::IP),
+                vm.read_register_value(Register::SP),
+                vm.read_register_value(Register::RA),
+                vm.read_register_value(Register::ERR),
+            );
+        }
+
+        if let Some(profile) = vm.opcode_profile() {
+            eprint!("{}", profile::format_opcode_profile(&profile));
+        }
+        if let Some(addresses) = vm.hot_addresses(profile::DEFAULT_HOT_ADDRESSES) {
+            let memory_map = symbols::MemoryMap::from_symbols(symbols, image.program.len() as u32);
+            eprint!("{}", profile::format_hot_addresses(&addresses, &memory_map));
+        }
+
+        Some(exit_code)
+    }
+    else {
+        eprintln!("Failed");
+        Some(ERROR_START_NUM as i32 + Error::Memory as i32)
+    }
+}
+
+/// Compiles and runs the program again, whenever the file is modified (a
+/// running program is stopped then)
+fn watch(file: &str, options: &Options, color: bool) -> ! {
+    let modified = || fs::metadata(file).and_then(|metadata| metadata.modified()).ok();
+    let mut last_modified = None;
+    loop {
+        let current = modified();
+        if current.is_some() && current != last_modified {
+            last_modified = current;
+            println!("--- {}", file);
+            match fs::read(file) {
+                Ok(input) => if let Some((image, symbols)) = compile_source(&input, color) {
+                    let symbols = read_symbols(options).unwrap_or(symbols);
+                    let mut last_check = Instant::now();
+                    let cancel = || {
+                        if last_check.elapsed() < WATCH_INTERVAL {
+                            return false;
+                        }
+
+                        last_check = Instant::now();
+                        modified() != last_modified
+                    };
+                    match execute(&image, &symbols, options, cancel) {
+                        Some(exit_code) => println!("--- Exited with {}", exit_code),
+                        None => {
+                            println!("--- Stopped, the file was modified");
+                            continue;
+                        },
+                    }
+                },
+                Err(_) => eprintln!("Error: Could not read file \"{}\"", file),
+            }
+        }
+
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Reads lines until `:quit` or the end of the input
+pub fn run_repl() {
+    let mut stdout = io::stdout();
+    let mut repl = repl::Repl::new(&mut stdout);
+    println!("Type :help for the commands");
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush().ok();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        let output = repl.eval(&line);
+        if !output.is_empty() {
+            println!("{}", output);
+        }
+        if repl.has_quit() {
+            break;
+        }
+    }
+}
+
+/// Runs the compiled program in `file` and exits with its exit code
+pub fn exec(file: &str, options: &Options) -> ! {
+    let image = Image::from_bytes(&read_input(file));
+    let symbols = read_symbols(options).unwrap_or_default();
+    exit(execute(&image, &symbols, options, || false).expect("Not cancelled"));
+}
+
+/// Runs the program (or the subcommand)
+pub fn run(cli: Cli) {
+    match cli.command {
+        Some(command) => run_command(command, cli.color),
+        None => exec(cli.file.as_deref().expect("Made sure by clap"), &cli.options),
+    }
+}
+
+/// Runs the subcommand
+pub fn run_command(command: Command, color: diagnostic::ColorChoice) {
+    let color = color.use_color();
+    match command {
+        Command::Asm(args) => ::compiler::assemble(args, color),
+        Command::Fmt(args) => ::compiler::format_command(&args, color),
+        Command::Disasm(args) => ::compiler::disassemble_command(&args),
+        Command::Run { watch: true, file, .. } if file == "-" => {
+            clap::Error::raw(ErrorKind::ArgumentConflict, "the standard input can't be watched\n").exit();
+        },
+        Command::Run { watch: true, file, options } => self::watch(&file, &options, color),
+        Command::Run { watch: false, file, options } => {
+            let (image, symbols) = compile_source(&read_input(&file), color).unwrap_or_else(|| exit(1));
+            let symbols = read_symbols(&options).unwrap_or(symbols);
+            exit(execute(&image, &symbols, &options, || false).expect("Not cancelled"));
+        },
+        Command::Exec { file, options } => exec(&file, &options),
+        Command::Debug => interpreter::dap::run(),
+        Command::Repl => run_repl(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, Parser};
+    use super::{Cli, Command};
+
+    #[test]
+    fn cli() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from(["rust-customvmcpu", "--register-table", "program.bin"]).unwrap();
+        assert!(cli.command.is_none() && cli.options.register_table);
+
+        let cli = Cli::try_parse_from(["rust-customvmcpu", "run", "--watch", "--color", "never", "--profile", "program.asm"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Run { watch: true, options, .. }) if options.profile));
+
+        assert!(Cli::try_parse_from(["rust-customvmcpu", "asm", "-o", "out.bin", "program.asm"]).is_ok());
+        assert!(Cli::try_parse_from(["rust-customvmcpu", "asm", "fmt", "program.asm"]).is_err());
+    }
+}